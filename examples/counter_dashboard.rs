@@ -0,0 +1,46 @@
+//! A counter dashboard: page views are buffered with a `CounterBuffer` and flushed as one
+//! `incr` per page, while a `CountingObserver` reports what the client did per server.
+//!
+//! Run a memcached on 127.0.0.1:12345 (or pass a url) and `cargo run --example counter_dashboard`.
+
+use memcached::metrics::CountingObserver;
+use std::{env, sync::Arc, time::Duration};
+
+const PAGES: [&str; 3] = ["home", "search", "checkout"];
+
+#[async_std::main]
+async fn main() -> memcached::Result<()> {
+    let url = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "memcache://127.0.0.1:12345".to_owned());
+    let metrics = Arc::new(CountingObserver::default());
+    let client = memcached::connect(&url)?.with_metrics_observer(Arc::clone(&metrics));
+    let run = std::process::id();
+
+    let views = client
+        .counter_buffer(Duration::from_secs(1), 1000)
+        .with_expiration(24 * 60 * 60);
+    for view in 0..30 {
+        let page = PAGES[view % PAGES.len()];
+        views.increment(format!("views:{run}:{page}"), 1).await?;
+    }
+    views.flush().await?;
+
+    println!("{:<10} {:>6}", "page", "views");
+    for page in PAGES {
+        // Adding 0 reads a counter.
+        let count = client.increment(format!("views:{run}:{page}"), 0).await?;
+        println!("{page:<10} {count:>6}");
+        assert_eq!(count, 10);
+    }
+
+    println!();
+    println!("{:<30} {:>10} {:>8}", "server", "operations", "errors");
+    for (server, counts) in metrics.counts() {
+        println!(
+            "{server:<30} {:>10} {:>8}",
+            counts.operations, counts.errors
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! A fixed window rate limiter: one counter per client and window, created with the
+//! window as expiration by `increment_with`, so old windows clean themselves up.
+//!
+//! Run a memcached on 127.0.0.1:12345 (or pass a url) and `cargo run --example rate_limiter`.
+
+use memcached::Client;
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+struct RateLimiter {
+    client: Client,
+    limit: u64,
+    window: u32,
+}
+
+impl RateLimiter {
+    /// Count a request of `caller`, `false` once it made more than `limit` in this window.
+    async fn allow(&self, caller: &str) -> memcached::Result<bool> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let window = now / u64::from(self.window);
+        let key = format!("rate:{caller}:{window}");
+        let requests = self.client.increment_with(&key, 1, 1, self.window).await?;
+        Ok(requests <= self.limit)
+    }
+}
+
+#[async_std::main]
+async fn main() -> memcached::Result<()> {
+    let url = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "memcache://127.0.0.1:12345".to_owned());
+    let limiter = RateLimiter {
+        client: memcached::connect(&url)?,
+        limit: 5,
+        window: 60,
+    };
+
+    let caller = format!("example-{}", std::process::id());
+    let mut allowed = 0;
+    for request in 1..=8 {
+        let allow = limiter.allow(&caller).await?;
+        println!(
+            "request {request}: {}",
+            if allow { "allowed" } else { "throttled" }
+        );
+        if allow {
+            allowed += 1;
+        }
+    }
+    // A window boundary in between would allow a few more.
+    assert!(allowed >= 5);
+    Ok(())
+}
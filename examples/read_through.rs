@@ -0,0 +1,64 @@
+//! A read-through repository: reads go to memcached first and fall back to the database,
+//! `get_or_set` refreshes stale entries in the background while callers get the old value.
+//!
+//! Run a memcached on 127.0.0.1:12345 (or pass a url) and `cargo run --example read_through`.
+
+use memcached::Client;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Queries that reached the database.
+static QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct User {
+    id: u64,
+    name: String,
+}
+
+/// Stands in for a slow database query.
+async fn query_user(id: u64) -> memcached::Result<User> {
+    let _ = QUERIES.fetch_add(1, Ordering::Relaxed);
+    async_std::task::sleep(std::time::Duration::from_millis(20)).await;
+    Ok(User {
+        id,
+        name: format!("user {id}"),
+    })
+}
+
+struct UserRepository {
+    client: Client,
+}
+
+impl UserRepository {
+    /// Fresh for a minute, kept for an hour in case the database is down.
+    async fn find(&self, id: u64) -> memcached::Result<User> {
+        let key = format!("user:{}:{id}", std::process::id());
+        self.client
+            .get_or_set(key, 60, 60 * 60, move || query_user(id))
+            .await
+    }
+}
+
+#[async_std::main]
+async fn main() -> memcached::Result<()> {
+    let url = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "memcache://127.0.0.1:12345".to_owned());
+    let users = UserRepository {
+        client: memcached::connect(&url)?,
+    };
+
+    for _ in 0..3 {
+        let user = users.find(7).await?;
+        println!("{user:?}");
+        assert_eq!(user.id, 7);
+    }
+    let queries = QUERIES.load(Ordering::Relaxed);
+    println!("3 reads, {queries} database queries");
+    assert_eq!(queries, 1);
+    Ok(())
+}
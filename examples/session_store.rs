@@ -0,0 +1,92 @@
+//! A web session store: sessions are kept under `session:<id>` and slide their expiration
+//! on every request, the way a tide or axum session middleware would use the client.
+//!
+//! Run a memcached on 127.0.0.1:12345 (or pass a url) and `cargo run --example session_store`.
+
+use memcached::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// Idle sessions expire after 30 minutes.
+const SESSION_TTL: u32 = 30 * 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Session {
+    user_id: u64,
+    cart: Vec<String>,
+}
+
+#[derive(Clone)]
+struct SessionStore {
+    client: Client,
+}
+
+impl SessionStore {
+    fn key(id: &str) -> String {
+        format!("session:{id}")
+    }
+
+    async fn create(&self, id: &str, session: Session) -> memcached::Result<()> {
+        self.client.set(Self::key(id), session, SESSION_TTL).await
+    }
+
+    /// The session of a request, extending its expiration.
+    async fn load(&self, id: &str) -> memcached::Result<Option<Session>> {
+        let session = self.client.get(Self::key(id)).await?;
+        if session.is_some() {
+            let _ = self.client.touch(Self::key(id), SESSION_TTL).await?;
+        }
+        Ok(session)
+    }
+
+    async fn save(&self, id: &str, session: Session) -> memcached::Result<()> {
+        self.client
+            .replace(Self::key(id), session, SESSION_TTL)
+            .await
+    }
+
+    async fn destroy(&self, id: &str) -> memcached::Result<bool> {
+        self.client.delete(Self::key(id)).await
+    }
+}
+
+/// What a handler of `POST /cart` does with the session of the request.
+async fn add_to_cart(store: &SessionStore, id: &str, item: &str) -> memcached::Result<bool> {
+    let Some(mut session) = store.load(id).await? else {
+        return Ok(false);
+    };
+    session.cart.push(item.to_owned());
+    store.save(id, session).await?;
+    Ok(true)
+}
+
+#[async_std::main]
+async fn main() -> memcached::Result<()> {
+    let url = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "memcache://127.0.0.1:12345".to_owned());
+    let store = SessionStore {
+        client: memcached::connect(&url)?,
+    };
+
+    let id = "example-5f1c";
+    store
+        .create(
+            id,
+            Session {
+                user_id: 42,
+                cart: vec![],
+            },
+        )
+        .await?;
+    assert!(add_to_cart(&store, id, "book").await?);
+    assert!(add_to_cart(&store, id, "pen").await?);
+    let session = store.load(id).await?.expect("session was created");
+    println!("session {id}: {session:?}");
+    assert_eq!(session.cart, ["book", "pen"]);
+
+    assert!(store.destroy(id).await?);
+    assert!(!add_to_cart(&store, id, "lamp").await?);
+    println!("session {id} logged out");
+    Ok(())
+}
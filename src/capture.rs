@@ -0,0 +1,147 @@
+use crate::{error::ClientError, stream::ReplayStream, Client, Result};
+use std::{
+    fmt::{self, Write},
+    path::Path,
+    str::FromStr,
+};
+
+/// Hex digits per data line of the capture file.
+const LINE_BYTES: usize = 32;
+
+/// The bytes of one failed operation on a connection, written by
+/// [`ClientBuilder::capture_failures`](crate::ClientBuilder::capture_failures), to attach to bug reports.
+///
+/// A capture is a text file: a header with the operation, the url, the protocol and the error,
+/// then the request in lines starting with `>` and the response in lines starting with `<`, as hex.
+/// [`Capture::replay`] feeds the response back to the client, so the failure is reproduced
+/// without the server, e.g. in a regression test.
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+/// use memcached::{error::MemcachedError, Capture};
+/// // A `get` answered with a header claiming more bytes than follow.
+/// let capture: Capture = "\
+///     op get\n\
+///     url memcache://127.0.0.1:11211\n\
+///     protocol binary\n\
+///     error failed to fill whole buffer\n\
+///     > 8000000c000000000000000c000000000000000000000000636170747572655f\n\
+///     > 74657374\n\
+///     < 81000000040000000000000a000000000000000000000000000000006869\n"
+///     .parse()?;
+/// let client = capture.replay()?;
+/// let t = client.get::<String, _>("capture_test").await;
+/// assert!(matches!(t, Err(MemcachedError::IOError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture {
+    /// The operation, as reported to the metrics observer.
+    pub op: String,
+    /// The server.
+    pub url: String,
+    /// Whether the connection spoke the text protocol.
+    pub ascii: bool,
+    /// The error the operation failed with.
+    pub error: String,
+    /// Every byte sent for the operation.
+    pub sent: Vec<u8>,
+    /// Every byte read for the operation, up to the failure.
+    pub received: Vec<u8>,
+}
+
+impl Capture {
+    /// Read a capture file.
+    pub async fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        async_std::fs::read_to_string(path.as_ref()).await?.parse()
+    }
+
+    /// Write the capture to a file.
+    pub async fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        async_std::fs::write(path.as_ref(), self.to_string()).await?;
+        Ok(())
+    }
+
+    /// A client whose only server answers with the response of the capture, byte for byte.
+    ///
+    /// Run the captured operation on it to reproduce the failure through the protocol code.
+    /// The request must be the captured one, any other byte sent fails with an `IOError`.
+    /// Every new connection replays the capture from the start, connections are not probed.
+    pub fn replay(&self) -> Result<Client> {
+        let name = ReplayStream::register(self.clone());
+        let query = if self.ascii { "?protocol=ascii" } else { "" };
+        Client::connect(format!("memcache+replay://{name}{query}"))
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter, prefix: char, bytes: &[u8]) -> fmt::Result {
+    for line in bytes.chunks(LINE_BYTES) {
+        let mut hex = String::with_capacity(line.len() * 2);
+        for byte in line {
+            write!(hex, "{byte:02x}")?;
+        }
+        writeln!(f, "{prefix} {hex}")?;
+    }
+    Ok(())
+}
+
+fn read_hex(hex: &str) -> Result<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) {
+        return Err(ClientError::from(format!("odd number of hex digits in {hex}")).into());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| ClientError::from(format!("bad hex digits in {hex}")).into())
+        })
+        .collect()
+}
+
+impl fmt::Display for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "op {}", self.op)?;
+        writeln!(f, "url {}", self.url)?;
+        writeln!(
+            f,
+            "protocol {}",
+            if self.ascii { "ascii" } else { "binary" }
+        )?;
+        // The error is on one line, the parser reads up to its end.
+        writeln!(f, "error {}", self.error.replace(['\r', '\n'], " "))?;
+        write_hex(f, '>', &self.sent)?;
+        write_hex(f, '<', &self.received)
+    }
+}
+
+impl FromStr for Capture {
+    type Err = crate::error::MemcachedError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut capture = Capture {
+            op: String::new(),
+            url: String::new(),
+            ascii: false,
+            error: String::new(),
+            sent: vec![],
+            received: vec![],
+        };
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (field, value) = line.split_once(' ').unwrap_or((line, ""));
+            match field {
+                "op" => value.clone_into(&mut capture.op),
+                "url" => value.clone_into(&mut capture.url),
+                "protocol" => capture.ascii = value == "ascii",
+                "error" => value.clone_into(&mut capture.error),
+                ">" => capture.sent.extend(read_hex(value)?),
+                "<" => capture.received.extend(read_hex(value)?),
+                _ => return Err(ClientError::from(format!("unknown capture line: {line}")).into()),
+            }
+        }
+        Ok(capture)
+    }
+}
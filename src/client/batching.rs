@@ -0,0 +1,74 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+/// Bounds and targets of the adaptive multi-get batch size, see [`ClientBuilder::multi_get_batching`](crate::ClientBuilder::multi_get_batching).
+///
+/// Each server starts with batches of `min` keys. A full batch answered within `target_latency`
+/// and `target_bytes` grows the next batch by `min` keys, a batch exceeding either halves it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchSizing {
+    /// The smallest batch, and the step batches grow by. 16 by default.
+    pub min: usize,
+    /// The largest batch, 1024 by default.
+    pub max: usize,
+    /// Batches taking longer are halved, 5 ms by default.
+    pub target_latency: Duration,
+    /// Batches reading more bytes are halved, 512 KiB by default.
+    pub target_bytes: u64,
+}
+
+impl Default for BatchSizing {
+    fn default() -> Self {
+        BatchSizing {
+            min: 16,
+            max: 1024,
+            target_latency: Duration::from_millis(5),
+            target_bytes: 512 * 1024,
+        }
+    }
+}
+
+/// The current multi-get batch size of one server, tuned additive-increase / multiplicative-decrease.
+#[derive(Debug)]
+pub(crate) struct AdaptiveBatch {
+    sizing: BatchSizing,
+    size: AtomicUsize,
+}
+
+impl AdaptiveBatch {
+    pub(crate) fn new(sizing: BatchSizing) -> Self {
+        let min = sizing.min.max(1);
+        let sizing = BatchSizing {
+            min,
+            max: sizing.max.max(min),
+            ..sizing
+        };
+        AdaptiveBatch {
+            sizing,
+            size: AtomicUsize::new(min),
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Adjust the size after a batch of `keys` took `elapsed` and read `bytes`.
+    /// A short last batch says nothing about a bigger one, it only shrinks the size.
+    pub(crate) fn observe(&self, keys: usize, elapsed: Duration, bytes: u64) {
+        let size = self.size();
+        let next = if elapsed > self.sizing.target_latency || bytes > self.sizing.target_bytes {
+            size / 2
+        } else if keys >= size {
+            size + self.sizing.min
+        } else {
+            size
+        };
+        self.size.store(
+            next.clamp(self.sizing.min, self.sizing.max),
+            Ordering::Relaxed,
+        );
+    }
+}
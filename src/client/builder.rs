@@ -0,0 +1,871 @@
+use super::Client;
+use crate::{
+    client::{
+        batching::{AdaptiveBatch, BatchSizing},
+        checkout::{CheckoutCounters, FairQueue, DEFAULT_STARVATION_THRESHOLD},
+        client_hash::{default_hash_function, KeyHasher, KeyRouter, ModuloRouter},
+        flush_window::FlushWindowReads,
+        health::{HealthPolicy, RefusalCache},
+        key_dictionary::KeyDictionary,
+        kill_switch::KillSwitch,
+        serialization_memo::SerializationMemo,
+        server::{Server, UdpFallback},
+        ttl_rules::TtlRules,
+        verify::ReadSampler,
+    },
+    codec::BincodeCodec,
+    connection::ConnectionManager,
+    error::{ClientError, MemcachedError},
+    protocol::{parse::DEFAULT_PARSE_YIELD_EVERY, OutOfMemoryRetry},
+    stream::{Timeouts, UdpLoss},
+    Connectable, Result,
+};
+use mobc::Pool;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use url::Url;
+
+/// Configures a [`Client`] before connecting, see [`Client::builder`].
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+/// use memcached::error::{MemcachedError, WriteState};
+/// use std::time::Duration;
+///
+/// // Accepts connections but never answers.
+/// let silent = std::net::TcpListener::bind("127.0.0.1:0")?;
+/// let client = memcached::Client::builder(format!("memcache://{}", silent.local_addr()?))
+///     .connect_timeout(Duration::from_secs(1))
+///     .read_timeout(Duration::from_millis(100))
+///     .build()?;
+/// let t = client.get::<String, _>("timeout_test").await;
+/// assert!(matches!(t, Err(MemcachedError::Timeout(WriteState::Written))));
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+#[allow(clippy::struct_excessive_bools)]
+pub struct ClientBuilder {
+    urls: Vec<String>,
+    pool: PoolConfig,
+    router: Option<Arc<dyn KeyRouter>>,
+    timeouts: Timeouts,
+    health_policy: HealthPolicy,
+    fair_checkout: bool,
+    starvation_threshold: Duration,
+    multi_get_retries: u32,
+    multi_get_batching: Option<BatchSizing>,
+    degrade_on_error: bool,
+    verify_reads: u64,
+    key_dictionary: Option<Arc<KeyDictionary>>,
+    ttl_rules: Option<TtlRules>,
+    serialization_memo: Option<SerializationMemo>,
+    tcp_nodelay: bool,
+    noop_check: bool,
+    parse_yield_every: usize,
+    flush_window_reads: FlushWindowReads,
+    kill_switch: Option<KillSwitch>,
+    prewarm: bool,
+    capture_failures: Option<Arc<Path>>,
+    out_of_memory_retry: Option<OutOfMemoryRetry>,
+    overrides: HashMap<String, ServerOverrides>,
+}
+
+/// How a pooled connection is checked before it is handed out, see [`PoolConfig::liveness_check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LivenessCheck {
+    /// Never, a dead connection surfaces as an error of the operation using it.
+    Off,
+    /// When the connection was last checked at least this long ago.
+    Interval(Duration),
+    /// On every checkout, one `version` round trip each.
+    EveryCheckout,
+}
+
+/// Connection pool settings of every server, see [`ClientBuilder::pool_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// Connections open at most per server, 10 by default.
+    pub max_open: u64,
+    /// Idle connections kept per server, 1 by default. Capped at `max_open`.
+    pub max_idle: u64,
+    /// Close connections idle for longer, never by default.
+    pub idle_timeout: Option<Duration>,
+    /// Close connections older than this, never by default.
+    pub max_lifetime: Option<Duration>,
+    /// Check connections before handing them out, those unchecked for 10 seconds by default.
+    pub liveness_check: LivenessCheck,
+}
+
+/// How long a pooled connection is handed out unchecked by default.
+const DEFAULT_LIVENESS_INTERVAL: Duration = Duration::from_secs(10);
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_open: 10,
+            max_idle: 1,
+            idle_timeout: None,
+            max_lifetime: None,
+            liveness_check: LivenessCheck::Interval(DEFAULT_LIVENESS_INTERVAL),
+        }
+    }
+}
+
+/// Settings of one server replacing those of the builder, see [`ClientBuilder::server_overrides`].
+/// Settings left `None` are the builder's.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ServerOverrides {
+    /// Connection pool of the server.
+    pub pool: Option<PoolConfig>,
+    /// See [`ClientBuilder::connect_timeout`].
+    pub connect_timeout: Option<Duration>,
+    /// See [`ClientBuilder::read_timeout`].
+    pub read_timeout: Option<Duration>,
+    /// See [`ClientBuilder::write_timeout`].
+    pub write_timeout: Option<Duration>,
+    /// See [`ClientBuilder::tcp_nodelay`].
+    pub tcp_nodelay: Option<bool>,
+}
+
+/// Settings tuned for a kind of workload, see [`ClientBuilder::preset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Small operations answered as fast as possible: no Nagle delay, `noop` liveness checks
+    /// instead of `version`, and idle connections opened before the first operation.
+    LowLatency,
+    /// Many operations in flight and large batches: a bigger pool checked every 30 seconds.
+    Throughput,
+}
+
+/// mobc panics on zero durations, they mean "immediately" here and are as good as none.
+fn positive(duration: Option<Duration>) -> Option<Duration> {
+    duration.filter(|duration| !duration.is_zero())
+}
+
+impl PoolConfig {
+    fn build(&self, manager: ConnectionManager) -> Pool<ConnectionManager> {
+        let max_open = self.max_open.max(1);
+        let builder = Pool::builder()
+            .max_open(max_open)
+            .max_idle(self.max_idle.min(max_open))
+            .max_idle_lifetime(positive(self.idle_timeout))
+            .max_lifetime(positive(self.max_lifetime));
+        let builder = match self.liveness_check {
+            LivenessCheck::Off => builder.test_on_check_out(false),
+            LivenessCheck::Interval(interval) => {
+                builder.health_check_interval(positive(Some(interval)))
+            }
+            LivenessCheck::EveryCheckout => builder,
+        };
+        builder.build(manager)
+    }
+}
+
+impl ClientBuilder {
+    /// A builder for a client of `urls` with the [`PoolConfig::default`] pool per server, at most
+    /// 10 connections and 1 kept idle, and no timeouts.
+    pub fn new<T: Connectable>(urls: T) -> Self {
+        ClientBuilder {
+            urls: urls.get_urls(),
+            pool: PoolConfig::default(),
+            router: None,
+            timeouts: Timeouts::default(),
+            health_policy: HealthPolicy::default(),
+            fair_checkout: false,
+            starvation_threshold: DEFAULT_STARVATION_THRESHOLD,
+            multi_get_retries: 1,
+            multi_get_batching: None,
+            degrade_on_error: false,
+            verify_reads: 0,
+            key_dictionary: None,
+            ttl_rules: None,
+            serialization_memo: None,
+            tcp_nodelay: false,
+            noop_check: false,
+            parse_yield_every: DEFAULT_PARSE_YIELD_EVERY,
+            flush_window_reads: FlushWindowReads::Serve,
+            kill_switch: None,
+            prewarm: false,
+            capture_failures: None,
+            out_of_memory_retry: None,
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Number of idle connections kept per server, see [`PoolConfig::max_idle`]. At most
+    /// [`PoolConfig::max_open`] connections are open, 10 unless set with [`Self::pool_config`].
+    #[must_use]
+    pub fn pool_size(mut self, pool_size: u64) -> Self {
+        self.pool.max_idle = pool_size;
+        self
+    }
+
+    /// Size, connection lifetimes and liveness check of the pool of every server.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::{LivenessCheck, PoolConfig};
+    /// use std::time::Duration;
+    ///
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .pool_config(PoolConfig {
+    ///         max_open: 32,
+    ///         max_idle: 8,
+    ///         idle_timeout: Some(Duration::from_secs(60)),
+    ///         max_lifetime: Some(Duration::from_secs(3600)),
+    ///         liveness_check: LivenessCheck::Interval(Duration::from_secs(10)),
+    ///     })
+    ///     .build()?;
+    /// client.set("pool_config_test", "hello", 100).await?;
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn pool_config(mut self, pool: PoolConfig) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Pick the server of a key with `hash_function(key) % servers`.
+    #[must_use]
+    pub fn hash_function<F: Fn(&str) -> u64 + Send + Sync + 'static>(
+        self,
+        hash_function: F,
+    ) -> Self {
+        self.hasher(hash_function)
+    }
+
+    /// Pick the server of a key with `hasher.hash(key) % servers`, pass a [`HashFunction`](crate::HashFunction)
+    /// to swap the hasher at runtime.
+    #[must_use]
+    pub fn hasher<H: KeyHasher + 'static>(mut self, hasher: H) -> Self {
+        self.router = Some(Arc::new(ModuloRouter::new(self.urls.len(), hasher)));
+        self
+    }
+
+    /// Pick the server of a key with `router`.
+    #[must_use]
+    pub fn router<R: KeyRouter + 'static>(mut self, router: R) -> Self {
+        self.router = Some(Arc::new(router));
+        self
+    }
+
+    /// Give up connecting to a server after `timeout`.
+    #[must_use]
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+
+    /// Give up waiting for a response after `timeout`, the connection is closed afterwards.
+    #[must_use]
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.read = Some(timeout);
+        self
+    }
+
+    /// Give up sending a request after `timeout`, the connection is closed afterwards.
+    #[must_use]
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.write = Some(timeout);
+        self
+    }
+
+    /// Give up an operation after `timeout` from the moment it got its connection, e.g. a multi-get reading
+    /// many values which no single read or write timeout bounds. The connection is closed afterwards.
+    /// Checkouts, including their [`LivenessCheck`], are bounded by the read and write timeouts only.
+    ///
+    /// Like every timeout, it fails with [`MemcachedError::Timeout`](crate::error::MemcachedError::Timeout) saying whether the request was sent,
+    /// so the caller knows if a non-idempotent operation like `increment` may still be applied.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::error::{MemcachedError, WriteState};
+    /// use std::time::Duration;
+    ///
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .operation_timeout(Duration::from_secs(1))
+    ///     .build()?;
+    /// match client.increment_with("operation_timeout_test", 1, 0, 100).await {
+    ///     Ok(_) => {}
+    ///     // Sending the increment again could count it twice.
+    ///     Err(MemcachedError::Timeout(state)) if state.may_be_applied() => {}
+    ///     Err(MemcachedError::Timeout(WriteState::NotWritten)) => {
+    ///         client.increment_with("operation_timeout_test", 1, 0, 100).await?;
+    ///     }
+    ///     Err(e) => return Err(e),
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn operation_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.operation = Some(timeout);
+        self
+    }
+
+    /// Send the keys of a dead server to the next healthy server until it answers again.
+    /// Without failover the keys of a dead server keep failing.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::Client::builder(vec![
+    ///     "memcache://127.0.0.1:12345",
+    ///     "memcache://127.0.0.1:1",
+    /// ])
+    /// .failover(true)
+    /// .dead_after(1)
+    /// .build()?;
+    /// for i in 0..10 {
+    ///     let _ = client.set(format!("failover_test{i}"), i, 100).await;
+    /// }
+    /// assert_eq!(client.dead_servers(), vec!["memcache://127.0.0.1:1"]);
+    /// for i in 0..10 {
+    ///     client.set(format!("failover_test{i}"), i, 100).await?;
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn failover(mut self, failover: bool) -> Self {
+        self.health_policy.failover = failover;
+        self
+    }
+
+    /// Consider a server dead after `failures` consecutive connection errors, 3 by default.
+    #[must_use]
+    pub fn dead_after(mut self, failures: u32) -> Self {
+        self.health_policy.dead_after = failures.max(1);
+        self
+    }
+
+    /// Probe dead servers with `version` at most once per `interval`, 1 second by default.
+    #[must_use]
+    pub fn probe_interval(mut self, interval: Duration) -> Self {
+        self.health_policy.probe_interval = interval;
+        self
+    }
+
+    /// Remember a refused connect or authentication of a server for `ttl` and fail its checkouts
+    /// with [`ClientError::ServerRefused`] meanwhile, instead of dialing for every request.
+    /// The first checkout after `ttl` dials again, so a recovered server is noticed. Off by default.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::error::{ClientError, MemcachedError};
+    /// use std::time::Duration;
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:1")
+    ///     .refusal_ttl(Duration::from_millis(200))
+    ///     .build()?;
+    /// let t = client.get::<String, _>("refusal_test").await;
+    /// assert!(matches!(t, Err(MemcachedError::IOError(_))));
+    /// let t = client.get::<String, _>("refusal_test").await;
+    /// assert!(matches!(t, Err(MemcachedError::ClientError(ClientError::ServerRefused { .. }))));
+    /// async_std::task::sleep(Duration::from_millis(300)).await;
+    /// let t = client.get::<String, _>("refusal_test").await;
+    /// assert!(matches!(t, Err(MemcachedError::IOError(_))));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn refusal_ttl(mut self, ttl: Duration) -> Self {
+        self.health_policy.refusal_ttl = ttl;
+        self
+    }
+
+    /// Hand out connections in the order they were asked for, so no task starves under contention.
+    /// Without it the pool serves waiting tasks in no particular order.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .fair_checkout(true)
+    ///     .build()?;
+    /// let tasks: Vec<_> = (0..50)
+    ///     .map(|i| {
+    ///         let client = client.clone();
+    ///         async_std::task::spawn(async move { client.set(format!("fair_test{i}"), i, 100).await })
+    ///     })
+    ///     .collect();
+    /// for task in tasks {
+    ///     task.await?;
+    /// }
+    /// assert_eq!(client.checkout_stats()[0].1.checkouts, 50);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn fair_checkout(mut self, fair_checkout: bool) -> Self {
+        self.fair_checkout = fair_checkout;
+        self
+    }
+
+    /// Count checkouts waiting at least `threshold` as starved in [`Client::checkout_stats`], 1 second by default.
+    #[must_use]
+    pub fn starvation_threshold(mut self, threshold: Duration) -> Self {
+        self.starvation_threshold = threshold;
+        self
+    }
+
+    /// Send the keys of one server again on a fresh connection when its part of a `gets` fails halfway,
+    /// at most `retries` times, once by default. Keys of the other servers are not fetched again.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .multi_get_retries(3)
+    ///     .build()?;
+    /// client.set("multi_get_retries_test", "100", 100).await?;
+    /// let t = client
+    ///     .gets::<String, _>(&["multi_get_retries_test"])
+    ///     .await?;
+    /// assert_eq!(t["multi_get_retries_test"].0, "100");
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn multi_get_retries(mut self, retries: u32) -> Self {
+        self.multi_get_retries = retries;
+        self
+    }
+
+    /// Split the keys a multi-get sends to one server into batches, sized per server by observed
+    /// latency and response bytes within the bounds of `sizing`.
+    /// Without it all keys of a server go out in one batch.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::BatchSizing;
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .multi_get_batching(BatchSizing {
+    ///         min: 2,
+    ///         max: 8,
+    ///         ..BatchSizing::default()
+    ///     })
+    ///     .build()?;
+    /// let keys: Vec<_> = (0..10).map(|i| format!("batching_test{i}")).collect();
+    /// for key in &keys {
+    ///     client.set(key, "hello", 100).await?;
+    /// }
+    /// let t = client.gets::<String, _>(&keys).await?;
+    /// assert_eq!(t.len(), 10);
+    /// let size = client.multi_get_batch_sizes()[0].1;
+    /// assert!((2..=8).contains(&size));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn multi_get_batching(mut self, sizing: BatchSizing) -> Self {
+        self.multi_get_batching = Some(sizing);
+        self
+    }
+
+    /// Answer reads with a miss instead of an error when their server can't be reached or times out,
+    /// so callers fall back to recomputing the value. Degraded reads are counted in
+    /// [`Client::degraded_reads`] and reported to [`MetricsObserver::on_degraded`](crate::metrics::MetricsObserver::on_degraded).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:1")
+    ///     .degrade_on_error(true)
+    ///     .build()?;
+    /// let t: Option<String> = client.get("degrade_on_error_test").await?;
+    /// assert_eq!(t, None);
+    /// assert_eq!(client.degraded_reads(), 1);
+    /// assert!(client.set("degrade_on_error_test", "hello", 100).await.is_err());
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn degrade_on_error(mut self, degrade_on_error: bool) -> Self {
+        self.degrade_on_error = degrade_on_error;
+        self
+    }
+
+    /// Debug proxies and replication: every `every`-th `get` also reads the key on a second
+    /// connection and reports differences in value, flags or cas to
+    /// [`MetricsObserver::on_read_mismatch`](crate::metrics::MetricsObserver::on_read_mismatch),
+    /// the `tracing` log and [`Client::read_mismatches`]. 0, the default, verifies nothing.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .pool_size(2)
+    ///     .verify_reads(1)
+    ///     .build()?;
+    /// client.set("verify_reads_test", "hello", 100).await?;
+    /// let t: Option<String> = client.get("verify_reads_test").await?;
+    /// assert_eq!(t, Some("hello".to_owned()));
+    /// assert_eq!(client.read_mismatches(), 0);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn verify_reads(mut self, every: u64) -> Self {
+        self.verify_reads = every;
+        self
+    }
+
+    /// Send keys starting with a prefix of `dictionary` with the prefix replaced by its short code.
+    /// Keys returned by multi-key operations are expanded again, only the servers see the short keys.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::KeyDictionary;
+    /// let prefix = "service:accounts:users:profile:".repeat(8);
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .key_dictionary(KeyDictionary::new([(prefix.clone(), "~p:")])?)
+    ///     .build()?;
+    /// let key = format!("{prefix}key_dictionary_test");
+    /// assert!(key.len() > 250);
+    /// client.set(&key, "hello", 100).await?;
+    /// let t = client.gets::<String, _>(&[&key]).await?;
+    /// assert_eq!(t[&key].0, "hello");
+    /// assert_eq!(client.explain(&key).wire_key, "~p:key_dictionary_test");
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn key_dictionary(mut self, dictionary: KeyDictionary) -> Self {
+        self.key_dictionary = Some(Arc::new(dictionary));
+        self
+    }
+
+    /// Change the expiration of writes by key prefix with `rules`, e.g. for TTL experiments driven
+    /// from config. Keep a clone of `rules` to update them while the client runs. The rules apply to
+    /// every expiration sent: writes, pipelines, touches, `get_and_touch`, counters creating their key
+    /// and the TTLs of meta commands.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::{TtlAction, TtlRule, TtlRules};
+    /// let rules = TtlRules::default();
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345?protocol=ascii")
+    ///     .ttl_rules(rules.clone())
+    ///     .build()?;
+    /// rules.update(vec![TtlRule {
+    ///     prefix: "ttl_rules_test:".to_owned(),
+    ///     action: TtlAction::Override(1),
+    ///     fraction: 1.0,
+    /// }]);
+    /// client.set("ttl_rules_test:a", "hello", 100).await?;
+    /// async_std::task::sleep(std::time::Duration::from_millis(2100)).await;
+    /// let t: Option<String> = client.get("ttl_rules_test:a").await?;
+    /// assert_eq!(t, None);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn ttl_rules(mut self, rules: TtlRules) -> Self {
+        self.ttl_rules = Some(rules);
+        self
+    }
+
+    /// Keep the encoded values of [`Client::set_memoized`] and [`Client::sets_memoized`] in `memo`,
+    /// see [`SerializationMemo`].
+    #[must_use]
+    pub fn serialization_memo(mut self, memo: SerializationMemo) -> Self {
+        self.serialization_memo = Some(memo);
+        self
+    }
+
+    /// Send small requests right away instead of waiting to coalesce them, off by default.
+    #[must_use]
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Check the liveness of pooled connections with a `noop` instead of a `version` round trip.
+    /// The ASCII protocol has no `noop` and keeps using `version`.
+    #[must_use]
+    pub fn noop_check(mut self, noop_check: bool) -> Self {
+        self.noop_check = noop_check;
+        self
+    }
+
+    /// Let other tasks run after every `values` values parsed from one multi-get reply,
+    /// so thousands of values don't hold the executor thread. 64 by default, 0 never yields.
+    /// To also move the decoding off the executor, see [`Client::gets_offloaded`].
+    #[must_use]
+    pub fn parse_yield_every(mut self, values: usize) -> Self {
+        self.parse_yield_every = values;
+        self
+    }
+
+    /// How `get` and `gets` answer between a `flush_with_delay` of the client and the flush,
+    /// [`FlushWindowReads::Serve`] by default. See also [`Client::get_with_flush_time`].
+    #[must_use]
+    pub fn flush_window_reads(mut self, reads: FlushWindowReads) -> Self {
+        self.flush_window_reads = reads;
+        self
+    }
+
+    /// Bypass the cache while `switch` is engaged, see [`KillSwitch`].
+    #[must_use]
+    pub fn kill_switch(mut self, switch: KillSwitch) -> Self {
+        self.kill_switch = Some(switch);
+        self
+    }
+
+    /// Open the idle connections of every server in the background right after `build`
+    /// instead of on first use.
+    #[must_use]
+    pub fn prewarm(mut self, prewarm: bool) -> Self {
+        self.prewarm = prewarm;
+        self
+    }
+
+    /// Write the bytes sent and read by operations failing with other than a [`CommandError`](crate::error::CommandError),
+    /// e.g. a response the client could not parse, to a [`Capture`](crate::Capture) file in `dir`.
+    /// The files are named `{op}-{unix nanos}.capture`, failures to write them are ignored.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::Capture;
+    /// let dir = std::env::temp_dir().join("memcached_capture_test");
+    /// let _ = std::fs::remove_dir_all(&dir);
+    /// std::fs::create_dir_all(&dir)?;
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .capture_failures(&dir)
+    ///     .build()?;
+    /// client.set("capture_failures_test", "hello", 100).await?;
+    /// assert!(client.get::<u64, _>("capture_failures_test").await.is_err());
+    ///
+    /// let file = std::fs::read_dir(&dir)?.next().unwrap()?.path();
+    /// let capture = Capture::load(file).await?;
+    /// assert_eq!(capture.op, "get");
+    /// // The same failure, without the server.
+    /// let replay = capture.replay()?;
+    /// assert!(replay.get::<u64, _>("capture_failures_test").await.is_err());
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn capture_failures<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.capture_failures = Some(dir.into().into());
+        self
+    }
+
+    /// Send a `set`, `add`, `replace`, `append`, `prepend` or `cas` the server was out of memory for
+    /// once more after `retry.delay`, optionally with a shorter TTL. Off by default: the write fails with
+    /// [`CommandError::OutOfMemory`](crate::error::CommandError::OutOfMemory). The retry holds the connection
+    /// while it waits. Pipelined writes are not retried.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::OutOfMemoryRetry;
+    /// use std::time::Duration;
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .retry_out_of_memory(OutOfMemoryRetry {
+    ///         delay: Duration::from_millis(5),
+    ///         max_ttl: Some(60),
+    ///     })
+    ///     .build()?;
+    /// client.set("retry_out_of_memory_test", "hello", 3600).await?;
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn retry_out_of_memory(mut self, retry: OutOfMemoryRetry) -> Self {
+        self.out_of_memory_retry = Some(retry);
+        self
+    }
+
+    /// Use `overrides` instead of the settings of the builder for the server at `url`,
+    /// e.g. a bigger pool and no timeouts for a node on the same host. `url` must be one of the urls
+    /// of the builder, as given. The transport and protocol of every server already follow its url,
+    /// so one client can mix `memcache+unix://`, `memcache://`, `memcache+tls://` and ASCII servers.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::{PoolConfig, ServerOverrides};
+    /// use std::time::Duration;
+    /// let client = memcached::Client::builder(vec![
+    ///     "memcache+local://server_overrides_test",
+    ///     "memcache://127.0.0.1:12345",
+    /// ])
+    /// .read_timeout(Duration::from_secs(1))
+    /// .server_overrides(
+    ///     "memcache+local://server_overrides_test",
+    ///     ServerOverrides {
+    ///         pool: Some(PoolConfig { max_open: 2, ..PoolConfig::default() }),
+    ///         read_timeout: Some(Duration::from_millis(100)),
+    ///         ..ServerOverrides::default()
+    ///     },
+    /// )
+    /// .build()?;
+    /// client.set("server_overrides_test", "hello", 100).await?;
+    /// let t: Option<String> = client.get("server_overrides_test").await?;
+    /// assert_eq!(t, Some("hello".to_owned()));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn server_overrides<S: Into<String>>(mut self, url: S, overrides: ServerOverrides) -> Self {
+        let _ = self.overrides.insert(url.into(), overrides);
+        self
+    }
+
+    /// Apply the settings of `preset`, later calls override single settings.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::Preset;
+    ///
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .preset(Preset::LowLatency)
+    ///     .build()?;
+    /// client.set("preset_test", "hello", 100).await?;
+    /// let t: Option<String> = client.get("preset_test").await?;
+    /// assert_eq!(t, Some("hello".to_owned()));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn preset(mut self, preset: Preset) -> Self {
+        match preset {
+            Preset::LowLatency => {
+                self.tcp_nodelay = true;
+                self.noop_check = true;
+                self.prewarm = true;
+            }
+            Preset::Throughput => {
+                self.pool.max_open = 32;
+                self.pool.max_idle = 16;
+                self.pool.liveness_check = LivenessCheck::Interval(Duration::from_secs(30));
+            }
+        }
+        self
+    }
+
+    /// Open the idle connections of a server one after the other, holding the opened ones
+    /// so every `get` opens a new connection.
+    async fn prewarm_server(pool: Pool<ConnectionManager>, connections: u64) {
+        let mut opened = vec![];
+        for _ in 0..connections {
+            match pool.get().await {
+                Ok(connection) => opened.push(connection),
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// The pool and state of the server at `url`, with a TCP fallback for a lossy UDP server.
+    fn server(&self, index: usize, url: &Url, overrides: ServerOverrides) -> Result<Server> {
+        let refusals = Arc::new(RefusalCache::new(self.health_policy.refusal_ttl));
+        let udp_loss = Arc::<UdpLoss>::default();
+        let config = overrides.pool.unwrap_or(self.pool);
+        let timeouts = Timeouts {
+            connect: overrides.connect_timeout.or(self.timeouts.connect),
+            read: overrides.read_timeout.or(self.timeouts.read),
+            write: overrides.write_timeout.or(self.timeouts.write),
+            operation: self.timeouts.operation,
+        };
+        let manager = ConnectionManager {
+            url: url.clone(),
+            timeouts,
+            nodelay: overrides.tcp_nodelay.unwrap_or(self.tcp_nodelay),
+            noop_check: self.noop_check,
+            record: self.capture_failures.is_some(),
+            parse_yield_every: self.parse_yield_every,
+            out_of_memory_retry: self.out_of_memory_retry,
+            refusals: Arc::clone(&refusals),
+            udp_loss: Arc::clone(&udp_loss),
+            capabilities: Arc::default(),
+        };
+        let pool = config.build(manager.clone());
+        let udp = match UdpFallback::threshold(url)? {
+            Some(threshold) => Some(Arc::new(UdpFallback {
+                loss: udp_loss,
+                threshold,
+                tcp: self.server(index, &UdpFallback::tcp_url(url)?, overrides)?,
+            })),
+            None => None,
+        };
+        Ok(Server {
+            index,
+            url: url.to_string(),
+            pool,
+            manager,
+            health: Arc::default(),
+            refusals,
+            queue: self
+                .fair_checkout
+                .then(|| Arc::new(FairQueue::new(config.max_open))),
+            checkouts: Arc::new(CheckoutCounters::new(self.starvation_threshold)),
+            batch: self
+                .multi_get_batching
+                .map(|sizing| Arc::new(AdaptiveBatch::new(sizing))),
+            udp,
+        })
+    }
+
+    /// Create the client, connections are opened on first use unless prewarmed.
+    pub fn build(self) -> Result<Client> {
+        if let Some(url) = self.overrides.keys().find(|url| !self.urls.contains(url)) {
+            return Err(MemcachedError::BadURL(format!(
+                "server overrides for {url}, which is no server of the client"
+            )));
+        }
+        let mut connections = vec![];
+        for url in &self.urls {
+            let overrides = self.overrides.get(url).copied().unwrap_or_default();
+            let server = self.server(connections.len(), &Url::parse(url.as_str())?, overrides)?;
+            if self.prewarm {
+                let config = overrides.pool.unwrap_or(self.pool);
+                drop(async_std::task::spawn(Self::prewarm_server(
+                    server.pool.clone(),
+                    config.max_idle.min(config.max_open),
+                )));
+            }
+            connections.push(server);
+        }
+        if connections.is_empty() {
+            return Err(ClientError::ConnectionsIsEmpty.into());
+        }
+        let router = self.router.unwrap_or_else(|| {
+            Arc::new(ModuloRouter::new(connections.len(), default_hash_function))
+        });
+        Ok(Client {
+            connections,
+            router,
+            health_policy: self.health_policy,
+            multi_get_retries: self.multi_get_retries,
+            degrade_on_error: self.degrade_on_error,
+            operation_timeout: self.timeouts.operation,
+            degraded_reads: Arc::default(),
+            verify: Arc::new(ReadSampler::new(self.verify_reads)),
+            dictionary: self.key_dictionary,
+            ttl_rules: self.ttl_rules,
+            memo: self.serialization_memo,
+            flush_schedule: Arc::default(),
+            refresh_claims: Arc::default(),
+            flush_window_reads: self.flush_window_reads,
+            kill_switch: self.kill_switch,
+            capture_failures: self.capture_failures,
+            observer: None,
+            codec: BincodeCodec,
+        })
+    }
+}
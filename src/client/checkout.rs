@@ -0,0 +1,212 @@
+use crate::{
+    connection::{Connection, ConnectionManager},
+    metrics::{CheckoutStats, Sharded},
+};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+/// Checkouts waiting longer than this count as starved unless configured otherwise.
+pub(crate) const DEFAULT_STARVATION_THRESHOLD: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct Waiter {
+    granted: bool,
+    waker: Option<Waker>,
+}
+
+#[derive(Debug)]
+struct QueueState {
+    permits: u64,
+    waiters: VecDeque<Arc<Mutex<Waiter>>>,
+}
+
+/// Hands out at most `permits` checkouts at a time, in the order they were asked for.
+///
+/// mobc wakes the tasks waiting for a connection in no particular order, some of them
+/// can wait forever under contention. With as many permits as the pool has connections,
+/// only permit holders ask the pool, so it never has to pick among waiters.
+#[derive(Debug)]
+pub(crate) struct FairQueue {
+    state: Mutex<QueueState>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl FairQueue {
+    pub(crate) fn new(permits: u64) -> Self {
+        FairQueue {
+            state: Mutex::new(QueueState {
+                permits: permits.max(1),
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn acquire(self: &Arc<Self>) -> Acquire {
+        Acquire {
+            queue: Arc::clone(self),
+            waiter: None,
+            done: false,
+        }
+    }
+
+    /// Pass the permit to the oldest waiter, or put it back.
+    fn release(&self) {
+        let mut state = lock(&self.state);
+        if let Some(waiter) = state.waiters.pop_front() {
+            let mut waiter = lock(&waiter);
+            waiter.granted = true;
+            if let Some(waker) = waiter.waker.take() {
+                waker.wake();
+            }
+        } else {
+            state.permits += 1;
+        }
+    }
+}
+
+/// Returned to the queue on drop.
+#[derive(Debug)]
+pub(crate) struct Permit {
+    queue: Arc<FairQueue>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
+
+/// Waits for a [`Permit`], see [`FairQueue::acquire`].
+#[derive(Debug)]
+pub(crate) struct Acquire {
+    queue: Arc<FairQueue>,
+    waiter: Option<Arc<Mutex<Waiter>>>,
+    done: bool,
+}
+
+impl Future for Acquire {
+    type Output = Permit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit> {
+        let this = &mut *self;
+        let mut state = lock(&this.queue.state);
+        let granted = if let Some(waiter) = &this.waiter {
+            let mut waiter = lock(waiter);
+            if !waiter.granted {
+                waiter.waker = Some(cx.waker().clone());
+            }
+            waiter.granted
+        } else if state.permits > 0 && state.waiters.is_empty() {
+            state.permits -= 1;
+            true
+        } else {
+            let waiter = Arc::new(Mutex::new(Waiter {
+                granted: false,
+                waker: Some(cx.waker().clone()),
+            }));
+            state.waiters.push_back(Arc::clone(&waiter));
+            this.waiter = Some(waiter);
+            false
+        };
+        drop(state);
+        if granted {
+            this.done = true;
+            Poll::Ready(Permit {
+                queue: Arc::clone(&this.queue),
+            })
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Acquire {
+    /// A cancelled checkout leaves the queue, or passes on a permit it was granted but never used.
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if let Some(waiter) = self.waiter.take() {
+            let mut state = lock(&self.queue.state);
+            if lock(&waiter).granted {
+                drop(state);
+                self.queue.release();
+            } else {
+                state.waiters.retain(|queued| !Arc::ptr_eq(queued, &waiter));
+            }
+        }
+    }
+}
+
+/// Wait times of the checkouts of one server, sharded per core.
+#[derive(Debug)]
+pub(crate) struct CheckoutCounters {
+    starvation_threshold: Duration,
+    stats: Sharded<CheckoutStats>,
+}
+
+impl CheckoutCounters {
+    pub(crate) fn new(starvation_threshold: Duration) -> Self {
+        CheckoutCounters {
+            starvation_threshold,
+            stats: Sharded::default(),
+        }
+    }
+
+    pub(crate) fn record(&self, wait: Duration) {
+        let starved = wait >= self.starvation_threshold;
+        self.stats.update(|stats| {
+            stats.checkouts += 1;
+            stats.total_wait += wait;
+            stats.max_wait = stats.max_wait.max(wait);
+            stats.starved += u64::from(starved);
+        });
+    }
+
+    pub(crate) fn snapshot(&self) -> CheckoutStats {
+        self.stats
+            .fold(CheckoutStats::default(), CheckoutStats::merge)
+    }
+}
+
+/// A pooled connection and the permit it was checked out with.
+pub(crate) struct Checkout {
+    // Declared first so the connection goes back to the pool before the permit is passed on.
+    connection: mobc::Connection<ConnectionManager>,
+    _permit: Option<Permit>,
+}
+
+impl Checkout {
+    pub(crate) fn new(
+        connection: mobc::Connection<ConnectionManager>,
+        permit: Option<Permit>,
+    ) -> Self {
+        Checkout {
+            connection,
+            _permit: permit,
+        }
+    }
+}
+
+impl Deref for Checkout {
+    type Target = Connection;
+    fn deref(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+impl DerefMut for Checkout {
+    fn deref_mut(&mut self) -> &mut Connection {
+        &mut self.connection
+    }
+}
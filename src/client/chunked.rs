@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Chunk keys are prefixed with this, see `Client::set_chunked`.
+const CHUNK_PREFIX: &str = "__chunk:";
+
+/// The longest key memcached accepts.
+const MAX_KEY_LEN: usize = 250;
+
+/// The bytes of a chunk, half the default `item_size_max` so the item overhead fits too.
+const CHUNK_LEN: usize = 512 * 1024;
+
+/// The generation id and index in front of the bytes of every chunk.
+const HEADER_LEN: usize = 12;
+
+/// Stored under the key of a chunked value, the chunks are under keys derived from it.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// New on every write, so the chunks of two writes are never mixed up.
+    generation: u64,
+    pub(crate) chunks: u32,
+    len: u64,
+    crc32: u32,
+    /// The flags the codec encoded the value with.
+    pub(crate) flags: u32,
+}
+
+/// A generation id no other write of this or another process is likely to use.
+fn new_generation() -> u64 {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let seed = format!(
+        "{nanos}:{}:{}",
+        process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    );
+    let digest = md5::compute(seed);
+    let mut generation = [0; 8];
+    generation.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(generation)
+}
+
+/// The CRC-32 of zlib and Ethernet (IEEE 802.3, reflected polynomial `0xedb88320`).
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & 0u32.wrapping_sub(crc & 1));
+        }
+    }
+    !crc
+}
+
+/// The key of chunk `index` of a write of `key`. Keys too long for the suffix are replaced by their md5.
+fn chunk_key(key: &str, generation: u64, index: u32) -> String {
+    let suffix = format!(":{generation:016x}:{index}");
+    if CHUNK_PREFIX.len() + key.len() + suffix.len() <= MAX_KEY_LEN {
+        format!("{CHUNK_PREFIX}{key}{suffix}")
+    } else {
+        format!("{CHUNK_PREFIX}{:x}{suffix}", md5::compute(key))
+    }
+}
+
+impl Manifest {
+    /// The manifest of a new write of `bytes`, encoded with `flags`.
+    pub(crate) fn new(bytes: &[u8], flags: u32) -> Self {
+        Manifest {
+            generation: new_generation(),
+            chunks: bytes.len().div_ceil(CHUNK_LEN) as u32,
+            len: bytes.len() as u64,
+            crc32: crc32(bytes),
+            flags,
+        }
+    }
+
+    pub(crate) fn chunk_keys(&self, key: &str) -> Vec<String> {
+        (0..self.chunks)
+            .map(|index| chunk_key(key, self.generation, index))
+            .collect()
+    }
+
+    /// The chunks of `bytes` in the order of `chunk_keys`, each behind its generation and index.
+    pub(crate) fn chunks(&self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        bytes
+            .chunks(CHUNK_LEN)
+            .zip(0u32..)
+            .map(|(data, index)| {
+                let mut chunk = Vec::with_capacity(HEADER_LEN + data.len());
+                chunk.extend_from_slice(&self.generation.to_be_bytes());
+                chunk.extend_from_slice(&index.to_be_bytes());
+                chunk.extend_from_slice(data);
+                chunk
+            })
+            .collect()
+    }
+
+    /// The value of the chunks read under `keys`, `Err` with the number of chunks that are
+    /// missing or of another write. A complete value with another length or checksum than the
+    /// manifest counts as missing all of its chunks.
+    pub(crate) fn assemble(
+        &self,
+        keys: &[String],
+        mut found: HashMap<String, Vec<u8>>,
+    ) -> Result<Vec<u8>, u32> {
+        let mut bytes = Vec::with_capacity(self.len as usize);
+        let mut missing = 0;
+        for (key, index) in keys.iter().zip(0u32..) {
+            match found.remove(key) {
+                Some(chunk) if self.is_header_of(&chunk, index) => {
+                    bytes.extend_from_slice(&chunk[HEADER_LEN..]);
+                }
+                _ => missing += 1,
+            }
+        }
+        if missing > 0 {
+            Err(missing)
+        } else if bytes.len() as u64 != self.len || crc32(&bytes) != self.crc32 {
+            Err(self.chunks)
+        } else {
+            Ok(bytes)
+        }
+    }
+
+    fn is_header_of(&self, chunk: &[u8], index: u32) -> bool {
+        chunk.len() >= HEADER_LEN
+            && chunk[..8] == self.generation.to_be_bytes()
+            && chunk[8..HEADER_LEN] == index.to_be_bytes()
+    }
+}
@@ -1,10 +1,330 @@
+use crate::{error::MemcachedError, Result};
+use serde::{Deserialize, Serialize};
 use std::{
+    any,
     collections::hash_map::DefaultHasher,
+    fmt,
     hash::{Hash, Hasher},
+    sync::{Arc, PoisonError, RwLock},
 };
+use url::Url;
+
+/// Hashes per server of weight 1, every hash gives 4 points on the ring (libmemcached compatible).
+const HASHES_PER_SERVER: u64 = 40;
 
 pub(crate) fn default_hash_function(key: &str) -> u64 {
     let mut hasher = DefaultHasher::new();
     key.hash(&mut hasher);
     hasher.finish()
 }
+
+/// How a router placed a key, see [`KeyRouter::explain`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Route {
+    /// Index of the server owning the key.
+    pub server: usize,
+    /// The hash of the key, if the router hashes keys.
+    pub hash: Option<u64>,
+    /// The point on the ring the key was mapped to, for consistent hashing.
+    pub ring_point: Option<u32>,
+}
+
+/// What the routes of a router depend on besides the keys, see [`KeyRouter::parameters`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouterParameters {
+    /// The routing scheme, e.g. `modulo` or `ketama`.
+    pub scheme: String,
+    /// The function hashing keys, a type name for hashers given by the caller.
+    pub hasher: String,
+    /// The weights of the servers in client order, empty if the router ignores weights.
+    pub weights: Vec<u32>,
+    /// The points on the ring of a server of weight 1, 0 without a ring.
+    pub ring_points_per_server: u64,
+}
+
+/// Where a key of a client ends up, see [`Client::explain`](crate::Client::explain).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyExplanation {
+    /// The key as given.
+    pub key: String,
+    /// The tenant or namespace prefix put in front of the key, empty without one.
+    pub prefix: String,
+    /// The key sent to the server.
+    pub wire_key: String,
+    /// How the router placed the wire key.
+    pub route: Route,
+    /// Index of the server the key is sent to, not `route.server` while that server is failed over.
+    pub server_index: usize,
+    /// Url of the server the key is sent to.
+    pub server: String,
+}
+
+/// Decides which server a key is stored on.
+pub trait KeyRouter: Send + Sync {
+    /// Index of the server owning `key`, in the order the urls were given to the client.
+    fn route(&self, key: &str) -> usize;
+
+    /// Like `route`, with the intermediate values that led to the server.
+    /// Routers only reporting the server keep this default.
+    fn explain(&self, key: &str) -> Route {
+        Route {
+            server: self.route(key),
+            ..Route::default()
+        }
+    }
+
+    /// The scheme, hasher and weights the routes depend on, compared by
+    /// [`Client::verify_routing`](crate::Client::verify_routing).
+    /// Routers not reporting them keep this default.
+    fn parameters(&self) -> RouterParameters {
+        RouterParameters::default()
+    }
+}
+
+/// Hashes keys for a [`ModuloRouter`]. Closures and plain functions `Fn(&str) -> u64` are
+/// hashers, a struct can be one to carry state such as a seed or lookup tables.
+pub trait KeyHasher: Send + Sync {
+    /// The hash of `key`.
+    fn hash(&self, key: &str) -> u64;
+}
+
+impl<F: Fn(&str) -> u64 + Send + Sync> KeyHasher for F {
+    fn hash(&self, key: &str) -> u64 {
+        self(key)
+    }
+}
+
+/// A hasher that can be swapped at runtime, e.g. as part of a topology reload.
+/// Clones share the hasher, so keep one to swap the hasher of the clients built with another.
+///
+/// ## Example
+///
+/// ```rust
+/// use memcached::HashFunction;
+///
+/// fn by_length(key: &str) -> u64 {
+///     key.len() as u64
+/// }
+///
+/// let hasher = HashFunction::from(by_length as fn(&str) -> u64);
+/// let client = memcached::Client::builder(vec![
+///     "memcache://127.0.0.1:12345",
+///     "memcache://127.0.0.1:12346",
+/// ])
+/// .hasher(hasher.clone())
+/// .build()?;
+/// assert_eq!(client.explain("abc").route.hash, Some(3));
+/// // Keys move to the servers of the new hash right away.
+/// hasher.swap(|key: &str| key.len() as u64 * 2);
+/// assert_eq!(client.explain("abc").route.hash, Some(6));
+/// # Ok::<(), memcached::error::MemcachedError>(())
+/// ```
+#[derive(Clone)]
+pub struct HashFunction {
+    current: Arc<RwLock<Arc<dyn KeyHasher>>>,
+}
+
+impl HashFunction {
+    /// Hash with `hasher` until swapped.
+    pub fn new<H: KeyHasher + 'static>(hasher: H) -> Self {
+        HashFunction {
+            current: Arc::new(RwLock::new(Arc::new(hasher))),
+        }
+    }
+
+    /// Hash with `hasher` from now on, in every clone.
+    pub fn swap<H: KeyHasher + 'static>(&self, hasher: H) {
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(hasher);
+    }
+}
+
+impl Default for HashFunction {
+    fn default() -> Self {
+        HashFunction::new(default_hash_function)
+    }
+}
+
+impl From<fn(&str) -> u64> for HashFunction {
+    fn from(hash_function: fn(&str) -> u64) -> Self {
+        HashFunction::new(hash_function)
+    }
+}
+
+impl fmt::Debug for HashFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashFunction").finish_non_exhaustive()
+    }
+}
+
+impl KeyHasher for HashFunction {
+    fn hash(&self, key: &str) -> u64 {
+        let hasher = Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner));
+        hasher.hash(key)
+    }
+}
+
+/// `hash(key) % servers`, adding or removing a server remaps almost every key.
+#[derive(Clone)]
+pub struct ModuloRouter {
+    hasher: Arc<dyn KeyHasher>,
+    hasher_name: &'static str,
+    servers: usize,
+}
+
+impl ModuloRouter {
+    /// Route over `servers` servers with `hasher`.
+    #[must_use]
+    pub fn new<H: KeyHasher + 'static>(servers: usize, hasher: H) -> Self {
+        ModuloRouter {
+            hasher: Arc::new(hasher),
+            hasher_name: any::type_name::<H>(),
+            servers: servers.max(1),
+        }
+    }
+}
+
+impl fmt::Debug for ModuloRouter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModuloRouter")
+            .field("hasher", &self.hasher_name)
+            .field("servers", &self.servers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KeyRouter for ModuloRouter {
+    fn route(&self, key: &str) -> usize {
+        self.explain(key).server
+    }
+
+    fn explain(&self, key: &str) -> Route {
+        let hash = self.hasher.hash(key);
+        Route {
+            server: (hash % self.servers as u64) as usize,
+            hash: Some(hash),
+            ring_point: None,
+        }
+    }
+
+    fn parameters(&self) -> RouterParameters {
+        RouterParameters {
+            scheme: "modulo".to_owned(),
+            hasher: self.hasher_name.to_owned(),
+            weights: vec![],
+            ring_points_per_server: 0,
+        }
+    }
+}
+
+/// Consistent hashing compatible with libmemcached's ketama.
+/// Every server gets virtual nodes on a ring in proportion to its weight,
+/// so adding or removing a server only remaps about 1/N of the keys.
+#[derive(Debug, Clone)]
+pub struct KetamaRouter {
+    /// (point, server index) sorted by point
+    ring: Vec<(u32, usize)>,
+    weights: Vec<u32>,
+}
+
+fn point(digest: &[u8; 16], group: usize) -> u32 {
+    let bytes = digest.get(group * 4..group * 4 + 4).unwrap_or(&[0; 4]);
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |point, &b| (point << 8) | u32::from(b))
+}
+
+impl KetamaRouter {
+    /// Build a ring from `(name, weight)` pairs, the name is usually `host:port`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use memcached::{KetamaRouter, KeyRouter};
+    /// let router = KetamaRouter::new(&[("10.0.0.1:11211", 1), ("10.0.0.2:11211", 2)]);
+    /// assert!(router.route("foo") < 2);
+    /// ```
+    #[must_use]
+    pub fn new<S: AsRef<str>>(servers: &[(S, u32)]) -> Self {
+        let total_weight: u64 = servers.iter().map(|(_, weight)| u64::from(*weight)).sum();
+        let mut ring = vec![];
+        for (index, (name, weight)) in servers.iter().enumerate() {
+            let hashes = (HASHES_PER_SERVER * u64::from(*weight) * servers.len() as u64)
+                .checked_div(total_weight)
+                .unwrap_or(HASHES_PER_SERVER);
+            for i in 0..hashes {
+                let digest = md5::compute(format!("{}-{i}", name.as_ref()));
+                for group in 0..4 {
+                    ring.push((point(&digest, group), index));
+                }
+            }
+        }
+        ring.sort_unstable();
+        KetamaRouter {
+            ring,
+            weights: servers.iter().map(|(_, weight)| *weight).collect(),
+        }
+    }
+
+    /// Build a ring from memcached urls, using `host:port` as name and the `weight` query parameter (default 1).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # fn main() -> memcached::Result<()> {
+    /// let router = memcached::KetamaRouter::from_urls(&[
+    ///     "memcache://10.0.0.1:11211",
+    ///     "memcache://10.0.0.2:11211?weight=2",
+    /// ])?;
+    /// # Ok(()) }
+    /// ```
+    pub fn from_urls<S: AsRef<str>>(urls: &[S]) -> Result<Self> {
+        let mut servers = vec![];
+        for url in urls {
+            let url = Url::parse(url.as_ref())?;
+            let host = url
+                .host_str()
+                .ok_or_else(|| MemcachedError::BadURL(format!("No host in URL: {url}")))?;
+            let port = url.port().unwrap_or(11211);
+            let weight = match url.query_pairs().find(|(name, _)| name == "weight") {
+                Some((_, weight)) => weight
+                    .parse()
+                    .map_err(|_| MemcachedError::BadURL(format!("Bad weight: {weight}")))?,
+                None => 1,
+            };
+            servers.push((format!("{host}:{port}"), weight));
+        }
+        Ok(Self::new(&servers))
+    }
+}
+
+impl KeyRouter for KetamaRouter {
+    fn route(&self, key: &str) -> usize {
+        self.explain(key).server
+    }
+
+    /// The first ring point at or after the hash of the key, wrapping around.
+    fn explain(&self, key: &str) -> Route {
+        let hash = point(&md5::compute(key), 0);
+        let i = self.ring.partition_point(|&(point, _)| point < hash);
+        let (ring_point, server) = self
+            .ring
+            .get(i)
+            .or_else(|| self.ring.first())
+            .map_or((None, 0), |&(point, index)| (Some(point), index));
+        Route {
+            server,
+            hash: Some(u64::from(hash)),
+            ring_point,
+        }
+    }
+
+    fn parameters(&self) -> RouterParameters {
+        RouterParameters {
+            scheme: "ketama".to_owned(),
+            hasher: "md5".to_owned(),
+            weights: self.weights.clone(),
+            ring_points_per_server: HASHES_PER_SERVER * 4,
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use super::Client;
+use crate::{
+    codec::{BincodeCodec, ValueCodec},
+    Result,
+};
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex, PoisonError, Weak,
+    },
+    time::Duration,
+};
+
+#[derive(Debug, Default)]
+struct Pending {
+    deltas: HashMap<String, u64>,
+    increments: usize,
+}
+
+struct Inner<C> {
+    client: Client<C>,
+    max_increments: usize,
+    expiration: AtomicU32,
+    pending: Mutex<Pending>,
+}
+
+/// Sums increments per key locally and sends one `incr` per key and flush, see [`Client::counter_buffer`].
+///
+/// Deltas not flushed yet are lost when the last handle is dropped, call [`CounterBuffer::flush`] before.
+#[derive(Clone)]
+pub struct CounterBuffer<C = BincodeCodec> {
+    inner: Arc<Inner<C>>,
+}
+
+impl<C: ValueCodec> Inner<C> {
+    fn take(&self) -> HashMap<String, u64> {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        pending.increments = 0;
+        mem::take(&mut pending.deltas)
+    }
+
+    fn add(&self, key: &str, amount: u64) -> bool {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        let delta = pending.deltas.entry(key.to_owned()).or_default();
+        *delta = delta.saturating_add(amount);
+        pending.increments += 1;
+        pending.increments >= self.max_increments
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let mut deltas = self.take().into_iter();
+        while let Some((key, delta)) = deltas.next() {
+            let result = self
+                .client
+                .increment_or_init(&key, delta, 0, self.expiration.load(Ordering::Relaxed))
+                .await;
+            if let Err(e) = result {
+                // Keep what was not sent for the next flush.
+                let _ = self.add(&key, delta);
+                for (key, delta) in deltas {
+                    let _ = self.add(&key, delta);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<C: ValueCodec> CounterBuffer<C> {
+    pub(crate) fn new(client: Client<C>, flush_interval: Duration, max_increments: usize) -> Self {
+        let inner = Arc::new(Inner {
+            client,
+            max_increments: max_increments.max(1),
+            expiration: AtomicU32::new(0),
+            pending: Mutex::default(),
+        });
+        let weak = Arc::downgrade(&inner);
+        // Dropping the handle detaches the task, it ends with the last `CounterBuffer`.
+        drop(async_std::task::spawn(Self::flush_periodically(
+            weak,
+            flush_interval,
+        )));
+        CounterBuffer { inner }
+    }
+
+    async fn flush_periodically(inner: Weak<Inner<C>>, flush_interval: Duration) {
+        loop {
+            async_std::task::sleep(flush_interval).await;
+            match inner.upgrade() {
+                // Failed deltas stay buffered for the next tick.
+                Some(inner) => {
+                    let _ = inner.flush().await;
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Counters created by a flush expire after `expiration`, they never expire by default.
+    #[must_use]
+    pub fn with_expiration(self, expiration: u32) -> Self {
+        self.inner.expiration.store(expiration, Ordering::Relaxed);
+        self
+    }
+
+    /// Add `amount` to `key`, sent with the next flush.
+    /// Flushes right away once `max_increments` increments are buffered.
+    pub async fn increment<K: AsRef<str>>(&self, key: K, amount: u64) -> Result<()> {
+        if self.inner.add(key.as_ref(), amount) {
+            self.inner.flush().await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Send the buffered deltas now, deltas that could not be sent stay buffered.
+    pub async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+}
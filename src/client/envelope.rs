@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Lock keys of background refreshes are prefixed with this, see `Client::get_or_set`.
+const REFRESH_LOCK_PREFIX: &str = "__refresh:";
+
+/// The longest key memcached accepts.
+const MAX_KEY_LEN: usize = 250;
+
+/// A refresh that takes longer than this lets another client refresh too.
+pub(crate) const REFRESH_LOCK_EXPIRATION: u32 = 30;
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// A value stored by `get_or_set` with the unix time after which it is recomputed.
+/// The memcached expiration of the item is the hard TTL.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Envelope<V> {
+    soft_expires_at: u64,
+    pub(crate) value: V,
+}
+
+impl<V> Envelope<V> {
+    pub(crate) fn new(value: V, soft_ttl: u32) -> Self {
+        Envelope {
+            soft_expires_at: unix_secs() + u64::from(soft_ttl),
+            value,
+        }
+    }
+
+    pub(crate) fn is_stale(&self) -> bool {
+        unix_secs() >= self.soft_expires_at
+    }
+}
+
+/// The key of the refresh lock of `key`. Keys too long for the prefix are replaced by their md5.
+pub(crate) fn refresh_lock_key(key: &str) -> String {
+    if REFRESH_LOCK_PREFIX.len() + key.len() <= MAX_KEY_LEN {
+        format!("{REFRESH_LOCK_PREFIX}{key}")
+    } else {
+        format!("{REFRESH_LOCK_PREFIX}{:x}", md5::compute(key))
+    }
+}
+
+/// The keys this process is refreshing, so a stale key read by many tasks at once spawns one refresh.
+#[derive(Debug, Default)]
+pub(crate) struct RefreshClaims(Mutex<HashSet<String>>);
+
+impl RefreshClaims {
+    /// Claim the refresh of `key`, `None` while another task of the process holds it.
+    pub(crate) fn claim(self: &Arc<Self>, key: &str) -> Option<RefreshClaim> {
+        let mut keys = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        keys.insert(key.to_owned()).then(|| RefreshClaim {
+            claims: Arc::clone(self),
+            key: key.to_owned(),
+        })
+    }
+}
+
+/// The refresh of one key, released when dropped.
+#[derive(Debug)]
+pub(crate) struct RefreshClaim {
+    claims: Arc<RefreshClaims>,
+    key: String,
+}
+
+impl Drop for RefreshClaim {
+    fn drop(&mut self) {
+        let _ = self
+            .claims
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.key);
+    }
+}
@@ -0,0 +1,38 @@
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+/// How reads between a `flush_with_delay` of the client and the flush are answered,
+/// see [`ClientBuilder::flush_window_reads`](crate::ClientBuilder::flush_window_reads).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FlushWindowReads {
+    /// Return the items, they are still stored until the flush.
+    #[default]
+    Serve,
+    /// Answer `get` and `gets` with misses, so callers recompute and warm the cache early.
+    Miss,
+}
+
+/// When the last `flush_with_delay` sent by a client takes effect.
+#[derive(Debug, Default)]
+pub(crate) struct FlushSchedule {
+    at: Mutex<Option<SystemTime>>,
+}
+
+impl FlushSchedule {
+    /// Record a flush `delay` seconds from now, 0 flushed right away.
+    pub(crate) fn schedule(&self, delay: u32) {
+        let at = (delay > 0).then(|| SystemTime::now() + Duration::from_secs(u64::from(delay)));
+        *self.at.lock().unwrap_or_else(PoisonError::into_inner) = at;
+    }
+
+    /// The time of a flush that has not happened yet.
+    pub(crate) fn pending(&self) -> Option<SystemTime> {
+        let mut at = self.at.lock().unwrap_or_else(PoisonError::into_inner);
+        if at.is_some_and(|at| at <= SystemTime::now()) {
+            *at = None;
+        }
+        *at
+    }
+}
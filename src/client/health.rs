@@ -0,0 +1,110 @@
+use crate::{error::ClientError, Result};
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+/// When a server counts as dead and what happens to its keys, see [`ClientBuilder::failover`](super::builder::ClientBuilder::failover).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HealthPolicy {
+    /// Consecutive connection errors after which a server is dead.
+    pub(crate) dead_after: u32,
+    /// Minimum time between two probes of a dead server.
+    pub(crate) probe_interval: Duration,
+    /// Send the keys of dead servers to the next healthy server.
+    pub(crate) failover: bool,
+    /// How long a refused connect or authentication fails checkouts without dialing, zero never.
+    pub(crate) refusal_ttl: Duration,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        HealthPolicy {
+            dead_after: 3,
+            probe_interval: Duration::from_secs(1),
+            failover: false,
+            refusal_ttl: Duration::ZERO,
+        }
+    }
+}
+
+/// Consecutive connection errors of one server.
+#[derive(Debug, Default)]
+pub(crate) struct ServerHealth {
+    failures: AtomicU32,
+    last_probe: Mutex<Option<Instant>>,
+}
+
+impl ServerHealth {
+    pub(crate) fn record<T>(&self, result: &Result<T>) {
+        match result {
+            Err(err) if err.is_connection_error() => {
+                let _ = self.failures.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => self.failures.store(0, Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn is_dead(&self, policy: &HealthPolicy) -> bool {
+        self.failures.load(Ordering::Relaxed) >= policy.dead_after
+    }
+
+    /// Whether the caller should probe now, at most once per `probe_interval`.
+    pub(crate) fn probe_due(&self, policy: &HealthPolicy) -> bool {
+        let mut last_probe = self
+            .last_probe
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let due = last_probe.is_none_or(|last| last.elapsed() >= policy.probe_interval);
+        if due {
+            *last_probe = Some(Instant::now());
+        }
+        due
+    }
+}
+
+/// The last refused connect or authentication of one server, remembered for `ttl`
+/// so checkouts fail fast instead of dialing again.
+#[derive(Debug, Default)]
+pub(crate) struct RefusalCache {
+    ttl: Duration,
+    last: Mutex<Option<(Instant, String)>>,
+}
+
+impl RefusalCache {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        RefusalCache {
+            ttl,
+            last: Mutex::default(),
+        }
+    }
+
+    /// Remember a failed dial, forget it once one succeeds.
+    pub(crate) fn record<T>(&self, result: &Result<T>) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        *last = result
+            .as_ref()
+            .err()
+            .map(|err| (Instant::now(), err.to_string()));
+    }
+
+    /// Fails with [`ClientError::ServerRefused`] while a refusal of `server` is remembered.
+    /// Once it expired the next checkout dials again and tells whether the server recovered.
+    pub(crate) fn check(&self, server: &str) -> Result<()> {
+        let last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        match &*last {
+            Some((at, reason)) if at.elapsed() < self.ttl => Err(ClientError::ServerRefused {
+                server: server.to_owned(),
+                reason: reason.clone(),
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
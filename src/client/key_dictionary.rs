@@ -0,0 +1,97 @@
+use crate::{error::ClientError, Result};
+use std::borrow::Cow;
+
+/// Maps known long key prefixes to short codes, see [`ClientBuilder::key_dictionary`](crate::ClientBuilder::key_dictionary).
+///
+/// Keys starting with a prefix are sent with the prefix replaced by its code, which saves bytes
+/// on every operation and leaves more of the 250 bytes of a key to the rest of it.
+/// A key starting with a code but not with its prefix is refused with [`ClientError::KeyCollision`],
+/// it would read back as another key.
+///
+/// ## Example
+///
+/// ```rust
+/// # fn main() -> memcached::Result<()> {
+/// use memcached::KeyDictionary;
+/// let dictionary = KeyDictionary::new([("service:accounts:users:profile:", "~u:")])?;
+/// let key = dictionary.shorten("service:accounts:users:profile:42")?;
+/// assert_eq!(key, "~u:42");
+/// assert_eq!(dictionary.expand(&key), "service:accounts:users:profile:42");
+/// assert!(dictionary.shorten("~u:42").is_err());
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeyDictionary {
+    /// `(prefix, code)`, longest prefix first so the most specific prefix wins.
+    entries: Vec<(String, String)>,
+}
+
+fn invalid(message: String) -> ClientError {
+    ClientError::Error(Cow::Owned(message))
+}
+
+impl KeyDictionary {
+    /// A dictionary of `(prefix, code)` pairs. Codes must be shorter than their prefix, unique,
+    /// and none may start with another, so every shortened key expands to exactly one key.
+    pub fn new<P: Into<String>, C: Into<String>>(
+        entries: impl IntoIterator<Item = (P, C)>,
+    ) -> Result<Self> {
+        let mut entries: Vec<(String, String)> = entries
+            .into_iter()
+            .map(|(prefix, code)| (prefix.into(), code.into()))
+            .collect();
+        for (i, (prefix, code)) in entries.iter().enumerate() {
+            if code.is_empty() || code.len() >= prefix.len() {
+                return Err(invalid(format!(
+                    "The code {code:?} must be shorter than its prefix {prefix:?}"
+                ))
+                .into());
+            }
+            for (other_prefix, other) in &entries[i + 1..] {
+                if other_prefix == prefix
+                    || other.starts_with(code.as_str())
+                    || code.starts_with(other.as_str())
+                {
+                    return Err(invalid(format!(
+                        "The entries {prefix:?} => {code:?} and {other_prefix:?} => {other:?} collide"
+                    ))
+                    .into());
+                }
+            }
+        }
+        entries.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Ok(KeyDictionary { entries })
+    }
+
+    /// The key as sent to the server.
+    pub fn shorten<'a>(&self, key: &'a str) -> Result<Cow<'a, str>> {
+        if let Some((code, rest)) = self
+            .entries
+            .iter()
+            .find_map(|(prefix, code)| Some((code, key.strip_prefix(prefix.as_str())?)))
+        {
+            return Ok(Cow::Owned(format!("{code}{rest}")));
+        }
+        if self
+            .entries
+            .iter()
+            .any(|(_, code)| key.starts_with(code.as_str()))
+        {
+            return Err(ClientError::KeyCollision(key.to_owned()).into());
+        }
+        Ok(Cow::Borrowed(key))
+    }
+
+    /// The key a shortened key was made of.
+    #[must_use]
+    pub fn expand<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        match self
+            .entries
+            .iter()
+            .find_map(|(prefix, code)| Some((prefix, key.strip_prefix(code.as_str())?)))
+        {
+            Some((prefix, rest)) => Cow::Owned(format!("{prefix}{rest}")),
+            None => Cow::Borrowed(key),
+        }
+    }
+}
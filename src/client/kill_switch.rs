@@ -0,0 +1,93 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+
+#[derive(Debug, Default)]
+struct State {
+    engaged: AtomicBool,
+    bypassed: AtomicU64,
+}
+
+/// Takes the cache out of the request path of the clients using it while engaged, see
+/// [`ClientBuilder::kill_switch`](crate::ClientBuilder::kill_switch). Clones share the state.
+///
+/// Nothing is sent while engaged and every key is missing: reads are misses, writes succeed
+/// without storing anything, deletes and `cas` find nothing, counters fail with `KeyNotFound`
+/// unless they create the key, then they return its initial value, and pipelines answer each
+/// command that way. Only `version` and the statistics, which describe the servers rather
+/// than keys, fail with [`ClientError::CacheBypassed`](crate::error::ClientError::CacheBypassed).
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+/// let switch = memcached::KillSwitch::default();
+/// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+///     .kill_switch(switch.clone())
+///     .build()?;
+/// client.set("kill_switch_test", "hello", 100).await?;
+/// switch.engage();
+/// assert_eq!(client.get::<String, _>("kill_switch_test").await?, None);
+/// client.set("kill_switch_test", "ignored", 100).await?;
+/// assert_eq!(switch.bypassed(), 2);
+/// switch.release();
+/// assert_eq!(client.get::<String, _>("kill_switch_test").await?, Some("hello".to_owned()));
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KillSwitch {
+    state: Arc<State>,
+}
+
+impl KillSwitch {
+    /// Bypass the cache from now on.
+    pub fn engage(&self) {
+        self.state.engaged.store(true, Ordering::Relaxed);
+    }
+
+    /// Use the cache again.
+    pub fn release(&self) {
+        self.state.engaged.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether the cache is bypassed.
+    #[must_use]
+    pub fn is_engaged(&self) -> bool {
+        self.state.engaged.load(Ordering::Relaxed)
+    }
+
+    /// Operations not sent because the switch was engaged.
+    #[must_use]
+    pub fn bypassed(&self) -> u64 {
+        self.state.bypassed.load(Ordering::Relaxed)
+    }
+
+    /// Engage the switch while a file exists at `path`, checked every `interval`, so operators
+    /// can bypass the cache by creating the file. Watching stops with the last clone of the switch.
+    pub fn watch_file<P: Into<PathBuf>>(&self, path: P, interval: Duration) {
+        let path = path.into();
+        let state = Arc::downgrade(&self.state);
+        drop(async_std::task::spawn(async move {
+            while let Some(state) = Weak::upgrade(&state) {
+                let exists = async_std::fs::metadata(&path).await.is_ok();
+                state.engaged.store(exists, Ordering::Relaxed);
+                drop(state);
+                async_std::task::sleep(interval).await;
+            }
+        }));
+    }
+
+    /// Whether an operation is bypassed, counting it if it is.
+    pub(crate) fn bypass(&self) -> bool {
+        let engaged = self.is_engaged();
+        if engaged {
+            let _ = self.state.bypassed.fetch_add(1, Ordering::Relaxed);
+        }
+        engaged
+    }
+}
@@ -1,20 +1,230 @@
+pub(crate) mod batching;
+pub(crate) mod builder;
 mod check;
-mod client_hash;
+mod checkout;
+pub(crate) mod chunked;
+pub(crate) mod client_hash;
 pub(crate) mod connectable;
+pub(crate) mod counter_buffer;
+mod envelope;
+pub(crate) mod flush_window;
+pub(crate) mod health;
+pub(crate) mod key_dictionary;
+pub(crate) mod kill_switch;
+pub(crate) mod multi_get;
+mod namespace;
+pub(crate) mod pipeline;
+pub(crate) mod raw;
+pub(crate) mod routing_snapshot;
+pub(crate) mod self_test;
+pub(crate) mod serialization_memo;
+mod server;
+pub(crate) mod settings_drift;
+pub(crate) mod stop_signal;
+pub(crate) mod tenant;
+pub(crate) mod tiered;
+pub(crate) mod ttl_rules;
+pub(crate) mod verify;
+pub(crate) mod version_skew;
 
-use crate::connection::ConnectionManager;
-use crate::{error::ClientError, Connectable, Result};
-use client_hash::default_hash_function;
-use mobc::Pool;
+use crate::protocol::{
+    meta::{MetaDeleteFlags, MetaGetFlags, MetaResponse, MetaSetFlags, MetaStatus},
+    Command, ProtocolTrait, Reply, DONT_CREATE,
+};
+use crate::{
+    codec::{BincodeCodec, RawBytes, Utf8String, ValueCodec},
+    connection::Connection,
+    error::{ClientError, CommandError, MemcachedError, MultiError, MultiResult, WriteState},
+    metrics::{CheckoutStats, MetricsObserver},
+    stats::ItemMetadata,
+    CasResult, Connectable, DataType, ExtstoreStats, Operation, PrefixScan, PrefixStats, Result,
+    ServerCapabilities, ServerSettings, ServerStats, StopSignal,
+};
+use builder::ClientBuilder;
+use chunked::Manifest;
+use client_hash::{default_hash_function, KeyExplanation, KeyRouter, ModuloRouter};
+use counter_buffer::CounterBuffer;
+use envelope::{Envelope, RefreshClaims, REFRESH_LOCK_EXPIRATION};
+use flush_window::{FlushSchedule, FlushWindowReads};
+use health::HealthPolicy;
+use key_dictionary::KeyDictionary;
+use kill_switch::KillSwitch;
+use multi_get::MultiGet;
+use namespace::Record;
+use pipeline::Pipeline;
+use raw::RawConnection;
+use routing_snapshot::RoutingSnapshot;
+use self_test::SelfTestReport;
 use serde::{de::DeserializeOwned, Serialize};
-use std::collections::HashMap;
-use url::Url;
+use serialization_memo::{Encoded, SerializationMemo};
+use server::Server;
+use settings_drift::SettingsDrift;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    ops::ControlFlow,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tenant::Tenant;
+use tiered::{Tier, TierAttempt, TierBudget, TierOutcome, Tiered};
+use ttl_rules::TtlRules;
+use verify::{ReadDigest, ReadMismatch, ReadSampler};
+use version_skew::VersionSkew;
+
+/// Await `$call` for at most the operation timeout of the client. The call is abandoned when it fires,
+/// so the connection is poisoned and the error says how much of the request was sent.
+macro_rules! within_operation_timeout {
+    ($self:ident, $connection:ident, $call:expr) => {{
+        match $self.operation_timeout {
+            Some(limit) => {
+                // Boxed, clients without the timeout keep their futures small.
+                let outcome = Box::pin(async_std::future::timeout(limit, $call)).await;
+                outcome.unwrap_or_else(|_| {
+                    $connection.poison();
+                    Err(crate::error::MemcachedError::Timeout(
+                        $connection.write_state(),
+                    ))
+                })
+            }
+            None => $call.await,
+        }
+    }};
+}
+
+/// Run `$call` on a connection checked out of the pool of `$server`, then report how long the checkout,
+/// the write, the server and the read took, the bytes sent and received and the error if any
+/// to the observer and the tracing span, and whether the server was reachable to its health.
+/// The connection is closed after errors that may leave part of a response unread.
+/// A connection the server asks to authenticate again is re-authenticated,
+/// calls marked with `retry,` are then sent once more.
+/// Nothing is sent while the kill switch is engaged, the call fails with [`ClientError::CacheBypassed`],
+/// which callers turn into the answer for a missing key.
+macro_rules! with_connection {
+    ($self:ident, $op:literal, $server:expr, $($retry:ident,)? |$connection:ident| $call:expr) => {{
+        let server: &Server = $server;
+        if $self.bypass($op) {
+            Err(crate::error::ClientError::CacheBypassed.into())
+        } else {
+            let future = async {
+                let started = Instant::now();
+                let mut $connection = server.checkout().await?;
+                let pool_wait = started.elapsed();
+                $connection.start_timing();
+                $connection.begin_request();
+                let result = within_operation_timeout!($self, $connection, $call);
+                let result = if matches!(
+                    result,
+                    Err(crate::error::MemcachedError::CommandError(
+                        crate::error::CommandError::AuthenticationRequired
+                    ))
+                ) {
+                    if $connection.reauthenticate().await.is_ok() {
+                        $(
+                            let _ = stringify!($retry);
+                            let result = within_operation_timeout!($self, $connection, $call);
+                        )?
+                        result
+                    } else {
+                        $connection.poison();
+                        result
+                    }
+                } else {
+                    result
+                };
+                $connection.end_request();
+                if result
+                    .as_ref()
+                    .is_err_and(crate::error::MemcachedError::breaks_connection)
+                {
+                    $connection.poison();
+                    if let (Err(e), Some(dir)) = (&result, &$self.capture_failures) {
+                        Self::save_capture(dir, &mut $connection, $op, e).await;
+                    }
+                }
+                let timings = $connection.take_timings(pool_wait);
+                #[cfg(feature = "tracing")]
+                crate::metrics::record(&tracing::Span::current(), &$connection.url, &timings);
+                if let Some(observer) = &$self.observer {
+                    observer.on_timings($op, &$connection.url, &timings);
+                }
+                result
+            };
+            #[cfg(feature = "tracing")]
+            let span = crate::metrics::span($op, server.index);
+            #[cfg(feature = "tracing")]
+            let future = tracing::Instrument::instrument(future, span.clone());
+            let result = future.await;
+            server.health.record(&result);
+            if let Err(e) = &result {
+                #[cfg(feature = "tracing")]
+                crate::metrics::record_error(&span, e);
+                if let Some(observer) = &$self.observer {
+                    observer.on_error($op, &server.url, e);
+                }
+            }
+            result
+        }
+    }};
+}
+
+/// Attempts to update a namespace record before giving up on concurrent updates.
+const NAMESPACE_CAS_RETRIES: usize = 5;
+
+/// Attempts of `delete_with_cas` to delete the version it read before giving up on concurrent writes.
+const DELETE_CAS_RETRIES: usize = 5;
+
+/// Pause between two connection attempts of `ready`.
+const READY_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Number of keys pipelined between two checks of a `StopSignal`.
+const STOP_CHECK_BATCH_SIZE: usize = 100;
+
+/// The keys a read found, reported to the observer as hits.
+trait Found {
+    fn found(&self) -> usize;
+}
+
+impl<T> Found for Option<T> {
+    fn found(&self) -> usize {
+        usize::from(self.is_some())
+    }
+}
+
+impl<K, V> Found for HashMap<K, V> {
+    fn found(&self) -> usize {
+        self.len()
+    }
+}
 
 /// Client for operating connection pool
+/// Values are encoded with the codec `C`, see [`Client::with_codec`].
 #[derive(Clone)]
-pub struct Client {
-    connections: Vec<Pool<ConnectionManager>>,
-    hash_function: fn(&str) -> u64,
+pub struct Client<C = BincodeCodec> {
+    connections: Vec<Server>,
+    router: Arc<dyn KeyRouter>,
+    health_policy: HealthPolicy,
+    multi_get_retries: u32,
+    degrade_on_error: bool,
+    operation_timeout: Option<Duration>,
+    degraded_reads: Arc<AtomicU64>,
+    verify: Arc<ReadSampler>,
+    dictionary: Option<Arc<KeyDictionary>>,
+    ttl_rules: Option<TtlRules>,
+    memo: Option<SerializationMemo>,
+    flush_schedule: Arc<FlushSchedule>,
+    refresh_claims: Arc<RefreshClaims>,
+    flush_window_reads: FlushWindowReads,
+    kill_switch: Option<KillSwitch>,
+    /// The directory captures of failed operations are written to.
+    capture_failures: Option<Arc<Path>>,
+    observer: Option<Arc<dyn MetricsObserver>>,
+    codec: C,
 }
 
 impl Client {
@@ -39,29 +249,419 @@ impl Client {
     /// let client = memcached::Client::connect_with(vec!["memcache://127.0.0.1:12345".to_owned()], 2, |s|1)?;
     /// # Ok(()) } dbg!(foo().await.unwrap()); });
     /// ```
-    pub fn connect_with<T: Connectable>(
+    pub fn connect_with<T: Connectable, F: Fn(&str) -> u64 + Send + Sync + 'static>(
         urls: T,
         pool_size: u64,
-        hash_function: fn(&str) -> u64,
+        hash_function: F,
     ) -> Result<Self> {
-        let mut connections = vec![];
-        for url in urls.get_urls() {
-            let parsed = Url::parse(url.as_str())?;
-            let pool = Pool::builder()
-                .max_idle(pool_size)
-                .build(ConnectionManager { url: parsed });
-            connections.push(pool);
-        }
-        if connections.is_empty() {
-            return Err(ClientError::ConnectionsIsEmpty.into());
-        }
-        Ok(Client {
-            connections,
-            hash_function,
-        })
+        let urls = urls.get_urls();
+        let router = ModuloRouter::new(urls.len(), hash_function);
+        Self::connect_with_router(urls, pool_size, router)
+    }
+
+    /// Create a client that picks the server of a key with `router`.
+    /// [`KetamaRouter`](crate::KetamaRouter) keeps most keys on their server when the cluster is resized.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let urls = vec!["memcache://127.0.0.1:12345"];
+    /// let router = memcached::KetamaRouter::from_urls(&urls)?;
+    /// let client = memcached::Client::connect_with_router(urls, 2, router)?;
+    /// client.set("ketama_test", "hello", 100).await?;
+    /// let t: Option<String> = client.get("ketama_test").await?;
+    /// assert_eq!(t, Some("hello".to_owned()));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub fn connect_with_router<T: Connectable, R: KeyRouter + 'static>(
+        urls: T,
+        pool_size: u64,
+        router: R,
+    ) -> Result<Self> {
+        ClientBuilder::new(urls)
+            .pool_size(pool_size)
+            .router(router)
+            .build()
+    }
+
+    /// Configure the pool, the routing and the connect, read and write timeouts before connecting.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use std::time::Duration;
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .pool_size(2)
+    ///     .connect_timeout(Duration::from_secs(1))
+    ///     .read_timeout(Duration::from_secs(1))
+    ///     .write_timeout(Duration::from_secs(1))
+    ///     .build()?;
+    /// client.set("builder_test", "hello", 100).await?;
+    /// let t: Option<String> = client.get("builder_test").await?;
+    /// assert_eq!(t, Some("hello".to_owned()));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub fn builder<T: Connectable>(urls: T) -> ClientBuilder {
+        ClientBuilder::new(urls)
+    }
+}
+
+impl<C: ValueCodec> Client<C> {
+    /// Encode values with `codec` instead of the default [`BincodeCodec`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use memcached::codec::Utf8String;
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?.with_codec(Utf8String);
+    /// client.set("with_codec_test", "hello", 100).await?;
+    /// let t = client.gets::<String, _>(&["with_codec_test"]).await?;
+    /// assert_eq!(t["with_codec_test"].0, "hello");
+    /// assert_eq!(t["with_codec_test"].1, 0);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn with_codec<C2: ValueCodec>(self, codec: C2) -> Client<C2> {
+        Client {
+            connections: self.connections,
+            router: self.router,
+            health_policy: self.health_policy,
+            multi_get_retries: self.multi_get_retries,
+            degrade_on_error: self.degrade_on_error,
+            operation_timeout: self.operation_timeout,
+            degraded_reads: self.degraded_reads,
+            verify: self.verify,
+            dictionary: self.dictionary,
+            ttl_rules: self.ttl_rules,
+            memo: self.memo,
+            flush_schedule: self.flush_schedule,
+            refresh_claims: self.refresh_claims,
+            flush_window_reads: self.flush_window_reads,
+            kill_switch: self.kill_switch,
+            capture_failures: self.capture_failures,
+            observer: self.observer,
+            codec,
+        }
+    }
+
+    /// A handle whose keys are prefixed with `name:`, optionally limited by quotas and with its own metrics.
+    /// Handing out tenants lets several teams share one cluster without touching each other's keys.
+    /// Names containing `:` fail with [`ClientError::InvalidTenantName`], their keys could be another tenant's.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let tenant = client.tenant("team-a")?;
+    /// tenant.set("tenant_test", "hello", 100).await?;
+    /// let t: Option<String> = client.get("team-a:tenant_test").await?;
+    /// assert_eq!(t, Some("hello".to_owned()));
+    /// assert_eq!(tenant.metrics().ops, 1);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub fn tenant(&self, name: &str) -> Result<Tenant<C>> {
+        Tenant::new(self.clone(), name)
+    }
+
+    /// Buffer increments locally and send the summed delta of each key every `flush_interval`,
+    /// or as soon as `max_increments` increments are buffered.
+    /// High-frequency counters then cost one round trip per key and flush, at the price of
+    /// readers seeing them up to `flush_interval` late. Missing counters are created with `increment_or_init`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use std::time::Duration;
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.delete("counter_buffer_test").await?;
+    /// let counters = client.counter_buffer(Duration::from_millis(100), 1000);
+    /// for _ in 0..10 {
+    ///     counters.increment("counter_buffer_test", 2).await?;
+    /// }
+    /// let t: Option<u64> = client.get("counter_buffer_test").await?;
+    /// assert_eq!(t, None);
+    /// async_std::task::sleep(Duration::from_millis(300)).await;
+    /// let t: Option<u64> = client.get("counter_buffer_test").await?;
+    /// assert_eq!(t, Some(20));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn counter_buffer(
+        &self,
+        flush_interval: Duration,
+        max_increments: usize,
+    ) -> CounterBuffer<C> {
+        CounterBuffer::new(self.clone(), flush_interval, max_increments)
+    }
+
+    /// Report the timings of every operation to `observer`.
+    /// Each operation is split into waiting for a pooled connection, writing the request,
+    /// waiting for the server and reading the response.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use memcached::metrics::{MetricsObserver, Timings};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// #[derive(Default)]
+    /// struct Recorder(Mutex<Vec<(&'static str, Timings)>>);
+    ///
+    /// impl MetricsObserver for Recorder {
+    ///     fn on_timings(&self, op: &'static str, _server: &str, timings: &Timings) {
+    ///         self.0.lock().unwrap().push((op, *timings));
+    ///     }
+    /// }
+    ///
+    /// let recorder = Arc::new(Recorder::default());
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?
+    ///     .with_metrics_observer(Arc::clone(&recorder));
+    /// client.set("with_metrics_observer_test", "hello", 100).await?;
+    /// let recorded = recorder.0.lock().unwrap();
+    /// assert_eq!(recorded[0].0, "set");
+    /// assert!(recorded[0].1.server > std::time::Duration::default());
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn with_metrics_observer<O: MetricsObserver + 'static>(mut self, observer: O) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// URLs of the servers that failed too many times in a row, see [`ClientBuilder::failover`].
+    #[must_use]
+    pub fn dead_servers(&self) -> Vec<String> {
+        self.connections
+            .iter()
+            .filter(|server| server.health.is_dead(&self.health_policy))
+            .map(|server| server.url.clone())
+            .collect()
+    }
+
+    /// Wait until every server has an open, authenticated connection, e.g. to gate a readiness probe.
+    /// Servers failing to connect are retried until `timeout`, then the last error is returned.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use std::time::Duration;
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.ready(Duration::from_secs(1)).await?;
+    /// let client = memcached::Client::connect(vec![
+    ///     "memcache://127.0.0.1:12345",
+    ///     "memcache://127.0.0.1:1",
+    /// ])?;
+    /// assert!(client.ready(Duration::from_millis(200)).await.is_err());
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn ready(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        let tasks: Vec<_> = self
+            .connections
+            .iter()
+            .map(|server| {
+                let server = server.clone();
+                async_std::task::spawn(async move {
+                    loop {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        match async_std::future::timeout(remaining, server.checkout()).await {
+                            Ok(Ok(_)) => return Ok(()),
+                            Ok(Err(e)) if Instant::now() + READY_RETRY_INTERVAL >= deadline => {
+                                return Err(e)
+                            }
+                            Ok(Err(_)) => async_std::task::sleep(READY_RETRY_INTERVAL).await,
+                            Err(_) => return Err(MemcachedError::Timeout(WriteState::NotWritten)),
+                        }
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await?;
+        }
+        Ok(())
+    }
+
+    /// Run a small end-to-end test against every server, e.g. as a deployment preflight check:
+    /// connect and authenticate, `version`, `set`, `get` and `delete` of a canary key, `increment` and `decrement`.
+    /// Every check is timed, a server's test stops at its first failed check.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// for report in client.self_test().await {
+    ///     assert!(report.passed(), "{report:?}");
+    ///     assert_eq!(report.checks.len(), 7);
+    ///     println!("{} passed in {:?}", report.server, report.latency());
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn self_test(&self) -> Vec<SelfTestReport> {
+        let mut reports = vec![];
+        for server in &self.connections {
+            reports.push(self_test::run(server).await);
+        }
+        reports
+    }
+
+    /// Reads answered with a miss because their server could not be reached, see [`ClientBuilder::degrade_on_error`].
+    #[must_use]
+    pub fn degraded_reads(&self) -> u64 {
+        self.degraded_reads.load(Ordering::Relaxed)
+    }
+
+    /// Verified reads whose two answers differed, see [`ClientBuilder::verify_reads`].
+    #[must_use]
+    pub fn read_mismatches(&self) -> u64 {
+        self.verify.mismatches.load(Ordering::Relaxed)
+    }
+
+    /// Report the hits and misses of a read of `requested` keys on `server`,
+    /// with `degrade_on_error` a read that could not reach `server` is a miss.
+    fn finish_read<T: Default + Found>(
+        &self,
+        op: &'static str,
+        server: &Server,
+        requested: usize,
+        result: Result<T>,
+    ) -> Result<T> {
+        match result {
+            Ok(found) => {
+                if let Some(observer) = &self.observer {
+                    let hits = found.found();
+                    observer.on_lookup(
+                        op,
+                        &server.url,
+                        hits as u64,
+                        requested.saturating_sub(hits) as u64,
+                    );
+                }
+                Ok(found)
+            }
+            Err(MemcachedError::ClientError(ClientError::CacheBypassed)) => Ok(T::default()),
+            Err(e) if self.degrade_on_error && e.is_connection_error() => {
+                let _ = self.degraded_reads.fetch_add(1, Ordering::Relaxed);
+                if let Some(observer) = &self.observer {
+                    observer.on_degraded(op, &server.url, &e);
+                }
+                Ok(T::default())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// A write bypassed by the kill switch succeeds without storing anything.
+    fn finish_write<T: Default>(result: Result<T>) -> Result<T> {
+        Self::when_bypassed(result, || Ok(T::default()))
+    }
+
+    /// `answer` instead of [`ClientError::CacheBypassed`], the result of the operation on a missing key.
+    fn when_bypassed<T>(result: Result<T>, answer: impl FnOnce() -> Result<T>) -> Result<T> {
+        match result {
+            Err(MemcachedError::ClientError(ClientError::CacheBypassed)) => answer(),
+            result => result,
+        }
+    }
+
+    /// Report the size of the multi-get of `keys` keys just sent on `connection` to the observer.
+    fn observe_multi_get(&self, op: &'static str, keys: usize, connection: &mut Connection) {
+        if let Some(observer) = &self.observer {
+            let request_bytes = connection.bytes_written();
+            observer.on_multi_get(op, &connection.url, keys, request_bytes);
+        }
+    }
+
+    /// Whether `op` is not sent because the kill switch is engaged.
+    fn bypass(&self, op: &'static str) -> bool {
+        let bypassed = self.kill_switch.as_ref().is_some_and(KillSwitch::bypass);
+        if bypassed {
+            if let Some(observer) = &self.observer {
+                observer.on_bypassed(op);
+            }
+        }
+        bypassed
+    }
+
+    /// Write the request and response of `op` failing with `error` on `connection` to a capture file in `dir`.
+    async fn save_capture(
+        dir: &Path,
+        connection: &mut Connection,
+        op: &str,
+        error: &MemcachedError,
+    ) {
+        let Some(capture) = connection.take_capture(op, error) else {
+            return;
+        };
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        let _ = capture
+            .save(dir.join(format!("{op}-{nanos}.capture")))
+            .await;
+    }
+
+    /// Check a connection to the server at `server`, an index into the urls the client was
+    /// built with, out for requests of its own. See [`RawConnection`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::Client::connect(vec![
+    ///     "memcache://127.0.0.1:12345?protocol=ascii",
+    ///     "memcache://127.0.0.1:12345",
+    /// ])?;
+    /// let mut text = client.raw_connection(0).await?;
+    /// text.send_line("version", None).await?;
+    /// assert!(text.read_line().await?.starts_with("VERSION "));
+    ///
+    /// let mut binary = client.raw_connection(1).await?;
+    /// binary.send_packet(&memcached::RawPacket { opcode: 0x0b, ..Default::default() }).await?;
+    /// let reply = binary.read_packet().await?;
+    /// assert_eq!(reply.status, 0);
+    /// assert!(!reply.value.is_empty());
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn raw_connection(&self, server: usize) -> Result<RawConnection> {
+        let Some(server) = self.connections.get(server) else {
+            return Err(ClientError::Error(Cow::Owned(format!(
+                "No server {server}, the client has {}",
+                self.connections.len()
+            )))
+            .into());
+        };
+        Ok(RawConnection::new(server.checkout().await?))
+    }
+
+    /// How long the checkouts of each server waited for a connection, see [`ClientBuilder::fair_checkout`].
+    #[must_use]
+    pub fn checkout_stats(&self) -> Vec<(String, CheckoutStats)> {
+        self.connections
+            .iter()
+            .map(|server| (server.url.clone(), server.checkouts.snapshot()))
+            .collect()
+    }
+
+    /// The current multi-get batch size of every server, empty without
+    /// [`ClientBuilder::multi_get_batching`].
+    #[must_use]
+    pub fn multi_get_batch_sizes(&self) -> Vec<(String, usize)> {
+        self.connections
+            .iter()
+            .filter_map(|server| Some((server.url.clone(), server.batch.as_ref()?.size())))
+            .collect()
     }
 
-    /// Get server version
+    /// Get the version of every server, asked concurrently, by server url.
     ///
     /// ## Example
     ///
@@ -69,17 +669,67 @@ impl Client {
     /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
     /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
     /// let version = client.version().await?;
+    /// assert!(version.contains_key("memcache://127.0.0.1:12345"));
     /// # Ok(()) } dbg!(foo().await.unwrap()); });
     /// ```
     pub async fn version(&self) -> Result<HashMap<String, String>> {
+        let tasks: Vec<_> = self
+            .connections
+            .iter()
+            .map(|pool| {
+                let client = self.clone();
+                let pool = pool.clone();
+                async_std::task::spawn(async move {
+                    with_connection!(client, "version", &pool, retry, |connection| async {
+                        Result::Ok((connection.get_url(), connection.version().await?))
+                    })
+                })
+            })
+            .collect();
         let mut result: HashMap<String, String> = HashMap::new();
-        for connection in &self.connections {
-            let mut connection = connection.get().await?;
-            let _ = result.insert(connection.get_url(), connection.version().await?);
+        for task in tasks {
+            let (url, version) = task.await?;
+            let _ = result.insert(url, version);
         }
         Ok(result)
     }
 
+    /// Get the versions of all servers, trying all of them even if some fail.
+    /// See [`Client::stats_each`].
+    pub async fn version_each(&self) -> MultiResult<String> {
+        self.each_server(|pool| async move {
+            with_connection!(self, "version", pool, retry, |connection| connection
+                .version())
+        })
+        .await
+    }
+
+    /// Compare the versions of all servers, `None` if they all run the same one.
+    /// A skew is reported to the observer and, with the `tracing` feature, logged as a warning.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::Client::connect(vec![
+    ///     "memcache://127.0.0.1:12345",
+    ///     "memcache://127.0.0.1:12345?protocol=ascii",
+    /// ])?;
+    /// assert_eq!(client.version_skew().await?, None);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn version_skew(&self) -> Result<Option<VersionSkew>> {
+        let skew = VersionSkew::detect(self.version().await?);
+        if let Some(skew) = &skew {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(versions = ?skew.versions, "memcached servers run different versions");
+            if let Some(observer) = &self.observer {
+                observer.on_version_skew(skew);
+            }
+        }
+        Ok(skew)
+    }
+
     /// Get a value by key
     ///
     /// ## Example
@@ -95,12 +745,113 @@ impl Client {
         &self,
         key: K,
     ) -> Result<Option<V>> {
-        check::check_key_len(key.as_ref())?;
-        self.get_connection(key.as_ref())
-            .get()
-            .await?
-            .get(key.as_ref())
-            .await
+        let value = self.get_unfiltered(key).await?;
+        Ok(value.filter(|_| !self.reads_miss()))
+    }
+
+    /// Like `get`, with the time the item is flushed at if this client sent a
+    /// `flush_with_delay` that has not taken effect yet. Such items are returned even with
+    /// [`FlushWindowReads::Miss`]. Flushes sent by other clients are not known.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.set("get_with_flush_time_test", "hello", 100).await?;
+    /// let t = client.get_with_flush_time::<String, _>("get_with_flush_time_test").await?;
+    /// assert_eq!(t, Some(("hello".to_owned(), None)));
+    /// client.flush_with_delay(10).await?;
+    /// let (_, flushed_at) = client
+    ///     .get_with_flush_time::<String, _>("get_with_flush_time_test")
+    ///     .await?
+    ///     .unwrap();
+    /// assert!(flushed_at.is_some());
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn get_with_flush_time<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+    ) -> Result<Option<(V, Option<SystemTime>)>> {
+        let value = self.get_unfiltered(key).await?;
+        Ok(value.map(|value| (value, self.flush_schedule.pending())))
+    }
+
+    /// When the last `flush_with_delay` of this client or its clones takes effect,
+    /// `None` once it did or without one.
+    #[must_use]
+    pub fn pending_flush(&self) -> Option<SystemTime> {
+        self.flush_schedule.pending()
+    }
+
+    /// Whether reads are answered with misses because a flush is pending.
+    fn reads_miss(&self) -> bool {
+        self.flush_window_reads == FlushWindowReads::Miss && self.flush_schedule.pending().is_some()
+    }
+
+    async fn get_unfiltered<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+    ) -> Result<Option<V>> {
+        self.announce(|| Operation::Get { key: key.as_ref() });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        let server = self.get_connection(key);
+        let result = if self.verify.sample() {
+            with_connection!(self, "get", server, retry, |connection| self.verified_get(
+                &mut connection,
+                server,
+                key
+            ))
+        } else {
+            with_connection!(self, "get", server, retry, |connection| connection
+                .get(key, &self.codec))
+        };
+        self.finish_read("get", server, 1, result)
+    }
+
+    /// `get` on `connection` and again on another connection of `server`, reporting any difference,
+    /// see [`ClientBuilder::verify_reads`]. Both reads share `connection` if the pool only has one.
+    async fn verified_get<V: DeserializeOwned + 'static>(
+        &self,
+        connection: &mut Connection,
+        server: &Server,
+        key: &str,
+    ) -> Result<Option<V>> {
+        let first = connection
+            .get_with_cas::<Vec<u8>, _>(key, &RawBytes)
+            .await?;
+        let second = if server.pool.state().await.max_open > 1 {
+            let mut other = server.checkout().await?;
+            other.get_with_cas::<Vec<u8>, _>(key, &RawBytes).await?
+        } else {
+            connection
+                .get_with_cas::<Vec<u8>, _>(key, &RawBytes)
+                .await?
+        };
+        let digest = |read: &Option<(Vec<u8>, u32, u64)>| {
+            read.as_ref()
+                .map(|(value, flags, cas)| ReadDigest::new(value, *flags, Some(*cas)))
+        };
+        if let Some(mismatch) =
+            ReadMismatch::compare(key, &server.url, digest(&first), digest(&second))
+        {
+            let _ = self.verify.mismatches.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                key = %mismatch.key,
+                server = %mismatch.server,
+                first = ?mismatch.first,
+                second = ?mismatch.second,
+                "memcached read mismatch"
+            );
+            if let Some(observer) = &self.observer {
+                observer.on_read_mismatch(&mismatch);
+            }
+        }
+        first
+            .map(|(value, flags, _)| self.codec.decode(&value, flags))
+            .transpose()
     }
 
     /// Set a key with associate value into memcached server with expiration seconds.
@@ -121,12 +872,91 @@ impl Client {
         value: V,
         expiration: u32,
     ) -> Result<()> {
-        check::check_key_len(key.as_ref())?;
-        self.get_connection(key.as_ref())
-            .get()
-            .await?
-            .set(key.as_ref(), value, expiration)
-            .await
+        let expiration = self.expiration(key.as_ref(), expiration);
+        self.announce(|| Operation::Set {
+            key: key.as_ref(),
+            ttl: expiration,
+            bytes: self.encoded_len(&value),
+        });
+        let key = self.wire_key(key.as_ref())?;
+        Self::finish_write(with_connection!(
+            self,
+            "set",
+            self.get_connection(key.as_ref()),
+            |connection| connection.set(key.as_ref(), value, expiration, &self.codec)
+        ))
+    }
+
+    /// Get a value by key together with the `data_type` byte it was stored with.
+    /// Values marked as [`DataType::Json`] are text and can only be decoded into string types.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client
+    ///     .set_with_data_type("data_type_test", r#"{"a":1}"#, 100, memcached::DataType::Json)
+    ///     .await?;
+    /// let t: Option<(String, _)> = client.get_with_data_type("data_type_test").await?;
+    /// assert_eq!(t, Some((r#"{"a":1}"#.to_owned(), memcached::DataType::Json)));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn get_with_data_type<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+    ) -> Result<Option<(V, DataType)>> {
+        self.announce(|| Operation::Get { key: key.as_ref() });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        let server = self.get_connection(key);
+        let result = with_connection!(self, "get_with_data_type", server, retry, |connection| {
+            connection.get_with_data_type(key, &self.codec)
+        });
+        self.finish_read("get_with_data_type", server, 1, result)
+    }
+
+    /// Set a key with associate value and `data_type` byte into memcached server with expiration seconds.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client
+    ///     .set_with_data_type("data_type_raw_test", "hello", 100, memcached::DataType::Raw)
+    ///     .await?;
+    /// let t: Option<String> = client.get("data_type_raw_test").await?;
+    /// assert_eq!(t, Some("hello".to_owned()));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn set_with_data_type<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: V,
+        expiration: u32,
+        data_type: DataType,
+    ) -> Result<()> {
+        let expiration = self.expiration(key.as_ref(), expiration);
+        self.announce(|| Operation::Set {
+            key: key.as_ref(),
+            ttl: expiration,
+            bytes: self.encoded_len(&value),
+        });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::finish_write(with_connection!(
+            self,
+            "set_with_data_type",
+            self.get_connection(key),
+            |connection| connection.set_with_data_type(
+                key,
+                value,
+                expiration,
+                data_type,
+                &self.codec
+            )
+        ))
     }
 
     /// Flush all cache on memcached server immediately.
@@ -143,12 +973,36 @@ impl Client {
     /// # Ok(()) } dbg!(foo().await.unwrap()); });
     /// ```
     pub async fn flush(&self) -> Result<()> {
-        for connection in &self.connections {
-            connection.get().await?.flush().await?;
-        }
+        let _ = self.flush_each().await?;
         Ok(())
     }
 
+    /// Flush all cache on every server immediately, trying all of them even if some fail.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::Client::connect(vec!["memcache://127.0.0.1:12345", "memcache://127.0.0.1:1"])?;
+    /// let failed = client.flush_each().await.unwrap_err();
+    /// assert_eq!(failed.successes.len(), 1);
+    /// assert_eq!(failed.failures[0].0, "memcache://127.0.0.1:1");
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn flush_each(&self) -> MultiResult<()> {
+        self.announce(|| Operation::Flush { delay: None });
+        if self.bypass("flush") {
+            return Ok(self.each_url(()));
+        }
+        let results = self
+            .each_server(|pool| async move {
+                with_connection!(self, "flush", pool, retry, |connection| connection.flush())
+            })
+            .await;
+        self.schedule_flush(&results, 0);
+        results
+    }
+
     /// Flush all cache on memcached server with a delay seconds.
     ///
     /// ## Example
@@ -166,12 +1020,59 @@ impl Client {
     /// # Ok(()) } dbg!(foo().await.unwrap()); });
     /// ```
     pub async fn flush_with_delay(&self, delay: u32) -> Result<()> {
-        for connection in &self.connections {
-            connection.get().await?.flush_with_delay(delay).await?;
-        }
+        let _ = self.flush_with_delay_each(delay).await?;
         Ok(())
     }
 
+    /// Flush all cache on every server with a delay seconds, trying all of them even if some fail.
+    /// See [`Client::flush_each`].
+    pub async fn flush_with_delay_each(&self, delay: u32) -> MultiResult<()> {
+        self.announce(|| Operation::Flush { delay: Some(delay) });
+        if self.bypass("flush_with_delay") {
+            return Ok(self.each_url(()));
+        }
+        let results = self
+            .each_server(|pool| async move {
+                with_connection!(self, "flush_with_delay", pool, retry, |connection| {
+                    connection.flush_with_delay(delay)
+                })
+            })
+            .await;
+        self.schedule_flush(&results, delay);
+        results
+    }
+
+    /// Reads of the servers that were flushed may see the flush from now on.
+    fn schedule_flush(&self, results: &MultiResult<()>, delay: u32) {
+        let flushed = match results {
+            Ok(successes) | Err(MultiError { successes, .. }) => !successes.is_empty(),
+        };
+        if flushed {
+            self.flush_schedule.schedule(delay);
+        }
+    }
+
+    /// Run `op` on every server one after the other, whatever the earlier ones returned.
+    async fn each_server<'a, T, F, Fut>(&'a self, mut op: F) -> MultiResult<T>
+    where
+        F: FnMut(&'a Server) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut results = Vec::with_capacity(self.connections.len());
+        for server in &self.connections {
+            results.push((server.url.clone(), op(server).await));
+        }
+        MultiError::collect(results)
+    }
+
+    /// `value` for every server, the result of a fan-out operation the kill switch bypassed.
+    fn each_url<T: Clone>(&self, value: T) -> Vec<(String, T)> {
+        self.connections
+            .iter()
+            .map(|server| (server.url.clone(), value.clone()))
+            .collect()
+    }
+
     /// Add a key with associate value into memcached server with expiration seconds.
     ///
     /// ## Example
@@ -193,12 +1094,19 @@ impl Client {
         value: V,
         expiration: u32,
     ) -> Result<()> {
-        check::check_key_len(key.as_ref())?;
-        self.get_connection(key.as_ref())
-            .get()
-            .await?
-            .add(key.as_ref(), value, expiration)
-            .await
+        let expiration = self.expiration(key.as_ref(), expiration);
+        self.announce(|| Operation::Add {
+            key: key.as_ref(),
+            ttl: expiration,
+            bytes: self.encoded_len(&value),
+        });
+        let key = self.wire_key(key.as_ref())?;
+        Self::finish_write(with_connection!(
+            self,
+            "add",
+            self.get_connection(key.as_ref()),
+            |connection| connection.add(key.as_ref(), value, expiration, &self.codec)
+        ))
     }
 
     /// Replace a key with associate value into memcached server with expiration seconds.
@@ -223,13 +1131,20 @@ impl Client {
         value: V,
         expiration: u32,
     ) -> Result<()> {
-        let key = key.as_ref();
-        check::check_key_len(key)?;
-        self.get_connection(key)
-            .get()
-            .await?
-            .replace(key, value, expiration)
-            .await
+        let expiration = self.expiration(key.as_ref(), expiration);
+        self.announce(|| Operation::Replace {
+            key: key.as_ref(),
+            ttl: expiration,
+            bytes: self.encoded_len(&value),
+        });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::finish_write(with_connection!(
+            self,
+            "replace",
+            self.get_connection(key),
+            |connection| { connection.replace(key, value, expiration, &self.codec) }
+        ))
     }
 
     /// Append value to the key.
@@ -250,13 +1165,18 @@ impl Client {
         key: K,
         value: V,
     ) -> Result<()> {
-        let key = key.as_ref();
-        check::check_key_len(key)?;
-        self.get_connection(key)
-            .get()
-            .await?
-            .append(key, value)
-            .await
+        self.announce(|| Operation::Append {
+            key: key.as_ref(),
+            bytes: self.encoded_len(&value),
+        });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::finish_write(with_connection!(
+            self,
+            "append",
+            self.get_connection(key),
+            |connection| { connection.append(key, value, &self.codec) }
+        ))
     }
     /// Prepend value to the key.
     ///
@@ -276,13 +1196,18 @@ impl Client {
         key: K,
         value: V,
     ) -> Result<()> {
-        let key = key.as_ref();
-        check::check_key_len(key)?;
-        self.get_connection(key)
-            .get()
-            .await?
-            .prepend(key, value)
-            .await
+        self.announce(|| Operation::Prepend {
+            key: key.as_ref(),
+            bytes: self.encoded_len(&value),
+        });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::finish_write(with_connection!(
+            self,
+            "prepend",
+            self.get_connection(key),
+            |connection| { connection.prepend(key, value, &self.codec) }
+        ))
     }
 
     /// Delete a key from memcached server.
@@ -301,9 +1226,97 @@ impl Client {
     /// # Ok(()) } dbg!(foo().await.unwrap()); });
     /// ```
     pub async fn delete<K: AsRef<str>>(&self, key: K) -> Result<bool> {
-        let key = key.as_ref();
-        check::check_key_len(key)?;
-        self.get_connection(key).get().await?.delete(key).await
+        self.announce(|| Operation::Delete { key: key.as_ref() });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::finish_write(with_connection!(
+            self,
+            "delete",
+            self.get_connection(key),
+            retry,
+            |connection| { connection.delete(key) }
+        ))
+    }
+
+    /// Delete a key only if it was not modified since its cas was read, e.g. to invalidate
+    /// a read-modify-delete flow without removing a newer value. Sent as a meta delete on the ASCII protocol.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use memcached::CasResult;
+    /// for url in ["memcache://127.0.0.1:12345", "memcache://127.0.0.1:12345?protocol=ascii"] {
+    ///     let client = memcached::connect(url)?;
+    ///     client.set("delete_cas_test", "hello", 100).await?;
+    ///     let (_, _, cas) = client.get_with_cas::<String, _>("delete_cas_test").await?.unwrap();
+    ///     client.set("delete_cas_test", "changed", 100).await?;
+    ///     assert_eq!(client.delete_cas("delete_cas_test", cas).await?, CasResult::Exists);
+    ///     let (_, _, cas) = client.get_with_cas::<String, _>("delete_cas_test").await?.unwrap();
+    ///     assert_eq!(client.delete_cas("delete_cas_test", cas).await?, CasResult::Stored);
+    ///     assert_eq!(client.delete_cas("delete_cas_test", cas).await?, CasResult::NotFound);
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn delete_cas<K: AsRef<str>>(&self, key: K, cas: u64) -> Result<CasResult> {
+        self.announce(|| Operation::Delete { key: key.as_ref() });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::when_bypassed(
+            with_connection!(
+                self,
+                "delete_cas",
+                self.get_connection(key),
+                retry,
+                |connection| connection.delete_cas(key, cas)
+            ),
+            || Ok(CasResult::NotFound),
+        )
+    }
+
+    /// Delete a key and return the flags and cas of the version removed, `None` if the key was missing,
+    /// e.g. for invalidation pipelines logging which version of an object they removed.
+    ///
+    /// The item is read and then deleted only if its cas is unchanged, so the version returned is
+    /// exactly the one deleted. Concurrent writes in between make it read again, it fails with
+    /// [`CommandError::KeyExists`] if the key keeps changing.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// for url in ["memcache://127.0.0.1:12345", "memcache://127.0.0.1:12345?protocol=ascii"] {
+    ///     let client = memcached::connect(url)?;
+    ///     client.set("delete_with_cas_test", "hello", 100).await?;
+    ///     let (_, flags, cas) = client.get_with_cas::<String, _>("delete_with_cas_test").await?.unwrap();
+    ///     assert_eq!(client.delete_with_cas("delete_with_cas_test").await?, Some((flags, cas)));
+    ///     assert_eq!(client.delete_with_cas("delete_with_cas_test").await?, None);
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn delete_with_cas<K: AsRef<str>>(&self, key: K) -> Result<Option<(u32, u64)>> {
+        self.announce(|| Operation::Delete { key: key.as_ref() });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        let server = self.get_connection(key);
+        for _ in 0..DELETE_CAS_RETRIES {
+            let read = with_connection!(self, "delete_with_cas", server, retry, |connection| {
+                connection.get_with_cas::<Vec<u8>, _>(key, &RawBytes)
+            });
+            let read = self.finish_read("delete_with_cas", server, 1, read)?;
+            let Some((_, flags, cas)) = read else {
+                return Ok(None);
+            };
+            let deleted = with_connection!(self, "delete_cas", server, retry, |connection| {
+                connection.delete_cas(key, cas)
+            })?;
+            match deleted {
+                CasResult::Stored => return Ok(Some((flags, cas))),
+                CasResult::NotFound => return Ok(None),
+                CasResult::Exists => {}
+            }
+        }
+        Err(CommandError::KeyExists.into())
     }
 
     /// Increment the value with amount.
@@ -321,13 +1334,208 @@ impl Client {
     /// # Ok(()) } dbg!(foo().await.unwrap()); });
     /// ```
     pub async fn increment<K: AsRef<str>>(&self, key: K, amount: u64) -> Result<u64> {
-        let key = key.as_ref();
-        check::check_key_len(key)?;
-        self.get_connection(key)
-            .get()
-            .await?
-            .increment(key, amount)
+        self.announce(|| Operation::Increment {
+            key: key.as_ref(),
+            amount,
+        });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::when_bypassed(
+            with_connection!(
+                self,
+                "increment",
+                self.get_connection(key),
+                retry,
+                |connection| { connection.increment(key, amount) }
+            ),
+            || Err(CommandError::KeyNotFound.into()),
+        )
+    }
+
+    /// Increment the value with amount, a missing key is stored as `initial + amount` as if it
+    /// held `initial` before, where [`Client::increment_with`] stores `initial`.
+    /// The binary protocol creates the key in the same request, the ASCII protocol creates it
+    /// with `add` so a concurrent initialization or an existing value is never overwritten.
+    /// A value that is not a number fails with [`CommandError::IncrOrDecrOnNonNumericValue`],
+    /// see [`Client::increment_or_reset`] to overwrite it.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use memcached::error::{CommandError, MemcachedError};
+    /// for url in ["memcache://127.0.0.1:12345", "memcache://127.0.0.1:12345?protocol=ascii"] {
+    ///     let client = memcached::connect(url)?;
+    ///     client.delete("increment_or_init_test").await?;
+    ///     assert_eq!(110, client.increment_or_init("increment_or_init_test", 10, 100, 100).await?);
+    ///     assert_eq!(120, client.increment_or_init("increment_or_init_test", 10, 100, 100).await?);
+    ///     client.set("increment_or_init_test", "ten", 100).await?;
+    ///     assert!(matches!(
+    ///         client.increment_or_init("increment_or_init_test", 10, 100, 100).await,
+    ///         Err(MemcachedError::CommandError(CommandError::IncrOrDecrOnNonNumericValue))
+    ///     ));
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn increment_or_init<K: AsRef<str>>(
+        &self,
+        key: K,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.announce(|| Operation::Increment {
+            key: key.as_ref(),
+            amount,
+        });
+        let expiration = self.expiration(key.as_ref(), expiration);
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::when_bypassed(
+            with_connection!(
+                self,
+                "increment_or_init",
+                self.get_connection(key),
+                retry,
+                |connection| connection.increment_with(
+                    key,
+                    amount,
+                    initial.saturating_add(amount),
+                    expiration
+                )
+            ),
+            || Self::counter_created(initial.saturating_add(amount), expiration),
+        )
+    }
+
+    /// Like `increment_or_init`, but a value that is not a number is overwritten with
+    /// `initial + amount`, e.g. for counters whose keys were once written with other values.
+    /// The overwrite is a plain `set`, an increment racing with it is lost.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// for url in ["memcache://127.0.0.1:12345", "memcache://127.0.0.1:12345?protocol=ascii"] {
+    ///     let client = memcached::connect(url)?;
+    ///     client.set("increment_or_reset_test", "ten", 100).await?;
+    ///     assert_eq!(110, client.increment_or_reset("increment_or_reset_test", 10, 100, 100).await?);
+    ///     assert_eq!(120, client.increment_or_reset("increment_or_reset_test", 10, 100, 100).await?);
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn increment_or_reset<K: AsRef<str>>(
+        &self,
+        key: K,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        match self
+            .increment_or_init(key.as_ref(), amount, initial, expiration)
             .await
+        {
+            Err(MemcachedError::CommandError(CommandError::IncrOrDecrOnNonNumericValue)) => {}
+            result => return result,
+        }
+        let value = initial.saturating_add(amount);
+        // Counters are numbers in ASCII whatever the codec of the client is.
+        self.clone()
+            .with_codec(Utf8String)
+            .set(key, value.to_string(), expiration)
+            .await?;
+        Ok(value)
+    }
+
+    /// Increment the value with amount, a missing key is stored as `initial` (without adding `amount`)
+    /// and expires after `expiration`, atomically on the binary protocol.
+    /// With [`DONT_CREATE`](crate::DONT_CREATE) as expiration a missing key fails with `KeyNotFound` instead.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.delete("increment_with_test").await?;
+    /// // A rate limiter allowing 10 requests per minute.
+    /// assert_eq!(1, client.increment_with("increment_with_test", 1, 1, 60).await?);
+    /// assert_eq!(2, client.increment_with("increment_with_test", 1, 1, 60).await?);
+    /// client.delete("increment_with_test").await?;
+    /// assert!(client.increment_with("increment_with_test", 1, 1, memcached::DONT_CREATE).await.is_err());
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn increment_with<K: AsRef<str>>(
+        &self,
+        key: K,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.announce(|| Operation::Increment {
+            key: key.as_ref(),
+            amount,
+        });
+        let expiration = self.expiration(key.as_ref(), expiration);
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::when_bypassed(
+            with_connection!(
+                self,
+                "increment",
+                self.get_connection(key),
+                retry,
+                |connection| { connection.increment_with(key, amount, initial, expiration) }
+            ),
+            || Self::counter_created(initial, expiration),
+        )
+    }
+
+    /// A counter bypassed by the kill switch is missing: created with `initial`, unless it must not be.
+    fn counter_created(initial: u64, expiration: u32) -> Result<u64> {
+        if expiration == DONT_CREATE {
+            Err(CommandError::KeyNotFound.into())
+        } else {
+            Ok(initial)
+        }
+    }
+
+    /// Decrement the value with amount, a missing key is stored as `initial` (without subtracting `amount`)
+    /// and expires after `expiration`. See [`Client::increment_with`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345?protocol=ascii")?;
+    /// client.delete("decrement_with_test").await?;
+    /// assert_eq!(10, client.decrement_with("decrement_with_test", 1, 10, 60).await?);
+    /// assert_eq!(9, client.decrement_with("decrement_with_test", 1, 10, 60).await?);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn decrement_with<K: AsRef<str>>(
+        &self,
+        key: K,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.announce(|| Operation::Decrement {
+            key: key.as_ref(),
+            amount,
+        });
+        let expiration = self.expiration(key.as_ref(), expiration);
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::when_bypassed(
+            with_connection!(
+                self,
+                "decrement",
+                self.get_connection(key),
+                retry,
+                |connection| { connection.decrement_with(key, amount, initial, expiration) }
+            ),
+            || Self::counter_created(initial, expiration),
+        )
     }
 
     /// Decrement the value with amount.
@@ -345,13 +1553,22 @@ impl Client {
     /// # Ok(()) } dbg!(foo().await.unwrap()); });
     /// ```
     pub async fn decrement<K: AsRef<str>>(&self, key: K, amount: u64) -> Result<u64> {
-        let key = key.as_ref();
-        check::check_key_len(key)?;
-        self.get_connection(key)
-            .get()
-            .await?
-            .decrement(key, amount)
-            .await
+        self.announce(|| Operation::Decrement {
+            key: key.as_ref(),
+            amount,
+        });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::when_bypassed(
+            with_connection!(
+                self,
+                "decrement",
+                self.get_connection(key),
+                retry,
+                |connection| { connection.decrement(key, amount) }
+            ),
+            || Err(CommandError::KeyNotFound.into()),
+        )
     }
 
     /// Set a new expiration time for a exist key.
@@ -372,13 +1589,20 @@ impl Client {
     /// # Ok(()) } dbg!(foo().await.unwrap()); });
     /// ```
     pub async fn touch<K: AsRef<str>>(&self, key: K, expiration: u32) -> Result<bool> {
-        let key = key.as_ref();
-        check::check_key_len(key)?;
-        self.get_connection(key)
-            .get()
-            .await?
-            .touch(key, expiration)
-            .await
+        let expiration = self.expiration(key.as_ref(), expiration);
+        self.announce(|| Operation::Touch {
+            key: key.as_ref(),
+            ttl: expiration,
+        });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::finish_write(with_connection!(
+            self,
+            "touch",
+            self.get_connection(key),
+            retry,
+            |connection| { connection.touch(key, expiration) }
+        ))
     }
 
     /// Get all servers' statistics.
@@ -392,15 +1616,297 @@ impl Client {
     /// # Ok(()) } dbg!(foo().await.unwrap()); });
     /// ```
     pub async fn stats(&self) -> Result<Vec<(String, HashMap<String, String>)>> {
-        let mut result: Vec<(String, HashMap<String, String>)> = vec![];
-        for connection in &self.connections {
-            let mut connection = connection.get().await?;
-            let stats_info = connection.stats().await?;
-            let url = connection.url.to_string();
-            result.push((url, stats_info));
+        Ok(self.stats_each().await?)
+    }
+
+    /// Get all servers' statistics, trying all of them even if some fail.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::Client::connect(vec!["memcache://127.0.0.1:12345", "memcache://127.0.0.1:1"])?;
+    /// let failed = client.stats_each().await.unwrap_err();
+    /// assert_eq!(failed.successes[0].0, "memcache://127.0.0.1:12345");
+    /// assert_eq!(failed.failures[0].0, "memcache://127.0.0.1:1");
+    /// println!("{failed}");
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn stats_each(&self) -> MultiResult<HashMap<String, String>> {
+        self.each_server(|pool| async move {
+            with_connection!(self, "stats", pool, retry, |connection| connection.stats())
+        })
+        .await
+    }
+
+    /// Get all servers' statistics of one group, e.g. `items`, `slabs` or `settings`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// for (url, settings) in client.stats_group("settings").await? {
+    ///     println!("{url}: item_size_max = {}", settings["item_size_max"]);
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn stats_group(&self, group: &str) -> Result<Vec<(String, HashMap<String, String>)>> {
+        Ok(self.stats_group_each(group).await?)
+    }
+
+    /// Get all servers' statistics of one group, trying all of them even if some fail.
+    /// See [`Client::stats_each`].
+    pub async fn stats_group_each(&self, group: &str) -> MultiResult<HashMap<String, String>> {
+        self.each_server(|pool| async move {
+            with_connection!(self, "stats", pool, retry, |connection| connection
+                .stats_group(group))
+        })
+        .await
+    }
+
+    /// Get all servers' extstore (flash storage) statistics, parsed into [`ExtstoreStats`].
+    /// `stats extstore` adds to the general statistics, servers without extstore report `enabled: false`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345?protocol=ascii")?;
+    /// for (url, stats) in client.extstore_stats().await? {
+    ///     if stats.enabled {
+    ///         println!(
+    ///             "{url}: {} RAM misses, {} flash misses, {:?} of hits from flash",
+    ///             stats.ram_misses(),
+    ///             stats.flash_misses(),
+    ///             stats.flash_hit_ratio(),
+    ///         );
+    ///     }
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn extstore_stats(&self) -> Result<Vec<(String, ExtstoreStats)>> {
+        let mut result = vec![];
+        for pool in &self.connections {
+            result.push(with_connection!(
+                self,
+                "stats",
+                pool,
+                retry,
+                |connection| async {
+                    let mut stats = connection.stats().await?;
+                    // Servers without extstore reject the group, the general statistics still tell.
+                    match connection.stats_group("extstore").await {
+                        Ok(extstore) => stats.extend(extstore),
+                        Err(MemcachedError::CommandError(_) | MemcachedError::ServerError(_)) => {}
+                        Err(e) => return Err(e),
+                    }
+                    Result::Ok((connection.get_url(), ExtstoreStats::from_map(&stats)))
+                }
+            )?);
+        }
+        Ok(result)
+    }
+
+    /// Count the items of every server whose keys start with `prefix`, with their bytes and
+    /// remaining TTLs, by scanning all items with `lru_crawler metadump` (ASCII protocol only).
+    /// Memcached only reports totals per slab class, this tells which namespace occupies the cache.
+    /// The scan reads the metadata of every item, run it rarely on large caches.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345?protocol=ascii")?;
+    /// client.set("items_matching_test:1", "hello", 100).await?;
+    /// client.set("items_matching_test:2", "world", 0).await?;
+    /// for (url, stats) in client.stats_items_matching("items_matching_test:").await? {
+    ///     assert_eq!((stats.items, stats.expiring), (2, 1));
+    ///     assert!(stats.avg_ttl().unwrap().as_secs() <= 100);
+    ///     println!("{url}: {} bytes", stats.bytes);
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn stats_items_matching(&self, prefix: &str) -> Result<Vec<(String, PrefixStats)>> {
+        let scan = self
+            .stats_items_matching_with_stop(prefix, &StopSignal::new(), None)
+            .await?;
+        Ok(scan.scanned)
+    }
+
+    /// Like `stats_items_matching`, but checks `stop` between two items of the scan. A server
+    /// stopped halfway closes its connection and is left pending in the returned [`PrefixScan`],
+    /// pass it as `resume` to scan the pending servers only.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345?protocol=ascii")?;
+    /// client.set("items_matching_stop_test:1", "hello", 100).await?;
+    /// let stop = memcached::StopSignal::new();
+    /// stop.stop();
+    /// let scan = client
+    ///     .stats_items_matching_with_stop("items_matching_stop_test:", &stop, None)
+    ///     .await?;
+    /// assert!(!scan.is_complete());
+    /// let scan = client
+    ///     .stats_items_matching_with_stop("items_matching_stop_test:", &memcached::StopSignal::new(), Some(scan))
+    ///     .await?;
+    /// assert!(scan.is_complete());
+    /// assert_eq!(scan.scanned[0].1.items, 1);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn stats_items_matching_with_stop(
+        &self,
+        prefix: &str,
+        stop: &StopSignal,
+        resume: Option<PrefixScan>,
+    ) -> Result<PrefixScan> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut scan = resume.unwrap_or_else(|| PrefixScan {
+            scanned: vec![],
+            pending: self
+                .connections
+                .iter()
+                .map(|pool| pool.url.clone())
+                .collect(),
+        });
+        let pending = std::mem::take(&mut scan.pending);
+        for pool in self
+            .connections
+            .iter()
+            .filter(|pool| pending.contains(&pool.url))
+        {
+            let mut stats = PrefixStats::default();
+            let mut stopped = stop.is_stopped();
+            if !stopped {
+                let mut visit = |item: ItemMetadata| {
+                    if stop.is_stopped() {
+                        stopped = true;
+                        return ControlFlow::Break(());
+                    }
+                    if self.expand_key(&item.key).starts_with(prefix) {
+                        stats.add(&item, now);
+                    }
+                    ControlFlow::Continue(())
+                };
+                with_connection!(self, "stats", pool, |connection| connection
+                    .metadump(&mut visit))?;
+            }
+            if stopped {
+                scan.pending.push(pool.url.clone());
+            } else {
+                scan.scanned.push((pool.url.clone(), stats));
+            }
+        }
+        Ok(scan)
+    }
+
+    /// Get all servers' general statistics, parsed into [`ServerStats`].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let _: Option<String> = client.get("server_stats_test").await?;
+    /// for (url, stats) in client.server_stats().await? {
+    ///     assert!(stats.get_misses > 0);
+    ///     println!("{url}: {} items, hit ratio {:?}", stats.curr_items, stats.hit_ratio());
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn server_stats(&self) -> Result<Vec<(String, ServerStats)>> {
+        Ok(self
+            .stats()
+            .await?
+            .into_iter()
+            .map(|(url, stats)| (url, ServerStats::from_map(&stats)))
+            .collect())
+    }
+
+    /// Get all servers' settings, parsed from `stats settings` into [`ServerSettings`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// for (url, settings) in client.server_settings().await? {
+    ///     assert!(settings.maxbytes > 0);
+    ///     println!("{url}: {} threads, evictions {}", settings.num_threads, settings.evictions);
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn server_settings(&self) -> Result<Vec<(String, ServerSettings)>> {
+        Ok(self
+            .stats_group("settings")
+            .await?
+            .into_iter()
+            .map(|(url, settings)| (url, ServerSettings::from_map(&settings)))
+            .collect())
+    }
+
+    /// Compare `maxbytes`, `item_size_max`, `num_threads` and `evictions` across all servers,
+    /// `None` if they all agree. A drift is reported to the observer and, with the `tracing` feature,
+    /// logged as a warning.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::Client::connect(vec![
+    ///     "memcache://127.0.0.1:12345",
+    ///     "memcache://127.0.0.1:12345?protocol=ascii",
+    /// ])?;
+    /// if let Some(drift) = client.detect_drift().await? {
+    ///     for (setting, values) in &drift.settings {
+    ///         println!("{setting} differs: {values:?}");
+    ///     }
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn detect_drift(&self) -> Result<Option<SettingsDrift>> {
+        let drift = SettingsDrift::detect(&self.server_settings().await?);
+        if let Some(drift) = &drift {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(settings = ?drift.settings, "memcached servers are configured differently");
+            if let Some(observer) = &self.observer {
+                observer.on_settings_drift(drift);
+            }
+        }
+        Ok(drift)
+    }
+
+    /// Get the capabilities of every server, probed once on its first connection. A server that
+    /// answered the probe with errors has the default capabilities until a later connection probes it.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// for (url, capabilities) in client.server_capabilities().await? {
+    ///     assert!(!capabilities.version.is_empty());
+    ///     assert!(capabilities.item_size_max.is_some());
+    ///     if capabilities.gat {
+    ///         println!("{url} supports get and touch");
+    ///     }
+    /// }
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn server_capabilities(&self) -> Result<Vec<(String, ServerCapabilities)>> {
+        let mut result = vec![];
+        for server in &self.connections {
+            let connection = server.checkout().await?;
+            result.push((connection.get_url(), connection.capabilities.clone()));
         }
         Ok(result)
     }
+
     /// Get multiple keys from memcached server. Using this function instead of calling `get` multiple times can reduce netwark workloads.
     ///
     /// ## Example
@@ -419,76 +1925,1427 @@ impl Client {
         &self,
         keys: &[K],
     ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
-        for key in keys.iter().map(AsRef::as_ref) {
-            check::check_key_len(key)?;
+        self.announce(|| Operation::GetMany {
+            keys: keys.iter().map(AsRef::as_ref).collect(),
+        });
+        let keys = self.wire_keys(keys)?;
+        let mut result = HashMap::new();
+        for (connection_index, keys) in self.group_by_connection(&keys) {
+            if let Some(server) = self.connections.get(connection_index) {
+                result.extend(self.batched_gets(server, &keys).await?);
+            }
         }
-        let mut con_keys: HashMap<usize, Vec<&str>> = HashMap::new();
+        if self.reads_miss() {
+            result.clear();
+        }
+        Ok(self.expand_keys(result))
+    }
+
+    /// Like `gets`, but also returns the requested keys that were not found.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.set("gets_with_misses_test", "100", 100).await?;
+    /// let t = client
+    ///     .gets_with_misses::<String, _>(&["gets_with_misses_test", "gets_with_misses_none"])
+    ///     .await?;
+    /// assert_eq!(t.found["gets_with_misses_test"].0, "100");
+    /// assert_eq!(t.missed, vec!["gets_with_misses_none".to_owned()]);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn gets_with_misses<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        keys: &[K],
+    ) -> Result<MultiGet<V>> {
+        Ok(MultiGet::new(self.gets(keys).await?, keys))
+    }
+
+    /// `gets` of the keys of one server, split into batches of the adaptive size if there is one.
+    async fn batched_gets<V: DeserializeOwned + 'static>(
+        &self,
+        server: &Server,
+        keys: &[&str],
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
+        let Some(batch) = &server.batch else {
+            return self.shard_gets(server, keys).await;
+        };
         let mut result = HashMap::new();
-        let connections_count = self.connections.len() as u64;
+        let mut rest = keys;
+        while !rest.is_empty() {
+            let (keys, tail) = rest.split_at(batch.size().min(rest.len()));
+            result.extend(self.shard_gets(server, keys).await?);
+            rest = tail;
+        }
+        Ok(result)
+    }
 
-        for key in keys.iter().map(AsRef::as_ref) {
-            let connection_index = ((self.hash_function)(key) % connections_count) as usize;
-            let array = con_keys.entry(connection_index).or_insert_with(Vec::new);
-            array.push(key);
+    /// `gets` of the keys of one server. A batch that fails halfway is sent again on a fresh
+    /// connection, up to [`ClientBuilder::multi_get_retries`] times, the other servers are not asked again.
+    async fn shard_gets<V: DeserializeOwned + 'static>(
+        &self,
+        server: &Server,
+        keys: &[&str],
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
+        let mut retries = 0;
+        loop {
+            let result = with_connection!(self, "gets", server, retry, |connection| async {
+                let started = Instant::now();
+                let found = connection.gets(keys, &self.codec).await;
+                self.observe_multi_get("gets", keys.len(), &mut connection);
+                if let Some(batch) = &server.batch {
+                    batch.observe(keys.len(), started.elapsed(), connection.bytes_read());
+                }
+                found
+            });
+            match result {
+                Err(e) if e.breaks_connection() && retries < self.multi_get_retries => retries += 1,
+                result => return self.finish_read("gets", server, keys.len(), result),
+            }
         }
-        for (&connection_index, keys) in &con_keys {
-            if let Some(connection) = self.connections.get(connection_index) {
-                result.extend(connection.get().await?.gets(keys).await?);
+    }
+
+    /// Like `gets`, but checks `stop` before sending every batch of keys.
+    /// Returns the values fetched so far and the keys left unrequested because of the stop.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.set("gets_with_stop_test", "100", 100).await?;
+    /// let stop = memcached::StopSignal::new();
+    /// let (found, pending) = client
+    ///     .gets_with_stop::<String, _>(&["gets_with_stop_test"], &stop)
+    ///     .await?;
+    /// assert_eq!(found.len(), 1);
+    /// assert!(pending.is_empty());
+    /// stop.stop();
+    /// let (found, pending) = client
+    ///     .gets_with_stop::<String, _>(&["gets_with_stop_test"], &stop)
+    ///     .await?;
+    /// assert!(found.is_empty());
+    /// assert_eq!(pending, vec!["gets_with_stop_test".to_owned()]);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn gets_with_stop<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        keys: &[K],
+        stop: &StopSignal,
+    ) -> Result<(HashMap<String, (V, u32, Option<u64>)>, Vec<String>)> {
+        self.announce(|| Operation::GetMany {
+            keys: keys.iter().map(AsRef::as_ref).collect(),
+        });
+        let keys = self.wire_keys(keys)?;
+        let mut result = HashMap::new();
+        let mut pending = vec![];
+        for (connection_index, keys) in self.group_by_connection(&keys) {
+            if let Some(server) = self.connections.get(connection_index) {
+                for batch in keys.chunks(STOP_CHECK_BATCH_SIZE) {
+                    if stop.is_stopped() {
+                        pending.extend(batch.iter().map(|key| self.expand_key(key)));
+                    } else {
+                        result.extend(self.batched_gets(server, batch).await?);
+                    }
+                }
             }
         }
-        Ok(result)
+        Ok((self.expand_keys(result), pending))
     }
 
-    /// Compare and swap a key with the associate value into memcached server with expiration seconds.
-    /// `cas_id` should be obtained from a previous `gets` call.
+    /// Like `gets`, but the values are decoded on the blocking thread pool instead of the
+    /// executor thread. Worth it for multi-gets of many or large values, which otherwise keep
+    /// latency sensitive tasks of the same runtime waiting, see also [`ClientBuilder::parse_yield_every`].
     ///
     /// ## Example
     ///
     /// ```rust
     /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
     /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
-    /// client.set("cas_test1", "100", 100).await?;
-    /// let t = client
-    ///     .gets::<String, _>(&["cas_test1"])
-    ///     .await
-    ///     ?;
-    /// let k = t.get("cas_test1").unwrap();
-    /// assert_eq!(&k.0, "100");
-    /// let t = client
-    ///     .cas("cas_test1", "200", 100, k.2.unwrap() - 1)
-    ///     .await
-    ///     ?;
-    /// let t = client.get::<String, _>("cas_test1").await?;
-    /// assert_eq!(t.unwrap(), "100".to_owned());
-    /// let t = client
-    ///     .cas("cas_test1", "300", 100, k.2.unwrap())
-    ///     .await
-    ///     ?;
-    /// let t = client.get::<String, _>("cas_test1").await?;
-    /// assert_eq!(t.unwrap(), "300".to_owned());;
+    /// let keys: Vec<String> = (0..100).map(|i| format!("gets_offloaded_test{i}")).collect();
+    /// for key in &keys {
+    ///     client.set(key, vec![7_u64; 64], 100).await?;
+    /// }
+    /// let values = client.gets_offloaded::<Vec<u64>, _>(&keys).await?;
+    /// assert_eq!(values.len(), 100);
+    /// assert_eq!(values["gets_offloaded_test42"].0, vec![7_u64; 64]);
     /// # Ok(()) } dbg!(foo().await.unwrap()); });
     /// ```
-    pub async fn cas<V: Serialize + 'static, K: AsRef<str>>(
+    pub async fn gets_offloaded<V: DeserializeOwned + Send + 'static, K: AsRef<str>>(
         &self,
-        key: K,
-        value: V,
-        expiration: u32,
-        cas_id: u64,
-    ) -> Result<bool> {
-        let key = key.as_ref();
-        check::check_key_len(key)?;
-        self.get_connection(key)
-            .get()
-            .await?
-            .cas(key, value, expiration, cas_id)
-            .await
+        keys: &[K],
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
+        let raw: HashMap<String, (Vec<u8>, u32, Option<u64>)> =
+            self.clone().with_codec(RawBytes).gets(keys).await?;
+        let codec = self.codec.clone();
+        async_std::task::spawn_blocking(move || {
+            raw.into_iter()
+                .map(|(key, (bytes, flags, cas))| {
+                    Ok((key, (codec.decode(&bytes, flags)?, flags, cas)))
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Set multiple keys with one round trip per server, using quiet `SetQ` requests terminated by a `Noop`.
+    /// Returns the keys that were not stored with the reason, a value larger than the server's
+    /// `item_size_max` is not sent and fails its key with [`CommandError::ValueTooLarge`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let failed = client
+    ///     .sets(&[("sets_test1", "100", 100), ("sets_test2", "200", 100)])
+    ///     .await?;
+    /// assert!(failed.is_empty());
+    /// let t: Option<String> = client.get("sets_test2").await?;
+    /// assert_eq!(t, Some("200".to_owned()));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn sets<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        items: &[(K, V, u32)],
+    ) -> Result<HashMap<String, CommandError>> {
+        let (failures, _) = self.sets_until(items, None).await?;
+        Ok(failures)
+    }
+
+    /// Like `sets`, but checks `stop` before sending every batch of items.
+    /// Returns the keys that were not stored and the keys left unwritten because of the stop,
+    /// to pass the items of those again to resume.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let stop = memcached::StopSignal::new();
+    /// stop.stop();
+    /// let (failed, pending) = client
+    ///     .sets_with_stop(&[("sets_with_stop_test", "100", 100)], &stop)
+    ///     .await?;
+    /// assert!(failed.is_empty());
+    /// assert_eq!(pending, vec!["sets_with_stop_test".to_owned()]);
+    /// let t: Option<String> = client.get("sets_with_stop_test").await?;
+    /// assert_eq!(t, None);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn sets_with_stop<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        items: &[(K, V, u32)],
+        stop: &StopSignal,
+    ) -> Result<(HashMap<String, CommandError>, Vec<String>)> {
+        self.sets_until(items, Some(stop)).await
+    }
+
+    /// `sets`, in batches of `STOP_CHECK_BATCH_SIZE` items checking `stop` if there is one.
+    async fn sets_until<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        items: &[(K, V, u32)],
+        stop: Option<&StopSignal>,
+    ) -> Result<(HashMap<String, CommandError>, Vec<String>)> {
+        self.announce(|| Operation::SetMany {
+            keys: items.iter().map(|(key, _, _)| key.as_ref()).collect(),
+            bytes: items
+                .iter()
+                .map(|(_, value, _)| self.encoded_len(value))
+                .collect(),
+        });
+        let keys = items
+            .iter()
+            .map(|(key, _, _)| self.wire_key(key.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        let mut con_items: HashMap<usize, Vec<(&str, &V, u32)>> = HashMap::new();
+        for (key, (user_key, value, expiration)) in keys.iter().zip(items) {
+            let key = key.as_ref();
+            let connection_index = self.server_index(key);
+            con_items.entry(connection_index).or_default().push((
+                key,
+                value,
+                self.expiration(user_key.as_ref(), *expiration),
+            ));
+        }
+        let batch_size = stop.map_or(usize::MAX, |_| STOP_CHECK_BATCH_SIZE);
+        let mut failures = HashMap::new();
+        let mut pending = vec![];
+        for (connection_index, items) in con_items {
+            if let Some(pool) = self.connections.get(connection_index) {
+                for batch in items.chunks(batch_size) {
+                    if stop.is_some_and(StopSignal::is_stopped) {
+                        pending.extend(batch.iter().map(|(key, _, _)| self.expand_key(key)));
+                        continue;
+                    }
+                    failures.extend(Self::finish_write(with_connection!(
+                        self,
+                        "sets",
+                        pool,
+                        retry,
+                        |connection| { connection.sets(batch, &self.codec) }
+                    ))?);
+                }
+            }
+        }
+        Ok((self.expand_keys(failures), pending))
+    }
+
+    /// Set `key` to `value`, encoded once per `content_id` while it is in the memo of
+    /// [`ClientBuilder::serialization_memo`]. Without a memo the value is encoded on every call.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::SerializationMemo;
+    /// let memo = SerializationMemo::new(16);
+    /// let client = memcached::Client::builder("memcache://127.0.0.1:12345")
+    ///     .serialization_memo(memo.clone())
+    ///     .build()?;
+    /// let payload = vec![("dark_mode".to_owned(), true), ("beta".to_owned(), false)];
+    /// for user in 0..3 {
+    ///     let key = format!("set_memoized_test:{user}");
+    ///     client.set_memoized(&key, &payload, 100, "flags:v1").await?;
+    /// }
+    /// assert_eq!((memo.hits(), memo.misses()), (2, 1));
+    /// let t: Option<Vec<(String, bool)>> = client.get("set_memoized_test:2").await?;
+    /// assert_eq!(t, Some(payload));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn set_memoized<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: &V,
+        expiration: u32,
+        content_id: &str,
+    ) -> Result<()> {
+        let encoded = self.memoized(value, content_id)?;
+        self.clone()
+            .with_codec(encoded)
+            .set(key, (), expiration)
+            .await
+    }
+
+    /// Like [`Client::sets`], writing the same `value` to every key of `keys`, encoded once.
+    /// The encoding is reused across calls by `content_id` like in [`Client::set_memoized`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let keys: Vec<String> = (0..100).map(|user| format!("sets_memoized_test:{user}")).collect();
+    /// let failed = client.sets_memoized(&keys, &"payload", 100, "payload:v1").await?;
+    /// assert!(failed.is_empty());
+    /// let t: Option<String> = client.get("sets_memoized_test:99").await?;
+    /// assert_eq!(t, Some("payload".to_owned()));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn sets_memoized<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        keys: &[K],
+        value: &V,
+        expiration: u32,
+        content_id: &str,
+    ) -> Result<HashMap<String, CommandError>> {
+        let encoded = self.memoized(value, content_id)?;
+        let items: Vec<(&str, (), u32)> = keys
+            .iter()
+            .map(|key| (key.as_ref(), (), expiration))
+            .collect();
+        self.clone().with_codec(encoded).sets(&items).await
+    }
+
+    /// `value` encoded by the codec of the client, through the memo if there is one.
+    fn memoized<V: Serialize + 'static>(&self, value: &V, content_id: &str) -> Result<Encoded> {
+        match &self.memo {
+            Some(memo) => memo.encode(&self.codec, value, content_id),
+            None => Encoded::new(&self.codec, value),
+        }
+    }
+
+    /// Set a value too large for one item, split into chunks of 512 KiB. `key` holds a manifest
+    /// with the chunk count, the length and a CRC-32 of the encoded value, the chunks are stored
+    /// under keys derived from `key` and an id new on every write. Read it with [`Client::get_chunked`].
+    /// The chunks of the previous value of `key` are left to expire.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let big = "a".repeat(3 << 20);
+    /// client.set_chunked("set_chunked_test", big.clone(), 100).await?;
+    /// let t: Option<String> = client.get_chunked("set_chunked_test").await?;
+    /// assert_eq!(t, Some(big));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn set_chunked<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: V,
+        expiration: u32,
+    ) -> Result<()> {
+        let key = key.as_ref();
+        let (bytes, flags) = self.codec.encode(&value)?;
+        let manifest = Manifest::new(&bytes, flags);
+        let keys = manifest.chunk_keys(key);
+        let items: Vec<(&str, Vec<u8>, u32)> = keys
+            .iter()
+            .zip(manifest.chunks(&bytes))
+            .map(|(key, chunk)| (key.as_str(), chunk, expiration))
+            .collect();
+        let raw = self.clone().with_codec(RawBytes);
+        // The manifest is written last, a reader never finds it before all of its chunks.
+        if let Some((_, e)) = raw.sets(&items).await?.into_iter().next() {
+            let _ = raw.deletes(&keys).await;
+            return Err(e.into());
+        }
+        raw.set(key, bincode::serialize(&manifest)?, expiration)
+            .await
+    }
+
+    /// Get a value of [`Client::set_chunked`]. Every chunk of the manifest must be found, be of
+    /// the same write and add up to the length and CRC-32 of the manifest. Otherwise the chunks
+    /// found and the manifest, unless it changed meanwhile, are deleted and the read fails with
+    /// [`ClientError::PartialValue`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// use memcached::error::{ClientError, MemcachedError};
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.delete("get_chunked_test").await?;
+    /// assert_eq!(client.get_chunked::<String, _>("get_chunked_test").await?, None);
+    /// client.set_chunked("get_chunked_test", "a".repeat(1 << 20), 100).await?;
+    /// // Another value overwrote a chunk.
+    /// let chunks = client.chunk_keys("get_chunked_test").await?;
+    /// client.set(&chunks[1], "b", 100).await?;
+    /// assert!(matches!(
+    ///     client.get_chunked::<String, _>("get_chunked_test").await,
+    ///     Err(MemcachedError::ClientError(ClientError::PartialValue { missing: 1, chunks: 2, .. }))
+    /// ));
+    /// // The broken value was cleaned up.
+    /// assert_eq!(client.get_chunked::<String, _>("get_chunked_test").await?, None);
+    /// assert_eq!(client.get::<String, _>(&chunks[0]).await?, None);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn get_chunked<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+    ) -> Result<Option<V>> {
+        let key = key.as_ref();
+        let raw = self.clone().with_codec(RawBytes);
+        let Some((manifest, cas)) = self.manifest(key).await? else {
+            return Ok(None);
+        };
+        let keys = manifest.chunk_keys(key);
+        let found = raw
+            .gets::<Vec<u8>, _>(&keys)
+            .await?
+            .into_iter()
+            .map(|(key, (chunk, _, _))| (key, chunk))
+            .collect();
+        match manifest.assemble(&keys, found) {
+            Ok(bytes) => self.codec.decode(&bytes, manifest.flags).map(Some),
+            Err(missing) => {
+                let _ = raw.deletes(&keys).await;
+                let _ = raw.delete_cas(key, cas).await;
+                Err(ClientError::PartialValue {
+                    key: key.to_owned(),
+                    chunks: manifest.chunks,
+                    missing,
+                }
+                .into())
+            }
+        }
+    }
+
+    /// The keys of the chunks of a value of [`Client::set_chunked`] in order, empty if `key` is missing.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.set_chunked("chunk_keys_test", "a".repeat(1 << 20), 100).await?;
+    /// assert_eq!(client.chunk_keys("chunk_keys_test").await?.len(), 2);
+    /// client.delete("chunk_keys_test").await?;
+    /// assert!(client.chunk_keys("chunk_keys_test").await?.is_empty());
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn chunk_keys<K: AsRef<str>>(&self, key: K) -> Result<Vec<String>> {
+        let key = key.as_ref();
+        Ok(self
+            .manifest(key)
+            .await?
+            .map(|(manifest, _)| manifest.chunk_keys(key))
+            .unwrap_or_default())
+    }
+
+    /// The manifest of a value of [`Client::set_chunked`] and its cas.
+    async fn manifest(&self, key: &str) -> Result<Option<(Manifest, u64)>> {
+        let raw = self.clone().with_codec(RawBytes);
+        match raw.get_with_cas::<Vec<u8>, _>(key).await? {
+            Some((manifest, _, cas)) => Ok(Some((bincode::deserialize(&manifest)?, cas))),
+            None => Ok(None),
+        }
+    }
+
+    /// Delete multiple keys with one round trip per server, using quiet `DeleteQ` requests terminated by a `Noop`.
+    /// Returns the keys that were not deleted, missing keys fail with [`CommandError::KeyNotFound`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use memcached::error::CommandError;
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.set("deletes_test1", "100", 100).await?;
+    /// let failed = client.deletes(&["deletes_test1", "deletes_test_none"]).await?;
+    /// assert_eq!(failed.len(), 1);
+    /// assert_eq!(failed["deletes_test_none"], CommandError::KeyNotFound);
+    /// let t: Option<String> = client.get("deletes_test1").await?;
+    /// assert_eq!(t, None);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn deletes<K: AsRef<str>>(
+        &self,
+        keys: &[K],
+    ) -> Result<HashMap<String, CommandError>> {
+        let (failures, _) = self.deletes_until(keys, None).await?;
+        Ok(failures)
+    }
+
+    /// Like `deletes`, but checks `stop` before sending every batch of keys.
+    /// Returns the keys that were not deleted and the keys left undeleted because of the stop,
+    /// to pass again to resume.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.set("deletes_with_stop_test", "100", 100).await?;
+    /// let stop = memcached::StopSignal::new();
+    /// stop.stop();
+    /// let (failed, pending) = client
+    ///     .deletes_with_stop(&["deletes_with_stop_test"], &stop)
+    ///     .await?;
+    /// assert!(failed.is_empty());
+    /// let (failed, _) = client
+    ///     .deletes_with_stop(&pending, &memcached::StopSignal::new())
+    ///     .await?;
+    /// assert!(failed.is_empty());
+    /// let t: Option<String> = client.get("deletes_with_stop_test").await?;
+    /// assert_eq!(t, None);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn deletes_with_stop<K: AsRef<str>>(
+        &self,
+        keys: &[K],
+        stop: &StopSignal,
+    ) -> Result<(HashMap<String, CommandError>, Vec<String>)> {
+        self.deletes_until(keys, Some(stop)).await
+    }
+
+    /// `deletes`, in batches of `STOP_CHECK_BATCH_SIZE` keys checking `stop` if there is one.
+    async fn deletes_until<K: AsRef<str>>(
+        &self,
+        keys: &[K],
+        stop: Option<&StopSignal>,
+    ) -> Result<(HashMap<String, CommandError>, Vec<String>)> {
+        self.announce(|| Operation::DeleteMany {
+            keys: keys.iter().map(AsRef::as_ref).collect(),
+        });
+        let keys = self.wire_keys(keys)?;
+        let batch_size = stop.map_or(usize::MAX, |_| STOP_CHECK_BATCH_SIZE);
+        let mut failures = HashMap::new();
+        let mut pending = vec![];
+        for (connection_index, keys) in self.group_by_connection(&keys) {
+            if let Some(pool) = self.connections.get(connection_index) {
+                for batch in keys.chunks(batch_size) {
+                    if stop.is_some_and(StopSignal::is_stopped) {
+                        pending.extend(batch.iter().map(|key| self.expand_key(key)));
+                        continue;
+                    }
+                    failures.extend(Self::finish_write(with_connection!(
+                        self,
+                        "deletes",
+                        pool,
+                        retry,
+                        |connection| { connection.deletes(batch) }
+                    ))?);
+                }
+            }
+        }
+        Ok((self.expand_keys(failures), pending))
+    }
+
+    /// Get a value by key and set its expiration in the same round trip, e.g. to keep sessions alive while they are read.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.set("get_and_touch_test", "hello", 1).await?;
+    /// let t: Option<String> = client.get_and_touch("get_and_touch_test", 100).await?;
+    /// assert_eq!(t, Some("hello".to_owned()));
+    /// async_std::task::sleep(std::time::Duration::from_secs(2)).await;
+    /// let t: Option<String> = client.get("get_and_touch_test").await?;
+    /// assert_eq!(t, Some("hello".to_owned()));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn get_and_touch<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        expiration: u32,
+    ) -> Result<Option<V>> {
+        self.announce(|| Operation::Get { key: key.as_ref() });
+        let expiration = self.expiration(key.as_ref(), expiration);
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        let server = self.get_connection(key);
+        let result = with_connection!(self, "get_and_touch", server, retry, |connection| {
+            connection.get_and_touch(key, expiration, &self.codec)
+        });
+        self.finish_read("get_and_touch", server, 1, result)
+    }
+
+    /// Like `gets`, but also sets the expiration of the keys found, with one pipeline of quiet requests per server.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.set("gets_and_touch_test", "hello", 100).await?;
+    /// let t = client
+    ///     .gets_and_touch::<String, _>(&["gets_and_touch_test", "gets_and_touch_none"], 100)
+    ///     .await?;
+    /// assert_eq!(t.len(), 1);
+    /// assert_eq!(t["gets_and_touch_test"].0, "hello");
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn gets_and_touch<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        keys: &[K],
+        expiration: u32,
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
+        self.announce(|| Operation::GetMany {
+            keys: keys.iter().map(AsRef::as_ref).collect(),
+        });
+        let wire_keys = self.wire_keys(keys)?;
+        // Keys of one server are touched with one expiration per request, the rules may give them several.
+        let mut batches: BTreeMap<(usize, u32), Vec<&str>> = BTreeMap::new();
+        for (key, user_key) in wire_keys.iter().zip(keys) {
+            let key = key.as_ref();
+            let expiration = self.expiration(user_key.as_ref(), expiration);
+            batches
+                .entry((self.server_index(key), expiration))
+                .or_default()
+                .push(key);
+        }
+        let mut result = HashMap::new();
+        for ((connection_index, expiration), keys) in batches {
+            if let Some(server) = self.connections.get(connection_index) {
+                let found =
+                    with_connection!(self, "gets_and_touch", server, retry, |connection| async {
+                        let found = connection
+                            .gets_and_touch(&keys, expiration, &self.codec)
+                            .await;
+                        self.observe_multi_get("gets_and_touch", keys.len(), &mut connection);
+                        found
+                    });
+                result.extend(self.finish_read("gets_and_touch", server, keys.len(), found)?);
+            }
+        }
+        Ok(self.expand_keys(result))
+    }
+
+    /// Get a value by key with its flags and cas, for read-modify-write loops with `cas`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.set("get_with_cas_test", 1, 100).await?;
+    /// let (value, _flags, cas) = client.get_with_cas::<u64, _>("get_with_cas_test").await?.unwrap();
+    /// assert_eq!(value, 1);
+    /// assert!(client.cas("get_with_cas_test", value + 1, 100, cas).await?.is_stored());
+    /// assert_eq!(client.get_with_cas::<u64, _>("get_with_cas_none").await?, None);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn get_with_cas<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+    ) -> Result<Option<(V, u32, u64)>> {
+        self.announce(|| Operation::Get { key: key.as_ref() });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        let server = self.get_connection(key);
+        let result = with_connection!(self, "get_with_cas", server, retry, |connection| {
+            connection.get_with_cas(key, &self.codec)
+        });
+        self.finish_read("get_with_cas", server, 1, result)
+    }
+
+    /// Compare and swap a key with the associate value into memcached server with expiration seconds.
+    /// `cas_id` should be obtained from a previous `get_with_cas` or `gets` call.
+    /// [`CasResult::Exists`] means the key was modified in between and the update can be retried.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use memcached::CasResult;
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.set("cas_test1", "100", 100).await?;
+    /// let t = client
+    ///     .gets::<String, _>(&["cas_test1"])
+    ///     .await
+    ///     ?;
+    /// let k = t.get("cas_test1").unwrap();
+    /// assert_eq!(&k.0, "100");
+    /// let t = client
+    ///     .cas("cas_test1", "200", 100, k.2.unwrap() - 1)
+    ///     .await
+    ///     ?;
+    /// assert_eq!(t, CasResult::Exists);
+    /// let t = client.get::<String, _>("cas_test1").await?;
+    /// assert_eq!(t.unwrap(), "100".to_owned());
+    /// let t = client
+    ///     .cas("cas_test1", "300", 100, k.2.unwrap())
+    ///     .await
+    ///     ?;
+    /// assert_eq!(t, CasResult::Stored);
+    /// let t = client.get::<String, _>("cas_test1").await?;
+    /// assert_eq!(t.unwrap(), "300".to_owned());
+    /// client.delete("cas_test1").await?;
+    /// let t = client.cas("cas_test1", "400", 100, k.2.unwrap()).await?;
+    /// assert_eq!(t, CasResult::NotFound);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn cas<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: V,
+        expiration: u32,
+        cas_id: u64,
+    ) -> Result<CasResult> {
+        let expiration = self.expiration(key.as_ref(), expiration);
+        self.announce(|| Operation::Cas {
+            key: key.as_ref(),
+            ttl: expiration,
+            bytes: self.encoded_len(&value),
+            cas: cas_id,
+        });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::when_bypassed(
+            with_connection!(self, "cas", self.get_connection(key), |connection| {
+                connection.cas(key, value, expiration, cas_id, &self.codec)
+            }),
+            || Ok(CasResult::NotFound),
+        )
+    }
+
+    /// Meta get (`mg`, memcached 1.6+, ASCII protocol only): the value and metadata asked for in `flags`,
+    /// e.g. the cas and remaining TTL, in one round trip. With [`MetaGetFlags::vivify`] or
+    /// [`MetaGetFlags::recache_below`] only one client wins the right to recompute a missing or
+    /// expiring value, see [`MetaResponse::win`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use memcached::{MetaDeleteFlags, MetaGetFlags, MetaSetFlags, MetaStatus};
+    /// let client = memcached::connect("memcache://127.0.0.1:12345?protocol=ascii")?;
+    /// let stored = client
+    ///     .meta_set("meta_test", "hello", &MetaSetFlags { ttl: 100, ..MetaSetFlags::default() })
+    ///     .await?;
+    /// assert_eq!(stored.status, MetaStatus::Ok);
+    /// let flags = MetaGetFlags { value: true, cas: true, ttl: true, ..MetaGetFlags::default() };
+    /// let t = client.meta_get::<String, _>("meta_test", &flags).await?;
+    /// assert_eq!(t.value, Some("hello".to_owned()));
+    /// assert!(t.ttl.unwrap() > 0);
+    /// let wrong_cas = MetaDeleteFlags { cas: Some(t.cas.unwrap() + 1), ..MetaDeleteFlags::default() };
+    /// assert_eq!(client.meta_delete("meta_test", &wrong_cas).await?.status, MetaStatus::Exists);
+    /// assert!(client.meta_delete("meta_test", &MetaDeleteFlags::default()).await?.is_ok());
+    /// let t = client.meta_get::<String, _>("meta_test", &flags).await?;
+    /// assert_eq!((t.status, t.value), (MetaStatus::Miss, None));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn meta_get<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        flags: &MetaGetFlags,
+    ) -> Result<MetaResponse<V>> {
+        self.announce(|| Operation::Get { key: key.as_ref() });
+        let flags = &MetaGetFlags {
+            touch: flags.touch.map(|ttl| self.expiration(key.as_ref(), ttl)),
+            vivify: flags.vivify.map(|ttl| self.expiration(key.as_ref(), ttl)),
+            ..*flags
+        };
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        let mut response = Self::when_bypassed(
+            with_connection!(
+                self,
+                "meta_get",
+                self.get_connection(key),
+                retry,
+                |connection| connection.meta_get(key, flags)
+            ),
+            || Ok(MetaResponse::new(MetaStatus::Miss)),
+        )?;
+        // A vivified item is an empty placeholder until the winner fills it.
+        if response.win && response.value.as_ref().is_some_and(Vec::is_empty) {
+            response.value = None;
+        }
+        let client_flags = response.flags.unwrap_or_default();
+        response.try_map(|value| self.codec.decode(&value, client_flags))
+    }
+
+    /// Meta set (`ms`, memcached 1.6+, ASCII protocol only), see [`Client::meta_get`].
+    pub async fn meta_set<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: V,
+        flags: &MetaSetFlags,
+    ) -> Result<MetaResponse<()>> {
+        let flags = &MetaSetFlags {
+            ttl: self.expiration(key.as_ref(), flags.ttl),
+            ..*flags
+        };
+        self.announce(|| Operation::Set {
+            key: key.as_ref(),
+            ttl: flags.ttl,
+            bytes: self.encoded_len(&value),
+        });
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        // Like `set`, the write succeeds without storing anything.
+        Self::when_bypassed(
+            with_connection!(self, "meta_set", self.get_connection(key), |connection| {
+                connection.meta_set(key, value, flags, &self.codec)
+            }),
+            || Ok(MetaResponse::new(MetaStatus::Ok)),
+        )
+    }
+
+    /// Meta delete (`md`, memcached 1.6+, ASCII protocol only), with [`MetaDeleteFlags::invalidate`]
+    /// the item is marked stale instead, see [`Client::meta_get`].
+    pub async fn meta_delete<K: AsRef<str>>(
+        &self,
+        key: K,
+        flags: &MetaDeleteFlags,
+    ) -> Result<MetaResponse<()>> {
+        self.announce(|| Operation::Delete { key: key.as_ref() });
+        let flags = &MetaDeleteFlags {
+            ttl: flags.ttl.map(|ttl| self.expiration(key.as_ref(), ttl)),
+            ..*flags
+        };
+        let key = self.wire_key(key.as_ref())?;
+        let key = &*key;
+        Self::when_bypassed(
+            with_connection!(
+                self,
+                "meta_delete",
+                self.get_connection(key),
+                retry,
+                |connection| connection.meta_delete(key, flags)
+            ),
+            || Ok(MetaResponse::new(MetaStatus::NotFound)),
+        )
+    }
+
+    /// Read-through with a soft and a hard TTL: the value of `key`, computed by `compute` and stored on a miss.
+    /// Once `soft_ttl` seconds passed, the stored value is still returned while one client recomputes it
+    /// in the background. The item expires after `hard_ttl` seconds in case no one reads it in time.
+    ///
+    /// The value is stored together with its soft expiration, so the codec must encode structs,
+    /// like [`BincodeCodec`] or `JsonCodec`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.delete("get_or_set_test").await?;
+    /// let t: String = client
+    ///     .get_or_set("get_or_set_test", 0, 100, || async { Ok("computed".to_owned()) })
+    ///     .await?;
+    /// assert_eq!(t, "computed");
+    /// // Stale right away, served while it is recomputed in the background.
+    /// let t: String = client
+    ///     .get_or_set("get_or_set_test", 60, 100, || async { Ok("recomputed".to_owned()) })
+    ///     .await?;
+    /// assert_eq!(t, "computed");
+    /// async_std::task::sleep(std::time::Duration::from_millis(500)).await;
+    /// let t: String = client
+    ///     .get_or_set("get_or_set_test", 60, 100, || async { Ok("unused".to_owned()) })
+    ///     .await?;
+    /// assert_eq!(t, "recomputed");
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn get_or_set<V, K, F, Fut>(
+        &self,
+        key: K,
+        soft_ttl: u32,
+        hard_ttl: u32,
+        compute: F,
+    ) -> Result<V>
+    where
+        V: Serialize + DeserializeOwned + Clone + Send + 'static,
+        K: AsRef<str>,
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<V>> + Send + 'static,
+    {
+        let key = key.as_ref();
+        if let Some(envelope) = self.get::<Envelope<V>, _>(key).await? {
+            if envelope.is_stale() {
+                self.refresh_in_background(key, soft_ttl, hard_ttl, compute);
+            }
+            return Ok(envelope.value);
+        }
+        let value = compute().await?;
+        self.set(key, Envelope::new(value.clone(), soft_ttl), hard_ttl)
+            .await?;
+        Ok(value)
+    }
+
+    /// Recompute a stale value unless another task of the process refreshes it
+    /// or another client holds its refresh lock.
+    fn refresh_in_background<V, F, Fut>(&self, key: &str, soft_ttl: u32, hard_ttl: u32, compute: F)
+    where
+        V: Serialize + Send + 'static,
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<V>> + Send + 'static,
+    {
+        let Some(claim) = self.refresh_claims.claim(key) else {
+            return;
+        };
+        let client = self.clone();
+        let key = key.to_owned();
+        drop(async_std::task::spawn(async move {
+            let _claim = claim;
+            let lock = envelope::refresh_lock_key(&key);
+            if client
+                .add(&lock, 0_u8, REFRESH_LOCK_EXPIRATION)
+                .await
+                .is_ok()
+            {
+                if let Ok(value) = compute().await {
+                    let _ = client
+                        .set(&key, Envelope::new(value, soft_ttl), hard_ttl)
+                        .await;
+                }
+                let _ = client.delete(&lock).await;
+            }
+        }));
+    }
+
+    /// Read `key` from the in-process cache `l1`, then from the servers of this client, then from
+    /// `loader`, each within its [`TierBudget`]. A cache tier that misses, fails or is over budget
+    /// passes on to the next. The value is copied into the tiers that missed, in the background
+    /// with `ttl`. The result tells which tier served the value and what each tier asked gave.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use memcached::{Tier, TierBudget};
+    /// let l1 = memcached::connect("memcache+local://tiered_get_test")?;
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// client.delete("tiered_get_test").await?;
+    /// let t = client
+    ///     .tiered_get(&l1, "tiered_get_test", 100, TierBudget::default(), || async {
+    ///         Ok("loaded".to_owned())
+    ///     })
+    ///     .await?;
+    /// assert_eq!((t.value.as_str(), t.tier), ("loaded", Tier::Loader));
+    /// println!("{:?} in {:?}", t.attempts, t.elapsed());
+    /// async_std::task::sleep(std::time::Duration::from_millis(100)).await;
+    /// let t = client
+    ///     .tiered_get(&l1, "tiered_get_test", 100, TierBudget::default(), || async {
+    ///         Ok("unused".to_owned())
+    ///     })
+    ///     .await?;
+    /// assert_eq!((t.value.as_str(), t.tier), ("loaded", Tier::L1));
+    ///
+    /// use memcached::error::{ClientError, MemcachedError};
+    /// let budget = TierBudget {
+    ///     loader: Some(std::time::Duration::from_millis(10)),
+    ///     ..TierBudget::default()
+    /// };
+    /// let t = client
+    ///     .tiered_get(&l1, "tiered_get_test_slow", 100, budget, || async {
+    ///         async_std::task::sleep(std::time::Duration::from_secs(1)).await;
+    ///         Ok("late".to_owned())
+    ///     })
+    ///     .await;
+    /// assert!(matches!(t, Err(MemcachedError::ClientError(ClientError::LoaderTimeout(_)))));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn tiered_get<V, K, F, Fut>(
+        &self,
+        l1: &Client<C>,
+        key: K,
+        ttl: u32,
+        budget: TierBudget,
+        loader: F,
+    ) -> Result<Tiered<V>>
+    where
+        V: Serialize + DeserializeOwned + Clone + Send + 'static,
+        K: AsRef<str>,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        let key = key.as_ref();
+        let mut attempts = vec![];
+        for (tier, client, limit) in [
+            (Tier::L1, l1, budget.l1),
+            (Tier::Remote, self, budget.remote),
+        ] {
+            // The budget bounds the whole read, pool wait and connect included. The operation
+            // timeout closes the connection of a read over budget, so does dropping it halfway.
+            let mut client = client.clone();
+            client.operation_timeout =
+                Some(client.operation_timeout.map_or(limit, |t| t.min(limit)));
+            let started = Instant::now();
+            let read = async_std::future::timeout(limit, client.get::<V, _>(key)).await;
+            let (outcome, value) = match read {
+                Ok(Ok(Some(value))) => (TierOutcome::Hit, Some(value)),
+                Ok(Ok(None)) => (TierOutcome::Miss, None),
+                Ok(Err(MemcachedError::Timeout(_))) | Err(_) => (TierOutcome::OverBudget, None),
+                Ok(Err(_)) => (TierOutcome::Failed, None),
+            };
+            attempts.push(TierAttempt {
+                tier,
+                outcome,
+                elapsed: started.elapsed(),
+            });
+            if let Some(value) = value {
+                if tier == Tier::Remote {
+                    l1.fill_in_background(key, value.clone(), ttl);
+                }
+                return Ok(Tiered {
+                    value,
+                    tier,
+                    attempts,
+                });
+            }
+        }
+        let started = Instant::now();
+        let value = match budget.loader {
+            Some(limit) => async_std::future::timeout(limit, loader())
+                .await
+                .map_err(|_| ClientError::LoaderTimeout(limit))??,
+            None => loader().await?,
+        };
+        attempts.push(TierAttempt {
+            tier: Tier::Loader,
+            outcome: TierOutcome::Hit,
+            elapsed: started.elapsed(),
+        });
+        self.fill_in_background(key, value.clone(), ttl);
+        l1.fill_in_background(key, value.clone(), ttl);
+        Ok(Tiered {
+            value,
+            tier: Tier::Loader,
+            attempts,
+        })
+    }
+
+    /// Store `value` without waiting for it, a failed fill only costs a later miss.
+    fn fill_in_background<V: Serialize + Send + 'static>(&self, key: &str, value: V, ttl: u32) {
+        let client = self.clone();
+        let key = key.to_owned();
+        drop(async_std::task::spawn(async move {
+            let _ = client.set(&key, value, ttl).await;
+        }));
+    }
+
+    /// The key of `key` in the namespace `prefix`, e.g. `users:3:42` while version 3 of `users` is active.
+    /// The active version is read from a record stored in memcached, see `schedule_invalidation`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let key = client.namespace_key("namespace_key_test", "42").await?;
+    /// assert!(key.starts_with("namespace_key_test:"));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn namespace_key(&self, prefix: &str, key: &str) -> Result<String> {
+        let version = match self.namespace_record(prefix).await? {
+            Some((record, _)) => record.active(SystemTime::now()),
+            None => 0,
+        };
+        Ok(format!("{prefix}:{version}:{key}"))
+    }
+
+    /// Which server `key` is sent to and why: the hash, the ring point of consistent hashing
+    /// and whether failover moved it away from its owner.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::Client::builder(vec![
+    ///     "memcache://127.0.0.1:12345",
+    ///     "memcache://127.0.0.1:12346",
+    /// ])
+    /// .router(memcached::KetamaRouter::from_urls(&[
+    ///     "memcache://127.0.0.1:12345",
+    ///     "memcache://127.0.0.1:12346",
+    /// ])?)
+    /// .build()?;
+    /// let explanation = client.explain("explain_test");
+    /// assert_eq!(explanation.wire_key, "explain_test");
+    /// assert!(explanation.route.ring_point.is_some());
+    /// println!("explain_test is on {}", explanation.server);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn explain(&self, key: &str) -> KeyExplanation {
+        self.explain_prefixed("", key)
+    }
+
+    /// Like `explain` for the key of `key` in the namespace `prefix`, see `namespace_key`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let explanation = client.explain_namespaced("explain_namespaced_test", "42").await?;
+    /// assert_eq!(explanation.wire_key, client.namespace_key("explain_namespaced_test", "42").await?);
+    /// assert!(explanation.prefix.starts_with("explain_namespaced_test:"));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn explain_namespaced(&self, prefix: &str, key: &str) -> Result<KeyExplanation> {
+        let wire_key = self.namespace_key(prefix, key).await?;
+        let prefix = wire_key.strip_suffix(key).unwrap_or_default();
+        Ok(self.explain_prefixed(prefix, key))
+    }
+
+    /// The servers of the client and where its router sends a fixed set of probe keys,
+    /// to check with `verify_routing` that other processes route identically before a deploy.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// let urls = vec!["memcache://127.0.0.1:12345", "memcache://127.0.0.1:12346"];
+    /// let client = memcached::Client::builder(urls.clone())
+    ///     .router(memcached::KetamaRouter::from_urls(&urls)?)
+    ///     .build()?;
+    /// let snapshot = bincode::serialize(&client.routing_snapshot()).unwrap();
+    ///
+    /// // In another process.
+    /// let snapshot: memcached::RoutingSnapshot = bincode::deserialize(&snapshot).unwrap();
+    /// let other = memcached::Client::builder(urls.clone())
+    ///     .router(memcached::KetamaRouter::from_urls(&urls)?)
+    ///     .build()?;
+    /// other.verify_routing(&snapshot)?;
+    /// let weighted = memcached::Client::builder(urls.clone())
+    ///     .router(memcached::KetamaRouter::new(&[("127.0.0.1:12345", 1), ("127.0.0.1:12346", 2)]))
+    ///     .build()?;
+    /// assert_eq!(weighted.routing_snapshot().router.weights, vec![1, 2]);
+    /// assert!(weighted.verify_routing(&snapshot).is_err());
+    /// let modulo = memcached::Client::builder(urls).build()?;
+    /// assert!(modulo.verify_routing(&snapshot).is_err());
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn routing_snapshot(&self) -> RoutingSnapshot {
+        let len = self.connections.len();
+        RoutingSnapshot {
+            servers: self
+                .connections
+                .iter()
+                .map(|server| server.url.clone())
+                .collect(),
+            router: self.router.parameters(),
+            probes: routing_snapshot::probe_keys()
+                .map(|key| self.router.route(&key) % len)
+                .collect(),
+        }
+    }
+
+    /// Fails with [`ClientError::RoutingMismatch`] unless the client has the servers of
+    /// `snapshot` and routes its probe keys identically.
+    pub fn verify_routing(&self, snapshot: &RoutingSnapshot) -> Result<()> {
+        match snapshot.diff(&self.routing_snapshot()) {
+            Some(reason) => Err(ClientError::RoutingMismatch(reason).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Queue operations to send them together, one round trip per server.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use memcached::Reply;
+    ///
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let replies = client
+    ///     .pipeline()
+    ///     .set("pipeline_test1", "100", 100)
+    ///     .get("pipeline_test1")
+    ///     .delete("pipeline_test2")
+    ///     .increment("pipeline_test3", 1)
+    ///     .execute()
+    ///     .await?;
+    /// assert_eq!(replies.replies()[0], Reply::Stored);
+    /// assert_eq!(replies.value::<String>(1)?, Some("100".to_owned()));
+    /// assert_eq!(replies.replies()[2], Reply::Deleted(false));
+    /// assert!(matches!(replies.replies()[3], Reply::Counter(_)));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub fn pipeline(&self) -> Pipeline<'_, C> {
+        Pipeline::new(self)
+    }
+
+    /// Send the commands of each server in one write, the replies are in the order of `commands`.
+    async fn execute_pipeline(&self, commands: Vec<Command>) -> Result<Vec<Reply>> {
+        if self.bypass("pipeline") {
+            return Ok(commands.iter().map(Command::bypassed).collect());
+        }
+        let count = commands.len();
+        let mut flush_delays = vec![];
+        let mut batches: BTreeMap<usize, (Vec<usize>, Vec<Command>)> = BTreeMap::new();
+        for (position, command) in commands.into_iter().enumerate() {
+            if let Command::Flush { delay } = command {
+                flush_delays.push((position, delay.unwrap_or(0)));
+                for index in 0..self.connections.len() {
+                    let batch = batches.entry(index).or_default();
+                    batch.0.push(position);
+                    batch.1.push(Command::Flush { delay });
+                }
+                continue;
+            }
+            let batch = batches
+                .entry(self.server_index(command.key().unwrap_or_default()))
+                .or_default();
+            batch.0.push(position);
+            batch.1.push(command);
+        }
+        let mut replies: Vec<Option<Reply>> = vec![None; count];
+        for (index, (positions, commands)) in batches {
+            if let Some(server) = self.connections.get(index) {
+                let batch = with_connection!(self, "pipeline", server, retry, |connection| {
+                    connection.pipeline(&commands)
+                })
+                .or_else(|e| match e {
+                    // Engaged while the batches of other servers were sent.
+                    MemcachedError::ClientError(ClientError::CacheBypassed) => {
+                        Ok(commands.iter().map(Command::bypassed).collect())
+                    }
+                    e => Err(e),
+                })?;
+                for (position, reply) in positions.into_iter().zip(batch) {
+                    if let Some(slot) = replies.get_mut(position) {
+                        // A flush fails if any server failed it.
+                        if !matches!(slot, Some(Reply::Failed(_))) {
+                            *slot = Some(reply);
+                        }
+                    }
+                }
+            }
+        }
+        for (position, delay) in flush_delays {
+            if matches!(replies.get(position), Some(Some(Reply::Flushed))) {
+                self.flush_schedule.schedule(delay);
+            }
+        }
+        Ok(replies.into_iter().flatten().collect())
+    }
+
+    /// server_index < len
+    #[allow(clippy::indexing_slicing)]
+    pub(crate) fn explain_prefixed(&self, prefix: &str, key: &str) -> KeyExplanation {
+        let full_key = format!("{prefix}{key}");
+        let wire_key = self
+            .wire_key(&full_key)
+            .map_or_else(|_| full_key.clone(), Cow::into_owned);
+        let server_index = self.server_index(&wire_key);
+        KeyExplanation {
+            key: key.to_owned(),
+            prefix: prefix.to_owned(),
+            route: self.router.explain(&wire_key),
+            server: self.connections[server_index].url.clone(),
+            server_index,
+            wire_key,
+        }
+    }
+
+    /// Switch every client to a new version of the namespace `prefix` at the wall clock time `at`,
+    /// which invalidates all keys built by `namespace_key` at once. A time in the past switches immediately.
+    /// Clients must have synchronized clocks for the switch to happen at the same time everywhere.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use std::time::{Duration, SystemTime};
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let key = client.namespace_key("schedule_invalidation_test", "42").await?;
+    /// client.set(&key, "hello", 100).await?;
+    /// let release = SystemTime::now() + Duration::from_secs(3600);
+    /// client.schedule_invalidation("schedule_invalidation_test", release).await?;
+    /// assert_eq!(key, client.namespace_key("schedule_invalidation_test", "42").await?);
+    /// client.schedule_invalidation("schedule_invalidation_test", SystemTime::now()).await?;
+    /// let key = client.namespace_key("schedule_invalidation_test", "42").await?;
+    /// let t: Option<String> = client.get(&key).await?;
+    /// assert_eq!(t, None);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub async fn schedule_invalidation(&self, prefix: &str, at: SystemTime) -> Result<()> {
+        let key = namespace::record_key(prefix);
+        for _ in 0..NAMESPACE_CAS_RETRIES {
+            let now = SystemTime::now();
+            let stored = if let Some((record, cas)) = self.namespace_record(prefix).await? {
+                let record = record.schedule(now, at).to_string();
+                with_connection!(self, "cas", self.get_connection(&key), |connection| {
+                    connection.cas(&key, record, 0, cas, &Utf8String)
+                })?
+                .is_stored()
+            } else {
+                let record = Record::default().schedule(now, at).to_string();
+                let added =
+                    with_connection!(self, "add", self.get_connection(&key), |connection| {
+                        connection.add(&key, record, 0, &Utf8String)
+                    });
+                match Self::finish_write(added) {
+                    Ok(()) => true,
+                    Err(MemcachedError::CommandError(CommandError::KeyExists)) => false,
+                    Err(e) => return Err(e),
+                }
+            };
+            if stored {
+                return Ok(());
+            }
+        }
+        Err(CommandError::KeyExists.into())
+    }
+
+    /// The namespace record and its cas.
+    async fn namespace_record(&self, prefix: &str) -> Result<Option<(Record, u64)>> {
+        let key = namespace::record_key(prefix);
+        let keys = [key.as_str()];
+        let mut found = Self::finish_write(with_connection!(
+            self,
+            "gets",
+            self.get_connection(&key),
+            retry,
+            |connection| { connection.gets::<String, _>(&keys, &Utf8String) }
+        ))?;
+        match found.remove(&key) {
+            Some((record, _, cas)) => Ok(Some((Record::parse(&record)?, cas.unwrap_or(0)))),
+            None => Ok(None),
+        }
+    }
+
+    /// The expiration a write of `key` is stored with after the TTL rules.
+    fn expiration(&self, key: &str, expiration: u32) -> u32 {
+        self.ttl_rules
+            .as_ref()
+            .map_or(expiration, |rules| rules.apply(key, expiration))
+    }
+
+    /// Tell the observer what is about to be sent, `operation` is only built if there is one.
+    fn announce<'a>(&self, operation: impl FnOnce() -> Operation<'a>) {
+        if let Some(observer) = &self.observer {
+            observer.on_operation(&operation());
+        }
+    }
+
+    /// The size of the encoded value, 0 if the codec refuses it.
+    fn encoded_len<V: Serialize + 'static>(&self, value: &V) -> usize {
+        self.codec.encode(value).map_or(0, |(bytes, _)| bytes.len())
+    }
+
+    /// The key as sent to the server: shortened by the key dictionary, and checked to fit.
+    fn wire_key<'a>(&self, key: &'a str) -> Result<Cow<'a, str>> {
+        let key = match &self.dictionary {
+            Some(dictionary) => dictionary.shorten(key)?,
+            None => Cow::Borrowed(key),
+        };
+        check::check_key_len(&key)?;
+        Ok(key)
+    }
+
+    fn wire_keys<'a, K: AsRef<str>>(&self, keys: &'a [K]) -> Result<Vec<Cow<'a, str>>> {
+        keys.iter().map(|key| self.wire_key(key.as_ref())).collect()
+    }
+
+    /// The key a wire key was shortened from.
+    fn expand_key(&self, key: &str) -> String {
+        match &self.dictionary {
+            Some(dictionary) => dictionary.expand(key).into_owned(),
+            None => key.to_owned(),
+        }
+    }
+
+    fn expand_keys<T>(&self, found: HashMap<String, T>) -> HashMap<String, T> {
+        match &self.dictionary {
+            Some(dictionary) => found
+                .into_iter()
+                .map(|(key, value)| (dictionary.expand(&key).into_owned(), value))
+                .collect(),
+            None => found,
+        }
+    }
+
+    fn group_by_connection<'a, K: AsRef<str>>(
+        &self,
+        keys: &'a [K],
+    ) -> HashMap<usize, Vec<&'a str>> {
+        let mut con_keys: HashMap<usize, Vec<&str>> = HashMap::new();
+        for key in keys.iter().map(AsRef::as_ref) {
+            let connection_index = self.server_index(key);
+            con_keys.entry(connection_index).or_default().push(key);
+        }
+        con_keys
+    }
+
+    /// The server of `key`, or with failover the next healthy one if it is dead.
+    /// Dead servers on the way are probed so they come back once they answer again.
+    fn server_index(&self, key: &str) -> usize {
+        let len = self.connections.len();
+        let index = self.router.route(key) % len;
+        if !self.health_policy.failover {
+            return index;
+        }
+        (0..len)
+            .map(|offset| (index + offset) % len)
+            .find(|&candidate| {
+                let server = &self.connections[candidate];
+                let dead = server.health.is_dead(&self.health_policy);
+                if dead {
+                    server.probe(&self.health_policy);
+                }
+                !dead
+            })
+            .unwrap_or(index)
     }
 
     /// index < len
     /// 没有风险
     #[allow(clippy::indexing_slicing)]
-    fn get_connection(&self, key: &str) -> &Pool<ConnectionManager> {
-        let hash = ((self.hash_function)(key) % self.connections.len() as u64) as usize;
-        &self.connections[hash]
+    fn get_connection(&self, key: &str) -> &Server {
+        self.connections[self.server_index(key)].single_key()
     }
 }
@@ -0,0 +1,27 @@
+use std::collections::{HashMap, HashSet};
+
+/// The result of [`Client::gets_with_misses`](crate::Client::gets_with_misses).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiGet<V> {
+    /// The value, flags and cas of every key found.
+    pub found: HashMap<String, (V, u32, Option<u64>)>,
+    /// The requested keys that were not found, in request order and without duplicates.
+    pub missed: Vec<String>,
+}
+
+impl<V> MultiGet<V> {
+    /// Split `requested` into the keys of `found` and the misses.
+    pub(crate) fn new<K: AsRef<str>>(
+        found: HashMap<String, (V, u32, Option<u64>)>,
+        requested: &[K],
+    ) -> Self {
+        let mut seen = HashSet::new();
+        let missed = requested
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|key| !found.contains_key(*key) && seen.insert(*key))
+            .map(str::to_owned)
+            .collect();
+        MultiGet { found, missed }
+    }
+}
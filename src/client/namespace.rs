@@ -0,0 +1,60 @@
+use crate::{error::ServerError, Result};
+use std::{
+    borrow::Cow,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The version record of a namespace, stored as `<version> <next version> <activation unix seconds>`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Record {
+    version: u64,
+    next: u64,
+    activate_at: u64,
+}
+
+pub(crate) fn record_key(prefix: &str) -> String {
+    format!("__ns:{prefix}")
+}
+
+fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl Record {
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        let mut parts = s.split(' ').map(str::parse::<u64>);
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(version), Some(next), Some(activate_at), None) => Ok(Record {
+                version: version?,
+                next: next?,
+                activate_at: activate_at?,
+            }),
+            _ => Err(ServerError::BadResponse(Cow::Owned(format!("namespace record: {s}"))).into()),
+        }
+    }
+
+    /// The version in use at `now`, every client switches to `next` at the same wall clock second.
+    pub(crate) fn active(&self, now: SystemTime) -> u64 {
+        if unix_secs(now) >= self.activate_at {
+            self.next
+        } else {
+            self.version
+        }
+    }
+
+    /// Switch from the version active at `now` to the one after it at `at`.
+    pub(crate) fn schedule(&self, now: SystemTime, at: SystemTime) -> Self {
+        let version = self.active(now);
+        Record {
+            version,
+            next: version + 1,
+            activate_at: unix_secs(at),
+        }
+    }
+}
+
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.version, self.next, self.activate_at)
+    }
+}
@@ -0,0 +1,165 @@
+use super::Client;
+use crate::{
+    codec::{BincodeCodec, ValueCodec},
+    error::{ClientError, MemcachedError},
+    protocol::{Command, Reply},
+    Result,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::borrow::Cow;
+
+/// Operations queued to be sent together, see [`Client::pipeline`].
+///
+/// Every server gets the operations on its keys in one write, gets, sets, deletes and flushes
+/// as quiet requests, and answers them in one round trip.
+pub struct Pipeline<'a, C = BincodeCodec> {
+    client: &'a Client<C>,
+    commands: Vec<Command>,
+    /// The first key or value that could not be queued, returned by `execute`.
+    error: Option<MemcachedError>,
+}
+
+/// The replies of a pipeline in the order the operations were queued, see [`Pipeline::execute`].
+#[derive(Debug, Clone)]
+pub struct Replies<C = BincodeCodec> {
+    replies: Vec<Reply>,
+    codec: C,
+}
+
+impl<'a, C: ValueCodec> Pipeline<'a, C> {
+    pub(crate) fn new(client: &'a Client<C>) -> Self {
+        Pipeline {
+            client,
+            commands: vec![],
+            error: None,
+        }
+    }
+
+    fn push(&mut self, command: Command) -> &mut Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// The key as sent to the server, a key that can't be sent is kept to fail `execute`.
+    fn wire_key(&mut self, key: &str) -> String {
+        match self.client.wire_key(key) {
+            Ok(key) => key.into_owned(),
+            Err(e) => {
+                let _ = self.error.get_or_insert(e);
+                key.to_owned()
+            }
+        }
+    }
+
+    /// Queue a `get` of `key`, decode the value with [`Replies::value`].
+    pub fn get<K: AsRef<str>>(&mut self, key: K) -> &mut Self {
+        let key = self.wire_key(key.as_ref());
+        self.push(Command::Get(key))
+    }
+
+    /// Queue a `set` of `key` to `value`.
+    pub fn set<V: Serialize + 'static, K: AsRef<str>>(
+        &mut self,
+        key: K,
+        value: V,
+        expiration: u32,
+    ) -> &mut Self {
+        let expiration = self.client.expiration(key.as_ref(), expiration);
+        let key = self.wire_key(key.as_ref());
+        match self.client.codec.encode(&value) {
+            Ok((value, flags)) => self.push(Command::Set {
+                key,
+                value,
+                flags,
+                expiration,
+            }),
+            Err(e) => {
+                let _ = self.error.get_or_insert(e);
+                self
+            }
+        }
+    }
+
+    /// Queue a `delete` of `key`.
+    pub fn delete<K: AsRef<str>>(&mut self, key: K) -> &mut Self {
+        let key = self.wire_key(key.as_ref());
+        self.push(Command::Delete(key))
+    }
+
+    /// Queue an `increment` of `key` by `amount`, see [`Client::increment`].
+    pub fn increment<K: AsRef<str>>(&mut self, key: K, amount: u64) -> &mut Self {
+        let key = self.wire_key(key.as_ref());
+        self.push(Command::Increment { key, amount })
+    }
+
+    /// Queue a quiet `flush` of every server, see [`Client::flush`]. Operations queued after it
+    /// on a server run after the flush, failures are collected with the other replies.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+    /// use memcached::Reply;
+    ///
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let replies = client
+    ///     .pipeline()
+    ///     .flush()
+    ///     .set("pipeline_flush_test", "fixture", 100)
+    ///     .execute()
+    ///     .await?;
+    /// assert_eq!(replies.replies(), [Reply::Flushed, Reply::Stored]);
+    /// let t: Option<String> = client.get("pipeline_flush_test").await?;
+    /// assert_eq!(t, Some("fixture".to_owned()));
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    pub fn flush(&mut self) -> &mut Self {
+        self.push(Command::Flush { delay: None })
+    }
+
+    /// Queue a quiet `flush` of every server invalidating the items in `delay` seconds, see [`Client::flush_with_delay`].
+    pub fn flush_with_delay(&mut self, delay: u32) -> &mut Self {
+        self.push(Command::Flush { delay: Some(delay) })
+    }
+
+    /// Send the queued operations, one round trip per server, and wait for all replies.
+    /// Nothing is sent if an operation could not be queued.
+    pub async fn execute(&mut self) -> Result<Replies<C>> {
+        if let Some(e) = self.error.take() {
+            self.commands.clear();
+            return Err(e);
+        }
+        let commands = std::mem::take(&mut self.commands);
+        Ok(Replies {
+            replies: self.client.execute_pipeline(commands).await?,
+            codec: self.client.codec.clone(),
+        })
+    }
+}
+
+impl<C: ValueCodec> Replies<C> {
+    /// The reply of every operation.
+    #[must_use]
+    pub fn replies(&self) -> &[Reply] {
+        &self.replies
+    }
+
+    /// The value of the `get` queued at `index`, `None` if the key is missing.
+    pub fn value<V: DeserializeOwned + 'static>(&self, index: usize) -> Result<Option<V>> {
+        match self.replies.get(index) {
+            Some(Reply::Value(Some((bytes, flags)))) => self.codec.decode(bytes, *flags).map(Some),
+            Some(Reply::Value(None)) => Ok(None),
+            Some(Reply::Failed(e)) => Err((*e).into()),
+            _ => Err(ClientError::Error(Cow::Owned(format!("Reply {index} is not a get"))).into()),
+        }
+    }
+}
+
+impl<C> IntoIterator for Replies<C> {
+    type Item = Reply;
+    type IntoIter = std::vec::IntoIter<Reply>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.replies.into_iter()
+    }
+}
@@ -0,0 +1,70 @@
+use super::checkout::Checkout;
+use crate::{protocol::RawPacket, Result};
+
+/// A connection checked out for requests the client has no method for, e.g. server extensions
+/// or commands of newer memcached versions, see [`Client::raw_connection`](crate::Client::raw_connection).
+///
+/// Text commands need a server connected with `?protocol=ascii`, packets one using the binary
+/// protocol. Nothing is checked: the caller writes whole requests and reads every reply.
+/// The connection is closed when dropped, since a half read reply would corrupt the next request.
+/// Requests are not reported to the observer and ignore the kill switch.
+pub struct RawConnection {
+    connection: Checkout,
+}
+
+impl RawConnection {
+    pub(crate) fn new(connection: Checkout) -> Self {
+        RawConnection { connection }
+    }
+
+    /// The url of the server.
+    #[must_use]
+    pub fn url(&self) -> String {
+        self.connection.get_url()
+    }
+
+    /// Whether the connection speaks the text protocol.
+    #[must_use]
+    pub fn is_ascii(&self) -> bool {
+        self.connection.is_ascii()
+    }
+
+    /// Send a text command line, `\r\n` is appended, and the data block following it if any.
+    pub async fn send_line(&mut self, line: &str, data: Option<&[u8]>) -> Result<()> {
+        self.connection.protocol.send_line(line, data).await
+    }
+
+    /// Read one reply line, without its `\r\n`.
+    pub async fn read_line(&mut self) -> Result<String> {
+        self.connection.protocol.read_line().await
+    }
+
+    /// Read a data block of `len` bytes, e.g. the value after a `VALUE` line, and its `\r\n`.
+    pub async fn read_data(&mut self, len: usize) -> Result<Vec<u8>> {
+        self.connection.protocol.read_data(len).await
+    }
+
+    /// Send a binary request.
+    pub async fn send_packet(&mut self, packet: &RawPacket) -> Result<()> {
+        self.connection.protocol.send_packet(packet).await
+    }
+
+    /// Read one binary response, errors of the server are returned in its `status`.
+    pub async fn read_packet(&mut self) -> Result<RawPacket> {
+        self.connection.protocol.read_packet().await
+    }
+}
+
+impl Drop for RawConnection {
+    fn drop(&mut self) {
+        self.connection.poison();
+    }
+}
+
+impl std::fmt::Debug for RawConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawConnection")
+            .field("url", &self.url())
+            .finish()
+    }
+}
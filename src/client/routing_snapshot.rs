@@ -0,0 +1,56 @@
+use super::client_hash::RouterParameters;
+use serde::{Deserialize, Serialize};
+
+/// Keys routed to fingerprint a router, enough to tell apart rings that differ in one server.
+const ROUTING_PROBES: usize = 1024;
+
+/// The probe keys routed by a snapshot, the same in every process.
+pub(crate) fn probe_keys() -> impl Iterator<Item = String> {
+    (0..ROUTING_PROBES).map(|i| format!("routing-probe:{i}"))
+}
+
+/// The topology of a client and where it routes a fixed set of probe keys,
+/// see [`Client::routing_snapshot`](crate::Client::routing_snapshot).
+///
+/// Routers may hash with functions that change between builds, e.g. `DefaultHasher`,
+/// so routes are compared besides the router parameters. Failover is not part of the snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutingSnapshot {
+    /// The server urls in client order, weights included.
+    pub servers: Vec<String>,
+    /// The scheme, hasher, ring size and server weights of the router.
+    pub router: RouterParameters,
+    /// The server index of every probe key.
+    pub probes: Vec<usize>,
+}
+
+impl RoutingSnapshot {
+    /// Why `other` routes differently, `None` if it routes identically.
+    pub(crate) fn diff(&self, other: &RoutingSnapshot) -> Option<String> {
+        if self.servers != other.servers {
+            return Some(format!(
+                "servers {:?} differ from {:?}",
+                self.servers, other.servers
+            ));
+        }
+        if self.router != other.router {
+            return Some(format!(
+                "router {:?} differs from {:?}",
+                self.router, other.router
+            ));
+        }
+        let moved = self
+            .probes
+            .iter()
+            .zip(&other.probes)
+            .filter(|(a, b)| a != b)
+            .count();
+        if moved > 0 || self.probes.len() != other.probes.len() {
+            return Some(format!(
+                "{moved} of {} probe keys route to other servers",
+                self.probes.len()
+            ));
+        }
+        None
+    }
+}
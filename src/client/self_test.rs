@@ -0,0 +1,125 @@
+use super::server::Server;
+use crate::{codec::Utf8String, error::ClientError, protocol::ProtocolTrait, Result};
+use std::{
+    borrow::Cow,
+    future::Future,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Canary keys live this long in case the test stops before deleting them.
+const CANARY_EXPIRATION: u32 = 60;
+
+/// One step of a self test, see [`Client::self_test`](crate::Client::self_test).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestCheck {
+    /// What was checked: `connect`, `version`, `set`, `get`, `increment`, `decrement` or `delete`.
+    pub name: &'static str,
+    /// How long the check took.
+    pub latency: Duration,
+    /// Why the check failed, `None` if it passed.
+    pub error: Option<String>,
+}
+
+/// The self test of one server, see [`Client::self_test`](crate::Client::self_test).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// The url of the server.
+    pub server: String,
+    /// The checks run, in order. The test stops at the first failed check.
+    pub checks: Vec<SelfTestCheck>,
+}
+
+impl SelfTestReport {
+    /// Whether every check passed.
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.error.is_none())
+    }
+
+    /// The time all checks took together.
+    #[must_use]
+    pub fn latency(&self) -> Duration {
+        self.checks.iter().map(|check| check.latency).sum()
+    }
+}
+
+fn unexpected(what: &'static str) -> ClientError {
+    ClientError::Error(Cow::Borrowed(what))
+}
+
+/// Time `future` and record it as the check `name`, returns its value if it passed.
+async fn check<T>(
+    checks: &mut Vec<SelfTestCheck>,
+    name: &'static str,
+    future: impl Future<Output = Result<T>>,
+) -> Option<T> {
+    let started = Instant::now();
+    let result = future.await;
+    checks.push(SelfTestCheck {
+        name,
+        latency: started.elapsed(),
+        error: result.as_ref().err().map(ToString::to_string),
+    });
+    result.ok()
+}
+
+pub(crate) async fn run(server: &Server) -> SelfTestReport {
+    let mut checks = vec![];
+    let _ = run_checks(server, &mut checks).await;
+    SelfTestReport {
+        server: server.url.clone(),
+        checks,
+    }
+}
+
+async fn run_checks(server: &Server, checks: &mut Vec<SelfTestCheck>) -> Option<()> {
+    // Checking out a new connection also authenticates it if the url has credentials.
+    let mut connection = check(checks, "connect", server.checkout()).await?;
+    let _ = check(checks, "version", connection.version()).await?;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    let key = format!("__self_test:{nanos}");
+    let counter = format!("__self_test:{nanos}:counter");
+    let value = format!("canary {nanos}");
+    check(
+        checks,
+        "set",
+        connection.set(&key, value.clone(), CANARY_EXPIRATION, &Utf8String),
+    )
+    .await?;
+    check(checks, "get", async {
+        match connection.get::<String, _>(&key, &Utf8String).await? {
+            Some(found) if found == value => Ok(()),
+            Some(_) => Err(unexpected("Read back a different value").into()),
+            None => Err(unexpected("The value just set is missing").into()),
+        }
+    })
+    .await?;
+    check(checks, "increment", async {
+        connection
+            .set(&counter, "10", CANARY_EXPIRATION, &Utf8String)
+            .await?;
+        match connection.increment(&counter, 5).await? {
+            15 => Ok(()),
+            _ => Err(unexpected("10 + 5 is not 15").into()),
+        }
+    })
+    .await?;
+    check(checks, "decrement", async {
+        match connection.decrement(&counter, 3).await? {
+            12 => Ok(()),
+            _ => Err(unexpected("15 - 3 is not 12").into()),
+        }
+    })
+    .await?;
+    check(checks, "delete", async {
+        let _ = connection.delete(&counter).await?;
+        if connection.delete(&key).await? {
+            Ok(())
+        } else {
+            Err(unexpected("The value just set was not deleted").into())
+        }
+    })
+    .await
+}
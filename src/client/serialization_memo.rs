@@ -0,0 +1,162 @@
+use crate::{codec::ValueCodec, error::ClientError, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    any::TypeId,
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+};
+
+/// The codec and value types a content id was encoded with, and the id.
+type MemoKey = (TypeId, TypeId, String);
+
+#[derive(Debug, Default)]
+struct Entries {
+    encoded: HashMap<MemoKey, Encoded>,
+    /// Keys in the order they were inserted, the first is dropped when full.
+    order: VecDeque<MemoKey>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Encoded values by a content id of the caller, so a value written under many keys, e.g. a
+/// feature flag payload fanned out to every user, is serialized once, see
+/// [`ClientBuilder::serialization_memo`](crate::ClientBuilder::serialization_memo) and
+/// [`Client::set_memoized`](crate::Client::set_memoized).
+///
+/// The id must change with the content: a value written with a known id is not encoded again.
+/// Ids are kept apart per codec and value type. The memo keeps at most `capacity` values,
+/// the oldest is dropped first. Clones share the values.
+///
+/// ## Example
+///
+/// ```rust
+/// use memcached::SerializationMemo;
+/// let memo = SerializationMemo::new(16);
+/// assert_eq!((memo.len(), memo.hits(), memo.misses()), (0, 0, 0));
+/// memo.forget("flags:v1");
+/// memo.clear();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SerializationMemo {
+    inner: Arc<Inner>,
+}
+
+impl SerializationMemo {
+    /// A memo keeping the values of at most `capacity` content ids.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        SerializationMemo {
+            inner: Arc::new(Inner {
+                capacity,
+                entries: Mutex::default(),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.inner
+            .entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The number of values kept.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries().encoded.len()
+    }
+
+    /// Whether no value is kept.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes that reused a value.
+    #[must_use]
+    pub fn hits(&self) -> u64 {
+        self.inner.hits.load(Ordering::Relaxed)
+    }
+
+    /// Writes that encoded their value.
+    #[must_use]
+    pub fn misses(&self) -> u64 {
+        self.inner.misses.load(Ordering::Relaxed)
+    }
+
+    /// Drop the values of `content_id`, e.g. after reusing an id for new content.
+    pub fn forget(&self, content_id: &str) {
+        let mut entries = self.entries();
+        entries.encoded.retain(|(_, _, id), _| id != content_id);
+        entries.order.retain(|(_, _, id)| id != content_id);
+    }
+
+    /// Drop every value.
+    pub fn clear(&self) {
+        *self.entries() = Entries::default();
+    }
+
+    /// `value` encoded by `codec`, from the memo if `content_id` was encoded before.
+    pub(crate) fn encode<C: ValueCodec, V: Serialize + 'static>(
+        &self,
+        codec: &C,
+        value: &V,
+        content_id: &str,
+    ) -> Result<Encoded> {
+        let key = (TypeId::of::<C>(), TypeId::of::<V>(), content_id.to_owned());
+        if let Some(encoded) = self.entries().encoded.get(&key) {
+            let _ = self.inner.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(encoded.clone());
+        }
+        let _ = self.inner.misses.fetch_add(1, Ordering::Relaxed);
+        // Encoded outside the lock, a concurrent miss of the same id encodes it too.
+        let encoded = Encoded::new(codec, value)?;
+        if self.inner.capacity > 0 {
+            let mut entries = self.entries();
+            if entries
+                .encoded
+                .insert(key.clone(), encoded.clone())
+                .is_none()
+            {
+                entries.order.push_back(key);
+            }
+            while entries.order.len() > self.inner.capacity {
+                if let Some(oldest) = entries.order.pop_front() {
+                    let _ = entries.encoded.remove(&oldest);
+                }
+            }
+        }
+        Ok(encoded)
+    }
+}
+
+/// A value encoded already, written by a client using it as its codec whatever value it is given.
+#[derive(Debug, Clone)]
+pub(crate) struct Encoded(Arc<(Vec<u8>, u32)>);
+
+impl Encoded {
+    pub(crate) fn new<C: ValueCodec, V: Serialize + 'static>(codec: &C, value: &V) -> Result<Self> {
+        Ok(Encoded(Arc::new(codec.encode(value)?)))
+    }
+}
+
+impl ValueCodec for Encoded {
+    fn encode<V: Serialize + 'static>(&self, _value: &V) -> Result<(Vec<u8>, u32)> {
+        Ok((*self.0).clone())
+    }
+
+    fn decode<V: DeserializeOwned + 'static>(&self, _bytes: &[u8], _flags: u32) -> Result<V> {
+        Err(ClientError::Error("encoded values are write only".into()).into())
+    }
+}
@@ -0,0 +1,114 @@
+use super::{
+    batching::AdaptiveBatch,
+    checkout::{Checkout, CheckoutCounters, FairQueue},
+    health::{HealthPolicy, RefusalCache, ServerHealth},
+};
+use crate::{
+    connection::ConnectionManager, error::MemcachedError, protocol::ProtocolTrait, stream::UdpLoss,
+    Result,
+};
+use mobc::{Manager, Pool};
+use std::{sync::Arc, time::Instant};
+use url::Url;
+
+/// The pool of one server, its health and checkout statistics.
+#[derive(Clone)]
+pub(crate) struct Server {
+    /// The position of the server in the client, recorded in tracing spans.
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    pub(crate) index: usize,
+    pub(crate) url: String,
+    pub(crate) pool: Pool<ConnectionManager>,
+    /// Dials the replacements of poisoned connections, and holds the capabilities of the server
+    /// its first connection probed.
+    pub(crate) manager: ConnectionManager,
+    pub(crate) health: Arc<ServerHealth>,
+    /// Shared with the connection manager, which records failed dials.
+    pub(crate) refusals: Arc<RefusalCache>,
+    /// `Some` if checkouts are served in FIFO order.
+    pub(crate) queue: Option<Arc<FairQueue>>,
+    pub(crate) checkouts: Arc<CheckoutCounters>,
+    /// `Some` if multi-gets are split into batches of an adaptive size.
+    pub(crate) batch: Option<Arc<AdaptiveBatch>>,
+    /// `Some` for a UDP server with a TCP fallback.
+    pub(crate) udp: Option<Arc<UdpFallback>>,
+}
+
+/// The TCP pool that takes over the single key operations of a lossy UDP server,
+/// configured with the `udp_fallback_loss` url parameter.
+pub(crate) struct UdpFallback {
+    pub(crate) loss: Arc<UdpLoss>,
+    /// Retransmissions per request above which keys are sent over TCP.
+    pub(crate) threshold: f64,
+    pub(crate) tcp: Server,
+}
+
+impl UdpFallback {
+    /// The `udp_fallback_loss` of a `memcache+udp` url, `None` without fallback.
+    pub(crate) fn threshold(url: &Url) -> Result<Option<f64>> {
+        if url.scheme() != "memcache+udp" {
+            return Ok(None);
+        }
+        url.query_pairs()
+            .find(|(name, _)| name == "udp_fallback_loss")
+            .map(|(_, value)| {
+                value
+                    .parse()
+                    .map_err(|_| MemcachedError::BadURL(format!("Bad udp_fallback_loss: {value}")))
+            })
+            .transpose()
+    }
+
+    /// The url of the same server over TCP.
+    pub(crate) fn tcp_url(url: &Url) -> Result<Url> {
+        let mut tcp = url.clone();
+        tcp.set_scheme("memcache")
+            .map_err(|()| MemcachedError::BadURL(format!("No TCP url for {url}")))?;
+        Ok(tcp)
+    }
+}
+
+impl Server {
+    /// The server of a single key operation: the TCP fallback while the UDP loss
+    /// is above its threshold, otherwise this one.
+    pub(crate) fn single_key(&self) -> &Server {
+        match &self.udp {
+            Some(udp) if udp.loss.ratio() > udp.threshold => &udp.tcp,
+            _ => self,
+        }
+    }
+
+    /// Check a connection out of the pool, waiting for its turn if checkouts are fair.
+    /// Fails fast while a refused connect or authentication is remembered.
+    /// A connection a request was abandoned on is replaced by a new one.
+    pub(crate) async fn checkout(&self) -> Result<Checkout> {
+        self.refusals.check(&self.url)?;
+        let started = Instant::now();
+        let permit = match &self.queue {
+            Some(queue) => Some(queue.acquire().await),
+            None => None,
+        };
+        let connection = self.pool.get().await;
+        self.checkouts.record(started.elapsed());
+        let mut checkout = Checkout::new(connection?, permit);
+        // mobc never wakes its waiters once it drops a returned connection, so a poisoned one
+        // stays pooled and is replaced here, before it is used again.
+        if checkout.is_poisoned() {
+            *checkout = self.manager.connect().await?;
+        }
+        Ok(checkout)
+    }
+
+    /// Ask a dead server for its version in the background, an answer brings it back.
+    pub(crate) fn probe(&self, policy: &HealthPolicy) {
+        if !self.health.probe_due(policy) {
+            return;
+        }
+        let server = self.clone();
+        // Dropping the handle detaches the task.
+        drop(async_std::task::spawn(async move {
+            let result = async { server.checkout().await?.version().await }.await;
+            server.health.record(&result);
+        }));
+    }
+}
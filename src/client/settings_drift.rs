@@ -0,0 +1,47 @@
+use crate::ServerSettings;
+use std::collections::BTreeMap;
+
+/// Servers of one client configured differently, see [`Client::detect_drift`](crate::Client::detect_drift).
+///
+/// Nodes with less memory, a smaller `item_size_max`, fewer threads or evictions turned off
+/// evict, reject or slow down only the keys hashed to them, which looks like random misses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingsDrift {
+    /// For every setting that differs, the urls of the servers having each value, sorted.
+    /// The settings compared are `maxbytes`, `item_size_max`, `num_threads` and `evictions`.
+    pub settings: BTreeMap<&'static str, BTreeMap<String, Vec<String>>>,
+}
+
+impl SettingsDrift {
+    /// The compared setting `name` of `settings`, as reported.
+    fn value(settings: &ServerSettings, name: &str) -> String {
+        match name {
+            "maxbytes" => settings.maxbytes.to_string(),
+            "item_size_max" => settings.item_size_max.to_string(),
+            "num_threads" => settings.num_threads.to_string(),
+            _ => settings.evictions.to_string(),
+        }
+    }
+
+    /// `None` if all servers agree on the compared settings.
+    pub(crate) fn detect(servers: &[(String, ServerSettings)]) -> Option<Self> {
+        let mut drifted = BTreeMap::new();
+        for name in ["maxbytes", "item_size_max", "num_threads", "evictions"] {
+            let mut grouped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for (server, settings) in servers {
+                grouped
+                    .entry(Self::value(settings, name))
+                    .or_default()
+                    .push(server.clone());
+            }
+            if grouped.len() < 2 {
+                continue;
+            }
+            for servers in grouped.values_mut() {
+                servers.sort();
+            }
+            let _ = drifted.insert(name, grouped);
+        }
+        (!drifted.is_empty()).then_some(SettingsDrift { settings: drifted })
+    }
+}
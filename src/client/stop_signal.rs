@@ -0,0 +1,40 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// A cloneable flag used to abort a long running batch operation.
+///
+/// Batch operations check the flag between two batches, so the request in flight is
+/// always finished and nothing is left half written on the connection. Metadump scans check it
+/// between two items and close their connection when they stop halfway.
+///
+/// ## Example
+///
+/// ```rust
+/// let stop = memcached::StopSignal::new();
+/// let handle = stop.clone();
+/// handle.stop();
+/// assert!(stop.is_stopped());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct StopSignal(Arc<AtomicBool>);
+
+impl StopSignal {
+    /// Create a signal which is not stopped.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation watching this signal to stop at its next checkpoint.
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether `stop` has been called.
+    #[must_use]
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
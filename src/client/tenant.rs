@@ -0,0 +1,360 @@
+use super::{client_hash::KeyExplanation, Client};
+use crate::{
+    codec::{BincodeCodec, ValueCodec},
+    error::ClientError,
+    CasResult, Result,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::Instant,
+};
+
+/// Refills `rate` tokens per second up to a burst of one second.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32) -> Self {
+        TokenBucket {
+            rate: f64::from(rate),
+            tokens: f64::from(rate),
+            last: Instant::now(),
+        }
+    }
+
+    /// Refill, then whether `amount` tokens are available.
+    fn has(&mut self, amount: f64) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.rate);
+        self.last = now;
+        self.tokens >= amount
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.tokens -= amount;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    ops: AtomicU64,
+    bytes_written: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// Counters of the operations of one tenant.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TenantMetrics {
+    /// Operations sent to the servers.
+    pub ops: u64,
+    /// Bytes of the values written, as encoded by the codec of the client.
+    pub bytes_written: u64,
+    /// Operations refused because of a quota.
+    pub rejected: u64,
+}
+
+/// A handle restricted to the keys of one tenant, see [`Client::tenant`].
+///
+/// Keys are prefixed with `<name>:`, cluster wide operations like `flush` are not available.
+#[derive(Clone)]
+pub struct Tenant<C = BincodeCodec> {
+    client: Client<C>,
+    prefix: String,
+    ops_quota: Option<Arc<Mutex<TokenBucket>>>,
+    bytes_quota: Option<Arc<Mutex<TokenBucket>>>,
+    counters: Arc<Counters>,
+}
+
+fn lock(bucket: Option<&Arc<Mutex<TokenBucket>>>) -> Option<MutexGuard<'_, TokenBucket>> {
+    bucket.map(|bucket| bucket.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+impl<C: ValueCodec> Tenant<C> {
+    pub(crate) fn new(client: Client<C>, name: &str) -> Result<Self> {
+        // The prefix ends at the first `:`, so `a` with key `b:c` and `a:b` with key `c` can't share a key.
+        if name.contains(':') {
+            return Err(ClientError::InvalidTenantName(name.to_owned()).into());
+        }
+        Ok(Tenant {
+            client,
+            prefix: format!("{name}:"),
+            ops_quota: None,
+            bytes_quota: None,
+            counters: Arc::default(),
+        })
+    }
+
+    /// Refuse operations beyond `ops_per_sec` with [`ClientError::QuotaExceeded`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let tenant = client.tenant("ops_quota_test")?.with_ops_quota(1);
+    /// tenant.set("a", "hello", 100).await?;
+    /// tenant.set("a", "hello", 100).await.unwrap_err();
+    /// assert_eq!(tenant.metrics().rejected, 1);
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn with_ops_quota(mut self, ops_per_sec: u32) -> Self {
+        self.ops_quota = Some(Arc::new(Mutex::new(TokenBucket::new(ops_per_sec))));
+        self
+    }
+
+    /// Refuse writes beyond `bytes_per_sec` of encoded values with [`ClientError::QuotaExceeded`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+    /// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    /// let tenant = client.tenant("bytes_quota_test")?.with_bytes_quota(16);
+    /// tenant.set("small", "hello", 100).await?;
+    /// tenant.set("big", "hello world, hello world", 100).await.unwrap_err();
+    /// # Ok(()) } dbg!(foo().await.unwrap()); });
+    /// ```
+    #[must_use]
+    pub fn with_bytes_quota(mut self, bytes_per_sec: u32) -> Self {
+        self.bytes_quota = Some(Arc::new(Mutex::new(TokenBucket::new(bytes_per_sec))));
+        self
+    }
+
+    /// Counters of this tenant only.
+    #[must_use]
+    pub fn metrics(&self) -> TenantMetrics {
+        TenantMetrics {
+            ops: self.counters.ops.load(Ordering::Relaxed),
+            bytes_written: self.counters.bytes_written.load(Ordering::Relaxed),
+            rejected: self.counters.rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    /// See [`Client::explain`], the prefix is the tenant prefix.
+    #[must_use]
+    pub fn explain(&self, key: &str) -> KeyExplanation {
+        self.client.explain_prefixed(&self.prefix, key)
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    fn admit(&self, bytes: u64) -> Result<()> {
+        let amount = u32::try_from(bytes).map_or(f64::MAX, f64::from);
+        // Both quotas are checked before either is charged, a refused write costs no tokens.
+        let mut ops = lock(self.ops_quota.as_ref());
+        let mut bytes_quota = lock(self.bytes_quota.as_ref()).filter(|_| bytes > 0);
+        let admitted = ops.as_mut().is_none_or(|ops| ops.has(1.0))
+            && bytes_quota.as_mut().is_none_or(|quota| quota.has(amount));
+        if admitted {
+            if let Some(ops) = &mut ops {
+                ops.take(1.0);
+            }
+            if let Some(quota) = &mut bytes_quota {
+                quota.take(amount);
+            }
+            let _ = self.counters.ops.fetch_add(1, Ordering::Relaxed);
+            let _ = self
+                .counters
+                .bytes_written
+                .fetch_add(bytes, Ordering::Relaxed);
+            Ok(())
+        } else {
+            let _ = self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+            Err(ClientError::QuotaExceeded.into())
+        }
+    }
+
+    /// Admit a write of `value`, charged as many bytes as the codec of the client encodes it to.
+    fn admit_value<V: Serialize + 'static>(&self, value: &V) -> Result<()> {
+        let (bytes, _) = self.client.codec.encode(value)?;
+        self.admit(bytes.len() as u64)
+    }
+
+    /// See [`Client::get`].
+    pub async fn get<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+    ) -> Result<Option<V>> {
+        self.admit(0)?;
+        self.client.get(self.key(key.as_ref())).await
+    }
+
+    /// See [`Client::gets`], the returned keys are without the tenant prefix.
+    pub async fn gets<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        keys: &[K],
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
+        self.admit(0)?;
+        let keys: Vec<String> = keys.iter().map(|key| self.key(key.as_ref())).collect();
+        Ok(self
+            .client
+            .gets(&keys)
+            .await?
+            .into_iter()
+            .map(|(key, value)| match key.strip_prefix(&self.prefix) {
+                Some(key) => (key.to_owned(), value),
+                None => (key, value),
+            })
+            .collect())
+    }
+
+    /// See [`Client::set`].
+    pub async fn set<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: V,
+        expiration: u32,
+    ) -> Result<()> {
+        self.admit_value(&value)?;
+        self.client
+            .set(self.key(key.as_ref()), value, expiration)
+            .await
+    }
+
+    /// See [`Client::add`].
+    pub async fn add<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: V,
+        expiration: u32,
+    ) -> Result<()> {
+        self.admit_value(&value)?;
+        self.client
+            .add(self.key(key.as_ref()), value, expiration)
+            .await
+    }
+
+    /// See [`Client::replace`].
+    pub async fn replace<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: V,
+        expiration: u32,
+    ) -> Result<()> {
+        self.admit_value(&value)?;
+        self.client
+            .replace(self.key(key.as_ref()), value, expiration)
+            .await
+    }
+
+    /// See [`Client::append`].
+    pub async fn append<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<()> {
+        self.admit_value(&value)?;
+        self.client.append(self.key(key.as_ref()), value).await
+    }
+
+    /// See [`Client::prepend`].
+    pub async fn prepend<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<()> {
+        self.admit_value(&value)?;
+        self.client.prepend(self.key(key.as_ref()), value).await
+    }
+
+    /// See [`Client::cas`].
+    pub async fn cas<V: Serialize + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: V,
+        expiration: u32,
+        cas_id: u64,
+    ) -> Result<CasResult> {
+        self.admit_value(&value)?;
+        self.client
+            .cas(self.key(key.as_ref()), value, expiration, cas_id)
+            .await
+    }
+
+    /// See [`Client::get_and_touch`].
+    pub async fn get_and_touch<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+        expiration: u32,
+    ) -> Result<Option<V>> {
+        self.admit(0)?;
+        self.client
+            .get_and_touch(self.key(key.as_ref()), expiration)
+            .await
+    }
+
+    /// See [`Client::get_with_cas`].
+    pub async fn get_with_cas<V: DeserializeOwned + 'static, K: AsRef<str>>(
+        &self,
+        key: K,
+    ) -> Result<Option<(V, u32, u64)>> {
+        self.admit(0)?;
+        self.client.get_with_cas(self.key(key.as_ref())).await
+    }
+
+    /// See [`Client::delete`].
+    pub async fn delete<K: AsRef<str>>(&self, key: K) -> Result<bool> {
+        self.admit(0)?;
+        self.client.delete(self.key(key.as_ref())).await
+    }
+
+    /// See [`Client::increment`].
+    pub async fn increment<K: AsRef<str>>(&self, key: K, amount: u64) -> Result<u64> {
+        self.admit(0)?;
+        self.client.increment(self.key(key.as_ref()), amount).await
+    }
+
+    /// See [`Client::increment_with`].
+    pub async fn increment_with<K: AsRef<str>>(
+        &self,
+        key: K,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.admit(0)?;
+        self.client
+            .increment_with(self.key(key.as_ref()), amount, initial, expiration)
+            .await
+    }
+
+    /// See [`Client::decrement`].
+    pub async fn decrement<K: AsRef<str>>(&self, key: K, amount: u64) -> Result<u64> {
+        self.admit(0)?;
+        self.client.decrement(self.key(key.as_ref()), amount).await
+    }
+
+    /// See [`Client::decrement_with`].
+    pub async fn decrement_with<K: AsRef<str>>(
+        &self,
+        key: K,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.admit(0)?;
+        self.client
+            .decrement_with(self.key(key.as_ref()), amount, initial, expiration)
+            .await
+    }
+
+    /// See [`Client::touch`].
+    pub async fn touch<K: AsRef<str>>(&self, key: K, expiration: u32) -> Result<bool> {
+        self.admit(0)?;
+        self.client.touch(self.key(key.as_ref()), expiration).await
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+/// A source of [`Client::tiered_get`](crate::Client::tiered_get), in the order they are asked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tier {
+    /// The in-process cache, e.g. a `memcache+local://` client.
+    L1,
+    /// The memcached servers of the client.
+    Remote,
+    /// The loader computing the value.
+    Loader,
+}
+
+/// How long each tier of [`Client::tiered_get`](crate::Client::tiered_get) may take.
+/// A cache tier over its budget counts as a miss, a loader over its budget fails with
+/// [`ClientError::LoaderTimeout`](crate::error::ClientError::LoaderTimeout).
+/// The budget of a cache tier bounds its whole read, the wait for a pooled connection and
+/// connects included. The connection of a read over budget is closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TierBudget {
+    /// 1 ms by default.
+    pub l1: Duration,
+    /// 20 ms by default.
+    pub remote: Duration,
+    /// Unlimited by default.
+    pub loader: Option<Duration>,
+}
+
+impl Default for TierBudget {
+    fn default() -> Self {
+        TierBudget {
+            l1: Duration::from_millis(1),
+            remote: Duration::from_millis(20),
+            loader: None,
+        }
+    }
+}
+
+/// What asking one tier gave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TierOutcome {
+    /// The tier had the value.
+    Hit,
+    /// The tier answered without the value.
+    Miss,
+    /// The tier took longer than its budget.
+    OverBudget,
+    /// The tier failed, e.g. the server is unreachable.
+    Failed,
+}
+
+/// One tier asked by [`Client::tiered_get`](crate::Client::tiered_get).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TierAttempt {
+    /// The tier asked.
+    pub tier: Tier,
+    /// What it gave.
+    pub outcome: TierOutcome,
+    /// How long it took, at most about its budget.
+    pub elapsed: Duration,
+}
+
+/// A value read by [`Client::tiered_get`](crate::Client::tiered_get) and where it came from, for logging.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tiered<V> {
+    /// The value.
+    pub value: V,
+    /// The tier that served it.
+    pub tier: Tier,
+    /// Every tier asked, in order, the last one served the value.
+    pub attempts: Vec<TierAttempt>,
+}
+
+impl<V> Tiered<V> {
+    /// The time spent in all tiers.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.attempts.iter().map(|attempt| attempt.elapsed).sum()
+    }
+}
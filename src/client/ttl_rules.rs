@@ -0,0 +1,122 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// Expirations above this are unix timestamps to memcached, rules leave them alone.
+const MAX_RELATIVE_EXPIRATION: u32 = 30 * 24 * 60 * 60;
+
+/// How a [`TtlRule`] changes the expiration of a write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TtlAction {
+    /// Store with this expiration instead, at most 30 days.
+    Override(u32),
+    /// Multiply the expiration, rounded, at least 1 second and at most 30 days.
+    Multiply(f64),
+}
+
+/// Changes the expiration of writes to keys starting with `prefix`, for a share of the keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TtlRule {
+    /// Keys the rule applies to, as passed by the caller.
+    pub prefix: String,
+    /// The new expiration.
+    pub action: TtlAction,
+    /// The share of keys the rule applies to, from 0 to 1. Keys are picked by their md5,
+    /// so a key is always in or out of the experiment, across processes and builds.
+    pub fraction: f64,
+}
+
+/// A rule set shared with the clients using it, see [`ClientBuilder::ttl_rules`](crate::ClientBuilder::ttl_rules).
+///
+/// The first rule matching a key applies. Expirations of 0 (never) and unix timestamps are not changed.
+/// Changed expirations stay relative: memcached reads more than 30 days as a unix timestamp, long
+/// past, so they are capped at 30 days.
+///
+/// ## Example
+///
+/// ```rust
+/// use memcached::{TtlAction, TtlRule, TtlRules};
+/// let rules = TtlRules::default();
+/// assert_eq!(rules.apply("search:rust", 600), 600);
+/// // Halve the TTL of search results.
+/// rules.update(vec![TtlRule {
+///     prefix: "search:".to_owned(),
+///     action: TtlAction::Multiply(0.5),
+///     fraction: 1.0,
+/// }]);
+/// assert_eq!(rules.apply("search:rust", 600), 300);
+/// assert_eq!(rules.apply("search:rust", 0), 0);
+/// assert_eq!(rules.apply("users:42", 600), 600);
+/// // Doubling 20 days is capped at 30 days.
+/// rules.update(vec![TtlRule {
+///     prefix: "search:".to_owned(),
+///     action: TtlAction::Multiply(2.0),
+///     fraction: 1.0,
+/// }]);
+/// assert_eq!(rules.apply("search:rust", 20 * 86400), 30 * 86400);
+/// // Half of the keys, the same half in every build.
+/// rules.update(vec![TtlRule {
+///     prefix: "exp:".to_owned(),
+///     action: TtlAction::Override(60),
+///     fraction: 0.5,
+/// }]);
+/// let ttls: Vec<u32> = (0..4).map(|i| rules.apply(&format!("exp:{i}"), 600)).collect();
+/// assert_eq!(ttls, [600, 600, 60, 600]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TtlRules {
+    rules: Arc<RwLock<Vec<TtlRule>>>,
+}
+
+impl TtlRules {
+    /// Replace the rules, writes of every client sharing the set use the new ones right away.
+    pub fn update(&self, rules: Vec<TtlRule>) {
+        *self.rules.write().unwrap_or_else(PoisonError::into_inner) = rules;
+    }
+
+    /// The rules in effect.
+    #[must_use]
+    pub fn rules(&self) -> Vec<TtlRule> {
+        self.rules
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// The expiration a write of `key` with `expiration` is stored with.
+    #[must_use]
+    #[allow(clippy::cast_sign_loss)]
+    pub fn apply(&self, key: &str, expiration: u32) -> u32 {
+        if expiration == 0 || expiration > MAX_RELATIVE_EXPIRATION {
+            return expiration;
+        }
+        let rules = self.rules.read().unwrap_or_else(PoisonError::into_inner);
+        let Some(rule) = rules
+            .iter()
+            .find(|rule| key.starts_with(rule.prefix.as_str()))
+        else {
+            return expiration;
+        };
+        if !sampled(key, rule.fraction) {
+            return expiration;
+        }
+        match rule.action {
+            TtlAction::Override(expiration) => expiration.min(MAX_RELATIVE_EXPIRATION),
+            TtlAction::Multiply(factor) => (f64::from(expiration) * factor)
+                .round()
+                .clamp(1.0, f64::from(MAX_RELATIVE_EXPIRATION))
+                as u32,
+        }
+    }
+}
+
+/// Whether `key` falls into the `fraction` of keys a rule applies to.
+#[allow(clippy::cast_precision_loss)]
+fn sampled(key: &str, fraction: f64) -> bool {
+    if fraction >= 1.0 {
+        return true;
+    }
+    // `DefaultHasher` may change between Rust releases, md5 picks the same keys in every build.
+    let digest = md5::compute(key);
+    let mut hash = [0; 8];
+    hash.copy_from_slice(&digest[..8]);
+    (u64::from_le_bytes(hash) as f64 / u64::MAX as f64) < fraction
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// What one of the two reads of a verified `get` saw, see [`ClientBuilder::verify_reads`](crate::ClientBuilder::verify_reads).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadDigest {
+    /// A hash of the value bytes, only meaningful within one process.
+    pub value_hash: u64,
+    /// The flags of the item.
+    pub flags: u32,
+    /// The cas of the item, if the server returned one.
+    pub cas: Option<u64>,
+}
+
+impl ReadDigest {
+    pub(crate) fn new(value: &[u8], flags: u32, cas: Option<u64>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        ReadDigest {
+            value_hash: hasher.finish(),
+            flags,
+            cas,
+        }
+    }
+}
+
+/// Two reads of the same key on different connections disagreed, `None` is a miss.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadMismatch {
+    /// The key as sent to the server.
+    pub key: String,
+    /// The url of the server.
+    pub server: String,
+    /// What the read answering the caller saw.
+    pub first: Option<ReadDigest>,
+    /// What the verification read saw.
+    pub second: Option<ReadDigest>,
+}
+
+impl ReadMismatch {
+    pub(crate) fn compare(
+        key: &str,
+        server: &str,
+        first: Option<ReadDigest>,
+        second: Option<ReadDigest>,
+    ) -> Option<Self> {
+        (first != second).then(|| ReadMismatch {
+            key: key.to_owned(),
+            server: server.to_owned(),
+            first,
+            second,
+        })
+    }
+}
+
+/// Picks every `every`-th `get` to be verified and counts the mismatches found.
+#[derive(Debug, Default)]
+pub(crate) struct ReadSampler {
+    /// 0 verifies nothing.
+    every: u64,
+    reads: AtomicU64,
+    pub(crate) mismatches: AtomicU64,
+}
+
+impl ReadSampler {
+    pub(crate) fn new(every: u64) -> Self {
+        ReadSampler {
+            every,
+            ..ReadSampler::default()
+        }
+    }
+
+    pub(crate) fn sample(&self) -> bool {
+        self.every > 0
+            && self
+                .reads
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(self.every)
+    }
+}
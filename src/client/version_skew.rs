@@ -0,0 +1,28 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Servers of one client running different memcached versions, see [`Client::version_skew`](crate::Client::version_skew).
+///
+/// Versions differ in subtle protocol behavior, e.g. meta commands, `item_size_max` or how
+/// `flush_all` delays are applied, so a skewed cluster answers the same request differently per key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionSkew {
+    /// The urls of the servers running each version, sorted.
+    pub versions: BTreeMap<String, Vec<String>>,
+}
+
+impl VersionSkew {
+    /// `None` if all servers run the same version.
+    pub(crate) fn detect(versions: HashMap<String, String>) -> Option<Self> {
+        let mut grouped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (server, version) in versions {
+            grouped.entry(version).or_default().push(server);
+        }
+        if grouped.len() < 2 {
+            return None;
+        }
+        for servers in grouped.values_mut() {
+            servers.sort();
+        }
+        Some(VersionSkew { versions: grouped })
+    }
+}
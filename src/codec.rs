@@ -0,0 +1,295 @@
+use crate::{error::ClientError, protocol::parse, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    any::{Any, TypeId},
+    borrow::Cow,
+    fmt,
+};
+
+/// Turns values into the bytes and flags stored in memcached and back.
+///
+/// The default [`BincodeCodec`] is only understood by this crate,
+/// [`RawBytes`], [`Utf8String`] and `JsonCodec` (`json` feature) store data other clients can read.
+pub trait ValueCodec: Clone + Send + Sync + 'static {
+    /// Encode `value` into the stored bytes and flags.
+    fn encode<V: Serialize + 'static>(&self, value: &V) -> Result<(Vec<u8>, u32)>;
+    /// Decode a value from the stored bytes and flags.
+    fn decode<V: DeserializeOwned + 'static>(&self, bytes: &[u8], flags: u32) -> Result<V>;
+}
+
+fn unsupported_type<V>(codec: &str) -> ClientError {
+    ClientError::Error(Cow::Owned(format!(
+        "{codec} can't handle values of type {}",
+        std::any::type_name::<V>()
+    )))
+}
+
+/// Move `value` into `V` if it is of that type.
+fn downcast<T: 'static, V: 'static>(value: T) -> Option<V> {
+    let value: Box<dyn Any> = Box::new(value);
+    value.downcast().ok().map(|value| *value)
+}
+
+/// Strings and numbers are stored as text, other values with bincode.
+///
+/// ## Wire format
+///
+/// - Strings are their UTF-8 bytes, integers and floats their decimal text, so
+///   `increment` / `decrement` and other clients can use them.
+/// - Other values are bincode 1 with its default options: little endian, fixed size integers.
+/// - Bits 24..32 of the flags hold the [`FORMAT_VERSION`] the value was written with.
+///   Values without a version were written before it was introduced and are decoded as
+///   version 0. Values of a newer version than this crate knows fail with
+///   [`ClientError::FormatVersionMismatch`] instead of being misread.
+/// - Bits 16..24 hold the [`ValueType`] of the value, the lower 16 bits are 0. Reading a value
+///   as another type fails with [`ClientError::TypeMismatch`], numbers and strings are both text
+///   and may be read as each other. Values without a type, e.g. counters created by `increment`,
+///   are decoded as requested.
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+/// use memcached::codec::{format_version, FORMAT_VERSION};
+/// let client = memcached::connect("memcache://127.0.0.1:12345")?;
+/// client.set("format_version_test", vec![1, 2, 3], 100).await?;
+/// let t = client.gets::<Vec<u32>, _>(&["format_version_test"]).await?;
+/// assert_eq!(t["format_version_test"].0, vec![1, 2, 3]);
+/// assert_eq!(format_version(t["format_version_test"].1), FORMAT_VERSION);
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BincodeCodec;
+
+/// The version of the [`BincodeCodec`] wire format written by this crate.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The flags bits below the format version.
+const FORMAT_VERSION_SHIFT: u32 = 24;
+
+/// The flags bits below the value type.
+const VALUE_TYPE_SHIFT: u32 = 16;
+
+/// The [`BincodeCodec`] format version of a value with `flags`, 0 if it has none.
+#[must_use]
+pub fn format_version(flags: u32) -> u32 {
+    flags >> FORMAT_VERSION_SHIFT
+}
+
+/// What a [`BincodeCodec`] value was written from, stored in its flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    /// An integer or float, stored as decimal text.
+    Numeric,
+    /// A `String`, `&str` or `bool`, stored as text.
+    Utf8,
+    /// Raw bytes, `Vec<u8>` or `&[u8]`.
+    Bytes,
+    /// Any other value, stored with bincode.
+    Bincode,
+}
+
+impl ValueType {
+    /// The type values of `V` are stored as.
+    #[must_use]
+    pub fn of<V: 'static>() -> Self {
+        let id = TypeId::of::<V>();
+        let is = |ids: &[TypeId]| ids.contains(&id);
+        if is(&[
+            TypeId::of::<u8>(),
+            TypeId::of::<u16>(),
+            TypeId::of::<u32>(),
+            TypeId::of::<u64>(),
+            TypeId::of::<u128>(),
+            TypeId::of::<i8>(),
+            TypeId::of::<i16>(),
+            TypeId::of::<i32>(),
+            TypeId::of::<i64>(),
+            TypeId::of::<i128>(),
+            TypeId::of::<f32>(),
+            TypeId::of::<f64>(),
+        ]) {
+            ValueType::Numeric
+        } else if is(&[
+            TypeId::of::<String>(),
+            TypeId::of::<&str>(),
+            TypeId::of::<bool>(),
+        ]) {
+            ValueType::Utf8
+        } else if is(&[TypeId::of::<Vec<u8>>(), TypeId::of::<&[u8]>()]) {
+            ValueType::Bytes
+        } else {
+            ValueType::Bincode
+        }
+    }
+
+    /// The type in `flags`, `None` for values written without one.
+    #[must_use]
+    pub fn from_flags(flags: u32) -> Option<Self> {
+        match (flags >> VALUE_TYPE_SHIFT) & 0xff {
+            1 => Some(ValueType::Numeric),
+            2 => Some(ValueType::Utf8),
+            3 => Some(ValueType::Bytes),
+            4 => Some(ValueType::Bincode),
+            _ => None,
+        }
+    }
+
+    fn flags(self) -> u32 {
+        let tag = match self {
+            ValueType::Numeric => 1,
+            ValueType::Utf8 => 2,
+            ValueType::Bytes => 3,
+            ValueType::Bincode => 4,
+        };
+        tag << VALUE_TYPE_SHIFT
+    }
+
+    /// Whether a value stored as `self` can be read as `requested`. Numbers and strings are both
+    /// text, a string may hold a counter and a number is read as its digits.
+    fn readable_as(self, requested: ValueType) -> bool {
+        let text = |t| matches!(t, ValueType::Numeric | ValueType::Utf8);
+        self == requested || (text(self) && text(requested))
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ValueType::Numeric => "numeric string",
+            ValueType::Utf8 => "utf8 string",
+            ValueType::Bytes => "raw bytes",
+            ValueType::Bincode => "bincode struct",
+        })
+    }
+}
+
+impl ValueCodec for BincodeCodec {
+    fn encode<V: Serialize + 'static>(&self, value: &V) -> Result<(Vec<u8>, u32)> {
+        Ok((
+            parse::serialize_bytes(value)?,
+            FORMAT_VERSION << FORMAT_VERSION_SHIFT | ValueType::of::<V>().flags(),
+        ))
+    }
+
+    fn decode<V: DeserializeOwned + 'static>(&self, bytes: &[u8], flags: u32) -> Result<V> {
+        let requested = ValueType::of::<V>();
+        if let Some(stored) = ValueType::from_flags(flags) {
+            if !stored.readable_as(requested) {
+                return Err(ClientError::TypeMismatch { stored, requested }.into());
+            }
+        }
+        match format_version(flags) {
+            // Written before the version was stored, the bytes are laid out like version 1.
+            0 | FORMAT_VERSION => parse::deserialize_bytes(bytes),
+            found => Err(ClientError::FormatVersionMismatch {
+                found,
+                supported: FORMAT_VERSION,
+            }
+            .into()),
+        }
+    }
+}
+
+/// Bytes are stored as they are, values must be `Vec<u8>` or `&'static [u8]` and are read as `Vec<u8>`.
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+/// use memcached::codec::RawBytes;
+/// let client = memcached::connect("memcache://127.0.0.1:12345")?.with_codec(RawBytes);
+/// client.set("raw_bytes_test", vec![1u8, 2, 3], 100).await?;
+/// let t: Option<Vec<u8>> = client.get("raw_bytes_test").await?;
+/// assert_eq!(t, Some(vec![1, 2, 3]));
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RawBytes;
+
+impl ValueCodec for RawBytes {
+    fn encode<V: Serialize + 'static>(&self, value: &V) -> Result<(Vec<u8>, u32)> {
+        let value: &dyn Any = value;
+        if let Some(bytes) = value.downcast_ref::<Vec<u8>>() {
+            Ok((bytes.clone(), 0))
+        } else if let Some(bytes) = value.downcast_ref::<&[u8]>() {
+            Ok((bytes.to_vec(), 0))
+        } else {
+            Err(unsupported_type::<V>("RawBytes").into())
+        }
+    }
+
+    fn decode<V: DeserializeOwned + 'static>(&self, bytes: &[u8], _flags: u32) -> Result<V> {
+        downcast(bytes.to_vec()).ok_or_else(|| unsupported_type::<V>("RawBytes").into())
+    }
+}
+
+/// UTF-8 text, values must be `String` or `&'static str` and are read as `String`.
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+/// use memcached::codec::Utf8String;
+/// let client = memcached::connect("memcache://127.0.0.1:12345")?.with_codec(Utf8String);
+/// client.set("utf8_string_test", "hello", 100).await?;
+/// let t: Option<String> = client.get("utf8_string_test").await?;
+/// assert_eq!(t, Some("hello".to_owned()));
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Utf8String;
+
+impl ValueCodec for Utf8String {
+    fn encode<V: Serialize + 'static>(&self, value: &V) -> Result<(Vec<u8>, u32)> {
+        let value: &dyn Any = value;
+        if let Some(s) = value.downcast_ref::<String>() {
+            Ok((s.as_bytes().to_vec(), 0))
+        } else if let Some(s) = value.downcast_ref::<&str>() {
+            Ok((s.as_bytes().to_vec(), 0))
+        } else {
+            Err(unsupported_type::<V>("Utf8String").into())
+        }
+    }
+
+    fn decode<V: DeserializeOwned + 'static>(&self, bytes: &[u8], _flags: u32) -> Result<V> {
+        downcast(String::from_utf8(bytes.to_vec())?)
+            .ok_or_else(|| unsupported_type::<V>("Utf8String").into())
+    }
+}
+
+/// JSON documents, stored with flags 6 like PHP memcached's JSON serializer.
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+/// use memcached::codec::JsonCodec;
+/// let client = memcached::connect("memcache://127.0.0.1:12345")?.with_codec(JsonCodec);
+/// client.set("json_codec_test", vec![1, 2, 3], 100).await?;
+/// let t: Option<Vec<u32>> = client.get("json_codec_test").await?;
+/// assert_eq!(t, Some(vec![1, 2, 3]));
+/// let t = client.gets::<Vec<u32>, _>(&["json_codec_test"]).await?;
+/// assert_eq!(t["json_codec_test"].1, JsonCodec::FLAGS);
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+#[cfg(feature = "json")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl JsonCodec {
+    /// The flags of JSON values.
+    pub const FLAGS: u32 = 6;
+}
+
+#[cfg(feature = "json")]
+impl ValueCodec for JsonCodec {
+    fn encode<V: Serialize + 'static>(&self, value: &V) -> Result<(Vec<u8>, u32)> {
+        Ok((serde_json::to_vec(value)?, Self::FLAGS))
+    }
+
+    fn decode<V: DeserializeOwned + 'static>(&self, bytes: &[u8], _flags: u32) -> Result<V> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
@@ -1,13 +1,75 @@
-use crate::{error::MemcachedError, protocol::BinaryProtocol, stream::Stream, Result};
-use async_std::net::TcpStream;
+#[cfg(feature = "tls")]
+use crate::stream::TlsStream;
+use crate::{
+    capture::Capture,
+    client::health::RefusalCache,
+    error::{CommandError, MemcachedError, WriteState},
+    metrics::Timings,
+    protocol::{OutOfMemoryRetry, Protocol, ProtocolTrait},
+    stream::{
+        LocalStream, ReplayStream, Retransmission, Stream, Timeouts, Transport, UdpLoss, UdpStream,
+    },
+    Result,
+};
+use async_std::{future::timeout, net::TcpStream};
 use mobc::{async_trait, Manager};
-use std::ops::{Deref, DerefMut};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 use url::Url;
 
+/// What a server supports, probed after connect and auth on the first connection to it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ServerCapabilities {
+    /// The version the server reported.
+    pub version: String,
+    /// Meta commands (`mg`, `ms`, ...), memcached 1.6 and later.
+    pub meta_protocol: bool,
+    /// Get and touch, memcached 1.5.3 and later.
+    pub gat: bool,
+    /// The SASL mechanisms offered, empty if SASL is disabled.
+    pub sasl_mechanisms: Vec<String>,
+    /// The largest item the server accepts, from `stats settings`.
+    pub item_size_max: Option<u64>,
+}
+
+/// `major.minor.patch` of versions like `1.6.21` or `1.5.3-rc1`.
+fn parse_version(version: &str) -> (u32, u32, u32) {
+    let mut parts = version.split(|c: char| !c.is_ascii_digit()).map(str::parse);
+    let mut next = || parts.next().and_then(std::result::Result::ok).unwrap_or(0);
+    (next(), next(), next())
+}
+
+impl ServerCapabilities {
+    async fn probe(protocol: &mut Protocol) -> Result<Self> {
+        let version = protocol.version().await?;
+        let parsed = parse_version(&version);
+        // Servers without SASL or `stats settings` answer with an error.
+        let sasl_mechanisms = protocol.sasl_mechanisms().await.unwrap_or_default();
+        let item_size_max = protocol
+            .stats_group("settings")
+            .await
+            .ok()
+            .and_then(|settings| settings.get("item_size_max")?.parse().ok());
+        Ok(ServerCapabilities {
+            meta_protocol: parsed >= (1, 6, 0),
+            gat: parsed >= (1, 5, 3),
+            version,
+            sasl_mechanisms,
+            item_size_max,
+        })
+    }
+}
+
 /// A connection to the memcached server
 pub(crate) struct Connection {
-    pub(crate) protocol: BinaryProtocol,
+    pub(crate) protocol: Protocol,
     pub(crate) url: String,
+    pub(crate) capabilities: ServerCapabilities,
+    /// Username and password from the url, to authenticate again when the server asks for it.
+    credentials: Option<(String, String)>,
 }
 
 impl DerefMut for Connection {
@@ -17,34 +79,201 @@ impl DerefMut for Connection {
 }
 
 impl Deref for Connection {
-    type Target = BinaryProtocol;
+    type Target = Protocol;
     fn deref(&self) -> &Self::Target {
         &self.protocol
     }
 }
 
 async fn tcp_stream(url: &Url) -> Result<Stream> {
-    Ok(Stream::Tcp(
-        TcpStream::connect(&*url.socket_addrs(|| None)?).await?,
+    Ok(Transport::Tcp(TcpStream::connect(&*url.socket_addrs(|| None)?).await?).into())
+}
+
+/// `memcache+unix:///path/to/memcached.sock`.
+#[cfg(unix)]
+async fn unix_stream(url: &Url) -> Result<Stream> {
+    use async_std::os::unix::net::UnixStream;
+    Ok(Transport::Unix(UnixStream::connect(url.path()).await?).into())
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::unused_async)]
+async fn unix_stream(url: &Url) -> Result<Stream> {
+    Err(MemcachedError::BadURL(format!(
+        "Unix domain sockets are not supported on this platform: {url}"
+    )))
+}
+
+#[cfg(feature = "tls")]
+async fn tls_stream(url: &Url) -> Result<Stream> {
+    Ok(Transport::Tls(Box::new(TlsStream::connect(url).await?)).into())
+}
+
+#[cfg(not(feature = "tls"))]
+#[allow(clippy::unused_async)]
+async fn tls_stream(url: &Url) -> Result<Stream> {
+    Err(MemcachedError::BadURL(format!(
+        "Enable the `tls` feature to connect to {url}"
+    )))
+}
+
+async fn udp_stream(url: &Url, loss: &Arc<UdpLoss>) -> Result<Stream> {
+    Ok(Transport::Udp(Box::new(
+        UdpStream::connect(
+            &url.socket_addrs(|| None)?,
+            Retransmission::from_url(url)?,
+            Arc::clone(loss),
+        )
+        .await?,
     ))
+    .into())
 }
 
 impl Connection {
     pub(crate) fn get_url(&self) -> String {
-        self.url.to_string()
+        self.url.clone()
+    }
+    /// Forget the phases of earlier requests, e.g. the liveness check of the pool.
+    pub(crate) fn start_timing(&mut self) {
+        let _ = self.protocol.stream().take_timings(Duration::default());
+        self.protocol.stream().restart_recording();
+    }
+    /// The bytes of the request since the timing started and `error` it failed with,
+    /// `None` unless the connection is recorded.
+    pub(crate) fn take_capture(&mut self, op: &str, error: &MemcachedError) -> Option<Capture> {
+        let ascii = self.protocol.is_ascii();
+        let recording = self.protocol.stream().take_recording()?;
+        Some(Capture {
+            op: op.to_owned(),
+            url: self.url.clone(),
+            ascii,
+            error: error.to_string(),
+            sent: recording.sent,
+            received: recording.received,
+        })
+    }
+    /// Bytes read since the timing started.
+    pub(crate) fn bytes_read(&mut self) -> u64 {
+        self.protocol.stream().bytes_read()
+    }
+    /// Bytes sent since the timing started.
+    pub(crate) fn bytes_written(&mut self) -> u64 {
+        self.protocol.stream().bytes_written()
+    }
+    pub(crate) fn take_timings(&mut self, pool_wait: Duration) -> Timings {
+        self.protocol.stream().take_timings(pool_wait)
+    }
+    /// Authenticate again with the credentials of the url, e.g. after a SASL proxy lost the session.
+    pub(crate) async fn reauthenticate(&mut self) -> Result<()> {
+        let Some((username, password)) = self.credentials.clone() else {
+            return Err(CommandError::AuthenticationRequired.into());
+        };
+        self.protocol.auth(&username, &password).await
     }
-    async fn connect(url: &Url) -> Result<Self> {
-        let stream = tcp_stream(url).await?;
-        let protocol = BinaryProtocol { stream };
+
+    /// Replace the connection before it is used again, see `Server::checkout`.
+    pub(crate) fn poison(&mut self) {
+        self.protocol.stream().poison();
+    }
+    /// Whether a request was abandoned halfway, the stream may still hold its response.
+    pub(crate) fn is_poisoned(&mut self) -> bool {
+        self.protocol.stream().is_poisoned()
+    }
+    /// Replace the connection unless `end_request` is called, see `Stream::begin_request`.
+    pub(crate) fn begin_request(&mut self) {
+        self.protocol.stream().begin_request();
+    }
+    pub(crate) fn end_request(&mut self) {
+        self.protocol.stream().end_request();
+    }
+    /// How much of the request since the timing started was sent.
+    pub(crate) fn write_state(&mut self) -> WriteState {
+        self.protocol.stream().write_state()
+    }
+    async fn connect(
+        url: &Url,
+        timeouts: Timeouts,
+        nodelay: bool,
+        record: bool,
+        udp_loss: &Arc<UdpLoss>,
+    ) -> Result<Self> {
+        let stream = async {
+            match url.scheme() {
+                "memcache+udp" => udp_stream(url, udp_loss).await,
+                "memcache+tls" => tls_stream(url).await,
+                "memcache+unix" => unix_stream(url).await,
+                "memcache+local" => {
+                    Ok(Transport::Local(Box::new(LocalStream::connect(url)?)).into())
+                }
+                "memcache+replay" => {
+                    Ok(Transport::Replay(Box::new(ReplayStream::connect(url)?)).into())
+                }
+                _ => tcp_stream(url).await,
+            }
+        };
+        let stream = match timeouts.connect {
+            Some(limit) => timeout(limit, stream)
+                .await
+                .map_err(|_| MemcachedError::Timeout(WriteState::NotWritten))??,
+            None => stream.await?,
+        }
+        .with_timeouts(timeouts);
+        let stream = if record { stream.recorded() } else { stream };
+        stream.set_nodelay(nodelay)?;
+        let name = url
+            .query_pairs()
+            .find(|(name, _)| name == "protocol")
+            .map(|(_, value)| value);
+        let protocol = Protocol::new(name.as_deref(), stream)?;
         Ok(Connection {
             url: url.to_string(),
             protocol,
+            capabilities: ServerCapabilities::default(),
+            credentials: None,
         })
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct ConnectionManager {
     pub(crate) url: Url,
+    pub(crate) timeouts: Timeouts,
+    /// Disable Nagle's algorithm on new connections.
+    pub(crate) nodelay: bool,
+    /// Check liveness with a `noop` instead of `version`.
+    pub(crate) noop_check: bool,
+    /// Keep the bytes of each request for captures of failed operations.
+    pub(crate) record: bool,
+    /// Yield to other tasks after this many values of a multi-get reply, 0 never.
+    pub(crate) parse_yield_every: usize,
+    /// Store once more when the server is out of memory.
+    pub(crate) out_of_memory_retry: Option<OutOfMemoryRetry>,
+    pub(crate) refusals: Arc<RefusalCache>,
+    /// Shared by the UDP connections of the server and its `UdpFallback`.
+    pub(crate) udp_loss: Arc<UdpLoss>,
+    /// Shared with the `Server`, set by the first successful probe.
+    pub(crate) capabilities: Arc<OnceLock<ServerCapabilities>>,
+}
+
+impl ConnectionManager {
+    /// Open a connection and authenticate it if the url has credentials.
+    async fn dial(&self) -> Result<Connection> {
+        let url = &self.url;
+        let mut connection = Connection::connect(
+            url,
+            self.timeouts,
+            self.nodelay,
+            self.record,
+            &self.udp_loss,
+        )
+        .await?;
+        if url.has_authority() && !url.username().is_empty() && url.password().is_some() {
+            let username = url.username();
+            let password = url.password().unwrap_or("");
+            connection.auth(username, password).await?;
+            connection.credentials = Some((username.to_owned(), password.to_owned()));
+        }
+        Ok(connection)
+    }
 }
 
 #[async_trait]
@@ -54,13 +283,31 @@ impl Manager for ConnectionManager {
     type Error = MemcachedError;
     /// Attempts to create a new connection.
     async fn connect(&self) -> std::result::Result<Self::Connection, Self::Error> {
-        let url = &self.url;
-        let mut connection = Connection::connect(url).await?;
-        if url.has_authority() && !url.username().is_empty() && url.password().is_some() {
-            let username = url.username();
-            let password = url.password().unwrap_or("");
-            connection.auth(username, password).await?;
+        let dialed = self.dial().await;
+        self.refusals.record(&dialed);
+        let mut connection = dialed?;
+        // A replayed capture holds the response of one operation only.
+        if !connection.protocol.stream().is_replay() {
+            connection.capabilities = match self.capabilities.get() {
+                Some(capabilities) => capabilities.clone(),
+                // A server that answers the probe with an error gets the defaults, and is
+                // probed again on its next connection. A connection lost meanwhile is unusable.
+                None => match ServerCapabilities::probe(&mut connection.protocol).await {
+                    Ok(probed) => self.capabilities.get_or_init(|| probed).clone(),
+                    Err(e @ (MemcachedError::IOError(_) | MemcachedError::Timeout(_))) => {
+                        return Err(e)
+                    }
+                    Err(_) => ServerCapabilities::default(),
+                },
+            };
         }
+        connection
+            .protocol
+            .set_item_size_max(connection.capabilities.item_size_max);
+        connection.protocol.set_yield_every(self.parse_yield_every);
+        connection
+            .protocol
+            .set_out_of_memory_retry(self.out_of_memory_retry);
         Ok(connection)
     }
     /// Determines if the connection is still connected to the database.
@@ -71,7 +318,15 @@ impl Manager for ConnectionManager {
         &self,
         mut conn: Self::Connection,
     ) -> std::result::Result<Self::Connection, Self::Error> {
-        let _ = conn.version().await?;
+        // A poisoned connection is replaced at checkout, its stream may hold a stale response.
+        if conn.protocol.stream().is_replay() || conn.protocol.stream().is_poisoned() {
+            return Ok(conn);
+        }
+        if self.noop_check {
+            conn.noop().await?;
+        } else {
+            let _ = conn.version().await?;
+        }
         Ok(conn)
     }
 }
@@ -15,6 +15,61 @@ pub enum ClientError {
     Error(Cow<'static, str>),
     ///connections is empty
     ConnectionsIsEmpty,
+    /// A tenant used up its ops/sec or bytes/sec quota.
+    QuotaExceeded,
+    /// The tenant name contains the `:` ending the tenant prefix, so its keys could be another
+    /// tenant's, see [`Client::tenant`](crate::Client::tenant).
+    InvalidTenantName(String),
+    /// The serialized value is larger than the `item_size_max` of the server.
+    ValueTooLarge {
+        /// Serialized size of the value in bytes.
+        size: usize,
+        /// The `item_size_max` of the server in bytes.
+        limit: u64,
+    },
+    /// The key starts with a code of the key dictionary without starting with its prefix,
+    /// it could not be told apart from a shortened key.
+    KeyCollision(String),
+    /// The value was written in a newer [`BincodeCodec`](crate::codec::BincodeCodec) format than this crate reads.
+    FormatVersionMismatch {
+        /// The format version in the flags of the value.
+        found: u32,
+        /// The newest format version this crate reads.
+        supported: u32,
+    },
+    /// The value was written from another type than it is read as, e.g. a struct read as a
+    /// `String`, see [`ValueType`](crate::codec::ValueType).
+    TypeMismatch {
+        /// The type in the flags of the value.
+        stored: crate::codec::ValueType,
+        /// The type the value was read as.
+        requested: crate::codec::ValueType,
+    },
+    /// The server refused the last connect or authentication less than
+    /// [`ClientBuilder::refusal_ttl`](crate::ClientBuilder::refusal_ttl) ago, it was not dialed again.
+    ServerRefused {
+        /// The url of the server.
+        server: String,
+        /// Why the last attempt failed.
+        reason: String,
+    },
+    /// The client routes keys differently than the snapshot it was verified against,
+    /// see [`Client::verify_routing`](crate::Client::verify_routing).
+    RoutingMismatch(String),
+    /// The [`KillSwitch`](crate::KillSwitch) of the client is engaged, nothing was sent.
+    CacheBypassed,
+    /// The loader of [`Client::tiered_get`](crate::Client::tiered_get) took longer than its budget.
+    LoaderTimeout(std::time::Duration),
+    /// Chunks of a value of [`Client::set_chunked`](crate::Client::set_chunked) are missing, of
+    /// another write, or do not add up to the checksum of its manifest.
+    PartialValue {
+        /// The key of the manifest.
+        key: String,
+        /// The chunks of the manifest.
+        chunks: u32,
+        /// The chunks missing or of another write, all of them if only the checksum is wrong.
+        missing: u32,
+    },
 }
 
 impl fmt::Display for ClientError {
@@ -22,6 +77,40 @@ impl fmt::Display for ClientError {
         match self {
             ClientError::KeyTooLong => write!(f, "The provided key was too long."),
             ClientError::ConnectionsIsEmpty => write!(f, "The Connections is empty."),
+            ClientError::QuotaExceeded => write!(f, "The tenant quota was exceeded."),
+            ClientError::InvalidTenantName(name) => {
+                write!(f, "The tenant name {name} contains a ':'.")
+            }
+            ClientError::ValueTooLarge { size, limit } => write!(
+                f,
+                "The value of {size} bytes exceeds the item_size_max of {limit} bytes."
+            ),
+            ClientError::KeyCollision(key) => {
+                write!(f, "The key {key} starts with a code of the key dictionary.")
+            }
+            ClientError::FormatVersionMismatch { found, supported } => write!(
+                f,
+                "The value has format version {found}, this crate reads up to {supported}."
+            ),
+            ClientError::TypeMismatch { stored, requested } => {
+                write!(f, "The value is a {stored}, it was read as a {requested}.")
+            }
+            ClientError::ServerRefused { server, reason } => {
+                write!(f, "{server} refused the last connection: {reason}")
+            }
+            ClientError::CacheBypassed => write!(f, "The cache is bypassed by the kill switch."),
+            ClientError::LoaderTimeout(budget) => {
+                write!(f, "The loader took longer than its budget of {budget:?}.")
+            }
+            ClientError::PartialValue {
+                key,
+                chunks,
+                missing,
+            } => write!(
+                f,
+                "The chunked value of {key} is incomplete, {missing} of {chunks} chunks are missing or corrupt."
+            ),
+            ClientError::RoutingMismatch(reason) => write!(f, "The routing differs: {reason}"),
             ClientError::Error(s) => write!(f, "{s}"),
         }
     }
@@ -77,6 +166,10 @@ pub enum CommandError {
     Unknown(u16),
     /// The client sent an invalid command to the server.
     InvalidCommand,
+    /// The server had no memory left to store the item, e.g. `SERVER_ERROR out of memory storing object`
+    /// while its slab class is under eviction pressure. Usually transient, see [`MemcachedError::is_retryable`]
+    /// and [`ClientBuilder::retry_out_of_memory`](crate::ClientBuilder::retry_out_of_memory).
+    OutOfMemory,
 }
 
 impl From<String> for ClientError {
@@ -104,6 +197,7 @@ impl fmt::Display for CommandError {
             }
             CommandError::Unknown(code) => write!(f, "Unknown error occurred with code: {code}."),
             CommandError::InvalidCommand => write!(f, "Invalid command sent to the server."),
+            CommandError::OutOfMemory => write!(f, "Out of memory storing the item."),
         }
     }
 }
@@ -117,6 +211,7 @@ impl From<u16> for CommandError {
             0x4 => CommandError::InvalidArguments,
             0x6 => CommandError::IncrOrDecrOnNonNumericValue,
             0x20 => CommandError::AuthenticationRequired,
+            0x82 => CommandError::OutOfMemory,
             e => CommandError::Unknown(e),
         }
     }
@@ -133,16 +228,22 @@ impl From<ServerError> for MemcachedError {
         MemcachedError::ServerError(err)
     }
 }
+/// Values, numbers or urls that could not be parsed.
+///
+/// Features add variants, so matches need a wildcard arm.
 #[allow(missing_docs)]
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ParseError {
     Bool(str::ParseBoolError),
     Int(num::ParseIntError),
     Float(num::ParseFloatError),
     String(string::FromUtf8Error),
-    Str(std::str::Utf8Error),
+    Str(str::Utf8Error),
     Url(url::ParseError),
     Bincode(bincode::Error),
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
 }
 
 impl error::Error for ParseError {
@@ -155,6 +256,8 @@ impl error::Error for ParseError {
             ParseError::Str(ref e) => e.source(),
             ParseError::Url(ref e) => e.source(),
             ParseError::Bincode(ref e) => e.source(),
+            #[cfg(feature = "json")]
+            ParseError::Json(ref e) => e.source(),
         }
     }
 }
@@ -169,6 +272,8 @@ impl fmt::Display for ParseError {
             ParseError::Str(ref e) => e.fmt(f),
             ParseError::Url(ref e) => e.fmt(f),
             ParseError::Bincode(ref e) => e.fmt(f),
+            #[cfg(feature = "json")]
+            ParseError::Json(ref e) => e.fmt(f),
         }
     }
 }
@@ -185,8 +290,8 @@ impl From<string::FromUtf8Error> for MemcachedError {
     }
 }
 
-impl From<std::str::Utf8Error> for MemcachedError {
-    fn from(err: std::str::Utf8Error) -> MemcachedError {
+impl From<str::Utf8Error> for MemcachedError {
+    fn from(err: str::Utf8Error) -> MemcachedError {
         ParseError::Str(err).into()
     }
 }
@@ -216,10 +321,12 @@ impl From<str::ParseBoolError> for MemcachedError {
 }
 
 /// Stands for errors raised from rust-memcache
+///
+/// Features add variants, so matches need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum MemcachedError {
-    /// Error raised when the provided memcache URL doesn't have a host name
-    #[cfg(feature = "tls")]
+    /// Error raised when the provided memcache URL can't be used to connect
     BadURL(String),
     /// `std::io` related errors.
     IOError(io::Error),
@@ -229,18 +336,84 @@ pub enum MemcachedError {
     ServerError(ServerError),
     /// Command specific Errors
     CommandError(CommandError),
+    /// TLS setup or handshake errors
     #[cfg(feature = "tls")]
-    OpensslError(openssl::ssl::HandshakeError<std::net::TcpStream>),
+    OpensslError(openssl::ssl::Error),
     /// Parse errors
     ParseError(ParseError),
     /// pool error
     PoolError(&'static str),
+    /// Connecting, reading or writing took longer than the configured timeout,
+    /// with how much of the request had been sent.
+    Timeout(WriteState),
+}
+
+/// How much of a request was sent when it timed out, see [`MemcachedError::Timeout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteState {
+    /// Nothing was sent, e.g. connecting or waiting for a pooled connection timed out.
+    NotWritten,
+    /// The request was sent in part, the server may have received some of its commands.
+    Partial,
+    /// The request was sent in full and the response did not arrive in time.
+    Written,
+}
+
+impl WriteState {
+    /// Whether the server may have applied the request, so a non-idempotent operation
+    /// like `increment` or `append` must not simply be sent again.
+    #[must_use]
+    pub fn may_be_applied(self) -> bool {
+        self != WriteState::NotWritten
+    }
+}
+
+impl fmt::Display for WriteState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WriteState::NotWritten => write!(f, "not sent"),
+            WriteState::Partial => write!(f, "sent in part"),
+            WriteState::Written => write!(f, "sent"),
+        }
+    }
+}
+
+impl MemcachedError {
+    /// Errors that say nothing about the command, only that the server could not be reached.
+    pub(crate) fn is_connection_error(&self) -> bool {
+        match self {
+            MemcachedError::IOError(_)
+            | MemcachedError::PoolError(_)
+            | MemcachedError::Timeout(_)
+            | MemcachedError::ClientError(ClientError::ServerRefused { .. }) => true,
+            #[cfg(feature = "tls")]
+            MemcachedError::OpensslError(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether sending the same request again may succeed: the server was out of memory,
+    /// or the request timed out before any of it was sent.
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            MemcachedError::CommandError(CommandError::OutOfMemory)
+                | MemcachedError::Timeout(WriteState::NotWritten)
+        )
+    }
+
+    /// Errors that may leave unread responses on the connection. Command errors are read in full:
+    /// readers of multi-response replies read on to the end, or close the connection themselves
+    /// when they stop early.
+    pub(crate) fn breaks_connection(&self) -> bool {
+        !matches!(self, MemcachedError::CommandError(_))
+    }
 }
 
 impl fmt::Display for MemcachedError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            #[cfg(feature = "tls")]
             MemcachedError::BadURL(ref s) => s.fmt(f),
             MemcachedError::IOError(ref err) => err.fmt(f),
             #[cfg(feature = "tls")]
@@ -250,6 +423,9 @@ impl fmt::Display for MemcachedError {
             MemcachedError::ServerError(ref err) => err.fmt(f),
             MemcachedError::CommandError(ref err) => err.fmt(f),
             MemcachedError::PoolError(ref err) => err.fmt(f),
+            MemcachedError::Timeout(state) => {
+                write!(f, "Operation timed out, the request was {state}.")
+            }
         }
     }
 }
@@ -257,7 +433,6 @@ impl fmt::Display for MemcachedError {
 impl error::Error for MemcachedError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
-            #[cfg(feature = "tls")]
             MemcachedError::BadURL(_) => None,
             MemcachedError::IOError(ref err) => err.source(),
             #[cfg(feature = "tls")]
@@ -266,20 +441,97 @@ impl error::Error for MemcachedError {
             MemcachedError::ClientError(_)
             | MemcachedError::ServerError(_)
             | MemcachedError::CommandError(_)
-            | MemcachedError::PoolError(_) => None,
+            | MemcachedError::PoolError(_)
+            | MemcachedError::Timeout(_) => None,
+        }
+    }
+}
+
+/// The results of an operation sent to every server, see [`MultiError`].
+pub type MultiResult<T> = Result<Vec<(String, T)>, MultiError<T>>;
+
+/// The outcome of an operation sent to every server when at least one server failed, e.g. of
+/// [`Client::flush_each`](crate::Client::flush_each). Every server was tried, in the order of the client.
+///
+/// Converts into the [`MemcachedError`] of the first failure, so `?` works in functions
+/// returning [`memcached::Result`](crate::Result).
+#[derive(Debug)]
+pub struct MultiError<T> {
+    /// The urls of the servers that succeeded, with their results.
+    pub successes: Vec<(String, T)>,
+    /// The urls of the servers that failed, with their errors.
+    pub failures: Vec<(String, MemcachedError)>,
+}
+
+impl<T> MultiError<T> {
+    /// `Ok` with every result unless a server failed.
+    pub(crate) fn collect(results: Vec<(String, crate::Result<T>)>) -> MultiResult<T> {
+        let mut successes = Vec::with_capacity(results.len());
+        let mut failures = vec![];
+        for (url, result) in results {
+            match result {
+                Ok(value) => successes.push((url, value)),
+                Err(err) => failures.push((url, err)),
+            }
+        }
+        if failures.is_empty() {
+            Ok(successes)
+        } else {
+            Err(MultiError {
+                successes,
+                failures,
+            })
         }
     }
 }
 
+impl<T> fmt::Display for MultiError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} servers failed",
+            self.failures.len(),
+            self.failures.len() + self.successes.len()
+        )?;
+        for (i, (url, err)) in self.failures.iter().enumerate() {
+            write!(f, "{} {url}: {err}", if i == 0 { ":" } else { ";" })?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: fmt::Debug> error::Error for MultiError<T> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.failures
+            .first()
+            .map(|(_, err)| err as &(dyn error::Error + 'static))
+    }
+}
+
+impl<T> From<MultiError<T>> for MemcachedError {
+    fn from(err: MultiError<T>) -> MemcachedError {
+        err.failures
+            .into_iter()
+            .next()
+            .map_or(MemcachedError::PoolError("no server failed"), |(_, err)| {
+                err
+            })
+    }
+}
+
 impl From<io::Error> for MemcachedError {
     fn from(err: io::Error) -> MemcachedError {
         MemcachedError::IOError(err)
     }
 }
 
-impl<T> From<mobc::Error<T>> for MemcachedError {
-    fn from(_: mobc::Error<T>) -> MemcachedError {
-        MemcachedError::PoolError("mobc error")
+impl From<mobc::Error<MemcachedError>> for MemcachedError {
+    fn from(err: mobc::Error<MemcachedError>) -> MemcachedError {
+        match err {
+            mobc::Error::Inner(err) => err,
+            mobc::Error::Timeout => MemcachedError::Timeout(WriteState::NotWritten),
+            mobc::Error::BadConn => MemcachedError::PoolError("mobc error"),
+        }
     }
 }
 
@@ -289,18 +541,23 @@ impl From<bincode::Error> for MemcachedError {
     }
 }
 
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for MemcachedError {
+    fn from(e: serde_json::Error) -> MemcachedError {
+        MemcachedError::ParseError(ParseError::Json(e))
+    }
+}
+
 #[cfg(feature = "tls")]
 impl From<openssl::error::ErrorStack> for MemcachedError {
     fn from(err: openssl::error::ErrorStack) -> MemcachedError {
-        MemcachedError::OpensslError(openssl::ssl::HandshakeError::<std::net::TcpStream>::from(
-            err,
-        ))
+        MemcachedError::OpensslError(err.into())
     }
 }
 
 #[cfg(feature = "tls")]
-impl From<openssl::ssl::HandshakeError<std::net::TcpStream>> for MemcachedError {
-    fn from(err: openssl::ssl::HandshakeError<std::net::TcpStream>) -> MemcachedError {
+impl From<openssl::ssl::Error> for MemcachedError {
+    fn from(err: openssl::ssl::Error) -> MemcachedError {
         MemcachedError::OpensslError(err)
     }
 }
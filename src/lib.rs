@@ -1,4 +1,4 @@
-//!
+//! Async memcached rust client built on async-std.
 
 #![deny(
     missing_docs,
@@ -38,20 +38,64 @@
     clippy::multiple_crate_versions,
     clippy::doc_markdown,
     clippy::cast_possible_truncation, //
-    clippy::integer_arithmetic, //
+    clippy::arithmetic_side_effects, //
     trivial_casts,
 )]
 
+mod capture;
 mod client;
+/// value serialization
+pub mod codec;
 mod connection;
 /// memcached error
 pub mod error;
+/// operation measurements
+pub mod metrics;
+mod operation;
 mod protocol;
+mod stats;
 mod stream;
+/// testing helpers
+#[cfg(feature = "test-util")]
+pub mod test_util;
 
 /// memcached result
 pub type Result<T> = std::result::Result<T, error::MemcachedError>;
-pub use client::{connectable::Connectable, Client};
+pub use capture::Capture;
+pub use client::{
+    batching::BatchSizing,
+    builder::{ClientBuilder, LivenessCheck, PoolConfig, Preset, ServerOverrides},
+    client_hash::{
+        HashFunction, KetamaRouter, KeyExplanation, KeyHasher, KeyRouter, ModuloRouter, Route,
+        RouterParameters,
+    },
+    connectable::Connectable,
+    counter_buffer::CounterBuffer,
+    flush_window::FlushWindowReads,
+    key_dictionary::KeyDictionary,
+    kill_switch::KillSwitch,
+    multi_get::MultiGet,
+    pipeline::{Pipeline, Replies},
+    raw::RawConnection,
+    routing_snapshot::RoutingSnapshot,
+    self_test::{SelfTestCheck, SelfTestReport},
+    serialization_memo::SerializationMemo,
+    settings_drift::SettingsDrift,
+    stop_signal::StopSignal,
+    tenant::{Tenant, TenantMetrics},
+    tiered::{Tier, TierAttempt, TierBudget, TierOutcome, Tiered},
+    ttl_rules::{TtlAction, TtlRule, TtlRules},
+    verify::{ReadDigest, ReadMismatch},
+    version_skew::VersionSkew,
+    Client,
+};
+pub use connection::ServerCapabilities;
+pub use operation::Operation;
+pub use protocol::{
+    meta::{MetaDeleteFlags, MetaGetFlags, MetaResponse, MetaSetFlags, MetaStatus},
+    CasResult, DataType, OutOfMemoryRetry, RawPacket, Reply, DONT_CREATE,
+};
+pub use stats::{ExtstoreStats, PrefixScan, PrefixStats, ServerSettings, ServerStats};
 
 /// Create a memcached client instance and connect to memcached server.
 /// The default connection pool has only one connection.
@@ -72,15 +116,15 @@ pub fn connect(url: &str) -> Result<Client> {
 /// ```rust
 /// let client = memcached::Client::connect_with(vec!["memcache://127.0.0.1:12345".to_owned()], 2, |s|1).unwrap();
 /// ```
-pub fn connect_with(
+pub fn connect_with<F: Fn(&str) -> u64 + Send + Sync + 'static>(
     urls: Vec<String>,
     pool_size: u64,
-    hash_function: fn(&str) -> u64,
+    hash_function: F,
 ) -> Result<Client> {
     Client::connect_with(urls, pool_size, hash_function)
 }
 
-#[allow(clippy::result_unwrap_used, clippy::option_unwrap_used)]
+#[allow(clippy::unwrap_used)]
 #[cfg(test)]
 mod tests {
     #[async_std::test]
@@ -96,4 +140,344 @@ mod tests {
         assert_eq!(t, Some("hello".to_owned()));
         Ok(())
     }
+
+    #[test]
+    fn sharded_counts_merge_across_threads() {
+        use crate::metrics::{CountingObserver, MetricsObserver, Timings};
+        use std::{sync::Arc, thread};
+        let counts = Arc::new(CountingObserver::default());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let counts = Arc::clone(&counts);
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        counts.on_timings("get", "a", &Timings::default());
+                        counts.on_lookup("get", "a", 1, 0);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let a = &counts.counts()["a"];
+        assert_eq!((a.operations, a.hits), (8000, 8000));
+    }
+
+    #[test]
+    fn ketama_resize_moves_few_keys() {
+        use crate::{KetamaRouter, KeyRouter};
+        let four = KetamaRouter::new(&[
+            ("a:11211", 1),
+            ("b:11211", 1),
+            ("c:11211", 1),
+            ("d:11211", 1),
+        ]);
+        let five = KetamaRouter::new(&[
+            ("a:11211", 1),
+            ("b:11211", 1),
+            ("c:11211", 1),
+            ("d:11211", 1),
+            ("e:11211", 1),
+        ]);
+        let moved = (0..10_000)
+            .map(|i| format!("key{i}"))
+            .filter(|key| four.route(key) != five.route(key))
+            .count();
+        assert!(moved < 3_000, "{} keys moved", moved);
+    }
+
+    #[test]
+    fn crc32_matches_check_value() {
+        use crate::client::chunked::crc32;
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn cram_md5_answers_rfc_2195_challenge() -> crate::Result<()> {
+        let mut mechanism = crate::protocol::sasl::Mechanism::CramMd5 { answered: false };
+        let response = mechanism.step(
+            "tim",
+            "tanstaaftanstaaf",
+            b"<1896.697170952@postoffice.reston.mci.net>",
+        )?;
+        assert_eq!(response, b"tim b913a602c7eda7a495b4e6e7334d3890");
+        Ok(())
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn scram_sha1_matches_rfc_5802_exchange() -> crate::Result<()> {
+        let mut scram =
+            crate::protocol::sasl::scram::ScramSha1::with_nonce("fyko+d2lbbFgONRv9qkxdawL");
+        assert_eq!(
+            scram.client_first("user"),
+            b"n,,n=user,r=fyko+d2lbbFgONRv9qkxdawL"
+        );
+        let client_final = scram.client_final(
+            "pencil",
+            b"r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,s=QSXCR+Q6sek8bf92,i=4096",
+        )?;
+        assert_eq!(
+            client_final,
+            b"c=biws,r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,p=v0X8v3Bz2T0CJGbJQyF0X+HI4Ts="
+                .to_vec()
+        );
+        scram.verify_server_final(b"v=rmF9pqV8S7suAoZWja4dJRkFsKQ=")?;
+        assert!(scram
+            .verify_server_final(b"v=AAAAAAAAAAAAAAAAAAAAAAAAAAA=")
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn read_mismatch_compares_value_flags_and_cas() {
+        use crate::client::verify::{ReadDigest, ReadMismatch};
+        let read = |value: &[u8], cas| Some(ReadDigest::new(value, 0, Some(cas)));
+        assert_eq!(
+            ReadMismatch::compare("k", "s", read(b"a", 1), read(b"a", 1)),
+            None
+        );
+        assert!(ReadMismatch::compare("k", "s", read(b"a", 1), read(b"b", 1)).is_some());
+        assert!(ReadMismatch::compare("k", "s", read(b"a", 1), read(b"a", 2)).is_some());
+        assert!(ReadMismatch::compare("k", "s", read(b"a", 1), None).is_some());
+    }
+
+    #[test]
+    fn meta_commands_and_responses() -> crate::Result<()> {
+        use crate::protocol::meta::{self, MetaGetFlags, MetaResponse, MetaStatus};
+        let flags = MetaGetFlags {
+            value: true,
+            ttl: true,
+            vivify: Some(30),
+            recache_below: Some(5),
+            ..MetaGetFlags::default()
+        };
+        assert_eq!(meta::get_command("k", &flags), "mg k v f t N30 R5");
+        let (response, len): (MetaResponse<Vec<u8>>, _) = meta::parse_response("VA 2 f3 t4 W X Z")?;
+        assert_eq!(len, Some(2));
+        assert_eq!((response.flags, response.ttl), (Some(3), Some(4)));
+        assert!(response.win && response.stale && response.win_sent);
+        let (response, len): (MetaResponse<()>, _) = meta::parse_response("EN")?;
+        assert_eq!((response.status, len), (MetaStatus::Miss, None));
+        assert!(meta::parse_response::<()>("VA x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn adaptive_batch_grows_additively_and_halves() {
+        use crate::client::batching::AdaptiveBatch;
+        use std::time::Duration;
+        let batch = AdaptiveBatch::new(crate::BatchSizing {
+            min: 4,
+            max: 16,
+            target_latency: Duration::from_millis(10),
+            target_bytes: 1000,
+        });
+        assert_eq!(batch.size(), 4);
+        batch.observe(4, Duration::from_millis(1), 100);
+        assert_eq!(batch.size(), 8);
+        // A short last batch does not grow the size.
+        batch.observe(3, Duration::from_millis(1), 100);
+        assert_eq!(batch.size(), 8);
+        for _ in 0..5 {
+            batch.observe(batch.size(), Duration::from_millis(1), 100);
+        }
+        assert_eq!(batch.size(), 16);
+        batch.observe(16, Duration::from_millis(20), 100);
+        assert_eq!(batch.size(), 8);
+        batch.observe(8, Duration::from_millis(1), 5000);
+        assert_eq!(batch.size(), 4);
+        batch.observe(4, Duration::from_millis(20), 100);
+        assert_eq!(batch.size(), 4);
+    }
+
+    #[test]
+    fn metadump_lines_parse() {
+        use crate::stats::ItemMetadata;
+        let item =
+            ItemMetadata::parse("key=a%3Ab exp=1700000100 la=1 cas=2 fetch=no cls=1 size=63");
+        assert_eq!(
+            item,
+            Some(ItemMetadata {
+                key: "a:b".to_owned(),
+                expires_at: Some(1_700_000_100),
+                size: 63,
+            })
+        );
+        let item = ItemMetadata::parse("key=c exp=-1 la=1 cas=2 fetch=no cls=1 size=60").unwrap();
+        assert_eq!(item.expires_at, None);
+        assert_eq!(ItemMetadata::parse("key=%4 exp=-1 size=1"), None);
+        assert_eq!(ItemMetadata::parse("BUSY"), None);
+    }
+
+    #[test]
+    fn flush_schedule_is_pending_until_the_flush() {
+        use crate::client::flush_window::FlushSchedule;
+        use std::time::{Duration, SystemTime};
+        let schedule = FlushSchedule::default();
+        assert_eq!(schedule.pending(), None);
+        schedule.schedule(60);
+        let at = schedule.pending().unwrap();
+        assert!(at > SystemTime::now() + Duration::from_secs(50));
+        schedule.schedule(0);
+        assert_eq!(schedule.pending(), None);
+    }
+
+    #[test]
+    fn bincode_codec_checks_format_version() -> crate::Result<()> {
+        use crate::codec::{format_version, BincodeCodec, ValueCodec, FORMAT_VERSION};
+        use crate::error::{ClientError, MemcachedError};
+        let (bytes, flags) = BincodeCodec.encode(&vec![1_u32, 2])?;
+        assert_eq!(format_version(flags), FORMAT_VERSION);
+        assert_eq!(BincodeCodec.decode::<Vec<u32>>(&bytes, flags)?, vec![1, 2]);
+        // Written before the version was stored.
+        assert_eq!(BincodeCodec.decode::<Vec<u32>>(&bytes, 0)?, vec![1, 2]);
+        let t = BincodeCodec.decode::<Vec<u32>>(&bytes, (FORMAT_VERSION + 1) << 24);
+        assert!(matches!(
+            t,
+            Err(MemcachedError::ClientError(ClientError::FormatVersionMismatch { found, supported }))
+                if found == FORMAT_VERSION + 1 && supported == FORMAT_VERSION
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn bincode_codec_checks_value_type() -> crate::Result<()> {
+        use crate::codec::{BincodeCodec, ValueCodec, ValueType};
+        use crate::error::{ClientError, MemcachedError};
+        let (bytes, flags) = BincodeCodec.encode(&vec![1_u32, 2])?;
+        assert_eq!(ValueType::from_flags(flags), Some(ValueType::Bincode));
+        let t = BincodeCodec.decode::<String>(&bytes, flags);
+        assert!(matches!(
+            t,
+            Err(MemcachedError::ClientError(ClientError::TypeMismatch {
+                stored: ValueType::Bincode,
+                requested: ValueType::Utf8,
+            }))
+        ));
+        let (bytes, flags) = BincodeCodec.encode(&42_u64)?;
+        assert_eq!(ValueType::from_flags(flags), Some(ValueType::Numeric));
+        assert_eq!(BincodeCodec.decode::<String>(&bytes, flags)?, "42");
+        let (_, flags) = BincodeCodec.encode(&vec![1_u8])?;
+        assert_eq!(ValueType::from_flags(flags), Some(ValueType::Bytes));
+        // Values without a type are decoded as requested.
+        assert_eq!(BincodeCodec.decode::<u64>(b"7", 0)?, 7);
+        Ok(())
+    }
+
+    #[test]
+    fn version_skew_groups_servers_by_version() {
+        use crate::VersionSkew;
+        let versions = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|&(server, version)| (server.to_owned(), version.to_owned()))
+                .collect()
+        };
+        assert_eq!(
+            VersionSkew::detect(versions(&[("a", "1.6.21"), ("b", "1.6.21")])),
+            None
+        );
+        let skew = VersionSkew::detect(versions(&[
+            ("c", "1.6.21"),
+            ("a", "1.6.21"),
+            ("b", "1.5.22"),
+        ]));
+        assert_eq!(
+            skew.map(|skew| skew.versions.into_iter().collect::<Vec<_>>()),
+            Some(vec![
+                ("1.5.22".to_owned(), vec!["b".to_owned()]),
+                ("1.6.21".to_owned(), vec!["a".to_owned(), "c".to_owned()]),
+            ])
+        );
+    }
+
+    #[test]
+    fn settings_drift_reports_only_differing_settings() {
+        use crate::{ServerSettings, SettingsDrift};
+        let settings = |pairs: &[(&str, &str)]| {
+            ServerSettings::from_map(
+                &pairs
+                    .iter()
+                    .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+                    .collect(),
+            )
+        };
+        let a = settings(&[
+            ("maxbytes", "67108864"),
+            ("item_size_max", "1048576"),
+            ("num_threads", "4"),
+            ("evictions", "on"),
+            ("growth_factor", "1.25"),
+        ]);
+        assert_eq!((a.maxbytes, a.evictions), (67_108_864, true));
+        assert_eq!(a.growth_factor, "1.25");
+        let mut b = a.clone();
+        b.maxconns = 4096;
+        let servers = |list: &[(&str, &ServerSettings)]| {
+            list.iter()
+                .map(|&(url, settings)| (url.to_owned(), settings.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            SettingsDrift::detect(&servers(&[("a", &a), ("b", &b)])),
+            None
+        );
+        b.evictions = false;
+        b.num_threads = 8;
+        let drift = SettingsDrift::detect(&servers(&[("c", &a), ("b", &b), ("a", &a)])).unwrap();
+        assert_eq!(
+            drift.settings.keys().copied().collect::<Vec<_>>(),
+            ["evictions", "num_threads"]
+        );
+        assert_eq!(
+            drift.settings["evictions"].iter().collect::<Vec<_>>(),
+            [
+                (&"false".to_owned(), &vec!["b".to_owned()]),
+                (&"true".to_owned(), &vec!["a".to_owned(), "c".to_owned()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn extstore_misses_split_into_ram_and_flash() {
+        use crate::ExtstoreStats;
+        let stats: std::collections::HashMap<String, String> = [
+            ("get_hits", "90"),
+            ("get_misses", "10"),
+            ("get_extstore", "30"),
+            ("miss_from_extstore", "3"),
+            ("get_aborted_extstore", "1"),
+            ("extstore_bytes_used", "750"),
+            ("extstore_bytes_fragmented", "250"),
+        ]
+        .iter()
+        .map(|(name, value)| ((*name).to_owned(), (*value).to_owned()))
+        .collect();
+        let stats = ExtstoreStats::from_map(&stats);
+        assert!(stats.enabled);
+        assert_eq!((stats.ram_misses(), stats.flash_misses()), (6, 4));
+        assert_eq!(stats.flash_hit_ratio(), Some(30.0 / 90.0));
+        assert_eq!(stats.fragmentation(), Some(0.25));
+        assert!(stats.io_saturated(100));
+        assert!(!ExtstoreStats::from_map(&std::collections::HashMap::new()).enabled);
+    }
+
+    #[test]
+    fn serialization_memo_drops_oldest_and_keeps_types_apart() {
+        use crate::{codec::BincodeCodec, SerializationMemo};
+        let memo = SerializationMemo::new(2);
+        for id in ["a", "b", "a", "c", "a"] {
+            let _ = memo.encode(&BincodeCodec, &1_u64, id).unwrap();
+        }
+        // "a" was dropped for "c", then encoded again, dropping "b".
+        assert_eq!((memo.len(), memo.hits(), memo.misses()), (2, 1, 4));
+        let _ = memo.encode(&BincodeCodec, &"text", "a").unwrap();
+        assert_eq!((memo.len(), memo.misses()), (2, 5));
+        // Both types of "a" go, "c" was dropped for the text.
+        memo.forget("a");
+        assert!(memo.is_empty());
+    }
 }
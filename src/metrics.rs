@@ -0,0 +1,606 @@
+use crate::{error::MemcachedError, Operation, ReadMismatch, SettingsDrift, VersionSkew};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Shards of [`Sharded`] at most, more cores share them.
+const MAX_SHARDS: usize = 64;
+
+/// Keeps shards of neighbouring threads on separate cache lines.
+#[derive(Debug, Default)]
+#[repr(align(128))]
+struct CachePadded<T>(T);
+
+/// The shard index of the current thread, threads are numbered in the order they first record.
+fn thread_shard() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SHARD: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    SHARD.with(|shard| *shard)
+}
+
+/// Counters with one shard per core: each thread updates its own shard, so recording doesn't
+/// contend on one lock or cache line, and reads merge all shards.
+#[derive(Debug)]
+pub(crate) struct Sharded<T> {
+    shards: Box<[CachePadded<Mutex<T>>]>,
+}
+
+impl<T: Default> Default for Sharded<T> {
+    fn default() -> Self {
+        let cores = thread::available_parallelism().map_or(1, usize::from);
+        Sharded {
+            shards: (0..cores.next_power_of_two().min(MAX_SHARDS))
+                .map(|_| CachePadded::default())
+                .collect(),
+        }
+    }
+}
+
+impl<T> Sharded<T> {
+    /// Update the shard of the current thread.
+    pub(crate) fn update<R>(&self, update: impl FnOnce(&mut T) -> R) -> R {
+        // The length is a power of two.
+        let shard = &self.shards[thread_shard() & (self.shards.len() - 1)].0;
+        update(&mut shard.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Merge the shards into `init`.
+    pub(crate) fn fold<A>(&self, init: A, mut merge: impl FnMut(A, &T) -> A) -> A {
+        self.shards.iter().fold(init, |merged, shard| {
+            merge(
+                merged,
+                &shard.0.lock().unwrap_or_else(PoisonError::into_inner),
+            )
+        })
+    }
+}
+
+/// Where the time of one operation went, and how many bytes it moved.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Waiting for a connection from the pool, including its liveness check.
+    pub pool_wait: Duration,
+    /// Writing the request until it was flushed.
+    pub write: Duration,
+    /// From the flush until the first response bytes arrived.
+    pub server: Duration,
+    /// Reading the rest of the response.
+    pub read: Duration,
+    /// Bytes of the request.
+    pub bytes_written: u64,
+    /// Bytes of the response.
+    pub bytes_read: u64,
+}
+
+impl Timings {
+    /// The sum of all phases.
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.pool_wait + self.write + self.server + self.read
+    }
+}
+
+/// How long the checkouts of one server waited for a connection, see [`Client::checkout_stats`](crate::Client::checkout_stats).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CheckoutStats {
+    /// Connections checked out of the pool.
+    pub checkouts: u64,
+    /// The sum of all waits.
+    pub total_wait: Duration,
+    /// The longest wait.
+    pub max_wait: Duration,
+    /// Checkouts that waited at least the starvation threshold.
+    pub starved: u64,
+}
+
+impl CheckoutStats {
+    pub(crate) fn merge(mut self, other: &CheckoutStats) -> Self {
+        self.checkouts += other.checkouts;
+        self.total_wait += other.total_wait;
+        self.max_wait = self.max_wait.max(other.max_wait);
+        self.starved += other.starved;
+        self
+    }
+}
+
+/// Receives measurements of client operations.
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+/// use memcached::metrics::{MetricsObserver, Timings};
+///
+/// struct Printer;
+///
+/// impl MetricsObserver for Printer {
+///     fn on_timings(&self, op: &'static str, server: &str, timings: &Timings) {
+///         println!("{op} on {server} took {:?}", timings.total());
+///     }
+/// }
+///
+/// let client = memcached::connect("memcache://127.0.0.1:12345")?.with_metrics_observer(Printer);
+/// client.set("metrics_test", "hello", 100).await?;
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+pub trait MetricsObserver: Send + Sync {
+    /// Called before the client sends `operation`, with the keys the caller passed.
+    fn on_operation(&self, operation: &Operation<'_>) {
+        let _ = operation;
+    }
+
+    /// Called after every operation sent to `server`.
+    fn on_timings(&self, op: &'static str, server: &str, timings: &Timings) {
+        let _ = (op, server, timings);
+    }
+
+    /// Called when a read on `server` failed with `error` and was answered with a miss,
+    /// see [`ClientBuilder::degrade_on_error`](crate::ClientBuilder::degrade_on_error).
+    fn on_degraded(&self, op: &'static str, server: &str, error: &MemcachedError) {
+        let _ = (op, server, error);
+    }
+
+    /// Called when `op` was not sent because the [`KillSwitch`](crate::KillSwitch) is engaged.
+    fn on_bypassed(&self, op: &'static str) {
+        let _ = op;
+    }
+
+    /// Called after a read on `server` was answered, with the number of keys found and not found.
+    fn on_lookup(&self, op: &'static str, server: &str, hits: u64, misses: u64) {
+        let _ = (op, server, hits, misses);
+    }
+
+    /// Called after a multi-get of `keys` keys was sent to `server` in a request of `request_bytes`,
+    /// e.g. to spot fan-outs too large for one round trip.
+    fn on_multi_get(&self, op: &'static str, server: &str, keys: usize, request_bytes: u64) {
+        let _ = (op, server, keys, request_bytes);
+    }
+
+    /// Called when an operation on `server` failed, including checkouts that timed out.
+    fn on_error(&self, op: &'static str, server: &str, error: &MemcachedError) {
+        let _ = (op, server, error);
+    }
+
+    /// Called when the two reads of a verified `get` disagreed,
+    /// see [`ClientBuilder::verify_reads`](crate::ClientBuilder::verify_reads).
+    fn on_read_mismatch(&self, mismatch: &ReadMismatch) {
+        let _ = mismatch;
+    }
+
+    /// Called when [`Client::version_skew`](crate::Client::version_skew) found servers running different versions.
+    fn on_version_skew(&self, skew: &VersionSkew) {
+        let _ = skew;
+    }
+
+    /// Called when [`Client::detect_drift`](crate::Client::detect_drift) found servers configured differently.
+    fn on_settings_drift(&self, drift: &SettingsDrift) {
+        let _ = drift;
+    }
+}
+
+impl<T: MetricsObserver + ?Sized> MetricsObserver for Arc<T> {
+    fn on_operation(&self, operation: &Operation<'_>) {
+        (**self).on_operation(operation);
+    }
+
+    fn on_timings(&self, op: &'static str, server: &str, timings: &Timings) {
+        (**self).on_timings(op, server, timings);
+    }
+
+    fn on_degraded(&self, op: &'static str, server: &str, error: &MemcachedError) {
+        (**self).on_degraded(op, server, error);
+    }
+
+    fn on_bypassed(&self, op: &'static str) {
+        (**self).on_bypassed(op);
+    }
+
+    fn on_lookup(&self, op: &'static str, server: &str, hits: u64, misses: u64) {
+        (**self).on_lookup(op, server, hits, misses);
+    }
+
+    fn on_multi_get(&self, op: &'static str, server: &str, keys: usize, request_bytes: u64) {
+        (**self).on_multi_get(op, server, keys, request_bytes);
+    }
+
+    fn on_error(&self, op: &'static str, server: &str, error: &MemcachedError) {
+        (**self).on_error(op, server, error);
+    }
+
+    fn on_read_mismatch(&self, mismatch: &ReadMismatch) {
+        (**self).on_read_mismatch(mismatch);
+    }
+
+    fn on_version_skew(&self, skew: &VersionSkew) {
+        (**self).on_version_skew(skew);
+    }
+
+    fn on_settings_drift(&self, drift: &SettingsDrift) {
+        (**self).on_settings_drift(drift);
+    }
+}
+
+/// What a [`CountingObserver`] counted for one server.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ServerCounts {
+    /// Operations sent to the server, failed ones included.
+    pub operations: u64,
+    /// Keys found by reads.
+    pub hits: u64,
+    /// Keys not found by reads.
+    pub misses: u64,
+    /// Operations that timed out.
+    pub timeouts: u64,
+    /// Operations that failed otherwise, e.g. with a [`CommandError`](crate::error::CommandError).
+    pub errors: u64,
+    /// The sum of the durations of all operations.
+    pub total_time: Duration,
+    /// The longest operation.
+    pub max_time: Duration,
+}
+
+impl ServerCounts {
+    /// The share of keys read that were found, `None` before the first read.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let requested = self.hits.saturating_add(self.misses);
+        (requested > 0).then(|| self.hits as f64 / requested as f64)
+    }
+
+    fn merge(&mut self, other: &ServerCounts) {
+        self.operations += other.operations;
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.timeouts += other.timeouts;
+        self.errors += other.errors;
+        self.total_time += other.total_time;
+        self.max_time = self.max_time.max(other.max_time);
+    }
+}
+
+/// A [`MetricsObserver`] counting operations, hits, misses, timeouts and errors per server.
+/// Counts are kept per core and summed by [`CountingObserver::counts`], so busy clients don't contend on them.
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+/// use memcached::metrics::CountingObserver;
+/// use std::sync::Arc;
+///
+/// let counts = Arc::new(CountingObserver::default());
+/// let client = memcached::connect("memcache://127.0.0.1:12345")?
+///     .with_metrics_observer(Arc::clone(&counts));
+/// client.set("counting_observer_test", "hello", 100).await?;
+/// let _: Option<String> = client.get("counting_observer_test").await?;
+/// let _: Option<String> = client.get("counting_observer_test_none").await?;
+/// let server = &counts.counts()["memcache://127.0.0.1:12345"];
+/// assert_eq!((server.operations, server.hits, server.misses), (3, 1, 1));
+/// assert_eq!(server.hit_ratio(), Some(0.5));
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+#[derive(Debug, Default)]
+pub struct CountingObserver {
+    counts: Sharded<HashMap<String, ServerCounts>>,
+}
+
+impl CountingObserver {
+    /// What was counted so far, by server url.
+    #[must_use]
+    pub fn counts(&self) -> HashMap<String, ServerCounts> {
+        self.counts.fold(HashMap::new(), |mut merged, shard| {
+            for (server, counts) in shard {
+                merged.entry(server.clone()).or_default().merge(counts);
+            }
+            merged
+        })
+    }
+
+    fn update(&self, server: &str, update: impl FnOnce(&mut ServerCounts)) {
+        self.counts.update(|counts| {
+            if let Some(counts) = counts.get_mut(server) {
+                update(counts);
+            } else {
+                let mut fresh = ServerCounts::default();
+                update(&mut fresh);
+                let _ = counts.insert(server.to_owned(), fresh);
+            }
+        });
+    }
+}
+
+impl MetricsObserver for CountingObserver {
+    fn on_timings(&self, _op: &'static str, server: &str, timings: &Timings) {
+        let total = timings.total();
+        self.update(server, |counts| {
+            counts.operations += 1;
+            counts.total_time += total;
+            counts.max_time = counts.max_time.max(total);
+        });
+    }
+
+    fn on_lookup(&self, _op: &'static str, server: &str, hits: u64, misses: u64) {
+        self.update(server, |counts| {
+            counts.hits += hits;
+            counts.misses += misses;
+        });
+    }
+
+    fn on_error(&self, _op: &'static str, server: &str, error: &MemcachedError) {
+        let timeout = matches!(error, MemcachedError::Timeout(_));
+        self.update(server, |counts| {
+            if timeout {
+                counts.timeouts += 1;
+            } else {
+                counts.errors += 1;
+            }
+        });
+    }
+}
+
+/// Bytes written to one namespace, see [`ByteBudgetObserver`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NamespaceBytes {
+    /// Encoded bytes written, decayed by the half-life.
+    pub bytes: u64,
+    /// The soft budget of the namespace, if any.
+    pub budget: Option<u64>,
+    /// How often the namespace went over its budget.
+    pub warnings: u64,
+}
+
+/// Decaying byte counter of one namespace. The counters of all shards decay alike, so their
+/// decayed sum is the decayed total.
+#[derive(Debug)]
+struct Usage {
+    bytes: f64,
+    at: Instant,
+}
+
+impl Usage {
+    fn decayed(&self, half_life: Duration, now: Instant) -> f64 {
+        if half_life.is_zero() {
+            return self.bytes;
+        }
+        // Another shard may have read `now` before this one was updated.
+        let half_lives =
+            now.saturating_duration_since(self.at).as_secs_f64() / half_life.as_secs_f64();
+        self.bytes * 0.5_f64.powf(half_lives)
+    }
+}
+
+/// The soft budget of one namespace and whether the namespace is over it.
+#[derive(Debug)]
+struct Budget {
+    bytes: u64,
+    over: AtomicBool,
+    warnings: AtomicU64,
+}
+
+/// A [`MetricsObserver`] counting the encoded bytes written per key namespace, to see which
+/// namespace fills the cluster and causes evictions.
+///
+/// The namespace of a key is the key up to and including its first separator, `users:` for
+/// `users:42`, keys without one count as the empty namespace and every write counts to the
+/// global total `"*"` too. Counters halve every half-life, so they show recent writes.
+/// Going over a soft budget is counted as a warning and, with the `tracing` feature, logged.
+/// Writes are counted when they are sent, whether they succeed or not, `sets` key by key.
+/// Pipelines are not announced to observers and not counted.
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {   
+/// use memcached::metrics::ByteBudgetObserver;
+/// use std::{sync::Arc, time::Duration};
+///
+/// let budgets = Arc::new(
+///     ByteBudgetObserver::new(Duration::from_secs(600))
+///         .with_budget("byte_budget_test:", 8)
+///         .with_global_budget(1 << 20),
+/// );
+/// let client = memcached::connect("memcache://127.0.0.1:12345")?
+///     .with_metrics_observer(Arc::clone(&budgets));
+/// client.set("byte_budget_test:a", "hello", 100).await?;
+/// client.set("byte_budget_test:b", "hello", 100).await?;
+/// client.sets(&[("byte_budget_test:c", "hi", 100), ("byte_budget_sets_test", "hi", 100)]).await?;
+/// let usage = budgets.usage();
+/// let namespace = usage["byte_budget_test:"];
+/// assert_eq!((namespace.bytes, namespace.budget, namespace.warnings), (12, Some(8), 1));
+/// assert_eq!(usage[""].bytes, 2);
+/// assert_eq!(usage["*"].bytes, 14);
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+#[derive(Debug)]
+pub struct ByteBudgetObserver {
+    half_life: Duration,
+    separator: char,
+    budgets: HashMap<String, Budget>,
+    usage: Sharded<HashMap<String, Usage>>,
+}
+
+/// The namespace all writes count to.
+const GLOBAL_NAMESPACE: &str = "*";
+
+impl ByteBudgetObserver {
+    /// Count bytes halving every `half_life`, zero never decays.
+    #[must_use]
+    pub fn new(half_life: Duration) -> Self {
+        ByteBudgetObserver {
+            half_life,
+            separator: ':',
+            budgets: HashMap::new(),
+            usage: Sharded::default(),
+        }
+    }
+
+    /// End namespaces at `separator` instead of `:`.
+    #[must_use]
+    pub fn with_separator(mut self, separator: char) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// Warn when the decayed bytes of `namespace`, including its separator, exceed `bytes`.
+    #[must_use]
+    pub fn with_budget(mut self, namespace: &str, bytes: u64) -> Self {
+        let budget = Budget {
+            bytes,
+            over: AtomicBool::new(false),
+            warnings: AtomicU64::new(0),
+        };
+        let _ = self.budgets.insert(namespace.to_owned(), budget);
+        self
+    }
+
+    /// Warn when the decayed bytes of all writes exceed `bytes`.
+    #[must_use]
+    pub fn with_global_budget(self, bytes: u64) -> Self {
+        self.with_budget(GLOBAL_NAMESPACE, bytes)
+    }
+
+    /// The bytes of every namespace written to so far, `"*"` is the total.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn usage(&self) -> HashMap<String, NamespaceBytes> {
+        let now = Instant::now();
+        let merged = self.usage.fold(HashMap::new(), |mut merged, shard| {
+            for (namespace, usage) in shard {
+                *merged.entry(namespace.clone()).or_insert(0.0) +=
+                    usage.decayed(self.half_life, now);
+            }
+            merged
+        });
+        merged
+            .into_iter()
+            .map(|(namespace, bytes)| {
+                let budget = self.budgets.get(&namespace);
+                let bytes = NamespaceBytes {
+                    bytes: bytes.round() as u64,
+                    budget: budget.map(|budget| budget.bytes),
+                    warnings: budget.map_or(0, |budget| budget.warnings.load(Ordering::Relaxed)),
+                };
+                (namespace, bytes)
+            })
+            .collect()
+    }
+
+    fn namespace<'a>(&self, key: &'a str) -> &'a str {
+        key.find(self.separator)
+            .map_or("", |end| &key[..end + self.separator.len_utf8()])
+    }
+
+    /// The decayed bytes of `namespace` summed over all shards.
+    fn total(&self, namespace: &str, now: Instant) -> f64 {
+        self.usage.fold(0.0, |total, shard| {
+            total
+                + shard
+                    .get(namespace)
+                    .map_or(0.0, |usage| usage.decayed(self.half_life, now))
+        })
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn add(&self, namespace: &str, bytes: usize) {
+        let now = Instant::now();
+        self.usage.update(|usage| {
+            if let Some(usage) = usage.get_mut(namespace) {
+                usage.bytes = usage.decayed(self.half_life, now) + bytes as f64;
+                usage.at = now;
+            } else {
+                let fresh = Usage {
+                    bytes: bytes as f64,
+                    at: now,
+                };
+                let _ = usage.insert(namespace.to_owned(), fresh);
+            }
+        });
+        // Only namespaces with a budget merge the shards on write.
+        let Some(budget) = self.budgets.get(namespace) else {
+            return;
+        };
+        let total = self.total(namespace, now);
+        let over_budget = total > budget.bytes as f64;
+        if over_budget && !budget.over.swap(true, Ordering::Relaxed) {
+            let _ = budget.warnings.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                namespace,
+                bytes = total,
+                "memcached namespace went over its byte budget"
+            );
+        } else if !over_budget {
+            budget.over.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+impl MetricsObserver for ByteBudgetObserver {
+    fn on_operation(&self, operation: &Operation<'_>) {
+        if let Operation::SetMany { keys, bytes } = operation {
+            for (key, &bytes) in keys.iter().zip(bytes) {
+                self.add(self.namespace(key), bytes);
+                self.add(GLOBAL_NAMESPACE, bytes);
+            }
+            return;
+        }
+        let (Operation::Set { key, bytes, .. }
+        | Operation::Add { key, bytes, .. }
+        | Operation::Replace { key, bytes, .. }
+        | Operation::Append { key, bytes }
+        | Operation::Prepend { key, bytes }
+        | Operation::Cas { key, bytes, .. }) = *operation
+        else {
+            return;
+        };
+        self.add(self.namespace(key), bytes);
+        self.add(GLOBAL_NAMESPACE, bytes);
+    }
+}
+
+/// The span of one operation, the other fields are filled in by `record` and `record_error`.
+#[cfg(feature = "tracing")]
+pub(crate) fn span(op: &'static str, server_index: usize) -> tracing::Span {
+    use tracing::field::Empty;
+    tracing::debug_span!(
+        "memcached",
+        op,
+        server_index,
+        server = Empty,
+        pool_wait_us = Empty,
+        write_us = Empty,
+        server_us = Empty,
+        read_us = Empty,
+        bytes_written = Empty,
+        bytes_read = Empty,
+        error = Empty,
+    )
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn record(span: &tracing::Span, server: &str, timings: &Timings) {
+    let micros =
+        |d: Duration| -> u64 { std::convert::TryFrom::try_from(d.as_micros()).unwrap_or(u64::MAX) };
+    let _ = span
+        .record("server", server)
+        .record("pool_wait_us", micros(timings.pool_wait))
+        .record("write_us", micros(timings.write))
+        .record("server_us", micros(timings.server))
+        .record("read_us", micros(timings.read))
+        .record("bytes_written", timings.bytes_written)
+        .record("bytes_read", timings.bytes_read);
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn record_error(span: &tracing::Span, error: &MemcachedError) {
+    let _ = span.record("error", tracing::field::display(error));
+}
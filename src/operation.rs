@@ -0,0 +1,168 @@
+/// What the client is about to do, as seen by [`MetricsObserver::on_operation`](crate::metrics::MetricsObserver::on_operation).
+///
+/// Keys are the keys the caller passed, before namespaces or a key dictionary shortened them.
+/// New operations may be added, so matches need a wildcard arm.
+///
+/// ## Example
+///
+/// ```rust
+/// use memcached::Operation;
+/// let operation = Operation::Set { key: "abc", ttl: 100, bytes: 5 };
+/// assert_eq!(operation.name(), "set");
+/// assert_eq!(operation.keys(), ["abc"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation<'a> {
+    /// `get`, `get_with_cas`, `get_with_data_type`, `get_and_touch` and `meta_get`.
+    Get {
+        /// The key read.
+        key: &'a str,
+    },
+    /// `gets`, `gets_with_stop` and `gets_and_touch`.
+    GetMany {
+        /// The keys read.
+        keys: Vec<&'a str>,
+    },
+    /// `set`, `set_with_data_type` and `meta_set`.
+    Set {
+        /// The key written.
+        key: &'a str,
+        /// The expiration in seconds, 0 never expires.
+        ttl: u32,
+        /// The size of the encoded value.
+        bytes: usize,
+    },
+    /// `sets`, `sets_with_stop` and `sets_memoized`.
+    SetMany {
+        /// The keys written.
+        keys: Vec<&'a str>,
+        /// The sizes of the encoded values, in the order of `keys`.
+        bytes: Vec<usize>,
+    },
+    /// `add`.
+    Add {
+        /// The key written.
+        key: &'a str,
+        /// The expiration in seconds, 0 never expires.
+        ttl: u32,
+        /// The size of the encoded value.
+        bytes: usize,
+    },
+    /// `replace`.
+    Replace {
+        /// The key written.
+        key: &'a str,
+        /// The expiration in seconds, 0 never expires.
+        ttl: u32,
+        /// The size of the encoded value.
+        bytes: usize,
+    },
+    /// `append`.
+    Append {
+        /// The key written.
+        key: &'a str,
+        /// The size of the encoded value.
+        bytes: usize,
+    },
+    /// `prepend`.
+    Prepend {
+        /// The key written.
+        key: &'a str,
+        /// The size of the encoded value.
+        bytes: usize,
+    },
+    /// `cas`.
+    Cas {
+        /// The key written.
+        key: &'a str,
+        /// The expiration in seconds, 0 never expires.
+        ttl: u32,
+        /// The size of the encoded value.
+        bytes: usize,
+        /// The cas the item must still have.
+        cas: u64,
+    },
+    /// `delete`, `delete_cas` and `meta_delete`.
+    Delete {
+        /// The key removed.
+        key: &'a str,
+    },
+    /// `deletes`.
+    DeleteMany {
+        /// The keys removed.
+        keys: Vec<&'a str>,
+    },
+    /// `touch`.
+    Touch {
+        /// The key touched.
+        key: &'a str,
+        /// The new expiration in seconds.
+        ttl: u32,
+    },
+    /// `increment`, `increment_or_init` and `increment_with`.
+    Increment {
+        /// The counter.
+        key: &'a str,
+        /// The amount added.
+        amount: u64,
+    },
+    /// `decrement` and `decrement_with`.
+    Decrement {
+        /// The counter.
+        key: &'a str,
+        /// The amount subtracted.
+        amount: u64,
+    },
+    /// `flush` and `flush_with_delay` on every server.
+    Flush {
+        /// Seconds until the items are invalidated.
+        delay: Option<u32>,
+    },
+}
+
+impl Operation<'_> {
+    /// The name metrics and spans report the operation as.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Get { .. } => "get",
+            Operation::GetMany { .. } => "gets",
+            Operation::Set { .. } => "set",
+            Operation::SetMany { .. } => "sets",
+            Operation::Add { .. } => "add",
+            Operation::Replace { .. } => "replace",
+            Operation::Append { .. } => "append",
+            Operation::Prepend { .. } => "prepend",
+            Operation::Cas { .. } => "cas",
+            Operation::Delete { .. } => "delete",
+            Operation::DeleteMany { .. } => "deletes",
+            Operation::Touch { .. } => "touch",
+            Operation::Increment { .. } => "increment",
+            Operation::Decrement { .. } => "decrement",
+            Operation::Flush { .. } => "flush",
+        }
+    }
+
+    /// The keys the operation touches, none for `Flush`.
+    #[must_use]
+    pub fn keys(&self) -> Vec<&str> {
+        match self {
+            Operation::Get { key }
+            | Operation::Set { key, .. }
+            | Operation::Add { key, .. }
+            | Operation::Replace { key, .. }
+            | Operation::Append { key, .. }
+            | Operation::Prepend { key, .. }
+            | Operation::Cas { key, .. }
+            | Operation::Delete { key }
+            | Operation::Touch { key, .. }
+            | Operation::Increment { key, .. }
+            | Operation::Decrement { key, .. } => vec![key],
+            Operation::GetMany { keys }
+            | Operation::SetMany { keys, .. }
+            | Operation::DeleteMany { keys } => keys.clone(),
+            Operation::Flush { .. } => vec![],
+        }
+    }
+}
@@ -0,0 +1,837 @@
+//! [ASCII protocol](https://github.com/memcached/memcached/blob/master/doc/protocol.txt)
+
+use super::{
+    check_sizes,
+    code::DataType,
+    encode, encode_items,
+    meta::{self, MetaDeleteFlags, MetaGetFlags, MetaResponse, MetaSetFlags, MetaStatus},
+    parse, CasResult, Command, OutOfMemoryRetry, ProtocolTrait, Reply, DONT_CREATE,
+};
+use crate::{
+    codec::{Utf8String, ValueCodec},
+    error::{ClientError, CommandError, MemcachedError, ServerError},
+    stats::ItemMetadata,
+    stream::Stream,
+    Result,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{borrow::Cow, collections::HashMap, io, ops::ControlFlow};
+
+const READ_CHUNK_LEN: usize = 4096;
+
+/// A value line of a `get`/`gets` reply with its data block.
+struct Value {
+    key: String,
+    flags: u32,
+    cas: Option<u64>,
+    data: Vec<u8>,
+}
+
+pub(crate) struct AsciiProtocol {
+    pub(crate) stream: Stream,
+    /// The server's `item_size_max`, larger values are refused without a round trip.
+    pub(crate) item_size_max: Option<u64>,
+    /// Yield to other tasks after this many values of a multi-get reply, 0 never.
+    pub(crate) yield_every: usize,
+    /// Store once more when the server is out of memory.
+    pub(crate) out_of_memory_retry: Option<OutOfMemoryRetry>,
+    /// bytes read from the stream but not consumed yet
+    buf: Vec<u8>,
+}
+
+pub(super) fn binary_only(commands: &str) -> MemcachedError {
+    ClientError::Error(Cow::Owned(format!(
+        "{commands} need the binary protocol, connect without ?protocol=ascii"
+    )))
+    .into()
+}
+
+fn bad_response(line: &str) -> MemcachedError {
+    ServerError::BadResponse(Cow::Owned(line.to_owned())).into()
+}
+
+/// Keys are separated by spaces in the ASCII protocol, so they can't contain whitespace or control characters.
+fn check_key(key: &str) -> Result<()> {
+    if key.bytes().any(|b| b <= b' ' || b == 0x7f) {
+        Err(ClientError::Error(Cow::Borrowed(
+            "The ASCII protocol doesn't allow whitespace or control characters in keys.",
+        ))
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+impl AsciiProtocol {
+    pub(crate) fn new(stream: Stream) -> Self {
+        AsciiProtocol {
+            stream,
+            item_size_max: None,
+            yield_every: 0,
+            out_of_memory_retry: None,
+            buf: vec![],
+        }
+    }
+
+    async fn fill(&mut self) -> Result<()> {
+        let mut chunk = [0; READ_CHUNK_LEN];
+        let n = self.stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(())
+    }
+
+    pub(super) async fn read_line(&mut self) -> Result<String> {
+        loop {
+            if let Some(pos) = self.buf.windows(2).position(|w| w == b"\r\n") {
+                let line: Vec<u8> = self.buf.drain(..pos + 2).take(pos).collect();
+                return Ok(String::from_utf8(line)?);
+            }
+            self.fill().await?;
+        }
+    }
+
+    /// Read a data block of `len` bytes and its trailing `\r\n`.
+    pub(super) async fn read_data(&mut self, len: usize) -> Result<Vec<u8>> {
+        while self.buf.len() < len + 2 {
+            self.fill().await?;
+        }
+        let data = self.buf.drain(..len + 2).take(len).collect();
+        Ok(data)
+    }
+
+    /// Read a reply line, `ERROR`, `CLIENT_ERROR` and `SERVER_ERROR` are turned into errors.
+    async fn read_reply(&mut self) -> Result<String> {
+        let line = self.read_line().await?;
+        if line == "ERROR" {
+            Err(CommandError::InvalidCommand.into())
+        } else if let Some(message) = line.strip_prefix("CLIENT_ERROR ") {
+            Err(ClientError::from(message.to_owned()).into())
+        } else if let Some(message) = line.strip_prefix("SERVER_ERROR ") {
+            if message.starts_with("out of memory") {
+                Err(CommandError::OutOfMemory.into())
+            } else {
+                Err(ServerError::from(message.to_owned()).into())
+            }
+        } else {
+            Ok(line)
+        }
+    }
+
+    pub(super) async fn send(&mut self, command: &str, data: Option<&[u8]>) -> Result<()> {
+        self.write(command, data).await?;
+        self.stream.flush().await
+    }
+
+    async fn write(&mut self, command: &str, data: Option<&[u8]>) -> Result<()> {
+        self.stream.write_all(command.as_bytes()).await?;
+        self.stream.write_all(b"\r\n").await?;
+        if let Some(data) = data {
+            self.stream.write_all(data).await?;
+            self.stream.write_all(b"\r\n").await?;
+        }
+        Ok(())
+    }
+
+    /// Read one reply per pipelined command, `expected` means success.
+    /// Command errors, e.g. the server being out of memory, fail their key and the rest is read on.
+    async fn pipelined_replies(
+        &mut self,
+        keys: &[&str],
+        expected: &str,
+    ) -> Result<HashMap<String, CommandError>> {
+        self.stream.flush().await?;
+        let mut failures = HashMap::new();
+        for key in keys {
+            let error = match self.read_reply().await {
+                Ok(reply) if reply == expected => continue,
+                Ok(reply) => match &*reply {
+                    "NOT_STORED" | "EXISTS" => CommandError::KeyExists,
+                    "NOT_FOUND" => CommandError::KeyNotFound,
+                    _ => return Err(self.abandon(bad_response(&reply))),
+                },
+                Err(MemcachedError::ClientError(ClientError::Error(message)))
+                    if message.contains("too large") =>
+                {
+                    CommandError::ValueTooLarge
+                }
+                Err(MemcachedError::CommandError(e)) => e,
+                Err(e) => return Err(self.abandon(e)),
+            };
+            let _ = failures.insert((*key).to_owned(), error);
+        }
+        Ok(failures)
+    }
+
+    /// Stop reading a reply before its end: the rest is unread, so the connection is closed.
+    fn abandon(&mut self, e: MemcachedError) -> MemcachedError {
+        self.stream.poison();
+        e
+    }
+
+    /// `encoded` are the value bytes and flags from the codec.
+    async fn store(
+        &mut self,
+        command: &str,
+        key: &str,
+        encoded: (Vec<u8>, u32),
+        expiration: u32,
+        cas: Option<u64>,
+    ) -> Result<String> {
+        check_key(key)?;
+        let (value, flags) = encoded;
+        let len = value.len();
+        let line = match cas {
+            Some(cas) => format!("{command} {key} {flags} {expiration} {len} {cas}"),
+            None => format!("{command} {key} {flags} {expiration} {len}"),
+        };
+        self.send(&line, Some(&value)).await?;
+        match self.read_reply().await {
+            Err(MemcachedError::CommandError(CommandError::OutOfMemory)) => {
+                let Some(retry) = self.out_of_memory_retry else {
+                    return Err(CommandError::OutOfMemory.into());
+                };
+                async_std::task::sleep(retry.delay).await;
+                let expiration = retry.expiration(expiration);
+                let line = match cas {
+                    Some(cas) => format!("{command} {key} {flags} {expiration} {len} {cas}"),
+                    None => format!("{command} {key} {flags} {expiration} {len}"),
+                };
+                self.send(&line, Some(&value)).await?;
+                self.read_reply().await
+            }
+            reply => reply,
+        }
+    }
+
+    /// `NOT_STORED` means the precondition of the command failed, e.g. `add` on an existing key.
+    fn stored(reply: &str, not_stored: CommandError) -> Result<()> {
+        match reply {
+            "STORED" => Ok(()),
+            "NOT_STORED" => Err(not_stored.into()),
+            "EXISTS" => Err(CommandError::KeyExists.into()),
+            "NOT_FOUND" => Err(CommandError::KeyNotFound.into()),
+            _ => Err(bad_response(reply)),
+        }
+    }
+
+    async fn retrieve(&mut self, command: &str, keys: &[&str]) -> Result<Vec<Value>> {
+        for key in keys {
+            check_key(key)?;
+        }
+        self.send(&format!("{command} {}", keys.join(" ")), None)
+            .await?;
+        self.read_values().await
+    }
+
+    /// The value lines of a retrieval reply up to `END`.
+    async fn read_values(&mut self) -> Result<Vec<Value>> {
+        let mut values = vec![];
+        loop {
+            parse::yield_every(values.len(), self.yield_every).await;
+            let line = match self.read_reply().await {
+                Ok(line) => line,
+                // An error line after values, e.g. out of memory, ends the values but maybe not the reply.
+                Err(e) if !values.is_empty() => return Err(self.abandon(e)),
+                Err(e) => return Err(e),
+            };
+            if line == "END" {
+                return Ok(values);
+            }
+            let mut parts = line.split(' ');
+            if parts.next() != Some("VALUE") {
+                return Err(bad_response(&line));
+            }
+            let key = parts.next().ok_or_else(|| bad_response(&line))?.to_owned();
+            let flags = parts.next().ok_or_else(|| bad_response(&line))?.parse()?;
+            let len = parts.next().ok_or_else(|| bad_response(&line))?.parse()?;
+            let cas = parts.next().map(str::parse).transpose()?;
+            let data = self.read_data(len).await?;
+            values.push(Value {
+                key,
+                flags,
+                cas,
+                data,
+            });
+        }
+    }
+
+    async fn counter(&mut self, command: &str, key: &str, amount: u64) -> Result<u64> {
+        check_key(key)?;
+        self.send(&format!("{command} {key} {amount}"), None)
+            .await?;
+        self.read_counter().await
+    }
+
+    async fn read_counter(&mut self) -> Result<u64> {
+        match self.read_reply().await {
+            Ok(line) if line == "NOT_FOUND" => Err(CommandError::KeyNotFound.into()),
+            Ok(line) => Ok(line.trim().parse()?),
+            Err(MemcachedError::ClientError(ClientError::Error(message)))
+                if message.contains("non-numeric") =>
+            {
+                Err(CommandError::IncrOrDecrOnNonNumericValue.into())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl AsciiProtocol {
+    /// `incr`/`decr` can't create keys, so a missing key is created with `add`.
+    /// `add` never overwrites a concurrent initialization, which is counted again instead.
+    async fn counter_with(
+        &mut self,
+        command: &str,
+        key: &str,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        match self.counter(command, key, amount).await {
+            Err(MemcachedError::CommandError(CommandError::KeyNotFound))
+                if expiration != DONT_CREATE => {}
+            result => return result,
+        }
+        // Counters are numbers in ASCII whatever the codec of the client is.
+        match self
+            .add(key, initial.to_string(), expiration, &Utf8String)
+            .await
+        {
+            Ok(()) => Ok(initial),
+            Err(MemcachedError::CommandError(CommandError::KeyExists)) => {
+                self.counter(command, key, amount).await
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl AsciiProtocol {
+    /// The reply to one pipelined command, a command error fails only that command.
+    async fn pipeline_reply(&mut self, command: &Command) -> Result<Reply> {
+        Ok(match command {
+            Command::Get(_) => Reply::Value(
+                self.read_values()
+                    .await?
+                    .pop()
+                    .map(|value| (value.data, value.flags)),
+            ),
+            Command::Set { .. } => {
+                Self::stored(&self.read_reply().await?, CommandError::KeyExists)?;
+                Reply::Stored
+            }
+            Command::Delete(_) => match &*self.read_reply().await? {
+                "DELETED" => Reply::Deleted(true),
+                "NOT_FOUND" => Reply::Deleted(false),
+                line => return Err(bad_response(line)),
+            },
+            Command::Increment { .. } => Reply::Counter(self.read_counter().await?),
+            Command::Flush { .. } => match &*self.read_reply().await? {
+                "OK" => Reply::Flushed,
+                line => return Err(bad_response(line)),
+            },
+        })
+    }
+}
+
+impl ProtocolTrait for AsciiProtocol {
+    /// memcached started with `-Y` accepts the credentials as the data block of a `set`.
+    async fn auth(&mut self, username: &str, password: &str) -> Result<()> {
+        let credentials = format!("{username} {password}");
+        self.send(
+            &format!("set auth 0 0 {}", credentials.len()),
+            Some(credentials.as_bytes()),
+        )
+        .await?;
+        let reply = self.read_reply().await?;
+        Self::stored(&reply, CommandError::AuthenticationRequired)
+    }
+
+    async fn version(&mut self) -> Result<String> {
+        self.send("version", None).await?;
+        let line = self.read_reply().await?;
+        line.strip_prefix("VERSION ")
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| bad_response(&line))
+    }
+
+    /// The text protocol has no noop outside of meta commands, `version` is the next cheapest.
+    async fn noop(&mut self) -> Result<()> {
+        let _ = self.version().await?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.send("flush_all", None).await?;
+        match &*self.read_reply().await? {
+            "OK" => Ok(()),
+            line => Err(bad_response(line)),
+        }
+    }
+
+    async fn flush_with_delay(&mut self, delay: u32) -> Result<()> {
+        self.send(&format!("flush_all {delay}"), None).await?;
+        match &*self.read_reply().await? {
+            "OK" => Ok(()),
+            line => Err(bad_response(line)),
+        }
+    }
+
+    async fn get<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<V>> {
+        Ok(self.get_with_data_type(key, codec).await?.map(|(v, _)| v))
+    }
+
+    /// The ASCII protocol has no data type, values are always raw bytes.
+    async fn get_with_data_type<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<(V, DataType)>> {
+        match self.retrieve("get", &[key]).await?.pop() {
+            Some(value) => Ok(Some((
+                codec.decode(&value.data, value.flags)?,
+                DataType::Raw,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_and_touch<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<Option<V>> {
+        match self
+            .retrieve(&format!("gat {expiration}"), &[key])
+            .await?
+            .pop()
+        {
+            Some(value) => Ok(Some(codec.decode(&value.data, value.flags)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn gets_and_touch<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        keys: &[&str],
+        expiration: u32,
+        codec: &C,
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
+        let mut result = HashMap::new();
+        for value in self.retrieve(&format!("gats {expiration}"), keys).await? {
+            let _ = result.insert(
+                value.key,
+                (
+                    codec.decode(&value.data, value.flags)?,
+                    value.flags,
+                    value.cas,
+                ),
+            );
+        }
+        Ok(result)
+    }
+
+    async fn get_with_cas<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<(V, u32, u64)>> {
+        match self.retrieve("gets", &[key]).await?.pop() {
+            Some(value) => {
+                let cas = value
+                    .cas
+                    .ok_or_else(|| bad_response("gets reply without cas"))?;
+                Ok(Some((
+                    codec.decode(&value.data, value.flags)?,
+                    value.flags,
+                    cas,
+                )))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<()> {
+        let reply = self
+            .store(
+                "set",
+                key,
+                encode(codec, &value, self.item_size_max)?,
+                expiration,
+                None,
+            )
+            .await?;
+        Self::stored(&reply, CommandError::KeyExists)
+    }
+
+    async fn set_with_data_type<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        data_type: DataType,
+        codec: &C,
+    ) -> Result<()> {
+        if data_type != DataType::Raw {
+            return Err(ClientError::Error(Cow::Borrowed(
+                "The ASCII protocol can only store raw bytes.",
+            ))
+            .into());
+        }
+        self.set(key, value, expiration, codec).await
+    }
+
+    async fn add<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<()> {
+        let reply = self
+            .store(
+                "add",
+                key,
+                encode(codec, &value, self.item_size_max)?,
+                expiration,
+                None,
+            )
+            .await?;
+        Self::stored(&reply, CommandError::KeyExists)
+    }
+
+    async fn replace<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<()> {
+        let reply = self
+            .store(
+                "replace",
+                key,
+                encode(codec, &value, self.item_size_max)?,
+                expiration,
+                None,
+            )
+            .await?;
+        Self::stored(&reply, CommandError::KeyNotFound)
+    }
+
+    async fn append<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        codec: &C,
+    ) -> Result<()> {
+        let reply = self
+            .store(
+                "append",
+                key,
+                encode(codec, &value, self.item_size_max)?,
+                0,
+                None,
+            )
+            .await?;
+        Self::stored(&reply, CommandError::KeyNotFound)
+    }
+
+    async fn cas<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        cas: u64,
+        codec: &C,
+    ) -> Result<CasResult> {
+        let reply = self
+            .store(
+                "cas",
+                key,
+                encode(codec, &value, self.item_size_max)?,
+                expiration,
+                Some(cas),
+            )
+            .await?;
+        match Self::stored(&reply, CommandError::KeyExists) {
+            Ok(()) => Ok(CasResult::Stored),
+            Err(MemcachedError::CommandError(CommandError::KeyExists)) => Ok(CasResult::Exists),
+            Err(MemcachedError::CommandError(CommandError::KeyNotFound)) => Ok(CasResult::NotFound),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn prepend<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        codec: &C,
+    ) -> Result<()> {
+        let reply = self
+            .store(
+                "prepend",
+                key,
+                encode(codec, &value, self.item_size_max)?,
+                0,
+                None,
+            )
+            .await?;
+        Self::stored(&reply, CommandError::KeyNotFound)
+    }
+
+    async fn delete(&mut self, key: &str) -> Result<bool> {
+        check_key(key)?;
+        self.send(&format!("delete {key}"), None).await?;
+        match &*self.read_reply().await? {
+            "DELETED" => Ok(true),
+            "NOT_FOUND" => Ok(false),
+            line => Err(bad_response(line)),
+        }
+    }
+
+    /// Sent as a meta delete, the classic `delete` has no cas.
+    async fn delete_cas(&mut self, key: &str, cas: u64) -> Result<CasResult> {
+        let flags = MetaDeleteFlags {
+            cas: Some(cas),
+            ..MetaDeleteFlags::default()
+        };
+        match self.meta_delete(key, &flags).await?.status {
+            MetaStatus::Ok => Ok(CasResult::Stored),
+            MetaStatus::Exists => Ok(CasResult::Exists),
+            _ => Ok(CasResult::NotFound),
+        }
+    }
+
+    /// Unlike the binary protocol, a missing key is not created.
+    async fn increment(&mut self, key: &str, amount: u64) -> Result<u64> {
+        self.counter("incr", key, amount).await
+    }
+
+    async fn increment_with(
+        &mut self,
+        key: &str,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.counter_with("incr", key, amount, initial, expiration)
+            .await
+    }
+
+    /// Unlike the binary protocol, a missing key is not created.
+    async fn decrement(&mut self, key: &str, amount: u64) -> Result<u64> {
+        self.counter("decr", key, amount).await
+    }
+
+    async fn decrement_with(
+        &mut self,
+        key: &str,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.counter_with("decr", key, amount, initial, expiration)
+            .await
+    }
+
+    async fn touch(&mut self, key: &str, expiration: u32) -> Result<bool> {
+        check_key(key)?;
+        self.send(&format!("touch {key} {expiration}"), None)
+            .await?;
+        match &*self.read_reply().await? {
+            "TOUCHED" => Ok(true),
+            "NOT_FOUND" => Ok(false),
+            line => Err(bad_response(line)),
+        }
+    }
+
+    async fn stats(&mut self) -> Result<HashMap<String, String>> {
+        self.stats_group("").await
+    }
+
+    async fn stats_group(&mut self, group: &str) -> Result<HashMap<String, String>> {
+        if group.is_empty() {
+            self.send("stats", None).await?;
+        } else {
+            check_key(group)?;
+            self.send(&format!("stats {group}"), None).await?;
+        }
+        let mut result = HashMap::new();
+        loop {
+            let line = self.read_reply().await?;
+            if line == "END" {
+                return Ok(result);
+            }
+            let (key, value) = line
+                .strip_prefix("STAT ")
+                .and_then(|stat| stat.split_once(' '))
+                .ok_or_else(|| bad_response(&line))?;
+            let _ = result.insert(key.to_owned(), value.to_owned());
+        }
+    }
+
+    /// SASL is only available with the binary protocol.
+    async fn sasl_mechanisms(&mut self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    async fn metadump(
+        &mut self,
+        visit: &mut (dyn FnMut(ItemMetadata) -> ControlFlow<()> + Send),
+    ) -> Result<()> {
+        self.send("lru_crawler metadump all", None).await?;
+        loop {
+            let line = self.read_reply().await?;
+            if line == "END" {
+                return Ok(());
+            }
+            let item = ItemMetadata::parse(&line).ok_or_else(|| bad_response(&line))?;
+            if visit(item).is_break() {
+                self.stream.poison();
+                return Ok(());
+            }
+        }
+    }
+
+    async fn gets<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        keys: &[&str],
+        codec: &C,
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
+        let mut result = HashMap::new();
+        for value in self.retrieve("gets", keys).await? {
+            let _ = result.insert(
+                value.key,
+                (
+                    codec.decode(&value.data, value.flags)?,
+                    value.flags,
+                    value.cas,
+                ),
+            );
+        }
+        Ok(result)
+    }
+
+    async fn sets<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        items: &[(&str, &V, u32)],
+        codec: &C,
+    ) -> Result<HashMap<String, CommandError>> {
+        // Checked and encoded before anything is sent, a bad key or value sets none of the items.
+        // One the server cannot store only fails its key.
+        for (key, _, _) in items {
+            check_key(key)?;
+        }
+        let (encoded, mut failures) = encode_items(items, codec, self.item_size_max)?;
+        let mut keys = Vec::with_capacity(items.len());
+        for ((key, _, expiration), encoded) in items.iter().zip(encoded) {
+            let Some((value, flags)) = encoded else {
+                continue;
+            };
+            let line = format!("set {key} {flags} {expiration} {}", value.len());
+            self.write(&line, Some(&value)).await?;
+            keys.push(*key);
+        }
+        failures.extend(self.pipelined_replies(&keys, "STORED").await?);
+        Ok(failures)
+    }
+
+    async fn deletes(&mut self, keys: &[&str]) -> Result<HashMap<String, CommandError>> {
+        for key in keys {
+            check_key(key)?;
+        }
+        for key in keys {
+            self.write(&format!("delete {key}"), None).await?;
+        }
+        self.pipelined_replies(keys, "DELETED").await
+    }
+
+    /// Every command is answered in order, a get with its values up to `END`.
+    async fn pipeline(&mut self, commands: &[Command]) -> Result<Vec<Reply>> {
+        check_sizes(commands, self.item_size_max)?;
+        for key in commands.iter().filter_map(Command::key) {
+            check_key(key)?;
+        }
+        for command in commands {
+            match command {
+                Command::Get(key) => self.write(&format!("get {key}"), None).await?,
+                Command::Set {
+                    key,
+                    value,
+                    flags,
+                    expiration,
+                } => {
+                    let line = format!("set {key} {flags} {expiration} {}", value.len());
+                    self.write(&line, Some(value)).await?;
+                }
+                Command::Delete(key) => self.write(&format!("delete {key}"), None).await?,
+                Command::Increment { key, amount } => {
+                    self.write(&format!("incr {key} {amount}"), None).await?;
+                }
+                Command::Flush { delay: None } => self.write("flush_all", None).await?,
+                Command::Flush { delay: Some(delay) } => {
+                    self.write(&format!("flush_all {delay}"), None).await?;
+                }
+            }
+        }
+        self.stream.flush().await?;
+        let mut replies = vec![];
+        for command in commands {
+            match self.pipeline_reply(command).await {
+                Ok(reply) => replies.push(reply),
+                Err(MemcachedError::CommandError(e)) => replies.push(Reply::Failed(e)),
+                Err(e) => return Err(self.abandon(e)),
+            }
+        }
+        Ok(replies)
+    }
+
+    async fn meta_get(&mut self, key: &str, flags: &MetaGetFlags) -> Result<MetaResponse<Vec<u8>>> {
+        check_key(key)?;
+        self.send(&meta::get_command(key, flags), None).await?;
+        let (mut response, value_len) = meta::parse_response(&self.read_reply().await?)?;
+        if let Some(len) = value_len {
+            response.value = Some(self.read_data(len).await?);
+        }
+        Ok(response)
+    }
+
+    async fn meta_set<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        flags: &MetaSetFlags,
+        codec: &C,
+    ) -> Result<MetaResponse<()>> {
+        check_key(key)?;
+        let (data, client_flags) = encode(codec, &value, self.item_size_max)?;
+        let command = meta::set_command(key, data.len(), client_flags, flags);
+        self.send(&command, Some(&data)).await?;
+        Ok(meta::parse_response(&self.read_reply().await?)?.0)
+    }
+
+    async fn meta_delete(
+        &mut self,
+        key: &str,
+        flags: &MetaDeleteFlags,
+    ) -> Result<MetaResponse<()>> {
+        check_key(key)?;
+        self.send(&meta::delete_command(key, flags), None).await?;
+        Ok(meta::parse_response(&self.read_reply().await?)?.0)
+    }
+}
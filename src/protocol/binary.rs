@@ -0,0 +1,784 @@
+use super::{
+    binary_packet::{self, AuthStep, PacketHeader},
+    check_sizes,
+    code::{DataType, Magic, Opcode},
+    encode, encode_items,
+    meta::{MetaDeleteFlags, MetaGetFlags, MetaResponse, MetaSetFlags},
+    sasl::Mechanism,
+    CasResult, Command, OutOfMemoryRetry, ProtocolTrait, Reply,
+};
+use crate::{
+    codec::ValueCodec,
+    error::{ClientError, CommandError, MemcachedError},
+    stats::ItemMetadata,
+    stream::Stream,
+    Result,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{borrow::Cow, collections::HashMap, ops::ControlFlow};
+
+pub(super) fn ascii_only(commands: &str) -> MemcachedError {
+    ClientError::Error(Cow::Owned(format!(
+        "{commands} need the ASCII protocol, connect with ?protocol=ascii"
+    )))
+    .into()
+}
+
+pub(crate) struct BinaryProtocol {
+    pub(crate) stream: Stream,
+    /// The server's `item_size_max`, larger values are refused without a round trip.
+    pub(crate) item_size_max: Option<u64>,
+    /// Yield to other tasks after this many values of a multi-get reply, 0 never.
+    pub(crate) yield_every: usize,
+    /// Store once more when the server is out of memory.
+    pub(crate) out_of_memory_retry: Option<OutOfMemoryRetry>,
+}
+
+impl BinaryProtocol {
+    /// Write a store request without flushing it. `opcode` is `Set`, `Add`, `Replace`, `Append`
+    /// or `Prepend`, its quiet variant is sent if `options.quiet` is set.
+    async fn write_store(
+        &mut self,
+        opcode: Opcode,
+        key: &str,
+        value: &[u8],
+        options: &binary_packet::StoreOptions,
+    ) -> Result<()> {
+        let extras_length = if opcode.has_store_extras() { 8 } else { 0 };
+        let opcode = if options.quiet {
+            opcode.quiet()
+        } else {
+            opcode
+        };
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: opcode as u8,
+            key_length: key.len() as u16,
+            extras_length,
+            data_type: options.data_type.into(),
+            vbucket_id_or_status: options.vbucket,
+            total_body_length: (usize::from(extras_length) + key.len() + value.len()) as u32,
+            opaque: options.opaque,
+            cas: options.cas.unwrap_or(0),
+        };
+        request_header.write(&mut self.stream).await?;
+        if extras_length > 0 {
+            self.stream.write_u32(options.flags).await?;
+            self.stream.write_u32(options.expiration).await?;
+        }
+        self.stream.write_all(key.as_bytes()).await?;
+        self.stream.write_all(value).await?;
+        Ok(())
+    }
+
+    /// Send a store request, every store goes through here or `write_store`.
+    /// A quiet request is only written, its failure is read by whoever ends the pipeline.
+    async fn store_with(
+        &mut self,
+        opcode: Opcode,
+        key: &str,
+        value: &[u8],
+        options: binary_packet::StoreOptions,
+    ) -> Result<()> {
+        self.write_store(opcode, key, value, &options).await?;
+        if options.quiet {
+            return Ok(());
+        }
+        self.stream.flush().await?;
+        let stored = binary_packet::parse_response(&mut self.stream)
+            .await?
+            .err()
+            .map(|_| ());
+        match (stored, self.out_of_memory_retry) {
+            (Err(MemcachedError::CommandError(CommandError::OutOfMemory)), Some(retry)) => {
+                async_std::task::sleep(retry.delay).await;
+                let options = binary_packet::StoreOptions {
+                    expiration: retry.expiration(options.expiration),
+                    ..options
+                };
+                self.write_store(opcode, key, value, &options).await?;
+                self.stream.flush().await?;
+                binary_packet::parse_response(&mut self.stream)
+                    .await?
+                    .err()
+                    .map(|_| ())
+            }
+            (stored, _) => stored,
+        }
+    }
+
+    /// Finish a pipeline of quiet requests, the `opaque` of a request is the index of its key.
+    async fn finish_quiet(&mut self, keys: &[&str]) -> Result<HashMap<String, CommandError>> {
+        let noop_request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Noop as u8,
+            ..PacketHeader::default()
+        };
+        noop_request_header.write(&mut self.stream).await?;
+        self.stream.flush().await?;
+        let failures = binary_packet::parse_quiet_responses(&mut self.stream, keys.len()).await?;
+        Ok(failures
+            .into_iter()
+            .filter_map(|(opaque, e)| keys.get(opaque as usize).map(|key| ((*key).to_owned(), e)))
+            .collect())
+    }
+
+    /// `incr`/`decr`, a missing key is stored as `initial_value` unless `expiration` is `0xffffffff`.
+    async fn counter(
+        &mut self,
+        opcode: Opcode,
+        key: &str,
+        extras: binary_packet::CounterExtras,
+    ) -> Result<u64> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: opcode as u8,
+            key_length: key.len() as u16,
+            extras_length: 20,
+            total_body_length: (20 + key.len()) as u32,
+            ..PacketHeader::default()
+        };
+        self.write_counter_request(request_header, key, extras)
+            .await?;
+        self.stream.flush().await?;
+        binary_packet::parse_counter_response(&mut self.stream).await
+    }
+
+    async fn write_counter_request(
+        &mut self,
+        request_header: PacketHeader,
+        key: &str,
+        extras: binary_packet::CounterExtras,
+    ) -> Result<()> {
+        request_header.write(&mut self.stream).await?;
+        self.stream.write_u64(extras.amount).await?;
+        self.stream.write_u64(extras.initial_value).await?;
+        self.stream.write_u32(extras.expiration).await?;
+        self.stream.write_all(key.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+impl ProtocolTrait for BinaryProtocol {
+    /// Authenticate with the strongest mechanism both sides support, PLAIN for servers
+    /// that don't list their mechanisms.
+    async fn auth(&mut self, username: &str, password: &str) -> Result<()> {
+        let offered = match self.sasl_mechanisms().await {
+            Ok(offered) => offered,
+            Err(MemcachedError::CommandError(_)) => vec![],
+            Err(e) => return Err(e),
+        };
+        let mut mechanism = Mechanism::choose(&offered)?;
+        let mut opcode = Opcode::StartAuth;
+        let mut data = mechanism.start(username, password);
+        loop {
+            let key = mechanism.name();
+            let request_header = PacketHeader {
+                magic: Magic::Request as u8,
+                opcode: opcode as u8,
+                key_length: key.len() as u16,
+                total_body_length: (key.len() + data.len()) as u32,
+                ..PacketHeader::default()
+            };
+            request_header.write(&mut self.stream).await?;
+            self.stream.write_all(key.as_bytes()).await?;
+            self.stream.write_all(&data).await?;
+            self.stream.flush().await?;
+            match binary_packet::parse_auth_response(&mut self.stream).await? {
+                AuthStep::Done(data) => return mechanism.finish(&data),
+                AuthStep::Continue(challenge) => {
+                    data = mechanism.step(username, password, &challenge)?;
+                    opcode = Opcode::StepAuth;
+                }
+            }
+        }
+    }
+    async fn version(&mut self) -> Result<String> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Version as u8,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.flush().await?;
+        let version = binary_packet::parse_version_response(&mut self.stream).await?;
+        Ok(version)
+    }
+
+    async fn noop(&mut self) -> Result<()> {
+        let _ = self.finish_quiet(&[]).await?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Flush as u8,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.flush().await?;
+        binary_packet::parse_response(&mut self.stream)
+            .await?
+            .err()
+            .map(|_| ())
+    }
+
+    /// Flush all cache on memcached server with a delay seconds.
+    async fn flush_with_delay(&mut self, delay: u32) -> Result<()> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Flush as u8,
+            extras_length: 4,
+            total_body_length: 4,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.write_u32(delay).await?;
+        self.stream.flush().await?;
+        binary_packet::parse_response(&mut self.stream)
+            .await?
+            .err()
+            .map(|_| ())
+    }
+
+    async fn get<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<V>> {
+        Ok(self.get_with_data_type(key, codec).await?.map(|(v, _)| v))
+    }
+
+    async fn get_with_data_type<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<(V, DataType)>> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Get as u8,
+            key_length: key.len() as u16,
+            total_body_length: key.len() as u32,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.write_all(key.as_bytes()).await?;
+        self.stream.flush().await?;
+        Ok(binary_packet::parse_get_response(&mut self.stream, codec)
+            .await?
+            .map(|item| (item.value, item.data_type)))
+    }
+
+    async fn get_and_touch<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<Option<V>> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::GetAndTouch as u8,
+            key_length: key.len() as u16,
+            extras_length: 4,
+            total_body_length: (4 + key.len()) as u32,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.write_u32(expiration).await?;
+        self.stream.write_all(key.as_bytes()).await?;
+        self.stream.flush().await?;
+        Ok(binary_packet::parse_get_response(&mut self.stream, codec)
+            .await?
+            .map(|item| item.value))
+    }
+
+    /// Quiet `gatkq` requests terminated by a `Noop`, like `gets`.
+    async fn gets_and_touch<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        keys: &[&str],
+        expiration: u32,
+        codec: &C,
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
+        let request = binary_packet::multi_get_request(
+            Opcode::GetAndTouchKQ,
+            keys,
+            &expiration.to_be_bytes(),
+        );
+        self.stream.write_all(&request).await?;
+        self.stream.flush().await?;
+        binary_packet::parse_gets_response(&mut self.stream, keys.len(), codec, self.yield_every)
+            .await
+    }
+
+    /// `getk`, the cas is read from the response header.
+    async fn get_with_cas<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<(V, u32, u64)>> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::GetK as u8,
+            key_length: key.len() as u16,
+            total_body_length: key.len() as u32,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.write_all(key.as_bytes()).await?;
+        self.stream.flush().await?;
+        Ok(binary_packet::parse_get_response(&mut self.stream, codec)
+            .await?
+            .map(|item| (item.value, item.flags, item.cas)))
+    }
+
+    async fn set<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<()> {
+        let (value, flags) = encode(codec, &value, self.item_size_max)?;
+        self.store_with(
+            Opcode::Set,
+            key,
+            &value,
+            binary_packet::StoreOptions {
+                flags,
+                expiration,
+                ..binary_packet::StoreOptions::default()
+            },
+        )
+        .await
+    }
+
+    async fn set_with_data_type<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        data_type: DataType,
+        codec: &C,
+    ) -> Result<()> {
+        let (value, flags) = encode(codec, &value, self.item_size_max)?;
+        self.store_with(
+            Opcode::Set,
+            key,
+            &value,
+            binary_packet::StoreOptions {
+                flags,
+                expiration,
+                data_type,
+                ..binary_packet::StoreOptions::default()
+            },
+        )
+        .await
+    }
+
+    async fn add<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<()> {
+        let (value, flags) = encode(codec, &value, self.item_size_max)?;
+        self.store_with(
+            Opcode::Add,
+            key,
+            &value,
+            binary_packet::StoreOptions {
+                flags,
+                expiration,
+                ..binary_packet::StoreOptions::default()
+            },
+        )
+        .await
+    }
+
+    async fn replace<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<()> {
+        let (value, flags) = encode(codec, &value, self.item_size_max)?;
+        self.store_with(
+            Opcode::Replace,
+            key,
+            &value,
+            binary_packet::StoreOptions {
+                flags,
+                expiration,
+                ..binary_packet::StoreOptions::default()
+            },
+        )
+        .await
+    }
+
+    async fn append<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        codec: &C,
+    ) -> Result<()> {
+        let (value, flags) = encode(codec, &value, self.item_size_max)?;
+        self.store_with(
+            Opcode::Append,
+            key,
+            &value,
+            binary_packet::StoreOptions {
+                flags,
+                ..binary_packet::StoreOptions::default()
+            },
+        )
+        .await
+    }
+
+    async fn cas<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        cas: u64,
+        codec: &C,
+    ) -> Result<CasResult> {
+        let (value, flags) = encode(codec, &value, self.item_size_max)?;
+        let options = binary_packet::StoreOptions {
+            flags,
+            expiration,
+            cas: Some(cas),
+            ..binary_packet::StoreOptions::default()
+        };
+        match self.store_with(Opcode::Set, key, &value, options).await {
+            Ok(()) => Ok(CasResult::Stored),
+            Err(MemcachedError::CommandError(CommandError::KeyExists)) => Ok(CasResult::Exists),
+            Err(MemcachedError::CommandError(CommandError::KeyNotFound)) => Ok(CasResult::NotFound),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn prepend<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        codec: &C,
+    ) -> Result<()> {
+        let (value, flags) = encode(codec, &value, self.item_size_max)?;
+        self.store_with(
+            Opcode::Prepend,
+            key,
+            &value,
+            binary_packet::StoreOptions {
+                flags,
+                ..binary_packet::StoreOptions::default()
+            },
+        )
+        .await
+    }
+
+    async fn delete(&mut self, key: &str) -> Result<bool> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Delete as u8,
+            key_length: key.len() as u16,
+            total_body_length: key.len() as u32,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.write_all(key.as_bytes()).await?;
+        self.stream.flush().await?;
+        binary_packet::parse_delete_response(&mut self.stream).await
+    }
+
+    async fn delete_cas(&mut self, key: &str, cas: u64) -> Result<CasResult> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Delete as u8,
+            key_length: key.len() as u16,
+            total_body_length: key.len() as u32,
+            cas,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.write_all(key.as_bytes()).await?;
+        self.stream.flush().await?;
+        binary_packet::parse_cas_response(&mut self.stream).await
+    }
+
+    async fn increment(&mut self, key: &str, amount: u64) -> Result<u64> {
+        let extras = binary_packet::CounterExtras {
+            amount,
+            initial_value: 0,
+            expiration: 0,
+        };
+        self.counter(Opcode::Increment, key, extras).await
+    }
+
+    async fn increment_with(
+        &mut self,
+        key: &str,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        let extras = binary_packet::CounterExtras {
+            amount,
+            initial_value: initial,
+            expiration,
+        };
+        self.counter(Opcode::Increment, key, extras).await
+    }
+
+    async fn decrement(&mut self, key: &str, amount: u64) -> Result<u64> {
+        let extras = binary_packet::CounterExtras {
+            amount,
+            initial_value: 0,
+            expiration: 0,
+        };
+        self.counter(Opcode::Decrement, key, extras).await
+    }
+
+    async fn decrement_with(
+        &mut self,
+        key: &str,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        let extras = binary_packet::CounterExtras {
+            amount,
+            initial_value: initial,
+            expiration,
+        };
+        self.counter(Opcode::Decrement, key, extras).await
+    }
+
+    async fn touch(&mut self, key: &str, expiration: u32) -> Result<bool> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Touch as u8,
+            key_length: key.len() as u16,
+            extras_length: 4,
+            total_body_length: (key.len() as u32 + 4),
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.write_u32(expiration).await?;
+        self.stream.write_all(key.as_bytes()).await?;
+        self.stream.flush().await?;
+        binary_packet::parse_touch_response(&mut self.stream).await
+    }
+
+    async fn stats(&mut self) -> Result<HashMap<String, String>> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Stat as u8,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.flush().await?;
+
+        let stats_info = binary_packet::parse_stats_response(&mut self.stream).await?;
+        Ok(stats_info)
+    }
+
+    async fn stats_group(&mut self, group: &str) -> Result<HashMap<String, String>> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Stat as u8,
+            key_length: group.len() as u16,
+            total_body_length: group.len() as u32,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.write_all(group.as_bytes()).await?;
+        self.stream.flush().await?;
+        binary_packet::parse_stats_response(&mut self.stream).await
+    }
+
+    async fn metadump(
+        &mut self,
+        _visit: &mut (dyn FnMut(ItemMetadata) -> ControlFlow<()> + Send),
+    ) -> Result<()> {
+        Err(ascii_only("Metadumps"))
+    }
+
+    async fn sasl_mechanisms(&mut self) -> Result<Vec<String>> {
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::ListMechs as u8,
+            ..PacketHeader::default()
+        };
+        request_header.write(&mut self.stream).await?;
+        self.stream.flush().await?;
+        binary_packet::parse_sasl_mechanisms_response(&mut self.stream).await
+    }
+
+    async fn gets<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        keys: &[&str],
+        codec: &C,
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
+        let request = binary_packet::multi_get_request(Opcode::GetKQ, keys, &[]);
+        self.stream.write_all(&request).await?;
+        self.stream.flush().await?;
+        binary_packet::parse_gets_response(&mut self.stream, keys.len(), codec, self.yield_every)
+            .await
+    }
+
+    async fn sets<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        items: &[(&str, &V, u32)],
+        codec: &C,
+    ) -> Result<HashMap<String, CommandError>> {
+        // Encoded before anything is sent, a value that fails to encode sets none of the items.
+        // One the server cannot store only fails its key.
+        let (encoded, mut failures) = encode_items(items, codec, self.item_size_max)?;
+        for (opaque, ((key, _, expiration), encoded)) in items.iter().zip(encoded).enumerate() {
+            let Some((value, flags)) = encoded else {
+                continue;
+            };
+            let options = binary_packet::StoreOptions {
+                flags,
+                expiration: *expiration,
+                quiet: true,
+                opaque: opaque as u32,
+                ..binary_packet::StoreOptions::default()
+            };
+            self.store_with(Opcode::Set, key, &value, options).await?;
+        }
+        let keys: Vec<&str> = items.iter().map(|(key, _, _)| *key).collect();
+        failures.extend(self.finish_quiet(&keys).await?);
+        Ok(failures)
+    }
+
+    async fn deletes(&mut self, keys: &[&str]) -> Result<HashMap<String, CommandError>> {
+        for (opaque, key) in keys.iter().enumerate() {
+            let request_header = PacketHeader {
+                magic: Magic::Request as u8,
+                opcode: Opcode::DeleteQ as u8,
+                key_length: key.len() as u16,
+                total_body_length: key.len() as u32,
+                opaque: opaque as u32,
+                ..PacketHeader::default()
+            };
+            request_header.write(&mut self.stream).await?;
+            self.stream.write_all(key.as_bytes()).await?;
+        }
+        self.finish_quiet(keys).await
+    }
+
+    /// Gets, sets, deletes and flushes are quiet, only hits and failures are answered.
+    /// Increments are not, their reply is the new value.
+    async fn pipeline(&mut self, commands: &[Command]) -> Result<Vec<Reply>> {
+        check_sizes(commands, self.item_size_max)?;
+        for (opaque, command) in commands.iter().enumerate() {
+            let key = command.key().unwrap_or_default();
+            let request_header = PacketHeader {
+                magic: Magic::Request as u8,
+                key_length: key.len() as u16,
+                total_body_length: key.len() as u32,
+                opaque: opaque as u32,
+                ..PacketHeader::default()
+            };
+            match command {
+                Command::Get(_) => {
+                    let request_header = PacketHeader {
+                        opcode: Opcode::GetKQ as u8,
+                        ..request_header
+                    };
+                    request_header.write(&mut self.stream).await?;
+                    self.stream.write_all(key.as_bytes()).await?;
+                }
+                Command::Set {
+                    value,
+                    flags,
+                    expiration,
+                    ..
+                } => {
+                    let options = binary_packet::StoreOptions {
+                        flags: *flags,
+                        expiration: *expiration,
+                        quiet: true,
+                        opaque: opaque as u32,
+                        ..binary_packet::StoreOptions::default()
+                    };
+                    self.write_store(Opcode::Set, key, value, &options).await?;
+                }
+                Command::Delete(_) => {
+                    let request_header = PacketHeader {
+                        opcode: Opcode::DeleteQ as u8,
+                        ..request_header
+                    };
+                    request_header.write(&mut self.stream).await?;
+                    self.stream.write_all(key.as_bytes()).await?;
+                }
+                Command::Increment { amount, .. } => {
+                    let request_header = PacketHeader {
+                        opcode: Opcode::Increment as u8,
+                        extras_length: 20,
+                        total_body_length: (20 + key.len()) as u32,
+                        ..request_header
+                    };
+                    let extras = binary_packet::CounterExtras {
+                        amount: *amount,
+                        initial_value: 0,
+                        expiration: 0,
+                    };
+                    self.write_counter_request(request_header, key, extras)
+                        .await?;
+                }
+                Command::Flush { delay } => {
+                    let extras_length = if delay.is_some() { 4 } else { 0 };
+                    let request_header = PacketHeader {
+                        opcode: Opcode::Flush.quiet() as u8,
+                        extras_length,
+                        total_body_length: u32::from(extras_length),
+                        ..request_header
+                    };
+                    request_header.write(&mut self.stream).await?;
+                    if let Some(delay) = delay {
+                        self.stream.write_u32(*delay).await?;
+                    }
+                }
+            }
+        }
+        let noop_request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Noop as u8,
+            ..PacketHeader::default()
+        };
+        noop_request_header.write(&mut self.stream).await?;
+        self.stream.flush().await?;
+        binary_packet::parse_pipeline_responses(&mut self.stream, commands).await
+    }
+
+    async fn meta_get(
+        &mut self,
+        _key: &str,
+        _flags: &MetaGetFlags,
+    ) -> Result<MetaResponse<Vec<u8>>> {
+        Err(ascii_only("Meta commands"))
+    }
+
+    async fn meta_set<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        _key: &str,
+        _value: V,
+        _flags: &MetaSetFlags,
+        _codec: &C,
+    ) -> Result<MetaResponse<()>> {
+        Err(ascii_only("Meta commands"))
+    }
+
+    async fn meta_delete(
+        &mut self,
+        _key: &str,
+        _flags: &MetaDeleteFlags,
+    ) -> Result<MetaResponse<()>> {
+        Err(ascii_only("Meta commands"))
+    }
+}
@@ -1,17 +1,21 @@
 use super::{
-    code::{Magic, Opcode},
-    parse,
+    code::{DataType, Magic, Opcode},
+    parse, CasResult, Command, Reply,
 };
 use crate::{
-    error::{CommandError, MemcachedError, ServerError},
+    codec::ValueCodec,
+    error::{ClientError, CommandError, MemcachedError, ServerError},
     stream::Stream,
     Result,
 };
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::{borrow::Cow, collections::HashMap, io::Cursor};
+use std::{borrow::Cow, collections::HashMap, convert::TryFrom, io::Cursor};
 
 const OK_STATUS: u16 = 0x0;
+const AUTH_CONTINUE_STATUS: u16 = 0x21;
+/// Response bodies up to this size are decoded without a heap allocated buffer.
+const SMALL_BODY_LEN: usize = 256;
 
 #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
 pub(super) struct PacketHeader {
@@ -26,10 +30,19 @@ pub(super) struct PacketHeader {
     pub(super) cas: u64,
 }
 
-#[derive(Debug)]
-pub(super) struct StoreExtras {
+/// Everything but the key and value of a store request, see `BinaryProtocol::store_with`.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct StoreOptions {
     pub(super) flags: u32,
     pub(super) expiration: u32,
+    /// Only store if the cas of the item is still this.
+    pub(super) cas: Option<u64>,
+    /// Send the quiet opcode: only failures are answered, read by whoever ends the pipeline.
+    pub(super) quiet: bool,
+    pub(super) vbucket: u16,
+    pub(super) data_type: DataType,
+    /// Echoed by the response, the index of the request in a pipeline.
+    pub(super) opaque: u32,
 }
 
 #[derive(Debug)]
@@ -53,6 +66,19 @@ impl PacketHeader {
         Ok(())
     }
 
+    /// Append the header to `buf`, like `write` does to a stream.
+    fn encode(self, buf: &mut Vec<u8>) {
+        buf.push(self.magic);
+        buf.push(self.opcode);
+        buf.extend_from_slice(&self.key_length.to_be_bytes());
+        buf.push(self.extras_length);
+        buf.push(self.data_type);
+        buf.extend_from_slice(&self.vbucket_id_or_status.to_be_bytes());
+        buf.extend_from_slice(&self.total_body_length.to_be_bytes());
+        buf.extend_from_slice(&self.opaque.to_be_bytes());
+        buf.extend_from_slice(&self.cas.to_be_bytes());
+    }
+
     pub(super) async fn read(stream: &mut Stream) -> Result<PacketHeader> {
         let magic = stream.read_u8().await?;
         if magic != Magic::Response as u8 {
@@ -72,6 +98,41 @@ impl PacketHeader {
     }
 }
 
+/// Size of a request or response header.
+const HEADER_LEN: usize = 24;
+
+/// Quiet reads of `keys` with the same `extras` each, then the noop ending the replies.
+/// The buffer is sized from the key lengths up front, so a batch of thousands of keys
+/// is one allocation and one write.
+pub(super) fn multi_get_request(opcode: Opcode, keys: &[&str], extras: &[u8]) -> Vec<u8> {
+    let len = keys
+        .iter()
+        .map(|key| HEADER_LEN + extras.len() + key.len())
+        .sum::<usize>()
+        + HEADER_LEN;
+    let mut request = Vec::with_capacity(len);
+    for key in keys {
+        PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: opcode as u8,
+            key_length: key.len() as u16,
+            extras_length: extras.len() as u8,
+            total_body_length: (extras.len() + key.len()) as u32,
+            ..PacketHeader::default()
+        }
+        .encode(&mut request);
+        request.extend_from_slice(extras);
+        request.extend_from_slice(key.as_bytes());
+    }
+    PacketHeader {
+        magic: Magic::Request as u8,
+        opcode: Opcode::Noop as u8,
+        ..PacketHeader::default()
+    }
+    .encode(&mut request);
+    request
+}
+
 #[derive(Debug, Deserialize)]
 pub(super) struct Response {
     header: PacketHeader,
@@ -91,18 +152,82 @@ impl Response {
     }
 }
 
+/// A binary protocol packet sent or received on a [`RawConnection`](crate::RawConnection).
+/// The lengths of the header are computed from `extras`, `key` and `value`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RawPacket {
+    /// The command, see the opcodes of the binary protocol.
+    pub opcode: u8,
+    /// The vbucket of a request, the status of a response where 0 is success.
+    pub status: u16,
+    /// The `data_type` byte.
+    pub data_type: u8,
+    /// Copied from the request into its response, e.g. to match pipelined quiet requests.
+    pub opaque: u32,
+    /// The cas a request must match, or the cas of the item in a response.
+    pub cas: u64,
+    /// The command specific extras.
+    pub extras: Vec<u8>,
+    /// The key.
+    pub key: Vec<u8>,
+    /// The value.
+    pub value: Vec<u8>,
+}
+
+/// Write `packet` as a request, without flushing.
+pub(super) async fn write_raw(stream: &mut Stream, packet: &RawPacket) -> Result<()> {
+    let too_long = || ClientError::Error(Cow::Borrowed("Raw packet too long"));
+    let key_length = u16::try_from(packet.key.len()).map_err(|_| too_long())?;
+    let extras_length = u8::try_from(packet.extras.len()).map_err(|_| too_long())?;
+    let total_body_length =
+        u32::try_from(packet.extras.len() + packet.key.len() + packet.value.len())
+            .map_err(|_| too_long())?;
+    let header = PacketHeader {
+        magic: Magic::Request as u8,
+        opcode: packet.opcode,
+        key_length,
+        extras_length,
+        data_type: packet.data_type,
+        vbucket_id_or_status: packet.status,
+        total_body_length,
+        opaque: packet.opaque,
+        cas: packet.cas,
+    };
+    header.write(stream).await?;
+    stream.write_all(&packet.extras).await?;
+    stream.write_all(&packet.key).await?;
+    stream.write_all(&packet.value).await
+}
+
+/// Read one response packet, whatever its status.
+pub(super) async fn read_raw(stream: &mut Stream) -> Result<RawPacket> {
+    let Response {
+        header,
+        key,
+        extras,
+        value,
+    } = parse_response(stream).await?;
+    Ok(RawPacket {
+        opcode: header.opcode,
+        status: header.vbucket_id_or_status,
+        data_type: header.data_type,
+        opaque: header.opaque,
+        cas: header.cas,
+        extras,
+        key,
+        value,
+    })
+}
+
 pub(super) async fn parse_response(stream: &mut Stream) -> Result<Response> {
     let head = PacketHeader::read(stream).await?;
+    let value_len = value_len(&head)?;
     let mut extras = vec![0x0; head.extras_length as usize];
     stream.read_exact(extras.as_mut_slice()).await?;
 
     let mut key = vec![0x0; head.key_length as usize];
     stream.read_exact(key.as_mut_slice()).await?;
 
-    let value_len = (head.total_body_length
-        - u32::from(head.key_length)
-        - u32::from(head.extras_length)) as usize;
-    // TODO: return error if total_body_length < extras_length + key_length
     let mut value = vec![0x0; value_len];
     stream.read_exact(&mut value).await?;
 
@@ -114,14 +239,18 @@ pub(super) async fn parse_response(stream: &mut Stream) -> Result<Response> {
     })
 }
 
-pub(super) async fn parse_cas_response(stream: &mut Stream) -> Result<bool> {
+fn value_len(head: &PacketHeader) -> Result<usize> {
+    head.total_body_length
+        .checked_sub(u32::from(head.key_length) + u32::from(head.extras_length))
+        .map(|len| len as usize)
+        .ok_or_else(|| ServerError::BadResponse(Cow::Borrowed("total body length")).into())
+}
+
+pub(super) async fn parse_cas_response(stream: &mut Stream) -> Result<CasResult> {
     match parse_response(stream).await?.err() {
-        Err(MemcachedError::CommandError(e))
-            if e == CommandError::KeyNotFound || e == CommandError::KeyExists =>
-        {
-            Ok(false)
-        }
-        Ok(_) => Ok(true),
+        Ok(_) => Ok(CasResult::Stored),
+        Err(MemcachedError::CommandError(CommandError::KeyExists)) => Ok(CasResult::Exists),
+        Err(MemcachedError::CommandError(CommandError::KeyNotFound)) => Ok(CasResult::NotFound),
         Err(e) => Err(e),
     }
 }
@@ -131,41 +260,185 @@ pub(super) async fn parse_version_response(stream: &mut Stream) -> Result<String
     parse::deserialize_bytes(&value)
 }
 
-pub(super) async fn parse_get_response<T: DeserializeOwned + 'static>(
+/// The mechanisms are separated by spaces.
+pub(super) async fn parse_sasl_mechanisms_response(stream: &mut Stream) -> Result<Vec<String>> {
+    let Response { value, .. } = parse_response(stream).await?.err()?;
+    let mechanisms: String = parse::deserialize_bytes(&value)?;
+    Ok(mechanisms
+        .split_whitespace()
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// A value read by `get`/`getk` with the metadata from its header.
+pub(super) struct Item<T> {
+    pub(super) value: T,
+    pub(super) data_type: DataType,
+    pub(super) flags: u32,
+    pub(super) cas: u64,
+}
+
+/// Small bodies are read into a stack buffer, so the common point get needs no
+/// heap allocation besides the decoded value itself.
+pub(super) async fn parse_get_response<T: DeserializeOwned + 'static, C: ValueCodec>(
     stream: &mut Stream,
-) -> Result<Option<T>> {
-    match parse_response(stream).await?.err() {
-        Ok(Response { value, .. }) => Ok(Some(parse::deserialize_bytes(&value)?)),
-        Err(MemcachedError::CommandError(CommandError::KeyNotFound)) => Ok(None),
-        Err(e) => Err(e),
+    codec: &C,
+) -> Result<Option<Item<T>>> {
+    let head = PacketHeader::read(stream).await?;
+    let value_len = value_len(&head)?;
+    let body_len = head.total_body_length as usize;
+    let mut small_body = [0x0; SMALL_BODY_LEN];
+    let mut large_body = vec![];
+    let body = if body_len <= SMALL_BODY_LEN {
+        &mut small_body[..body_len]
+    } else {
+        large_body.resize(body_len, 0x0);
+        large_body.as_mut_slice()
+    };
+    stream.read_exact(body).await?;
+    match head.vbucket_id_or_status {
+        OK_STATUS => {
+            let data_type = DataType::from(head.data_type);
+            let flags = match body.get(..4) {
+                Some(extras) if head.extras_length >= 4 => BigEndian::read_u32(extras),
+                _ => 0,
+            };
+            let value =
+                parse::deserialize_value(codec, &body[body_len - value_len..], flags, data_type)?;
+            Ok(Some(Item {
+                value,
+                data_type,
+                flags,
+                cas: head.cas,
+            }))
+        }
+        status => match CommandError::from(status) {
+            CommandError::KeyNotFound => Ok(None),
+            e => Err(e.into()),
+        },
     }
 }
 
-pub(super) async fn parse_gets_response<V: DeserializeOwned + 'static>(
+pub(super) async fn parse_gets_response<V: DeserializeOwned + 'static, C: ValueCodec>(
     stream: &mut Stream,
     max_responses: usize,
+    codec: &C,
+    yield_every: usize,
 ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
     let mut result = HashMap::new();
-    for _ in 0..=max_responses {
+    // The responses are read on up to the Noop after a failed key, so none is left on the connection.
+    let mut failure = None;
+    for parsed in 0..=max_responses {
+        parse::yield_every(parsed, yield_every).await;
         let Response {
             header,
             key,
             extras,
             value,
-        } = parse_response(stream).await?.err()?;
+        } = match parse_response(stream).await?.err() {
+            Ok(response) => response,
+            Err(e) => {
+                failure = failure.or(Some(e));
+                continue;
+            }
+        };
         if header.opcode == Opcode::Noop as u8 {
-            return Ok(result);
+            return failure.map_or(Ok(result), Err);
+        }
+        if failure.is_some() {
+            continue;
         }
         let flags = Cursor::new(extras).read_u32::<BigEndian>()?;
         let key = parse::deserialize_bytes(&key)?;
         let _ = result.insert(
             key,
-            (parse::deserialize_bytes(&value)?, flags, Some(header.cas)),
+            (
+                parse::deserialize_value(codec, &value, flags, header.data_type.into())?,
+                flags,
+                Some(header.cas),
+            ),
         );
     }
     Err(ServerError::BadResponse(Cow::Borrowed("Expected end of gets response")).into())
 }
 
+/// Read the failures of quiet requests up to the closing Noop, keyed by `opaque`.
+pub(super) async fn parse_quiet_responses(
+    stream: &mut Stream,
+    max_responses: usize,
+) -> Result<HashMap<u32, CommandError>> {
+    let mut failures = HashMap::new();
+    for _ in 0..=max_responses {
+        let Response { header, .. } = parse_response(stream).await?;
+        if header.opcode == Opcode::Noop as u8 {
+            return Ok(failures);
+        }
+        if header.vbucket_id_or_status != OK_STATUS {
+            let _ = failures.insert(
+                header.opaque,
+                CommandError::from(header.vbucket_id_or_status),
+            );
+        }
+    }
+    Err(ServerError::BadResponse(Cow::Borrowed("Expected end of quiet responses")).into())
+}
+
+/// Read the responses of a pipeline up to the closing Noop.
+/// Quiet commands without a response succeeded, or missed for a get.
+pub(super) async fn parse_pipeline_responses(
+    stream: &mut Stream,
+    commands: &[Command],
+) -> Result<Vec<Reply>> {
+    let mut replies: Vec<Option<Reply>> = vec![None; commands.len()];
+    for _ in 0..=commands.len() {
+        let Response {
+            header,
+            extras,
+            value,
+            ..
+        } = parse_response(stream).await?;
+        if header.opcode == Opcode::Noop as u8 {
+            return commands
+                .iter()
+                .zip(replies)
+                .map(|(command, reply)| match (command, reply) {
+                    (_, Some(reply)) => Ok(reply),
+                    (Command::Get(_), None) => Ok(Reply::Value(None)),
+                    (Command::Set { .. }, None) => Ok(Reply::Stored),
+                    (Command::Delete(_), None) => Ok(Reply::Deleted(true)),
+                    (Command::Flush { .. }, None) => Ok(Reply::Flushed),
+                    (Command::Increment { .. }, None) => Err(ServerError::BadResponse(
+                        Cow::Borrowed("Missing increment response"),
+                    )
+                    .into()),
+                })
+                .collect();
+        }
+        let Some(command) = commands.get(header.opaque as usize) else {
+            return Err(ServerError::BadResponse(Cow::Borrowed("Unknown opaque")).into());
+        };
+        let reply = match (command, header.vbucket_id_or_status) {
+            (Command::Get(_), OK_STATUS) => {
+                let flags = Cursor::new(extras).read_u32::<BigEndian>()?;
+                Reply::Value(Some((value, flags)))
+            }
+            (Command::Set { .. }, OK_STATUS) => Reply::Stored,
+            (Command::Delete(_), OK_STATUS) => Reply::Deleted(true),
+            (Command::Flush { .. }, OK_STATUS) => Reply::Flushed,
+            (Command::Increment { .. }, OK_STATUS) => {
+                Reply::Counter(Cursor::new(&value).read_u64::<BigEndian>()?)
+            }
+            (command, status) => match (command, CommandError::from(status)) {
+                (Command::Get(_), CommandError::KeyNotFound) => Reply::Value(None),
+                (Command::Delete(_), CommandError::KeyNotFound) => Reply::Deleted(false),
+                (_, e) => Reply::Failed(e),
+            },
+        };
+        replies[header.opaque as usize] = Some(reply);
+    }
+    Err(ServerError::BadResponse(Cow::Borrowed("Expected end of pipeline responses")).into())
+}
+
 pub(super) async fn parse_delete_response(stream: &mut Stream) -> Result<bool> {
     match parse_response(stream).await?.err() {
         Ok(_) => Ok(true),
@@ -201,6 +474,20 @@ pub(super) async fn parse_stats_response(stream: &mut Stream) -> Result<HashMap<
     Ok(result)
 }
 
-pub(super) async fn parse_start_auth_response(stream: &mut Stream) -> Result<bool> {
-    parse_response(stream).await?.err().map(|_| true)
+/// A response to `StartAuth` or `StepAuth`.
+pub(super) enum AuthStep {
+    /// Authenticated, with the final data of the server.
+    Done(Vec<u8>),
+    /// The server's challenge to answer with a `StepAuth`.
+    Continue(Vec<u8>),
+}
+
+pub(super) async fn parse_auth_response(stream: &mut Stream) -> Result<AuthStep> {
+    let response = parse_response(stream).await?;
+    if response.header.vbucket_id_or_status == AUTH_CONTINUE_STATUS {
+        return Ok(AuthStep::Continue(response.value));
+    }
+    response
+        .err()
+        .map(|response| AuthStep::Done(response.value))
 }
@@ -1,5 +1,6 @@
 //! [BinaryProtocol](https://github.com/memcached/memcached/wiki/BinaryProtocolRevamped)
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(super) enum Opcode {
     Get = 0x00,
     Set = 0x01,
@@ -9,17 +10,81 @@ pub(super) enum Opcode {
     Increment = 0x05,
     Decrement = 0x06,
     Flush = 0x08,
+    FlushQ = 0x18,
     Stat = 0x10,
     Noop = 0x0a,
     Version = 0x0b,
+    GetK = 0x0c,
     GetKQ = 0x0d,
+    SetQ = 0x11,
+    AddQ = 0x12,
+    ReplaceQ = 0x13,
+    DeleteQ = 0x14,
     Append = 0x0e,
     Prepend = 0x0f,
     Touch = 0x1c,
+    GetAndTouch = 0x1d,
+    GetAndTouchKQ = 0x24,
+    ListMechs = 0x20,
     StartAuth = 0x21,
+    StepAuth = 0x22,
+}
+
+impl Opcode {
+    /// The variant answering only failures, commands without one are sent as they are.
+    pub(super) fn quiet(self) -> Self {
+        match self {
+            Opcode::Set => Opcode::SetQ,
+            Opcode::Add => Opcode::AddQ,
+            Opcode::Replace => Opcode::ReplaceQ,
+            Opcode::Delete => Opcode::DeleteQ,
+            Opcode::Flush => Opcode::FlushQ,
+            opcode => opcode,
+        }
+    }
+
+    /// Append and prepend keep the flags and expiration of the item and send no extras.
+    pub(super) fn has_store_extras(self) -> bool {
+        !matches!(self, Opcode::Append | Opcode::Prepend)
+    }
 }
 
 pub(super) enum Magic {
     Request = 0x80,
     Response = 0x81,
 }
+
+/// The `data_type` byte of a binary protocol packet header.
+///
+/// memcached only defines raw bytes, the other values are used by proxies and
+/// compatible servers, e.g. to mark JSON documents.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    /// Raw bytes, the only data type defined by memcached.
+    #[default]
+    Raw,
+    /// The value is a JSON document.
+    Json,
+    /// Any other data type.
+    Other(u8),
+}
+
+impl From<u8> for DataType {
+    fn from(data_type: u8) -> Self {
+        match data_type {
+            0x00 => DataType::Raw,
+            0x01 => DataType::Json,
+            e => DataType::Other(e),
+        }
+    }
+}
+
+impl From<DataType> for u8 {
+    fn from(data_type: DataType) -> Self {
+        match data_type {
+            DataType::Raw => 0x00,
+            DataType::Json => 0x01,
+            DataType::Other(e) => e,
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Types of the [meta commands](https://github.com/memcached/memcached/wiki/MetaCommands) `mg`, `ms` and `md`
+//! of memcached 1.6+, sent over the ASCII protocol.
+
+use crate::{error::ServerError, Result};
+use std::{borrow::Cow, fmt::Write, str::FromStr};
+
+/// What [`Client::meta_get`](crate::Client::meta_get) asks for and how the server treats the item.
+///
+/// `vivify` and `recache_below` hand out win tokens: of all clients missing the key or seeing it
+/// about to expire, only the one with [`MetaResponse::win`] recomputes the value, the others serve the
+/// stale value or wait. This protects the backend from a thundering herd.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct MetaGetFlags {
+    /// Return the value (`v`).
+    pub value: bool,
+    /// Return the cas (`c`).
+    pub cas: bool,
+    /// Return the remaining TTL in seconds (`t`).
+    pub ttl: bool,
+    /// Return whether the item was fetched before (`h`).
+    pub hit_before: bool,
+    /// Return the seconds since the item was last accessed (`l`).
+    pub last_access: bool,
+    /// Update the TTL of the item (`T`).
+    pub touch: Option<u32>,
+    /// On a miss, create an empty item with this TTL and win the right to fill it (`N`).
+    pub vivify: Option<u32>,
+    /// Win the right to recompute the item if its remaining TTL is below this (`R`).
+    pub recache_below: Option<u32>,
+}
+
+/// How [`Client::meta_set`](crate::Client::meta_set) stores the value.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MetaSetFlags {
+    /// The TTL of the item, 0 never expires (`T`).
+    pub ttl: u32,
+    /// Only store if the cas of the item is still this (`C`).
+    pub cas: Option<u64>,
+    /// With `cas`, store a value with an older cas too, but mark it stale (`I`).
+    pub invalidate: bool,
+    /// Return the cas of the stored item (`c`).
+    pub return_cas: bool,
+}
+
+/// How [`Client::meta_delete`](crate::Client::meta_delete) removes the item.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MetaDeleteFlags {
+    /// Only delete if the cas of the item is still this (`C`).
+    pub cas: Option<u64>,
+    /// Mark the item stale instead of removing it, the next `meta_get` wins the right to recompute it (`I`).
+    pub invalidate: bool,
+    /// With `invalidate`, the new TTL of the stale item (`T`).
+    pub ttl: Option<u32>,
+}
+
+/// The status code of a meta command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetaStatus {
+    /// `HD` or `VA`: the item was found, stored or deleted.
+    Ok,
+    /// `EN`: `meta_get` found no item.
+    Miss,
+    /// `NS`: `meta_set` did not store the value.
+    NotStored,
+    /// `EX`: the cas did not match.
+    Exists,
+    /// `NF`: the item to store with a cas or to delete does not exist.
+    NotFound,
+}
+
+/// The answer to a meta command, the fields are only `Some` if their flag was asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaResponse<V> {
+    /// The status code.
+    pub status: MetaStatus,
+    /// The value of a `meta_get` asking for it.
+    pub value: Option<V>,
+    /// The flags the value was stored with.
+    pub flags: Option<u32>,
+    /// The cas of the item.
+    pub cas: Option<u64>,
+    /// The remaining TTL in seconds, -1 if the item never expires.
+    pub ttl: Option<i64>,
+    /// Whether the item was fetched before.
+    pub hit_before: Option<bool>,
+    /// Seconds since the item was last accessed.
+    pub last_access: Option<u64>,
+    /// This client won the right to recompute the item (`W`).
+    pub win: bool,
+    /// The item is stale, see [`MetaDeleteFlags::invalidate`] (`X`).
+    pub stale: bool,
+    /// Another client already won the right to recompute the item (`Z`).
+    pub win_sent: bool,
+}
+
+impl<V> MetaResponse<V> {
+    pub(crate) fn new(status: MetaStatus) -> Self {
+        MetaResponse {
+            status,
+            value: None,
+            flags: None,
+            cas: None,
+            ttl: None,
+            hit_before: None,
+            last_access: None,
+            win: false,
+            stale: false,
+            win_sent: false,
+        }
+    }
+
+    /// Whether the status is [`MetaStatus::Ok`].
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.status == MetaStatus::Ok
+    }
+
+    pub(crate) fn try_map<W>(self, f: impl FnOnce(V) -> Result<W>) -> Result<MetaResponse<W>> {
+        Ok(MetaResponse {
+            status: self.status,
+            value: self.value.map(f).transpose()?,
+            flags: self.flags,
+            cas: self.cas,
+            ttl: self.ttl,
+            hit_before: self.hit_before,
+            last_access: self.last_access,
+            win: self.win,
+            stale: self.stale,
+            win_sent: self.win_sent,
+        })
+    }
+}
+
+fn bad_meta(line: &str) -> ServerError {
+    ServerError::BadResponse(Cow::Owned(line.to_owned()))
+}
+
+fn number<T: FromStr>(argument: &str, line: &str) -> Result<T> {
+    argument.parse().map_err(|_| bad_meta(line).into())
+}
+
+pub(crate) fn get_command(key: &str, flags: &MetaGetFlags) -> String {
+    let mut command = format!("mg {key}");
+    for (asked, flag) in [
+        (flags.value, " v f"),
+        (flags.cas, " c"),
+        (flags.ttl, " t"),
+        (flags.hit_before, " h"),
+        (flags.last_access, " l"),
+    ] {
+        if asked {
+            command.push_str(flag);
+        }
+    }
+    for (flag, seconds) in [
+        ('T', flags.touch),
+        ('N', flags.vivify),
+        ('R', flags.recache_below),
+    ] {
+        if let Some(seconds) = seconds {
+            let _ = write!(command, " {flag}{seconds}");
+        }
+    }
+    command
+}
+
+pub(crate) fn set_command(
+    key: &str,
+    len: usize,
+    client_flags: u32,
+    flags: &MetaSetFlags,
+) -> String {
+    let mut command = format!("ms {key} {len} F{client_flags} T{}", flags.ttl);
+    if let Some(cas) = flags.cas {
+        let _ = write!(command, " C{cas}");
+    }
+    if flags.invalidate {
+        command.push_str(" I");
+    }
+    if flags.return_cas {
+        command.push_str(" c");
+    }
+    command
+}
+
+pub(crate) fn delete_command(key: &str, flags: &MetaDeleteFlags) -> String {
+    let mut command = format!("md {key}");
+    if let Some(cas) = flags.cas {
+        let _ = write!(command, " C{cas}");
+    }
+    if flags.invalidate {
+        command.push_str(" I");
+    }
+    if let Some(ttl) = flags.ttl {
+        let _ = write!(command, " T{ttl}");
+    }
+    command
+}
+
+/// Parse a response line, returns the response and the length of the value block that follows a `VA`.
+pub(crate) fn parse_response<V>(line: &str) -> Result<(MetaResponse<V>, Option<usize>)> {
+    let mut tokens = line.split(' ');
+    let (status, value_len) = match tokens.next() {
+        Some("VA") => (
+            MetaStatus::Ok,
+            Some(number(tokens.next().unwrap_or(""), line)?),
+        ),
+        Some("HD") => (MetaStatus::Ok, None),
+        Some("EN") => (MetaStatus::Miss, None),
+        Some("NS") => (MetaStatus::NotStored, None),
+        Some("EX") => (MetaStatus::Exists, None),
+        Some("NF") => (MetaStatus::NotFound, None),
+        _ => return Err(bad_meta(line).into()),
+    };
+    let mut response = MetaResponse::new(status);
+    for token in tokens.filter(|token| !token.is_empty()) {
+        let (Some(flag), Some(argument)) = (token.get(..1), token.get(1..)) else {
+            return Err(bad_meta(line).into());
+        };
+        match flag {
+            "c" => response.cas = Some(number(argument, line)?),
+            "f" => response.flags = Some(number(argument, line)?),
+            "t" => response.ttl = Some(number(argument, line)?),
+            "h" => response.hit_before = Some(argument == "1"),
+            "l" => response.last_access = Some(number(argument, line)?),
+            "W" => response.win = true,
+            "X" => response.stale = true,
+            "Z" => response.win_sent = true,
+            // Key, size and opaque echo what the client knows already.
+            _ => {}
+        }
+    }
+    Ok((response, value_len))
+}
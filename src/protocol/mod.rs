@@ -1,346 +1,663 @@
+mod ascii;
+mod binary;
 pub(crate) mod binary_packet;
 mod code;
-mod parse;
+pub(crate) mod meta;
+pub(crate) mod parse;
+pub(crate) mod sasl;
 
-use self::binary_packet::PacketHeader;
-use crate::{stream::Stream, Result};
-use code::{Magic, Opcode};
+use crate::{
+    codec::ValueCodec,
+    error::{ClientError, CommandError, MemcachedError},
+    stats::ItemMetadata,
+    stream::Stream,
+    Result,
+};
+pub(crate) use ascii::AsciiProtocol;
+pub(crate) use binary::BinaryProtocol;
+pub use binary_packet::RawPacket;
+pub use code::DataType;
+use meta::{MetaDeleteFlags, MetaGetFlags, MetaResponse, MetaSetFlags};
 use serde::{de::DeserializeOwned, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::ControlFlow, time::Duration};
 
-pub(crate) struct BinaryProtocol {
-    pub(crate) stream: Stream,
+/// The counter expiration that makes `increment_with` and `decrement_with` fail with
+/// [`CommandError::KeyNotFound`] instead of creating a missing counter.
+pub const DONT_CREATE: u32 = 0xffff_ffff;
+
+/// The outcome of a compare-and-swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CasResult {
+    /// The value was stored, or deleted by [`Client::delete_cas`](crate::Client::delete_cas).
+    Stored,
+    /// The key was modified since its cas was read, read it again and retry.
+    Exists,
+    /// The key doesn't exist (anymore).
+    NotFound,
 }
 
-impl BinaryProtocol {
-    pub(crate) async fn auth(&mut self, username: &str, password: &str) -> Result<()> {
-        let key = "PLAIN";
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::StartAuth as u8,
-            key_length: key.len() as u16,
-            total_body_length: (key.len() + username.len() + password.len() + 2) as u32,
-            ..PacketHeader::default()
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.write_all(key.as_bytes()).await?;
-        self.stream
-            .write_all(format!("\x00{username}\x00{password}").as_bytes())
-            .await?;
-        self.stream.flush().await?;
-        binary_packet::parse_start_auth_response(&mut self.stream)
-            .await
-            .map(|_| ())
-    }
-    pub(crate) async fn version(&mut self) -> Result<String> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Version as u8,
-            ..PacketHeader::default()
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.flush().await?;
-        let version = binary_packet::parse_version_response(&mut self.stream).await?;
-        Ok(version)
+impl CasResult {
+    /// Whether the value was stored.
+    #[must_use]
+    pub fn is_stored(self) -> bool {
+        self == CasResult::Stored
     }
+}
+
+/// How a write the server was out of memory for is sent again, see
+/// [`ClientBuilder::retry_out_of_memory`](crate::ClientBuilder::retry_out_of_memory).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfMemoryRetry {
+    /// The wait before the retry, while the server evicts.
+    pub delay: Duration,
+    /// The retry is stored with at most this expiration in seconds, so it is evicted
+    /// first if memory stays tight. `None` keeps the expiration of the write.
+    pub max_ttl: Option<u32>,
+}
 
-    pub(crate) async fn flush(&mut self) -> Result<()> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Flush as u8,
-            ..PacketHeader::default()
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.flush().await?;
-        binary_packet::parse_response(&mut self.stream)
-            .await?
-            .err()
-            .map(|_| ())
+impl Default for OutOfMemoryRetry {
+    fn default() -> Self {
+        OutOfMemoryRetry {
+            delay: Duration::from_millis(10),
+            max_ttl: None,
+        }
     }
+}
 
-    /// Flush all cache on memcached server with a delay seconds.
-    pub(crate) async fn flush_with_delay(&mut self, delay: u32) -> Result<()> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Flush as u8,
-            extras_length: 4,
-            total_body_length: 4,
-            ..PacketHeader::default()
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.write_u32(delay).await?;
-        self.stream.flush().await?;
-        binary_packet::parse_response(&mut self.stream)
-            .await?
-            .err()
-            .map(|_| ())
+impl OutOfMemoryRetry {
+    /// The expiration of the retry of a write with `expiration`, 0 never expires.
+    fn expiration(self, expiration: u32) -> u32 {
+        match (self.max_ttl, expiration) {
+            (Some(max_ttl), 0) => max_ttl,
+            (Some(max_ttl), expiration) => expiration.min(max_ttl),
+            (None, expiration) => expiration,
+        }
     }
+}
 
-    pub(crate) async fn get<V: DeserializeOwned + 'static>(
-        &mut self,
-        key: &str,
-    ) -> Result<Option<V>> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Get as u8,
-            key_length: key.len() as u16,
-            total_body_length: key.len() as u32,
-            ..PacketHeader::default()
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.write_all(key.as_bytes()).await?;
-        self.stream.flush().await?;
-        binary_packet::parse_get_response(&mut self.stream).await
+/// One operation queued in a pipeline, values are encoded already.
+#[derive(Debug)]
+pub(crate) enum Command {
+    Get(String),
+    Set {
+        key: String,
+        value: Vec<u8>,
+        flags: u32,
+        expiration: u32,
+    },
+    Delete(String),
+    Increment {
+        key: String,
+        amount: u64,
+    },
+    /// Sent to every server, not routed by key.
+    Flush {
+        delay: Option<u32>,
+    },
+}
+
+impl Command {
+    /// The key the command is routed by, `None` for admin commands sent to every server.
+    pub(crate) fn key(&self) -> Option<&str> {
+        match self {
+            Command::Get(key)
+            | Command::Set { key, .. }
+            | Command::Delete(key)
+            | Command::Increment { key, .. } => Some(key),
+            Command::Flush { .. } => None,
+        }
+    }
+
+    /// The reply while the kill switch bypasses the cache: reads miss and writes succeed without storing.
+    pub(crate) fn bypassed(&self) -> Reply {
+        match self {
+            Command::Get(_) => Reply::Value(None),
+            Command::Set { .. } => Reply::Stored,
+            Command::Delete(_) => Reply::Deleted(false),
+            Command::Increment { .. } => Reply::Failed(CommandError::KeyNotFound),
+            Command::Flush { .. } => Reply::Flushed,
+        }
     }
+}
+
+/// The result of one operation of a pipeline, see [`Client::pipeline`](crate::Client::pipeline).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    /// A `get`: the stored bytes and flags, `None` if the key is missing.
+    Value(Option<(Vec<u8>, u32)>),
+    /// A `set` stored its value.
+    Stored,
+    /// A `delete`, `false` if the key was missing.
+    Deleted(bool),
+    /// An `increment`, the new value.
+    Counter(u64),
+    /// A `flush` invalidated the items of every server.
+    Flushed,
+    /// The server refused the operation.
+    Failed(CommandError),
+}
 
-    pub(crate) async fn set<V: Serialize + 'static>(
+/// Fail before anything is sent if a value of `commands` is larger than `item_size_max`.
+fn check_sizes(commands: &[Command], item_size_max: Option<u64>) -> Result<()> {
+    let Some(limit) = item_size_max else {
+        return Ok(());
+    };
+    for command in commands {
+        if let Command::Set { value, .. } = command {
+            if value.len() as u64 > limit {
+                return Err(ClientError::ValueTooLarge {
+                    size: value.len(),
+                    limit,
+                }
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Encode `value` with `codec`, failing with [`ClientError::ValueTooLarge`] if the server can't store it.
+pub(crate) fn encode<V: Serialize + 'static, C: ValueCodec>(
+    codec: &C,
+    value: &V,
+    item_size_max: Option<u64>,
+) -> Result<(Vec<u8>, u32)> {
+    let (bytes, flags) = codec.encode(value)?;
+    match item_size_max {
+        Some(limit) if bytes.len() as u64 > limit => Err(ClientError::ValueTooLarge {
+            size: bytes.len(),
+            limit,
+        }
+        .into()),
+        _ => Ok((bytes, flags)),
+    }
+}
+
+/// An encoded value and its flags, `None` if the server can't store it.
+type Encoded = Option<(Vec<u8>, u32)>;
+
+/// The encoded values of `sets` in the order of `items`, keys whose value the server can't store
+/// fail with [`CommandError::ValueTooLarge`] instead of the whole batch.
+fn encode_items<V: Serialize + 'static, C: ValueCodec>(
+    items: &[(&str, &V, u32)],
+    codec: &C,
+    item_size_max: Option<u64>,
+) -> Result<(Vec<Encoded>, HashMap<String, CommandError>)> {
+    let mut encoded = Vec::with_capacity(items.len());
+    let mut too_large = HashMap::new();
+    for (key, value, _) in items {
+        match encode(codec, *value, item_size_max) {
+            Ok(value) => encoded.push(Some(value)),
+            Err(MemcachedError::ClientError(ClientError::ValueTooLarge { .. })) => {
+                let _ = too_large.insert((*key).to_owned(), CommandError::ValueTooLarge);
+                encoded.push(None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((encoded, too_large))
+}
+
+/// The operations every memcached protocol implementation provides.
+pub(crate) trait ProtocolTrait {
+    async fn auth(&mut self, username: &str, password: &str) -> Result<()>;
+    async fn version(&mut self) -> Result<String>;
+    /// The cheapest round trip the protocol has, to check that the connection is alive.
+    async fn noop(&mut self) -> Result<()>;
+    async fn flush(&mut self) -> Result<()>;
+    async fn flush_with_delay(&mut self, delay: u32) -> Result<()>;
+    async fn get<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<V>>;
+    async fn get_with_data_type<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<(V, DataType)>>;
+    /// Get `key` and set its expiration in one round trip.
+    async fn get_and_touch<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<Option<V>>;
+    /// Pipelined `get_and_touch`, missing keys are left out.
+    async fn gets_and_touch<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        keys: &[&str],
+        expiration: u32,
+        codec: &C,
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>>;
+    /// The value of `key` with its flags and cas.
+    async fn get_with_cas<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<(V, u32, u64)>>;
+    async fn set<V: Serialize + 'static, C: ValueCodec>(
         &mut self,
         key: &str,
         value: V,
         expiration: u32,
-    ) -> Result<()> {
-        self.store(Opcode::Set, key, value, expiration, None).await
+        codec: &C,
+    ) -> Result<()>;
+    async fn set_with_data_type<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        data_type: DataType,
+        codec: &C,
+    ) -> Result<()>;
+    async fn add<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<()>;
+    async fn replace<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<()>;
+    async fn append<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        codec: &C,
+    ) -> Result<()>;
+    async fn cas<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        expiration: u32,
+        cas: u64,
+        codec: &C,
+    ) -> Result<CasResult>;
+    async fn prepend<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        codec: &C,
+    ) -> Result<()>;
+    async fn delete(&mut self, key: &str) -> Result<bool>;
+    /// Delete `key` only if its cas is still `cas`.
+    async fn delete_cas(&mut self, key: &str, cas: u64) -> Result<CasResult>;
+    async fn increment(&mut self, key: &str, amount: u64) -> Result<u64>;
+    /// Increment `key`, a missing key is stored as `initial` unless `expiration` is [`DONT_CREATE`].
+    async fn increment_with(
+        &mut self,
+        key: &str,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64>;
+    /// Decrement `key`, a missing key is stored as `initial` unless `expiration` is [`DONT_CREATE`].
+    async fn decrement_with(
+        &mut self,
+        key: &str,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64>;
+    async fn decrement(&mut self, key: &str, amount: u64) -> Result<u64>;
+    async fn touch(&mut self, key: &str, expiration: u32) -> Result<bool>;
+    async fn stats(&mut self) -> Result<HashMap<String, String>>;
+    /// `stats <group>`, e.g. `settings`
+    async fn stats_group(&mut self, group: &str) -> Result<HashMap<String, String>>;
+    /// The SASL mechanisms the server offers
+    async fn sasl_mechanisms(&mut self) -> Result<Vec<String>>;
+    /// `lru_crawler metadump all`, every item is passed to `visit` until it breaks, which closes
+    /// the connection with the rest of the dump unread.
+    async fn metadump(
+        &mut self,
+        visit: &mut (dyn FnMut(ItemMetadata) -> ControlFlow<()> + Send),
+    ) -> Result<()>;
+    async fn gets<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        keys: &[&str],
+        codec: &C,
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>>;
+    /// Pipelined sets, returns the keys that were not stored.
+    async fn sets<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        items: &[(&str, &V, u32)],
+        codec: &C,
+    ) -> Result<HashMap<String, CommandError>>;
+    /// Pipelined deletes, returns the keys that were not deleted.
+    async fn deletes(&mut self, keys: &[&str]) -> Result<HashMap<String, CommandError>>;
+    /// Send `commands` in one write, one reply per command in the same order.
+    async fn pipeline(&mut self, commands: &[Command]) -> Result<Vec<Reply>>;
+    /// `mg`, the value is left encoded.
+    async fn meta_get(&mut self, key: &str, flags: &MetaGetFlags) -> Result<MetaResponse<Vec<u8>>>;
+    /// `ms`
+    async fn meta_set<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        flags: &MetaSetFlags,
+        codec: &C,
+    ) -> Result<MetaResponse<()>>;
+    /// `md`
+    async fn meta_delete(&mut self, key: &str, flags: &MetaDeleteFlags)
+        -> Result<MetaResponse<()>>;
+}
+
+/// The protocol a connection speaks, selected by the `protocol` URL query parameter.
+pub(crate) enum Protocol {
+    Binary(BinaryProtocol),
+    Ascii(AsciiProtocol),
+}
+
+impl Protocol {
+    /// `protocol=binary` (default) or `protocol=ascii`
+    pub(crate) fn new(name: Option<&str>, stream: Stream) -> Result<Self> {
+        match name {
+            None | Some("binary") => Ok(Protocol::Binary(BinaryProtocol {
+                stream,
+                item_size_max: None,
+                yield_every: 0,
+                out_of_memory_retry: None,
+            })),
+            Some("ascii") => Ok(Protocol::Ascii(AsciiProtocol::new(stream))),
+            Some(name) => Err(MemcachedError::BadURL(format!("Unknown protocol: {name}"))),
+        }
+    }
+
+    /// Refuse values larger than `limit` before sending them.
+    pub(crate) fn set_item_size_max(&mut self, limit: Option<u64>) {
+        match self {
+            Protocol::Binary(p) => p.item_size_max = limit,
+            Protocol::Ascii(p) => p.item_size_max = limit,
+        }
+    }
+
+    /// Store once more after `retry.delay` when the server is out of memory.
+    pub(crate) fn set_out_of_memory_retry(&mut self, retry: Option<OutOfMemoryRetry>) {
+        match self {
+            Protocol::Binary(p) => p.out_of_memory_retry = retry,
+            Protocol::Ascii(p) => p.out_of_memory_retry = retry,
+        }
     }
 
-    pub(crate) async fn add<V: Serialize + 'static>(
+    /// Yield to other tasks after every `values` values of a multi-get reply, 0 never.
+    pub(crate) fn set_yield_every(&mut self, values: usize) {
+        match self {
+            Protocol::Binary(p) => p.yield_every = values,
+            Protocol::Ascii(p) => p.yield_every = values,
+        }
+    }
+
+    pub(crate) fn stream(&mut self) -> &mut Stream {
+        match self {
+            Protocol::Binary(p) => &mut p.stream,
+            Protocol::Ascii(p) => &mut p.stream,
+        }
+    }
+
+    pub(crate) fn is_ascii(&self) -> bool {
+        matches!(self, Protocol::Ascii(_))
+    }
+
+    /// Send a text command line and its data block, ASCII only.
+    pub(crate) async fn send_line(&mut self, line: &str, data: Option<&[u8]>) -> Result<()> {
+        match self {
+            Protocol::Ascii(p) => p.send(line, data).await,
+            Protocol::Binary(_) => Err(binary::ascii_only("Text commands")),
+        }
+    }
+
+    /// Read a reply line without its `\r\n`, ASCII only.
+    pub(crate) async fn read_line(&mut self) -> Result<String> {
+        match self {
+            Protocol::Ascii(p) => p.read_line().await,
+            Protocol::Binary(_) => Err(binary::ascii_only("Text commands")),
+        }
+    }
+
+    /// Read a data block of `len` bytes and its trailing `\r\n`, ASCII only.
+    pub(crate) async fn read_data(&mut self, len: usize) -> Result<Vec<u8>> {
+        match self {
+            Protocol::Ascii(p) => p.read_data(len).await,
+            Protocol::Binary(_) => Err(binary::ascii_only("Text commands")),
+        }
+    }
+
+    /// Send a request packet, binary only.
+    pub(crate) async fn send_packet(&mut self, packet: &RawPacket) -> Result<()> {
+        match self {
+            Protocol::Binary(p) => {
+                binary_packet::write_raw(&mut p.stream, packet).await?;
+                p.stream.flush().await
+            }
+            Protocol::Ascii(_) => Err(ascii::binary_only("Raw packets")),
+        }
+    }
+
+    /// Read a response packet, binary only.
+    pub(crate) async fn read_packet(&mut self) -> Result<RawPacket> {
+        match self {
+            Protocol::Binary(p) => binary_packet::read_raw(&mut p.stream).await,
+            Protocol::Ascii(_) => Err(ascii::binary_only("Raw packets")),
+        }
+    }
+}
+
+macro_rules! dispatch {
+    ($self:ident, $p:ident => $call:expr) => {
+        match $self {
+            Protocol::Binary($p) => $call.await,
+            Protocol::Ascii($p) => $call.await,
+        }
+    };
+}
+
+impl ProtocolTrait for Protocol {
+    async fn auth(&mut self, username: &str, password: &str) -> Result<()> {
+        dispatch!(self, p => p.auth(username, password))
+    }
+    async fn version(&mut self) -> Result<String> {
+        dispatch!(self, p => p.version())
+    }
+    async fn noop(&mut self) -> Result<()> {
+        dispatch!(self, p => p.noop())
+    }
+    async fn flush(&mut self) -> Result<()> {
+        dispatch!(self, p => p.flush())
+    }
+    async fn flush_with_delay(&mut self, delay: u32) -> Result<()> {
+        dispatch!(self, p => p.flush_with_delay(delay))
+    }
+    async fn get<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<V>> {
+        dispatch!(self, p => p.get(key, codec))
+    }
+    async fn get_with_data_type<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<(V, DataType)>> {
+        dispatch!(self, p => p.get_with_data_type(key, codec))
+    }
+    async fn get_and_touch<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        expiration: u32,
+        codec: &C,
+    ) -> Result<Option<V>> {
+        dispatch!(self, p => p.get_and_touch(key, expiration, codec))
+    }
+    async fn gets_and_touch<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        keys: &[&str],
+        expiration: u32,
+        codec: &C,
+    ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
+        dispatch!(self, p => p.gets_and_touch(keys, expiration, codec))
+    }
+    async fn get_with_cas<V: DeserializeOwned + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        codec: &C,
+    ) -> Result<Option<(V, u32, u64)>> {
+        dispatch!(self, p => p.get_with_cas(key, codec))
+    }
+    async fn set<V: Serialize + 'static, C: ValueCodec>(
         &mut self,
         key: &str,
         value: V,
         expiration: u32,
+        codec: &C,
     ) -> Result<()> {
-        self.store(Opcode::Add, key, value, expiration, None).await
+        dispatch!(self, p => p.set(key, value, expiration, codec))
     }
-
-    pub(crate) async fn replace<V: Serialize + 'static>(
+    async fn set_with_data_type<V: Serialize + 'static, C: ValueCodec>(
         &mut self,
         key: &str,
         value: V,
         expiration: u32,
+        data_type: DataType,
+        codec: &C,
     ) -> Result<()> {
-        self.store(Opcode::Replace, key, value, expiration, None)
-            .await
+        dispatch!(self, p => p.set_with_data_type(key, value, expiration, data_type, codec))
     }
-
-    async fn send_request(
+    async fn add<V: Serialize + 'static, C: ValueCodec>(
         &mut self,
-        opcode: Opcode,
         key: &str,
-        value: &[u8],
+        value: V,
         expiration: u32,
-        cas: Option<u64>,
+        codec: &C,
     ) -> Result<()> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: opcode as u8,
-            key_length: key.len() as u16,
-            extras_length: 8,
-            total_body_length: (8 + key.len() + value.len()) as u32,
-            cas: cas.unwrap_or(0),
-            ..PacketHeader::default()
-        };
-        let extras = binary_packet::StoreExtras {
-            flags: 0,
-            expiration,
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.write_u32(extras.flags).await?;
-        self.stream.write_u32(extras.expiration).await?;
-        self.stream.write_all(key.as_bytes()).await?;
-        self.stream.write_all(value).await?;
-        // value.write_to(&mut self.stream).await?;
-        self.stream.flush().await.map_err(Into::into)
+        dispatch!(self, p => p.add(key, value, expiration, codec))
     }
-
-    async fn store<V: Serialize + 'static>(
+    async fn replace<V: Serialize + 'static, C: ValueCodec>(
         &mut self,
-        opcode: Opcode,
         key: &str,
         value: V,
         expiration: u32,
-        cas: Option<u64>,
+        codec: &C,
     ) -> Result<()> {
-        let value = parse::serialize_bytes(&value)?;
-        self.send_request(opcode, key, &value, expiration, cas)
-            .await?;
-        binary_packet::parse_response(&mut self.stream)
-            .await?
-            .err()
-            .map(|_| ())
+        dispatch!(self, p => p.replace(key, value, expiration, codec))
     }
-
-    pub(crate) async fn append<V: Serialize + 'static>(
+    async fn append<V: Serialize + 'static, C: ValueCodec>(
         &mut self,
         key: &str,
         value: V,
+        codec: &C,
     ) -> Result<()> {
-        let value = parse::serialize_bytes(&value)?;
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Append as u8,
-            key_length: key.len() as u16,
-            total_body_length: (key.len() + value.len()) as u32,
-            ..PacketHeader::default()
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.write_all(key.as_bytes()).await?;
-        self.stream.write_all(&value).await?;
-        self.stream.flush().await?;
-        binary_packet::parse_response(&mut self.stream)
-            .await?
-            .err()
-            .map(|_| ())
+        dispatch!(self, p => p.append(key, value, codec))
     }
-
-    pub(crate) async fn cas<V: Serialize + 'static>(
+    async fn cas<V: Serialize + 'static, C: ValueCodec>(
         &mut self,
         key: &str,
         value: V,
         expiration: u32,
         cas: u64,
-    ) -> Result<bool> {
-        self.send_request(
-            Opcode::Set,
-            key,
-            &parse::serialize_bytes(&value)?,
-            expiration,
-            Some(cas),
-        )
-        .await?;
-        binary_packet::parse_cas_response(&mut self.stream).await
+        codec: &C,
+    ) -> Result<CasResult> {
+        dispatch!(self, p => p.cas(key, value, expiration, cas, codec))
     }
-
-    pub(crate) async fn prepend<V: Serialize + 'static>(
+    async fn prepend<V: Serialize + 'static, C: ValueCodec>(
         &mut self,
         key: &str,
         value: V,
+        codec: &C,
     ) -> Result<()> {
-        let value = parse::serialize_bytes(&value)?;
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Prepend as u8,
-            key_length: key.len() as u16,
-            total_body_length: (key.len() + value.len()) as u32,
-            ..PacketHeader::default()
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.write_all(key.as_bytes()).await?;
-        self.stream.write_all(&value).await?;
-        self.stream.flush().await?;
-        binary_packet::parse_response(&mut self.stream)
-            .await
-            .map(|_| ())
+        dispatch!(self, p => p.prepend(key, value, codec))
     }
-
-    pub(crate) async fn delete(&mut self, key: &str) -> Result<bool> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Delete as u8,
-            key_length: key.len() as u16,
-            total_body_length: key.len() as u32,
-            ..PacketHeader::default()
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.write_all(key.as_bytes()).await?;
-        self.stream.flush().await?;
-        binary_packet::parse_delete_response(&mut self.stream).await
+    async fn delete(&mut self, key: &str) -> Result<bool> {
+        dispatch!(self, p => p.delete(key))
     }
-
-    pub(crate) async fn increment(&mut self, key: &str, amount: u64) -> Result<u64> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Increment as u8,
-            key_length: key.len() as u16,
-            extras_length: 20,
-            total_body_length: (20 + key.len()) as u32,
-            ..PacketHeader::default()
-        };
-        let extras = binary_packet::CounterExtras {
-            amount,
-            initial_value: 0,
-            expiration: 0,
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.write_u64(extras.amount).await?;
-        self.stream.write_u64(extras.initial_value).await?;
-        self.stream.write_u32(extras.expiration).await?;
-        self.stream.write_all(key.as_bytes()).await?;
-        self.stream.flush().await?;
-        binary_packet::parse_counter_response(&mut self.stream).await
+    async fn delete_cas(&mut self, key: &str, cas: u64) -> Result<CasResult> {
+        dispatch!(self, p => p.delete_cas(key, cas))
     }
-
-    pub(crate) async fn decrement(&mut self, key: &str, amount: u64) -> Result<u64> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Decrement as u8,
-            key_length: key.len() as u16,
-            extras_length: 20,
-            total_body_length: (20 + key.len()) as u32,
-            ..PacketHeader::default()
-        };
-        let extras = binary_packet::CounterExtras {
-            amount,
-            initial_value: 0,
-            expiration: 0,
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.write_u64(extras.amount).await?;
-        self.stream.write_u64(extras.initial_value).await?;
-        self.stream.write_u32(extras.expiration).await?;
-        self.stream.write_all(key.as_bytes()).await?;
-        self.stream.flush().await?;
-        binary_packet::parse_counter_response(&mut self.stream).await
+    async fn increment(&mut self, key: &str, amount: u64) -> Result<u64> {
+        dispatch!(self, p => p.increment(key, amount))
     }
-
-    pub(crate) async fn touch(&mut self, key: &str, expiration: u32) -> Result<bool> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Touch as u8,
-            key_length: key.len() as u16,
-            extras_length: 4,
-            total_body_length: (key.len() as u32 + 4),
-            ..PacketHeader::default()
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.write_u32(expiration).await?;
-        self.stream.write_all(key.as_bytes()).await?;
-        self.stream.flush().await?;
-        binary_packet::parse_touch_response(&mut self.stream).await
+    async fn increment_with(
+        &mut self,
+        key: &str,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        dispatch!(self, p => p.increment_with(key, amount, initial, expiration))
     }
-
-    pub(crate) async fn stats(&mut self) -> Result<HashMap<String, String>> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Stat as u8,
-            ..PacketHeader::default()
-        };
-        request_header.write(&mut self.stream).await?;
-        self.stream.flush().await?;
-
-        let stats_info = binary_packet::parse_stats_response(&mut self.stream).await?;
-        Ok(stats_info)
+    async fn decrement_with(
+        &mut self,
+        key: &str,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        dispatch!(self, p => p.decrement_with(key, amount, initial, expiration))
     }
-
-    pub(crate) async fn gets<V: DeserializeOwned + 'static>(
+    async fn decrement(&mut self, key: &str, amount: u64) -> Result<u64> {
+        dispatch!(self, p => p.decrement(key, amount))
+    }
+    async fn touch(&mut self, key: &str, expiration: u32) -> Result<bool> {
+        dispatch!(self, p => p.touch(key, expiration))
+    }
+    async fn stats(&mut self) -> Result<HashMap<String, String>> {
+        dispatch!(self, p => p.stats())
+    }
+    async fn stats_group(&mut self, group: &str) -> Result<HashMap<String, String>> {
+        dispatch!(self, p => p.stats_group(group))
+    }
+    async fn sasl_mechanisms(&mut self) -> Result<Vec<String>> {
+        dispatch!(self, p => p.sasl_mechanisms())
+    }
+    async fn metadump(
+        &mut self,
+        visit: &mut (dyn FnMut(ItemMetadata) -> ControlFlow<()> + Send),
+    ) -> Result<()> {
+        dispatch!(self, p => p.metadump(visit))
+    }
+    async fn gets<V: DeserializeOwned + 'static, C: ValueCodec>(
         &mut self,
         keys: &[&str],
+        codec: &C,
     ) -> Result<HashMap<String, (V, u32, Option<u64>)>> {
-        for key in keys {
-            let request_header = PacketHeader {
-                magic: Magic::Request as u8,
-                opcode: Opcode::GetKQ as u8,
-                key_length: key.len() as u16,
-                total_body_length: key.len() as u32,
-                ..PacketHeader::default()
-            };
-            request_header.write(&mut self.stream).await?;
-            self.stream.write_all(key.as_bytes()).await?;
-        }
-        let noop_request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Noop as u8,
-            ..PacketHeader::default()
-        };
-        noop_request_header.write(&mut self.stream).await?;
-        binary_packet::parse_gets_response(&mut self.stream, keys.len()).await
+        dispatch!(self, p => p.gets(keys, codec))
+    }
+    async fn sets<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        items: &[(&str, &V, u32)],
+        codec: &C,
+    ) -> Result<HashMap<String, CommandError>> {
+        dispatch!(self, p => p.sets(items, codec))
+    }
+    async fn deletes(&mut self, keys: &[&str]) -> Result<HashMap<String, CommandError>> {
+        dispatch!(self, p => p.deletes(keys))
+    }
+    async fn pipeline(&mut self, commands: &[Command]) -> Result<Vec<Reply>> {
+        dispatch!(self, p => p.pipeline(commands))
+    }
+    async fn meta_get(&mut self, key: &str, flags: &MetaGetFlags) -> Result<MetaResponse<Vec<u8>>> {
+        dispatch!(self, p => p.meta_get(key, flags))
+    }
+    async fn meta_set<V: Serialize + 'static, C: ValueCodec>(
+        &mut self,
+        key: &str,
+        value: V,
+        flags: &MetaSetFlags,
+        codec: &C,
+    ) -> Result<MetaResponse<()>> {
+        dispatch!(self, p => p.meta_set(key, value, flags, codec))
+    }
+    async fn meta_delete(
+        &mut self,
+        key: &str,
+        flags: &MetaDeleteFlags,
+    ) -> Result<MetaResponse<()>> {
+        dispatch!(self, p => p.meta_delete(key, flags))
     }
 }
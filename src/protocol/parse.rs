@@ -4,17 +4,21 @@
 //! 1. 字符串类型前 会 加上字节数 (为了适配追加)
 //! 1. 其他暂时未知, 但是统一用bincode 序列化 和 反序列化 理论上不会有问题
 
-use crate::Result;
+use super::code::DataType;
+use crate::{codec::ValueCodec, error::ClientError, Result};
 use byteorder::{ByteOrder, LittleEndian};
 use std::{
     any::{Any, TypeId},
-    ptr,
+    ptr, str,
 };
 
+/// 长度前缀 + 短字符串 的栈缓冲区大小
+const SMALL_BUF_LEN: usize = 8 + 256;
+
 /// 对于字符串，跳过前8个字节
-pub(crate) fn serialize_bytes<T: 'static>(value: &T) -> Result<Vec<u8>>
+pub(crate) fn serialize_bytes<T>(value: &T) -> Result<Vec<u8>>
 where
-    T: serde::Serialize,
+    T: serde::Serialize + 'static,
 {
     Ok(match parse_as_str(value) {
         Some(s) => bincode::serialize(&s)?.into_iter().skip(8).collect(),
@@ -23,25 +27,83 @@ where
 }
 
 /// 反序列化, 如果可以视为字符串，则用小端表示，把字符串前面增加8个字节, 表示长度
+/// 短字符串在栈上拼接长度前缀, 避免额外的堆分配
 pub(crate) fn deserialize_bytes<T>(bytes: &[u8]) -> Result<T>
 where
     T: serde::de::DeserializeOwned + 'static,
 {
+    if let Some(num) = try_parse_number(bytes)? {
+        return Ok(num);
+    }
     Ok(if can_as_str::<T>() {
-        let mut buf = Vec::with_capacity(8 + bytes.len());
-        buf.append(&mut vec![0; 8]);
-        LittleEndian::write_u64(&mut buf, bytes.len() as u64);
-        for b in bytes {
-            buf.push(*b);
+        let len = 8 + bytes.len();
+        if len <= SMALL_BUF_LEN {
+            let mut buf = [0; SMALL_BUF_LEN];
+            LittleEndian::write_u64(&mut buf, bytes.len() as u64);
+            buf[8..len].copy_from_slice(bytes);
+            bincode::deserialize(&buf[..len])?
+        } else {
+            let mut buf = Vec::with_capacity(len);
+            buf.append(&mut vec![0; 8]);
+            LittleEndian::write_u64(&mut buf, bytes.len() as u64);
+            buf.extend_from_slice(bytes);
+            bincode::deserialize(&buf)?
         }
-        try_parse_number(&buf)?
     } else {
         bincode::deserialize(bytes)?
     })
 }
 
-/// 如果是数字，则返回对于的数字，否反正对应的类型
-fn try_parse_number<T>(bytes: &[u8]) -> Result<T>
+/// Values parsed from one multi-get reply between yields to other tasks.
+pub(crate) const DEFAULT_PARSE_YIELD_EVERY: usize = 64;
+
+/// Let other tasks run once every `every` values of a long reply, `parsed` values in.
+pub(crate) async fn yield_every(parsed: usize, every: usize) {
+    if every > 0 && parsed > 0 && parsed.is_multiple_of(every) {
+        async_std::task::yield_now().await;
+    }
+}
+
+/// 根据 data_type 选择解码方式
+/// JSON 文档是文本, 只能解码为字符串类型
+pub(crate) fn deserialize_value<T, C>(
+    codec: &C,
+    bytes: &[u8],
+    flags: u32,
+    data_type: DataType,
+) -> Result<T>
+where
+    T: serde::de::DeserializeOwned + 'static,
+    C: ValueCodec,
+{
+    match data_type {
+        DataType::Json if !can_as_str::<T>() => Err(ClientError::Error(
+            "JSON values can only be decoded into string types".into(),
+        )
+        .into()),
+        _ => codec.decode(bytes, flags),
+    }
+}
+
+/// Decode a key `lru_crawler metadump` escaped with `%XX`, `None` if it is malformed.
+pub(crate) fn uri_decode(encoded: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = tail.get(2..)?;
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// 如果是数字，则直接从字符串解析出对应的数字，否则返回 None
+fn try_parse_number<T>(bytes: &[u8]) -> Result<Option<T>>
 where
     T: serde::de::DeserializeOwned + 'static,
 {
@@ -49,15 +111,14 @@ where
     macro_rules! downcast {
             ($($ty:ty,)*) => {
                 $(if t_id == TypeId::of::<$ty>() {
-                    let s: String = bincode::deserialize(&bytes)?;
-                    let num: $ty = s.trim().parse()?;
-                    let p = (&num as *const $ty).cast::<T>();
-                    return Ok(unsafe { ptr::read(p) });
+                    let num: $ty = str::from_utf8(bytes)?.trim().parse()?;
+                    let p = ptr::addr_of!(num).cast::<T>();
+                    return Ok(Some(unsafe { ptr::read(p) }));
                 })*
             };
         }
     downcast![u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64,];
-    Ok(bincode::deserialize(bytes)?)
+    Ok(None)
 }
 
 /// 判断 value 是否可以视为str
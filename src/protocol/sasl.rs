@@ -0,0 +1,234 @@
+//! SASL mechanisms of the binary protocol: PLAIN, CRAM-MD5 and, with the `tls` feature, SCRAM-SHA-1.
+
+use crate::{
+    error::{ClientError, ServerError},
+    Result,
+};
+use std::borrow::Cow;
+
+const HMAC_BLOCK_LEN: usize = 64;
+
+fn bad_challenge(what: &'static str) -> ServerError {
+    ServerError::BadResponse(Cow::Borrowed(what))
+}
+
+/// HMAC-MD5 (RFC 2104), md5 has no HMAC of its own.
+fn hmac_md5(key: &[u8], message: &[u8]) -> md5::Digest {
+    let mut block = [0_u8; HMAC_BLOCK_LEN];
+    if key.len() > HMAC_BLOCK_LEN {
+        block[..16].copy_from_slice(&md5::compute(key).0);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = md5::Context::new();
+    inner.consume(block.iter().map(|b| b ^ 0x36).collect::<Vec<_>>());
+    inner.consume(message);
+    let mut outer = md5::Context::new();
+    outer.consume(block.iter().map(|b| b ^ 0x5c).collect::<Vec<_>>());
+    outer.consume(inner.compute().0);
+    outer.compute()
+}
+
+/// A mechanism and how far its exchange got.
+#[derive(Debug)]
+pub(crate) enum Mechanism {
+    Plain,
+    /// `answered` once the challenge was answered, the server only confirms afterwards.
+    CramMd5 {
+        answered: bool,
+    },
+    #[cfg(feature = "tls")]
+    ScramSha1(scram::ScramSha1),
+}
+
+impl Mechanism {
+    /// The strongest mechanism of `offered` this client supports, PLAIN if the server lists none.
+    pub(crate) fn choose(offered: &[String]) -> Result<Self> {
+        let offers = |name: &str| offered.iter().any(|offer| offer == name);
+        #[cfg(feature = "tls")]
+        {
+            if offers("SCRAM-SHA-1") {
+                return Ok(Mechanism::ScramSha1(scram::ScramSha1::new()?));
+            }
+        }
+        if offers("CRAM-MD5") {
+            Ok(Mechanism::CramMd5 { answered: false })
+        } else if offered.is_empty() || offers("PLAIN") {
+            Ok(Mechanism::Plain)
+        } else {
+            Err(ClientError::Error(Cow::Owned(format!(
+                "No supported SASL mechanism in: {}",
+                offered.join(" ")
+            )))
+            .into())
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Mechanism::Plain => "PLAIN",
+            Mechanism::CramMd5 { .. } => "CRAM-MD5",
+            #[cfg(feature = "tls")]
+            Mechanism::ScramSha1(_) => "SCRAM-SHA-1",
+        }
+    }
+
+    /// The data sent with the first auth request.
+    pub(crate) fn start(&mut self, username: &str, password: &str) -> Vec<u8> {
+        match self {
+            Mechanism::Plain => format!("\x00{username}\x00{password}").into_bytes(),
+            Mechanism::CramMd5 { .. } => vec![],
+            #[cfg(feature = "tls")]
+            Mechanism::ScramSha1(scram) => scram.client_first(username),
+        }
+    }
+
+    /// The answer to a `challenge` of the server.
+    pub(crate) fn step(
+        &mut self,
+        username: &str,
+        password: &str,
+        challenge: &[u8],
+    ) -> Result<Vec<u8>> {
+        match self {
+            Mechanism::CramMd5 { answered } if !*answered => {
+                *answered = true;
+                let digest = hmac_md5(password.as_bytes(), challenge);
+                Ok(format!("{username} {digest:x}").into_bytes())
+            }
+            #[cfg(feature = "tls")]
+            Mechanism::ScramSha1(scram) => scram.client_final(password, challenge),
+            _ => Err(bad_challenge("Unexpected SASL challenge").into()),
+        }
+    }
+
+    /// Check the data of the final success response, only SCRAM authenticates the server.
+    #[cfg_attr(not(feature = "tls"), allow(clippy::unnecessary_wraps))]
+    pub(crate) fn finish(&self, data: &[u8]) -> Result<()> {
+        match self {
+            #[cfg(feature = "tls")]
+            Mechanism::ScramSha1(scram) => scram.verify_server_final(data),
+            _ => {
+                let _ = data;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// SCRAM-SHA-1 (RFC 5802) without channel binding, built on openssl.
+#[cfg(feature = "tls")]
+pub(crate) mod scram {
+    use super::bad_challenge;
+    use crate::Result;
+    use openssl::{
+        base64, hash::MessageDigest, pkcs5::pbkdf2_hmac, pkey::PKey, rand::rand_bytes, sha::sha1,
+        sign::Signer,
+    };
+
+    const GS2_HEADER: &str = "n,,";
+
+    #[derive(Debug)]
+    pub(crate) struct ScramSha1 {
+        nonce: String,
+        client_first_bare: String,
+        /// The expected server signature, known after `client_final`.
+        server_signature: Option<Vec<u8>>,
+    }
+
+    fn hmac(key: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+        let key = PKey::hmac(key)?;
+        Ok(Signer::new(MessageDigest::sha1(), &key)?.sign_oneshot_to_vec(message)?)
+    }
+
+    /// `,` and `=` are escaped in user names.
+    fn sasl_name(username: &str) -> String {
+        username.replace('=', "=3D").replace(',', "=2C")
+    }
+
+    impl ScramSha1 {
+        pub(crate) fn new() -> Result<Self> {
+            let mut nonce = [0_u8; 18];
+            rand_bytes(&mut nonce)?;
+            Ok(Self::with_nonce(&base64::encode_block(&nonce)))
+        }
+
+        pub(crate) fn with_nonce(nonce: &str) -> Self {
+            ScramSha1 {
+                nonce: nonce.to_owned(),
+                client_first_bare: String::new(),
+                server_signature: None,
+            }
+        }
+
+        pub(crate) fn client_first(&mut self, username: &str) -> Vec<u8> {
+            self.client_first_bare = format!("n={},r={}", sasl_name(username), self.nonce);
+            format!("{GS2_HEADER}{}", self.client_first_bare).into_bytes()
+        }
+
+        pub(crate) fn client_final(
+            &mut self,
+            password: &str,
+            server_first: &[u8],
+        ) -> Result<Vec<u8>> {
+            if self.server_signature.is_some() {
+                return Err(bad_challenge("Unexpected SASL challenge").into());
+            }
+            let server_first = std::str::from_utf8(server_first)
+                .map_err(|_| bad_challenge("SCRAM server-first-message"))?;
+            let attribute = |name: char| {
+                server_first
+                    .split(',')
+                    .find_map(|part| part.strip_prefix(name)?.strip_prefix('='))
+                    .ok_or_else(|| bad_challenge("SCRAM server-first-message"))
+            };
+            let nonce = attribute('r')?;
+            if !nonce.starts_with(&self.nonce) {
+                return Err(bad_challenge("SCRAM nonce").into());
+            }
+            let salt = base64::decode_block(attribute('s')?)?;
+            let iterations = attribute('i')?
+                .parse()
+                .map_err(|_| bad_challenge("SCRAM iteration count"))?;
+            let mut salted_password = [0_u8; 20];
+            pbkdf2_hmac(
+                password.as_bytes(),
+                &salt,
+                iterations,
+                MessageDigest::sha1(),
+                &mut salted_password,
+            )?;
+            let client_key = hmac(&salted_password, b"Client Key")?;
+            let stored_key = sha1(&client_key);
+            let without_proof = format!(
+                "c={},r={nonce}",
+                base64::encode_block(GS2_HEADER.as_bytes())
+            );
+            let auth_message = format!("{},{server_first},{without_proof}", self.client_first_bare);
+            let client_signature = hmac(&stored_key, auth_message.as_bytes())?;
+            let proof: Vec<u8> = client_key
+                .iter()
+                .zip(client_signature)
+                .map(|(key, signature)| key ^ signature)
+                .collect();
+            let server_key = hmac(&salted_password, b"Server Key")?;
+            self.server_signature = Some(hmac(&server_key, auth_message.as_bytes())?);
+            Ok(format!("{without_proof},p={}", base64::encode_block(&proof)).into_bytes())
+        }
+
+        /// memcached answers a successful exchange with `Authenticated` instead of the
+        /// server-final-message, the signature is only checked if the server sent one.
+        pub(crate) fn verify_server_final(&self, server_final: &[u8]) -> Result<()> {
+            let Some(signature) = std::str::from_utf8(server_final)
+                .ok()
+                .and_then(|message| message.strip_prefix("v="))
+            else {
+                return Ok(());
+            };
+            match &self.server_signature {
+                Some(expected) if *expected == base64::decode_block(signature)? => Ok(()),
+                _ => Err(bad_challenge("SCRAM server signature").into()),
+            }
+        }
+    }
+}
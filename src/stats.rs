@@ -0,0 +1,387 @@
+use std::{collections::HashMap, convert::TryInto, str::FromStr, time::Duration};
+
+/// The general statistics of one server, parsed from the output of `stats`, see [`Client::server_stats`](crate::Client::server_stats).
+/// Counters the server did not report are 0.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Process id of the server.
+    pub pid: u64,
+    /// Seconds since the server started.
+    pub uptime: u64,
+    /// Unix time of the server.
+    pub time: u64,
+    /// The version of the server.
+    pub version: String,
+    /// Open connections.
+    pub curr_connections: u64,
+    /// Connections opened since the server started.
+    pub total_connections: u64,
+    /// Retrieval requests.
+    pub cmd_get: u64,
+    /// Storage requests.
+    pub cmd_set: u64,
+    /// Keys requested and found.
+    pub get_hits: u64,
+    /// Keys requested and not found.
+    pub get_misses: u64,
+    /// Items removed to free memory for new items.
+    pub evictions: u64,
+    /// Items currently stored.
+    pub curr_items: u64,
+    /// Items stored since the server started.
+    pub total_items: u64,
+    /// Bytes currently used to store items.
+    pub bytes: u64,
+    /// Bytes the server may use for storage.
+    pub limit_maxbytes: u64,
+    /// Bytes read from the network.
+    pub bytes_read: u64,
+    /// Bytes written to the network.
+    pub bytes_written: u64,
+    /// Worker threads.
+    pub threads: u64,
+}
+
+fn field<T: FromStr + Default>(stats: &HashMap<String, String>, name: &str) -> T {
+    stats
+        .get(name)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_default()
+}
+
+impl ServerStats {
+    /// Pick the known statistics out of the raw `stats` output, values that do not parse are 0.
+    #[must_use]
+    pub fn from_map(stats: &HashMap<String, String>) -> Self {
+        ServerStats {
+            pid: field(stats, "pid"),
+            uptime: field(stats, "uptime"),
+            time: field(stats, "time"),
+            version: field(stats, "version"),
+            curr_connections: field(stats, "curr_connections"),
+            total_connections: field(stats, "total_connections"),
+            cmd_get: field(stats, "cmd_get"),
+            cmd_set: field(stats, "cmd_set"),
+            get_hits: field(stats, "get_hits"),
+            get_misses: field(stats, "get_misses"),
+            evictions: field(stats, "evictions"),
+            curr_items: field(stats, "curr_items"),
+            total_items: field(stats, "total_items"),
+            bytes: field(stats, "bytes"),
+            limit_maxbytes: field(stats, "limit_maxbytes"),
+            bytes_read: field(stats, "bytes_read"),
+            bytes_written: field(stats, "bytes_written"),
+            threads: field(stats, "threads"),
+        }
+    }
+
+    /// The share of requested keys that were found, `None` before the first retrieval.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let requested = self.get_hits.saturating_add(self.get_misses);
+        (requested > 0).then(|| self.get_hits as f64 / requested as f64)
+    }
+}
+
+/// The settings of one server, parsed from the output of `stats settings`,
+/// see [`Client::server_settings`](crate::Client::server_settings).
+/// Numbers the server did not report are 0, switches are off.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ServerSettings {
+    /// Bytes the server may use for storage, `-m`.
+    pub maxbytes: u64,
+    /// Connections the server accepts at most, `-c`.
+    pub maxconns: u64,
+    /// The TCP port, 0 if TCP is off.
+    pub tcpport: u64,
+    /// The UDP port, 0 if UDP is off.
+    pub udpport: u64,
+    /// Worker threads, `-t`.
+    pub num_threads: u64,
+    /// The largest item the server stores, `-I`.
+    pub item_size_max: u64,
+    /// The factor between the chunk sizes of two slab classes, as reported, e.g. `1.25`.
+    pub growth_factor: String,
+    /// The smallest chunk for key, value and flags.
+    pub chunk_size: u64,
+    /// Whether old items are evicted when memory is full, instead of failing writes (`-M`).
+    pub evictions: bool,
+    /// Whether items have a cas value, `-C` turns it off.
+    pub cas_enabled: bool,
+    /// Whether SASL authentication is required.
+    pub sasl: bool,
+    /// Whether the LRU crawler reclaims expired items in the background.
+    pub lru_crawler: bool,
+    /// Whether slab pages can be moved between slab classes.
+    pub slab_reassign: bool,
+    /// How eagerly slab pages are moved, 0 is never.
+    pub slab_automove: u64,
+}
+
+/// `on`, `yes` and `true` are on, anything else off.
+fn switch(stats: &HashMap<String, String>, name: &str) -> bool {
+    matches!(
+        stats.get(name).map(String::as_str),
+        Some("on" | "yes" | "true")
+    )
+}
+
+impl ServerSettings {
+    /// Pick the known settings out of the raw `stats settings` output, values that do not parse are 0.
+    #[must_use]
+    pub fn from_map(settings: &HashMap<String, String>) -> Self {
+        ServerSettings {
+            maxbytes: field(settings, "maxbytes"),
+            maxconns: field(settings, "maxconns"),
+            tcpport: field(settings, "tcpport"),
+            udpport: field(settings, "udpport"),
+            num_threads: field(settings, "num_threads"),
+            item_size_max: field(settings, "item_size_max"),
+            growth_factor: field(settings, "growth_factor"),
+            chunk_size: field(settings, "chunk_size"),
+            evictions: switch(settings, "evictions"),
+            cas_enabled: switch(settings, "cas_enabled"),
+            sasl: switch(settings, "sasl"),
+            lru_crawler: switch(settings, "lru_crawler"),
+            slab_reassign: switch(settings, "slab_reassign"),
+            slab_automove: field(settings, "slab_automove"),
+        }
+    }
+}
+
+/// The extstore (flash storage) statistics of one server, parsed from `stats` and `stats extstore`,
+/// see [`Client::extstore_stats`](crate::Client::extstore_stats). Counters the server did not report are 0.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExtstoreStats {
+    /// Whether the server reported any `extstore_` statistic, i.e. runs with extstore.
+    pub enabled: bool,
+    /// Keys requested and found, in RAM or on flash.
+    pub get_hits: u64,
+    /// Keys requested and not found, including failed flash reads.
+    pub get_misses: u64,
+    /// Bytes of RAM the server may use for storage.
+    pub limit_maxbytes: u64,
+    /// Hits whose value was read from flash.
+    pub get_extstore: u64,
+    /// Keys whose item header was in RAM but whose value was gone from flash.
+    pub miss_from_extstore: u64,
+    /// Flash reads given up, e.g. because the IO queue was full.
+    pub get_aborted_extstore: u64,
+    /// Flash reads that failed to allocate memory for the value.
+    pub get_oom_extstore: u64,
+    /// Values read from flash with a bad checksum.
+    pub badcrc_from_extstore: u64,
+    /// Values read from flash and stored in RAM again.
+    pub recache_from_extstore: u64,
+    /// Flash reads and writes waiting for an IO thread.
+    pub extstore_io_queue: u64,
+    /// Bytes of flash the server may use.
+    pub extstore_limit_maxbytes: u64,
+    /// Bytes of live values on flash.
+    pub extstore_bytes_used: u64,
+    /// Bytes on flash taken by deleted or overwritten values.
+    pub extstore_bytes_fragmented: u64,
+    /// Bytes read from flash.
+    pub extstore_bytes_read: u64,
+    /// Bytes written to flash.
+    pub extstore_bytes_written: u64,
+    /// Bytes of values dropped when their page was reclaimed.
+    pub extstore_bytes_evicted: u64,
+    /// Live values on flash.
+    pub extstore_objects_used: u64,
+    /// Values read from flash.
+    pub extstore_objects_read: u64,
+    /// Values written to flash.
+    pub extstore_objects_written: u64,
+    /// Values dropped when their page was reclaimed.
+    pub extstore_objects_evicted: u64,
+    /// Flash pages in use.
+    pub extstore_pages_used: u64,
+    /// Flash pages free.
+    pub extstore_pages_free: u64,
+    /// Flash pages allocated since the server started.
+    pub extstore_page_allocs: u64,
+    /// Flash pages evicted with the values still on them.
+    pub extstore_page_evictions: u64,
+    /// Flash pages freed once all their values were gone.
+    pub extstore_page_reclaims: u64,
+    /// Values lost while compacting pages.
+    pub extstore_compact_lost: u64,
+    /// Values moved to a new page while compacting.
+    pub extstore_compact_rescues: u64,
+}
+
+impl ExtstoreStats {
+    /// Pick the known statistics out of the raw `stats` and `stats extstore` output,
+    /// values that do not parse are 0.
+    #[must_use]
+    pub fn from_map(stats: &HashMap<String, String>) -> Self {
+        ExtstoreStats {
+            enabled: stats.keys().any(|name| name.starts_with("extstore_")),
+            get_hits: field(stats, "get_hits"),
+            get_misses: field(stats, "get_misses"),
+            limit_maxbytes: field(stats, "limit_maxbytes"),
+            get_extstore: field(stats, "get_extstore"),
+            miss_from_extstore: field(stats, "miss_from_extstore"),
+            get_aborted_extstore: field(stats, "get_aborted_extstore"),
+            get_oom_extstore: field(stats, "get_oom_extstore"),
+            badcrc_from_extstore: field(stats, "badcrc_from_extstore"),
+            recache_from_extstore: field(stats, "recache_from_extstore"),
+            extstore_io_queue: field(stats, "extstore_io_queue"),
+            extstore_limit_maxbytes: field(stats, "extstore_limit_maxbytes"),
+            extstore_bytes_used: field(stats, "extstore_bytes_used"),
+            extstore_bytes_fragmented: field(stats, "extstore_bytes_fragmented"),
+            extstore_bytes_read: field(stats, "extstore_bytes_read"),
+            extstore_bytes_written: field(stats, "extstore_bytes_written"),
+            extstore_bytes_evicted: field(stats, "extstore_bytes_evicted"),
+            extstore_objects_used: field(stats, "extstore_objects_used"),
+            extstore_objects_read: field(stats, "extstore_objects_read"),
+            extstore_objects_written: field(stats, "extstore_objects_written"),
+            extstore_objects_evicted: field(stats, "extstore_objects_evicted"),
+            extstore_pages_used: field(stats, "extstore_pages_used"),
+            extstore_pages_free: field(stats, "extstore_pages_free"),
+            extstore_page_allocs: field(stats, "extstore_page_allocs"),
+            extstore_page_evictions: field(stats, "extstore_page_evictions"),
+            extstore_page_reclaims: field(stats, "extstore_page_reclaims"),
+            extstore_compact_lost: field(stats, "extstore_compact_lost"),
+            extstore_compact_rescues: field(stats, "extstore_compact_rescues"),
+        }
+    }
+
+    /// Misses of keys memcached still had an item header for, whose value could not be read from flash.
+    /// Evicting or compacting flash too eagerly shows here, more RAM or flash does not help RAM misses.
+    #[must_use]
+    pub fn flash_misses(&self) -> u64 {
+        self.miss_from_extstore
+            .saturating_add(self.get_aborted_extstore)
+            .saturating_add(self.get_oom_extstore)
+            .saturating_add(self.badcrc_from_extstore)
+    }
+
+    /// Misses of keys memcached knew nothing about, never stored, expired or evicted from RAM.
+    #[must_use]
+    pub fn ram_misses(&self) -> u64 {
+        self.get_misses.saturating_sub(self.flash_misses())
+    }
+
+    /// The share of hits served from flash, `None` before the first hit.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn flash_hit_ratio(&self) -> Option<f64> {
+        (self.get_hits > 0).then(|| self.get_extstore as f64 / self.get_hits as f64)
+    }
+
+    /// The bytes the server can store in RAM and on flash together.
+    #[must_use]
+    pub fn effective_capacity(&self) -> u64 {
+        self.limit_maxbytes
+            .saturating_add(self.extstore_limit_maxbytes)
+    }
+
+    /// The share of used flash taken by dead values, `None` with an empty flash.
+    /// A high share means compaction lags behind overwrites and deletes.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn fragmentation(&self) -> Option<f64> {
+        let total = self
+            .extstore_bytes_used
+            .saturating_add(self.extstore_bytes_fragmented);
+        (total > 0).then(|| self.extstore_bytes_fragmented as f64 / total as f64)
+    }
+
+    /// Whether flash reads are being dropped or queued beyond `max_io_queue`, a sign the IO
+    /// threads (`ext_threads`) or the device can not keep up.
+    #[must_use]
+    pub fn io_saturated(&self, max_io_queue: u64) -> bool {
+        self.get_aborted_extstore > 0 || self.extstore_io_queue > max_io_queue
+    }
+}
+
+/// One item of a `lru_crawler metadump`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ItemMetadata {
+    pub(crate) key: String,
+    /// Unix time the item expires at, `None` if it never does.
+    pub(crate) expires_at: Option<u64>,
+    /// The size of the item as the server counts it.
+    pub(crate) size: u64,
+}
+
+impl ItemMetadata {
+    /// Parse a line like `key=abc exp=-1 la=1700000000 cas=2 fetch=no cls=1 size=63`.
+    pub(crate) fn parse(line: &str) -> Option<Self> {
+        let mut key = None;
+        let mut expires_at = None;
+        let mut size = None;
+        for (name, value) in line.split(' ').filter_map(|field| field.split_once('=')) {
+            match name {
+                "key" => key = Some(crate::protocol::parse::uri_decode(value)?),
+                "exp" => expires_at = Some(value.parse::<i64>().ok()?.try_into().ok()),
+                "size" => size = Some(value.parse().ok()?),
+                _ => {}
+            }
+        }
+        Some(ItemMetadata {
+            key: key?,
+            expires_at: expires_at?,
+            size: size?,
+        })
+    }
+}
+
+/// The items of one server whose keys start with a prefix, see
+/// [`Client::stats_items_matching`](crate::Client::stats_items_matching).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrefixStats {
+    /// Items stored.
+    pub items: u64,
+    /// Their size as the server counts it, keys and item headers included.
+    pub bytes: u64,
+    /// Items with an expiration.
+    pub expiring: u64,
+    /// The remaining seconds of the expiring items, summed up.
+    pub total_ttl: u64,
+}
+
+impl PrefixStats {
+    /// The average remaining TTL of the expiring items, `None` without any.
+    #[must_use]
+    pub fn avg_ttl(&self) -> Option<Duration> {
+        self.total_ttl
+            .checked_div(self.expiring)
+            .map(Duration::from_secs)
+    }
+
+    /// Count `item` unless it expired before the unix time `now`.
+    pub(crate) fn add(&mut self, item: &ItemMetadata, now: u64) {
+        if let Some(expires_at) = item.expires_at {
+            if expires_at <= now {
+                return;
+            }
+            self.expiring += 1;
+            self.total_ttl += expires_at - now;
+        }
+        self.items += 1;
+        self.bytes += item.size;
+    }
+}
+
+/// The progress of [`Client::stats_items_matching_with_stop`](crate::Client::stats_items_matching_with_stop),
+/// pass it back to resume a stopped scan.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PrefixScan {
+    /// The servers scanned to the end, with their items.
+    pub scanned: Vec<(String, PrefixStats)>,
+    /// The servers left to scan. A server stopped halfway is scanned again from the start.
+    pub pending: Vec<String>,
+}
+
+impl PrefixScan {
+    /// Whether every server was scanned.
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
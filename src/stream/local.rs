@@ -0,0 +1,591 @@
+use crate::{error::MemcachedError, RawPacket, Result};
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryInto,
+    io,
+    sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use url::Url;
+
+/// Expirations above this are unix timestamps, like in memcached.
+const MAX_RELATIVE_EXPIRATION: u32 = 30 * 24 * 60 * 60;
+
+/// `increment` / `decrement` with this expiration fail on a missing key instead of creating it.
+const DONT_CREATE: u32 = u32::MAX;
+
+/// The version the server reports.
+const VERSION: &str = "1.6.21";
+
+/// The `item_size_max` of `stats settings`.
+const ITEM_SIZE_MAX: usize = 1024 * 1024;
+
+mod opcode {
+    pub(super) const GET: u8 = 0x00;
+    pub(super) const SET: u8 = 0x01;
+    pub(super) const ADD: u8 = 0x02;
+    pub(super) const REPLACE: u8 = 0x03;
+    pub(super) const DELETE: u8 = 0x04;
+    pub(super) const INCREMENT: u8 = 0x05;
+    pub(super) const DECREMENT: u8 = 0x06;
+    pub(super) const QUIT: u8 = 0x07;
+    pub(super) const FLUSH: u8 = 0x08;
+    pub(super) const GETQ: u8 = 0x09;
+    pub(super) const NOOP: u8 = 0x0a;
+    pub(super) const VERSION: u8 = 0x0b;
+    pub(super) const GETK: u8 = 0x0c;
+    pub(super) const GETKQ: u8 = 0x0d;
+    pub(super) const APPEND: u8 = 0x0e;
+    pub(super) const PREPEND: u8 = 0x0f;
+    pub(super) const STAT: u8 = 0x10;
+    pub(super) const SETQ: u8 = 0x11;
+    pub(super) const ADDQ: u8 = 0x12;
+    pub(super) const REPLACEQ: u8 = 0x13;
+    pub(super) const DELETEQ: u8 = 0x14;
+    pub(super) const INCREMENTQ: u8 = 0x15;
+    pub(super) const DECREMENTQ: u8 = 0x16;
+    pub(super) const QUITQ: u8 = 0x17;
+    pub(super) const FLUSHQ: u8 = 0x18;
+    pub(super) const APPENDQ: u8 = 0x19;
+    pub(super) const PREPENDQ: u8 = 0x1a;
+    pub(super) const TOUCH: u8 = 0x1c;
+    pub(super) const GAT: u8 = 0x1d;
+    pub(super) const GATQ: u8 = 0x1e;
+    pub(super) const GATK: u8 = 0x23;
+    pub(super) const GATKQ: u8 = 0x24;
+}
+
+mod status {
+    pub(super) const KEY_NOT_FOUND: u16 = 0x01;
+    pub(super) const KEY_EXISTS: u16 = 0x02;
+    pub(super) const VALUE_TOO_LARGE: u16 = 0x03;
+    pub(super) const INVALID_ARGUMENTS: u16 = 0x04;
+    pub(super) const NOT_STORED: u16 = 0x05;
+    pub(super) const NON_NUMERIC: u16 = 0x06;
+    pub(super) const UNKNOWN_COMMAND: u16 = 0x81;
+}
+
+#[derive(Debug, Clone)]
+struct Item {
+    value: Vec<u8>,
+    flags: u32,
+    /// Virtual unix time the item expires at.
+    expires_at: Option<u64>,
+    cas: u64,
+    /// Virtual unix time the item was written, for delayed flushes.
+    stored_at: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Virtual unix time in seconds, the time of the system if `None`.
+    clock: Option<u64>,
+    items: HashMap<Vec<u8>, Item>,
+    last_cas: u64,
+    /// Items stored up to this time are gone once it is reached.
+    flush_at: Option<u64>,
+    /// The requests received, if they are recorded.
+    requests: Option<Vec<RawPacket>>,
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl State {
+    fn now(&self) -> u64 {
+        self.clock.unwrap_or_else(unix_secs)
+    }
+
+    fn expires_at(&self, expiration: u32) -> Option<u64> {
+        match expiration {
+            0 => None,
+            relative if relative <= MAX_RELATIVE_EXPIRATION => {
+                Some(self.now() + u64::from(relative))
+            }
+            absolute => Some(u64::from(absolute)),
+        }
+    }
+
+    fn next_cas(&mut self) -> u64 {
+        self.last_cas += 1;
+        self.last_cas
+    }
+
+    /// The live item of `key`, expired and flushed items are removed.
+    fn item(&mut self, key: &[u8]) -> Option<&mut Item> {
+        let now = self.now();
+        let flush_at = self.flush_at.filter(|&at| at <= now);
+        let dead = self.items.get(key).is_some_and(|item| {
+            item.expires_at.is_some_and(|at| at <= now)
+                || flush_at.is_some_and(|at| item.stored_at <= at)
+        });
+        if dead {
+            let _ = self.items.remove(key);
+        }
+        self.items.get_mut(key)
+    }
+
+    fn store(&mut self, key: &[u8], value: Vec<u8>, flags: u32, expiration: u32) -> u64 {
+        let cas = self.next_cas();
+        let item = Item {
+            value,
+            flags,
+            expires_at: self.expires_at(expiration),
+            cas,
+            stored_at: self.now(),
+        };
+        let _ = self.items.insert(key.to_vec(), item);
+        cas
+    }
+}
+
+/// An in-process memcached speaking the binary protocol, behind `memcache+local://` urls and
+/// `test_util::MockServer`.
+#[derive(Debug, Default)]
+pub(crate) struct Store {
+    state: Mutex<State>,
+}
+
+/// The stores of `memcache+local://` urls by name, kept for the life of the process.
+static NAMED: OnceLock<Mutex<HashMap<String, Arc<Store>>>> = OnceLock::new();
+
+impl Store {
+    /// The store of `memcache+local://{name}`, shared by every connection to it.
+    pub(crate) fn named(name: &str) -> Arc<Store> {
+        let mut stores = NAMED
+            .get_or_init(Mutex::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(stores.entry(name.to_owned()).or_default())
+    }
+
+    /// A store whose time only moves when told to, recording every request.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn with_virtual_clock(now: u64) -> Self {
+        Store {
+            state: Mutex::new(State {
+                clock: Some(now),
+                requests: Some(vec![]),
+                ..State::default()
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The unix time of the store in seconds.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn now(&self) -> u64 {
+        self.state().now()
+    }
+
+    /// Set the virtual clock.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn set_time(&self, unix_secs: u64) {
+        self.state().clock = Some(unix_secs);
+    }
+
+    /// The requests recorded since the last call.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn requests(&self, take: bool) -> Vec<RawPacket> {
+        let mut state = self.state();
+        let requests = state.requests.get_or_insert_with(Vec::new);
+        if take {
+            std::mem::take(requests)
+        } else {
+            requests.clone()
+        }
+    }
+
+    /// Answer the complete requests at the start of `input` into `output`, returning the
+    /// bytes consumed and whether a quit was among them.
+    pub(crate) fn serve(&self, input: &[u8], output: &mut Vec<u8>) -> (usize, bool) {
+        let mut consumed = 0;
+        let mut state = self.state();
+        while let Some((request, len)) = decode_request(&input[consumed..]) {
+            consumed += len;
+            if let Some(requests) = &mut state.requests {
+                requests.push(request.clone());
+            }
+            for response in handle(&mut state, &request) {
+                encode_response(output, &response);
+            }
+            if matches!(request.opcode, opcode::QUIT | opcode::QUITQ) {
+                return (consumed, true);
+            }
+        }
+        (consumed, false)
+    }
+}
+
+/// A connection to a [`Store`], requests are answered when flushed.
+#[derive(Debug)]
+pub(crate) struct LocalStream {
+    store: Arc<Store>,
+    write_buf: Vec<u8>,
+    read_buf: VecDeque<u8>,
+}
+
+impl LocalStream {
+    /// Connect to the store named by the host of `url`, the text protocol is not supported.
+    pub(crate) fn connect(url: &Url) -> Result<Self> {
+        if url
+            .query_pairs()
+            .any(|(name, value)| name == "protocol" && value == "ascii")
+        {
+            return Err(MemcachedError::BadURL(format!(
+                "{url} only speaks the binary protocol"
+            )));
+        }
+        Ok(LocalStream {
+            store: Store::named(url.host_str().unwrap_or_default()),
+            write_buf: vec![],
+            read_buf: VecDeque::new(),
+        })
+    }
+
+    pub(crate) fn write_all(&mut self, buf: &[u8]) {
+        self.write_buf.extend_from_slice(buf);
+    }
+
+    pub(crate) fn flush(&mut self) {
+        let mut output = vec![];
+        let (consumed, _) = self.store.serve(&self.write_buf, &mut output);
+        drop(self.write_buf.drain(..consumed));
+        self.read_buf.extend(output);
+    }
+
+    /// Nothing arrives later, reading more than was answered is an error instead of a hang.
+    fn nothing_pending() -> io::Error {
+        io::Error::new(io::ErrorKind::UnexpectedEof, "no response pending")
+    }
+
+    pub(crate) fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read_buf.is_empty() && !buf.is_empty() {
+            return Err(Self::nothing_pending());
+        }
+        let n = buf.len().min(self.read_buf.len());
+        for (to, from) in buf.iter_mut().zip(self.read_buf.drain(..n)) {
+            *to = from;
+        }
+        Ok(n)
+    }
+
+    pub(crate) fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        if self.read_buf.len() < buf.len() {
+            return Err(Self::nothing_pending());
+        }
+        let _ = self.read(buf)?;
+        Ok(())
+    }
+}
+
+/// The request at the start of `input` and its length, `None` until it is complete.
+fn decode_request(input: &[u8]) -> Option<(RawPacket, usize)> {
+    let header = input.get(..24)?;
+    let key_length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+    let extras_length = usize::from(header[4]);
+    let total = u32::from_be_bytes(header[8..12].try_into().ok()?) as usize;
+    let body = input.get(24..24 + total)?;
+    let key_end = (extras_length + key_length).min(total);
+    Some((
+        RawPacket {
+            opcode: header[1],
+            status: u16::from_be_bytes([header[6], header[7]]),
+            data_type: header[5],
+            opaque: u32::from_be_bytes(header[12..16].try_into().ok()?),
+            cas: u64::from_be_bytes(header[16..24].try_into().ok()?),
+            extras: body[..extras_length.min(total)].to_vec(),
+            key: body[extras_length.min(total)..key_end].to_vec(),
+            value: body[key_end..].to_vec(),
+        },
+        24 + total,
+    ))
+}
+
+fn encode_response(output: &mut Vec<u8>, packet: &RawPacket) {
+    let total = packet.extras.len() + packet.key.len() + packet.value.len();
+    output.push(0x81);
+    output.push(packet.opcode);
+    output.extend_from_slice(&(packet.key.len() as u16).to_be_bytes());
+    output.push(packet.extras.len() as u8);
+    output.push(packet.data_type);
+    output.extend_from_slice(&packet.status.to_be_bytes());
+    output.extend_from_slice(&(total as u32).to_be_bytes());
+    output.extend_from_slice(&packet.opaque.to_be_bytes());
+    output.extend_from_slice(&packet.cas.to_be_bytes());
+    output.extend_from_slice(&packet.extras);
+    output.extend_from_slice(&packet.key);
+    output.extend_from_slice(&packet.value);
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// Gets answer the key.
+fn returns_key(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        opcode::GETK | opcode::GETKQ | opcode::GATK | opcode::GATKQ
+    )
+}
+
+/// The responses to `request`, none for a quiet request that needs none.
+fn handle(state: &mut State, request: &RawPacket) -> Vec<RawPacket> {
+    let result = match request.opcode {
+        opcode::GET
+        | opcode::GETQ
+        | opcode::GETK
+        | opcode::GETKQ
+        | opcode::GAT
+        | opcode::GATQ
+        | opcode::GATK
+        | opcode::GATKQ => get(state, request),
+        opcode::SET
+        | opcode::SETQ
+        | opcode::ADD
+        | opcode::ADDQ
+        | opcode::REPLACE
+        | opcode::REPLACEQ => store(state, request),
+        opcode::APPEND | opcode::APPENDQ | opcode::PREPEND | opcode::PREPENDQ => {
+            concat(state, request)
+        }
+        opcode::DELETE | opcode::DELETEQ => delete(state, request),
+        opcode::INCREMENT | opcode::INCREMENTQ | opcode::DECREMENT | opcode::DECREMENTQ => {
+            counter(state, request)
+        }
+        opcode::TOUCH => touch(state, request),
+        opcode::FLUSH | opcode::FLUSHQ => {
+            flush(state, request);
+            Ok(vec![RawPacket::default()])
+        }
+        opcode::NOOP | opcode::QUIT | opcode::QUITQ => Ok(vec![RawPacket::default()]),
+        opcode::VERSION => Ok(vec![RawPacket {
+            value: VERSION.as_bytes().to_vec(),
+            ..RawPacket::default()
+        }]),
+        opcode::STAT => stat(state, &request.key).map(|lines| {
+            lines
+                .into_iter()
+                .map(|(name, value)| RawPacket {
+                    key: name.as_bytes().to_vec(),
+                    value: value.into_bytes(),
+                    ..RawPacket::default()
+                })
+                .collect()
+        }),
+        _ => Err(status::UNKNOWN_COMMAND),
+    };
+    let quiet = matches!(
+        request.opcode,
+        opcode::GETQ
+            | opcode::GETKQ
+            | opcode::GATQ
+            | opcode::GATKQ
+            | opcode::SETQ
+            | opcode::ADDQ
+            | opcode::REPLACEQ
+            | opcode::DELETEQ
+            | opcode::INCREMENTQ
+            | opcode::DECREMENTQ
+            | opcode::QUITQ
+            | opcode::FLUSHQ
+            | opcode::APPENDQ
+            | opcode::PREPENDQ
+    );
+    let is_get = matches!(
+        request.opcode,
+        opcode::GETQ | opcode::GETKQ | opcode::GATQ | opcode::GATKQ
+    );
+    let answer = |packet: RawPacket| RawPacket {
+        opcode: request.opcode,
+        opaque: request.opaque,
+        ..packet
+    };
+    match result {
+        // Quiet gets answer only hits, the other quiet commands only failures.
+        Ok(_) if quiet && !is_get => vec![],
+        Err(status::KEY_NOT_FOUND) if quiet && is_get => vec![],
+        Ok(packets) => packets.into_iter().map(answer).collect(),
+        Err(status) => {
+            let message = match status {
+                status::KEY_NOT_FOUND => "Not found",
+                status::KEY_EXISTS => "Data exists for key.",
+                status::VALUE_TOO_LARGE => "Too large.",
+                status::NOT_STORED => "Not stored.",
+                status::NON_NUMERIC => "Non-numeric server-side value for incr or decr",
+                status::UNKNOWN_COMMAND => "Unknown command",
+                _ => "Invalid arguments",
+            };
+            vec![answer(RawPacket {
+                status,
+                key: if returns_key(request.opcode) {
+                    request.key.clone()
+                } else {
+                    vec![]
+                },
+                value: message.as_bytes().to_vec(),
+                ..RawPacket::default()
+            })]
+        }
+    }
+}
+
+/// The responses of a success or the status of the failure.
+type Answer<T = Vec<RawPacket>> = std::result::Result<T, u16>;
+
+fn get(state: &mut State, request: &RawPacket) -> Answer {
+    let touch = matches!(
+        request.opcode,
+        opcode::GAT | opcode::GATQ | opcode::GATK | opcode::GATKQ
+    );
+    let expires_at = u32_at(&request.extras, 0).map(|expiration| state.expires_at(expiration));
+    let item = state.item(&request.key).ok_or(status::KEY_NOT_FOUND)?;
+    if let (true, Some(expires_at)) = (touch, expires_at) {
+        item.expires_at = expires_at;
+    }
+    Ok(vec![RawPacket {
+        cas: item.cas,
+        extras: item.flags.to_be_bytes().to_vec(),
+        key: if returns_key(request.opcode) {
+            request.key.clone()
+        } else {
+            vec![]
+        },
+        value: item.value.clone(),
+        ..RawPacket::default()
+    }])
+}
+
+fn store(state: &mut State, request: &RawPacket) -> Answer {
+    let (Some(flags), Some(expiration)) = (u32_at(&request.extras, 0), u32_at(&request.extras, 4))
+    else {
+        return Err(status::INVALID_ARGUMENTS);
+    };
+    if request.value.len() > ITEM_SIZE_MAX {
+        return Err(status::VALUE_TOO_LARGE);
+    }
+    let current = state.item(&request.key).map(|item| item.cas);
+    match (request.opcode, current) {
+        (opcode::ADD | opcode::ADDQ, Some(_)) => return Err(status::KEY_EXISTS),
+        (opcode::REPLACE | opcode::REPLACEQ, None) => return Err(status::KEY_NOT_FOUND),
+        (_, None) if request.cas != 0 => return Err(status::KEY_NOT_FOUND),
+        (_, Some(cas)) if request.cas != 0 && request.cas != cas => return Err(status::KEY_EXISTS),
+        _ => {}
+    }
+    let cas = state.store(&request.key, request.value.clone(), flags, expiration);
+    Ok(vec![RawPacket {
+        cas,
+        ..RawPacket::default()
+    }])
+}
+
+fn concat(state: &mut State, request: &RawPacket) -> Answer {
+    let cas = state.next_cas();
+    let item = state.item(&request.key).ok_or(status::NOT_STORED)?;
+    if matches!(request.opcode, opcode::APPEND | opcode::APPENDQ) {
+        item.value.extend_from_slice(&request.value);
+    } else {
+        item.value = [request.value.as_slice(), &item.value].concat();
+    }
+    item.cas = cas;
+    Ok(vec![RawPacket {
+        cas,
+        ..RawPacket::default()
+    }])
+}
+
+fn delete(state: &mut State, request: &RawPacket) -> Answer {
+    let cas = state.item(&request.key).ok_or(status::KEY_NOT_FOUND)?.cas;
+    if request.cas != 0 && request.cas != cas {
+        return Err(status::KEY_EXISTS);
+    }
+    let _ = state.items.remove(&request.key);
+    Ok(vec![RawPacket::default()])
+}
+
+fn counter(state: &mut State, request: &RawPacket) -> Answer {
+    let (Some(amount), Some(initial), Some(expiration)) = (
+        u64_at(&request.extras, 0),
+        u64_at(&request.extras, 8),
+        u32_at(&request.extras, 16),
+    ) else {
+        return Err(status::INVALID_ARGUMENTS);
+    };
+    let next_cas = state.next_cas();
+    let (value, cas) = if let Some(item) = state.item(&request.key) {
+        let current = std::str::from_utf8(&item.value)
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .ok_or(status::NON_NUMERIC)?;
+        let value = if matches!(request.opcode, opcode::INCREMENT | opcode::INCREMENTQ) {
+            current.wrapping_add(amount)
+        } else {
+            current.saturating_sub(amount)
+        };
+        // The flags and expiration of the item stay.
+        item.value = value.to_string().into_bytes();
+        item.cas = next_cas;
+        (value, next_cas)
+    } else if expiration == DONT_CREATE {
+        return Err(status::KEY_NOT_FOUND);
+    } else {
+        let cas = state.store(
+            &request.key,
+            initial.to_string().into_bytes(),
+            0,
+            expiration,
+        );
+        (initial, cas)
+    };
+    Ok(vec![RawPacket {
+        cas,
+        value: value.to_be_bytes().to_vec(),
+        ..RawPacket::default()
+    }])
+}
+
+fn touch(state: &mut State, request: &RawPacket) -> Answer {
+    let expiration = u32_at(&request.extras, 0).ok_or(status::INVALID_ARGUMENTS)?;
+    let expires_at = state.expires_at(expiration);
+    let item = state.item(&request.key).ok_or(status::KEY_NOT_FOUND)?;
+    item.expires_at = expires_at;
+    Ok(vec![RawPacket {
+        cas: item.cas,
+        ..RawPacket::default()
+    }])
+}
+
+/// Items stored until the delay in the extras are gone from then on, all right away without one.
+fn flush(state: &mut State, request: &RawPacket) {
+    state.flush_at = u32_at(&request.extras, 0).and_then(|delay| state.expires_at(delay));
+    if state.flush_at.is_none() {
+        state.items.clear();
+    }
+}
+
+/// The lines of a stats group, the empty line ending them is added by the caller.
+fn stat(state: &State, group: &[u8]) -> Answer<Vec<(&'static str, String)>> {
+    let mut lines = match group {
+        b"" => vec![
+            ("pid", std::process::id().to_string()),
+            ("time", state.now().to_string()),
+            ("version", VERSION.to_owned()),
+            ("curr_items", state.items.len().to_string()),
+        ],
+        b"settings" => vec![("item_size_max", ITEM_SIZE_MAX.to_string())],
+        _ => return Err(status::KEY_NOT_FOUND),
+    };
+    lines.push(("", String::new()));
+    Ok(lines)
+}
@@ -1,42 +1,339 @@
-use crate::Result;
+use crate::{
+    error::{MemcachedError, WriteState},
+    metrics::Timings,
+    Result,
+};
+#[cfg(unix)]
+use async_std::os::unix::net::UnixStream;
 use async_std::{
+    future::{timeout, Future},
     io::prelude::{ReadExt, WriteExt},
     net::TcpStream,
 };
 use byteorder::{BigEndian, ByteOrder};
+use std::time::{Duration, Instant};
+
+mod local;
+mod replay;
+#[cfg(feature = "tls")]
+mod tls;
+mod udp;
 
-pub(crate) enum Stream {
+pub(crate) use local::LocalStream;
+#[cfg(feature = "test-util")]
+pub(crate) use local::Store;
+pub(crate) use replay::ReplayStream;
+#[cfg(feature = "tls")]
+pub(crate) use tls::TlsStream;
+pub(crate) use udp::{Retransmission, UdpLoss, UdpStream};
+
+pub(crate) enum Transport {
     Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Udp(Box<UdpStream>),
+    #[cfg(feature = "tls")]
+    Tls(Box<TlsStream>),
+    Local(Box<LocalStream>),
+    Replay(Box<ReplayStream>),
+}
+
+/// Marks the phases of the request in flight and counts its bytes, see `Stream::take_timings`.
+#[derive(Debug, Default, Clone, Copy)]
+struct PhaseClock {
+    write_start: Option<Instant>,
+    flushed: Option<Instant>,
+    first_read: Option<Instant>,
+    last_read: Option<Instant>,
+    /// Bytes were written since the last flush.
+    unflushed: bool,
+    bytes_written: u64,
+    bytes_read: u64,
+}
+
+/// Upper bounds of the connect, read and write operations, `None` waits forever.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Timeouts {
+    pub(crate) connect: Option<Duration>,
+    pub(crate) read: Option<Duration>,
+    pub(crate) write: Option<Duration>,
+    /// The whole operation on a connection, applied by the client.
+    pub(crate) operation: Option<Duration>,
+}
+
+pub(crate) struct Stream {
+    transport: Transport,
+    clock: PhaseClock,
+    timeouts: Timeouts,
+    /// A read or write timed out, the rest of the response may still arrive later.
+    poisoned: bool,
+    /// An operation of the client is running, still set if its future was dropped halfway.
+    in_request: bool,
+    /// The bytes sent and read since the last `restart_recording`, if recorded.
+    recording: Option<Recording>,
+}
+
+/// The bytes of the request in flight, see `Stream::take_recording`.
+#[derive(Debug, Default)]
+pub(crate) struct Recording {
+    pub(crate) sent: Vec<u8>,
+    pub(crate) received: Vec<u8>,
+}
+
+impl From<Transport> for Stream {
+    fn from(transport: Transport) -> Self {
+        Stream {
+            transport,
+            clock: PhaseClock::default(),
+            timeouts: Timeouts::default(),
+            poisoned: false,
+            in_request: false,
+            recording: None,
+        }
+    }
+}
+
+/// Run `future` for at most `limit`, a timeout poisons the stream and reports `state`.
+async fn within<T>(
+    limit: Option<Duration>,
+    poisoned: &mut bool,
+    state: WriteState,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(limit) = limit else {
+        return future.await;
+    };
+    if let Ok(result) = timeout(limit, future).await {
+        result
+    } else {
+        *poisoned = true;
+        Err(MemcachedError::Timeout(state))
+    }
+}
+
+fn between(from: Option<Instant>, to: Option<Instant>) -> Duration {
+    match (from, to) {
+        (Some(from), Some(to)) => to.saturating_duration_since(from),
+        _ => Duration::default(),
+    }
 }
 
 impl Stream {
-    // pub(crate) async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-    //     Ok(match self {
-    //         Stream::Tcp(ref mut stream) => stream.read(buf).await?,
-    //     })
-    // }
+    /// Durations of the write, server and read phases since the last call.
+    /// The server phase lasts from the last flush until the first response bytes arrive.
+    pub(crate) fn take_timings(&mut self, pool_wait: Duration) -> Timings {
+        let clock = std::mem::take(&mut self.clock);
+        Timings {
+            pool_wait,
+            write: between(clock.write_start, clock.flushed),
+            server: between(clock.flushed, clock.first_read),
+            read: between(clock.first_read, clock.last_read),
+            bytes_written: clock.bytes_written,
+            bytes_read: clock.bytes_read,
+        }
+    }
+
+    /// How much of the request since the last `take_timings` was sent.
+    pub(crate) fn write_state(&self) -> WriteState {
+        if self.clock.write_start.is_none() {
+            WriteState::NotWritten
+        } else if self.clock.unflushed {
+            WriteState::Partial
+        } else {
+            WriteState::Written
+        }
+    }
+
+    pub(crate) fn bytes_read(&self) -> u64 {
+        self.clock.bytes_read
+    }
+
+    pub(crate) fn bytes_written(&self) -> u64 {
+        self.clock.bytes_written
+    }
+
+    fn mark_read(&mut self, len: usize) {
+        self.clock.bytes_read += len as u64;
+        let now = Instant::now();
+        if self.clock.first_read.is_none() {
+            self.clock.first_read = Some(now);
+        }
+        self.clock.last_read = Some(now);
+    }
+
+    /// Keep the bytes of each request, see `take_recording`.
+    pub(crate) fn recorded(mut self) -> Self {
+        self.recording = Some(Recording::default());
+        self
+    }
+
+    /// Forget the bytes of earlier requests, e.g. the liveness check of the pool.
+    pub(crate) fn restart_recording(&mut self) {
+        if let Some(recording) = &mut self.recording {
+            *recording = Recording::default();
+        }
+    }
+
+    /// The bytes sent and read since the last `restart_recording`, `None` if not recorded.
+    pub(crate) fn take_recording(&mut self) -> Option<Recording> {
+        self.recording.as_mut().map(std::mem::take)
+    }
+
+    /// Whether the connection replays a capture instead of talking to a server.
+    pub(crate) fn is_replay(&self) -> bool {
+        matches!(self.transport, Transport::Replay(_))
+    }
+
+    pub(crate) fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Send small requests right away instead of coalescing them (Nagle's algorithm), TCP and TLS only.
+    pub(crate) fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        match &self.transport {
+            Transport::Tcp(tcp) => tcp.set_nodelay(nodelay)?,
+            #[cfg(feature = "tls")]
+            Transport::Tls(tls) => tls.set_nodelay(nodelay)?,
+            #[cfg(unix)]
+            Transport::Unix(_) => {}
+            Transport::Udp(_) | Transport::Local(_) | Transport::Replay(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Whether a request was abandoned halfway, the connection must not be reused.
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.poisoned || self.in_request
+    }
+
+    /// Mark an operation as running until `end_request`, a connection dropped in between,
+    /// e.g. by a timeout around the whole operation, is replaced before it is reused.
+    pub(crate) fn begin_request(&mut self) {
+        self.in_request = true;
+    }
+
+    pub(crate) fn end_request(&mut self) {
+        self.in_request = false;
+    }
+
+    /// Replace the connection before it is reused, e.g. after a response was only read in part.
+    pub(crate) fn poison(&mut self) {
+        self.poisoned = true;
+    }
+
+    pub(crate) async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let state = self.write_state();
+        let Stream {
+            transport,
+            timeouts,
+            poisoned,
+            ..
+        } = self;
+        let target = &mut *buf;
+        let n = within(timeouts.read, poisoned, state, async move {
+            Ok(match transport {
+                Transport::Tcp(stream) => stream.read(target).await?,
+                #[cfg(unix)]
+                Transport::Unix(stream) => stream.read(target).await?,
+                Transport::Udp(stream) => stream.read(target).await?,
+                #[cfg(feature = "tls")]
+                Transport::Tls(stream) => stream.read(target).await?,
+                Transport::Local(stream) => stream.read(target)?,
+                Transport::Replay(stream) => stream.read(target)?,
+            })
+        })
+        .await?;
+        if let Some(recording) = &mut self.recording {
+            recording.received.extend_from_slice(&buf[..n]);
+        }
+        self.mark_read(n);
+        Ok(n)
+    }
     pub(crate) async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
-        match self {
-            Stream::Tcp(ref mut stream) => stream.read_exact(buf).await?,
+        let len = buf.len();
+        let state = self.write_state();
+        let Stream {
+            transport,
+            timeouts,
+            poisoned,
+            ..
+        } = self;
+        let target = &mut *buf;
+        within(timeouts.read, poisoned, state, async move {
+            match transport {
+                Transport::Tcp(stream) => stream.read_exact(target).await?,
+                #[cfg(unix)]
+                Transport::Unix(stream) => stream.read_exact(target).await?,
+                Transport::Udp(stream) => stream.read_exact(target).await?,
+                #[cfg(feature = "tls")]
+                Transport::Tls(stream) => stream.read_exact(target).await?,
+                Transport::Local(stream) => stream.read_exact(target)?,
+                Transport::Replay(stream) => stream.read_exact(target)?,
+            }
+            Ok(())
+        })
+        .await?;
+        if let Some(recording) = &mut self.recording {
+            recording.received.extend_from_slice(buf);
         }
+        self.mark_read(len);
         Ok(())
     }
-    // pub(crate) async fn write(&mut self, buf: &[u8]) -> Result<usize> {
-    //     Ok(match self {
-    //         Stream::Tcp(ref mut stream) => stream.write(buf).await?,
-    //     })
-    // }
     pub(crate) async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
-        match self {
-            Stream::Tcp(ref mut stream) => stream.write_all(buf).await?,
+        if self.clock.write_start.is_none() {
+            self.clock.write_start = Some(Instant::now());
         }
-        Ok(())
+        self.clock.bytes_written += buf.len() as u64;
+        self.clock.unflushed = true;
+        if let Some(recording) = &mut self.recording {
+            recording.sent.extend_from_slice(buf);
+        }
+        let Stream {
+            transport,
+            timeouts,
+            poisoned,
+            ..
+        } = self;
+        within(timeouts.write, poisoned, WriteState::Partial, async move {
+            match transport {
+                Transport::Tcp(stream) => stream.write_all(buf).await?,
+                #[cfg(unix)]
+                Transport::Unix(stream) => stream.write_all(buf).await?,
+                Transport::Udp(stream) => stream.write_all(buf),
+                #[cfg(feature = "tls")]
+                Transport::Tls(stream) => stream.write_all(buf)?,
+                Transport::Local(stream) => stream.write_all(buf),
+                Transport::Replay(stream) => stream.write_all(buf)?,
+            }
+            Ok(())
+        })
+        .await
     }
 
     pub(crate) async fn flush(&mut self) -> Result<()> {
-        match self {
-            Stream::Tcp(ref mut stream) => stream.flush().await?,
-        }
+        let Stream {
+            transport,
+            timeouts,
+            poisoned,
+            ..
+        } = self;
+        within(timeouts.write, poisoned, WriteState::Partial, async move {
+            match transport {
+                Transport::Tcp(stream) => stream.flush().await?,
+                #[cfg(unix)]
+                Transport::Unix(stream) => stream.flush().await?,
+                Transport::Udp(stream) => stream.flush().await?,
+                #[cfg(feature = "tls")]
+                Transport::Tls(stream) => stream.flush().await?,
+                Transport::Local(stream) => stream.flush(),
+                Transport::Replay(_) => {}
+            }
+            Ok(())
+        })
+        .await?;
+        self.clock.flushed = Some(Instant::now());
+        self.clock.unflushed = false;
         Ok(())
     }
 
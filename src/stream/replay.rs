@@ -0,0 +1,107 @@
+use crate::{error::MemcachedError, Capture, Result};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock, PoisonError,
+    },
+};
+use url::Url;
+
+static REGISTERED: OnceLock<Mutex<HashMap<String, Arc<Capture>>>> = OnceLock::new();
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A connection answering with the response of a [`Capture`], see [`Capture::replay`].
+#[derive(Debug)]
+pub(crate) struct ReplayStream {
+    capture: Arc<Capture>,
+    /// Bytes of the captured request sent so far.
+    written: usize,
+    /// Bytes of the captured response read so far.
+    read: usize,
+}
+
+impl ReplayStream {
+    /// Keep `capture` for `memcache+replay://{name}` connections, returns the name.
+    pub(crate) fn register(capture: Capture) -> String {
+        let name = format!("capture-{}", NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        let _ = REGISTERED
+            .get_or_init(Mutex::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.clone(), Arc::new(capture));
+        name
+    }
+
+    /// Connect to the capture registered as the host of `url`.
+    pub(crate) fn connect(url: &Url) -> Result<Self> {
+        let name = url.host_str().unwrap_or_default();
+        let capture = REGISTERED
+            .get_or_init(Mutex::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .cloned()
+            .ok_or_else(|| MemcachedError::BadURL(format!("{url} is no registered capture")))?;
+        Ok(ReplayStream {
+            capture,
+            written: 0,
+            read: 0,
+        })
+    }
+
+    /// Accept `buf` if it continues the captured request.
+    pub(crate) fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let expected = self.capture.sent.get(self.written..).unwrap_or_default();
+        if let Some(at) = buf
+            .iter()
+            .zip(expected)
+            .position(|(sent, captured)| sent != captured)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "request differs from the capture at byte {}",
+                    self.written + at
+                ),
+            ));
+        }
+        if buf.len() > expected.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "request is longer than the capture at byte {}",
+                    self.written + expected.len()
+                ),
+            ));
+        }
+        self.written += buf.len();
+        Ok(())
+    }
+
+    /// Nothing arrives after the captured response, reading further is an error instead of a hang.
+    fn exhausted() -> io::Error {
+        io::Error::new(io::ErrorKind::UnexpectedEof, "end of the captured response")
+    }
+
+    pub(crate) fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let rest = self.capture.received.get(self.read..).unwrap_or_default();
+        if rest.is_empty() && !buf.is_empty() {
+            return Err(Self::exhausted());
+        }
+        let n = buf.len().min(rest.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        self.read += n;
+        Ok(n)
+    }
+
+    pub(crate) fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        if self.capture.received.len() - self.read < buf.len() {
+            return Err(Self::exhausted());
+        }
+        let _ = self.read(buf)?;
+        Ok(())
+    }
+}
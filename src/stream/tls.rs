@@ -0,0 +1,168 @@
+//! memcached TLS transport.
+//!
+//! openssl runs over an in-memory buffer: encrypted bytes are moved between the buffer
+//! and the async tcp stream whenever openssl asks for more input or has output ready.
+//!
+//! Supported URL query parameters:
+//! - `ca_path`: PEM file with the CA certificates used to verify the server
+//! - `verify_mode`: `peer` (default) or `none`
+//! - `cert_path` / `key_path`: PEM client certificate chain and private key
+
+use crate::{error::MemcachedError, Result};
+use async_std::{
+    io::prelude::{ReadExt, WriteExt},
+    net::TcpStream,
+};
+use openssl::ssl::{
+    HandshakeError, SslConnector, SslFiletype, SslMethod, SslStream, SslVerifyMode,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+};
+use url::Url;
+
+const READ_CHUNK_LEN: usize = 16 * 1024;
+
+#[derive(Default)]
+pub(crate) struct MemoryStream {
+    incoming: VecDeque<u8>,
+    outgoing: Vec<u8>,
+    chunk: Vec<u8>,
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.incoming.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        self.incoming.read(buf)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.outgoing.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl MemoryStream {
+    async fn send(&mut self, tcp: &mut TcpStream) -> Result<()> {
+        if !self.outgoing.is_empty() {
+            tcp.write_all(&self.outgoing).await?;
+            tcp.flush().await?;
+            self.outgoing.clear();
+        }
+        Ok(())
+    }
+
+    async fn receive(&mut self, tcp: &mut TcpStream) -> Result<()> {
+        self.chunk.resize(READ_CHUNK_LEN, 0);
+        let n = tcp.read(&mut self.chunk).await?;
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        self.incoming.extend(&self.chunk[..n]);
+        Ok(())
+    }
+}
+
+pub(crate) struct TlsStream {
+    tcp: TcpStream,
+    ssl: SslStream<MemoryStream>,
+}
+
+fn connector(url: &Url) -> Result<SslConnector> {
+    let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+    for (name, value) in url.query_pairs() {
+        match &*name {
+            "ca_path" => builder.set_ca_file(&*value)?,
+            "verify_mode" => match &*value {
+                "none" => builder.set_verify(SslVerifyMode::NONE),
+                "peer" => builder.set_verify(SslVerifyMode::PEER),
+                _ => {
+                    return Err(MemcachedError::BadURL(format!(
+                        "Unknown verify_mode: {value}"
+                    )))
+                }
+            },
+            "cert_path" => builder.set_certificate_chain_file(&*value)?,
+            "key_path" => builder.set_private_key_file(&*value, SslFiletype::PEM)?,
+            _ => {}
+        }
+    }
+    Ok(builder.build())
+}
+
+impl TlsStream {
+    pub(crate) fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        Ok(self.tcp.set_nodelay(nodelay)?)
+    }
+
+    pub(crate) async fn connect(url: &Url) -> Result<Self> {
+        let domain = url
+            .host_str()
+            .ok_or_else(|| MemcachedError::BadURL(format!("No host in URL: {url}")))?;
+        let connector = connector(url)?;
+        let mut tcp = TcpStream::connect(&*url.socket_addrs(|| None)?).await?;
+        let mut handshake = connector.connect(domain, MemoryStream::default());
+        let ssl = loop {
+            match handshake {
+                Ok(ssl) => break ssl,
+                Err(HandshakeError::WouldBlock(mut mid)) => {
+                    mid.get_mut().send(&mut tcp).await?;
+                    mid.get_mut().receive(&mut tcp).await?;
+                    handshake = mid.handshake();
+                }
+                Err(HandshakeError::SetupFailure(e)) => return Err(e.into()),
+                Err(HandshakeError::Failure(mid)) => return Err(mid.into_error().into()),
+            }
+        };
+        let mut stream = TlsStream { tcp, ssl };
+        stream.ssl.get_mut().send(&mut stream.tcp).await?;
+        Ok(stream)
+    }
+
+    pub(crate) async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match self.ssl.read(buf) {
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.ssl.get_mut().send(&mut self.tcp).await?;
+                    self.ssl.get_mut().receive(&mut self.tcp).await?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub(crate) async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.ssl.read(&mut buf[filled..]) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.ssl.get_mut().send(&mut self.tcp).await?;
+                    self.ssl.get_mut().receive(&mut self.tcp).await?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Encrypt into the memory buffer, `flush` puts it on the wire.
+    pub(crate) fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        Ok(self.ssl.write_all(buf)?)
+    }
+
+    pub(crate) async fn flush(&mut self) -> Result<()> {
+        self.ssl.get_mut().send(&mut self.tcp).await
+    }
+}
@@ -0,0 +1,234 @@
+//! memcached UDP transport.
+//!
+//! Every datagram starts with an 8 byte frame header:
+//! request id, sequence number, total datagrams in the message, reserved.
+//! A request must fit in one datagram, a response may be split into several.
+//!
+//! A request whose response is incomplete after `udp_retransmit_ms` (250 by default) is sent
+//! again under the same request id, up to `udp_retries` (2) times, datagrams received before
+//! are kept. Retransmitted increments, appends etc. may be applied twice.
+
+use crate::{
+    error::{MemcachedError, ServerError, WriteState},
+    Result,
+};
+use async_std::{
+    future::timeout,
+    net::{SocketAddr, UdpSocket},
+};
+use byteorder::{BigEndian, ByteOrder};
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use url::Url;
+
+const FRAME_HEADER_LEN: usize = 8;
+const MAX_DATAGRAM_LEN: usize = 65_507;
+
+/// Requests after which the loss counters are halved, so the ratio follows recent loss.
+const LOSS_WINDOW: u64 = 1024;
+
+/// When and how often a request is sent again, from the url of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Retransmission {
+    pub(crate) after: Duration,
+    pub(crate) retries: u32,
+}
+
+impl Default for Retransmission {
+    fn default() -> Self {
+        Retransmission {
+            after: Duration::from_millis(250),
+            retries: 2,
+        }
+    }
+}
+
+impl Retransmission {
+    pub(crate) fn from_url(url: &Url) -> Result<Self> {
+        let mut retransmission = Retransmission::default();
+        for (name, value) in url.query_pairs() {
+            let bad = || MemcachedError::BadURL(format!("Bad {name}: {value}"));
+            match &*name {
+                "udp_retransmit_ms" => {
+                    retransmission.after = Duration::from_millis(value.parse().map_err(|_| bad())?);
+                }
+                "udp_retries" => retransmission.retries = value.parse().map_err(|_| bad())?,
+                _ => {}
+            }
+        }
+        Ok(retransmission)
+    }
+}
+
+/// Requests and retransmissions of the UDP connections of one server.
+#[derive(Debug, Default)]
+pub(crate) struct UdpLoss {
+    requests: AtomicU64,
+    retransmits: AtomicU64,
+}
+
+impl UdpLoss {
+    fn record_request(&self) {
+        if self.requests.fetch_add(1, Ordering::Relaxed) >= LOSS_WINDOW {
+            self.requests.store(LOSS_WINDOW / 2, Ordering::Relaxed);
+            let retransmits = self.retransmits.load(Ordering::Relaxed);
+            self.retransmits.store(retransmits / 2, Ordering::Relaxed);
+        }
+    }
+
+    fn record_retransmit(&self) {
+        let _ = self.retransmits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Retransmissions per request, recently.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn ratio(&self) -> f64 {
+        let requests = self.requests.load(Ordering::Relaxed);
+        if requests == 0 {
+            return 0.0;
+        }
+        self.retransmits.load(Ordering::Relaxed) as f64 / requests as f64
+    }
+}
+
+pub(crate) struct UdpStream {
+    socket: UdpSocket,
+    request_id: u16,
+    write_buf: Vec<u8>,
+    read_buf: VecDeque<u8>,
+    /// The last datagram sent, for retransmission.
+    request: Vec<u8>,
+    retransmission: Retransmission,
+    loss: Arc<UdpLoss>,
+}
+
+impl UdpStream {
+    pub(crate) async fn connect(
+        addrs: &[SocketAddr],
+        retransmission: Retransmission,
+        loss: Arc<UdpLoss>,
+    ) -> Result<Self> {
+        let addr = addrs
+            .first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no socket address"))?;
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0_u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+        Ok(UdpStream {
+            socket,
+            request_id: 0,
+            write_buf: vec![],
+            read_buf: VecDeque::new(),
+            request: vec![],
+            retransmission,
+            loss,
+        })
+    }
+
+    pub(crate) fn write_all(&mut self, buf: &[u8]) {
+        self.write_buf.extend_from_slice(buf);
+    }
+
+    /// Send the buffered request as a single datagram under a new request id.
+    pub(crate) async fn flush(&mut self) -> Result<()> {
+        if self.write_buf.is_empty() {
+            return Ok(());
+        }
+        self.request_id = self.request_id.wrapping_add(1);
+        self.read_buf.clear();
+        let mut datagram = vec![0; FRAME_HEADER_LEN];
+        BigEndian::write_u16(&mut datagram[0..2], self.request_id);
+        BigEndian::write_u16(&mut datagram[4..6], 1);
+        datagram.append(&mut self.write_buf);
+        let _ = self.socket.send(&datagram).await?;
+        self.request = datagram;
+        self.loss.record_request();
+        Ok(())
+    }
+
+    pub(crate) async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.read_buf.is_empty() {
+            self.receive_message().await?;
+        }
+        let len = buf.len().min(self.read_buf.len());
+        for (b, r) in buf.iter_mut().zip(self.read_buf.drain(..len)) {
+            *b = r;
+        }
+        Ok(len)
+    }
+
+    pub(crate) async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        while self.read_buf.len() < buf.len() {
+            self.receive_message().await?;
+        }
+        let len = buf.len();
+        for (b, r) in buf.iter_mut().zip(self.read_buf.drain(..len)) {
+            *b = r;
+        }
+        Ok(())
+    }
+
+    /// Receive every datagram of the current response and append them in sequence order,
+    /// sending the request again while datagrams are missing.
+    async fn receive_message(&mut self) -> Result<()> {
+        let mut datagram = vec![0; MAX_DATAGRAM_LEN];
+        let mut parts: Vec<Option<Vec<u8>>> = vec![];
+        let mut received = 0;
+        let mut retries = 0;
+        loop {
+            let len =
+                match timeout(self.retransmission.after, self.socket.recv(&mut datagram)).await {
+                    Ok(len) => len?,
+                    Err(_) if retries < self.retransmission.retries => {
+                        retries += 1;
+                        self.loss.record_retransmit();
+                        let _ = self.socket.send(&self.request).await?;
+                        continue;
+                    }
+                    Err(_) => return Err(MemcachedError::Timeout(WriteState::Written)),
+                };
+            if len < FRAME_HEADER_LEN {
+                return Err(ServerError::BadResponse(Cow::Borrowed("UDP frame header")).into());
+            }
+            let request_id = BigEndian::read_u16(&datagram[0..2]);
+            if request_id != self.request_id {
+                // a late response to an earlier request
+                continue;
+            }
+            let sequence = BigEndian::read_u16(&datagram[2..4]) as usize;
+            let total = BigEndian::read_u16(&datagram[4..6]) as usize;
+            if parts.is_empty() {
+                parts.resize(total, None);
+            }
+            match parts.get_mut(sequence) {
+                Some(part @ None) => {
+                    *part = Some(datagram[FRAME_HEADER_LEN..len].to_vec());
+                    received += 1;
+                }
+                Some(Some(_)) => continue,
+                None => {
+                    return Err(
+                        ServerError::BadResponse(Cow::Borrowed("UDP sequence number")).into(),
+                    )
+                }
+            }
+            if received == parts.len() {
+                break;
+            }
+        }
+        self.read_buf.extend(parts.into_iter().flatten().flatten());
+        Ok(())
+    }
+}
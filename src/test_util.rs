@@ -0,0 +1,137 @@
+use crate::{stream::Store, RawPacket, Result};
+use async_std::{
+    io::{ReadExt, WriteExt},
+    net::{TcpListener, TcpStream},
+};
+use std::{
+    convert::TryInto,
+    sync::{Arc, Weak},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A memcached server in the test process, speaking the binary protocol on a local port.
+/// Enabled by the `test-util` feature.
+///
+/// Time on the server is virtual: it starts at the unix time the server was started and only
+/// moves with [`MockServer::advance`] or [`MockServer::set_time`], so expirations, `touch` and
+/// delayed flushes are tested without sleeping, and a server clock skewed against the client
+/// is one call away. Every request is recorded, see [`MockServer::requests`].
+///
+/// Items, counters, cas, touch, flushes, stats and noops are supported, SASL and the text
+/// protocol are not. The server stops accepting connections once dropped.
+///
+/// ## Example
+///
+/// ```rust
+/// # async_std::task::block_on(async { async fn foo() -> memcached::Result<()> {
+/// use memcached::test_util::MockServer;
+/// use std::time::Duration;
+/// let server = MockServer::start().await?;
+/// let client = memcached::connect(&server.url())?;
+/// client.set("mock_ttl_test", "hello", 60).await?;
+/// server.advance(Duration::from_secs(59));
+/// let t: Option<String> = client.get("mock_ttl_test").await?;
+/// assert_eq!(t, Some("hello".to_owned()));
+/// server.advance(Duration::from_secs(1));
+/// let t: Option<String> = client.get("mock_ttl_test").await?;
+/// assert_eq!(t, None);
+///
+/// client.set("mock_flush_test", "hello", 0).await?;
+/// client.flush_with_delay(10).await?;
+/// server.advance(Duration::from_secs(9));
+/// assert!(client.get::<String, _>("mock_flush_test").await?.is_some());
+/// server.advance(Duration::from_secs(1));
+/// assert!(client.get::<String, _>("mock_flush_test").await?.is_none());
+///
+/// let set = server.requests().into_iter().find(|packet| packet.opcode == 0x01).unwrap();
+/// assert_eq!(set.key, b"mock_ttl_test");
+/// assert_eq!(set.extras[4..], 60_u32.to_be_bytes());
+/// # Ok(()) } dbg!(foo().await.unwrap()); });
+/// ```
+#[derive(Debug)]
+pub struct MockServer {
+    url: String,
+    store: Arc<Store>,
+}
+
+impl MockServer {
+    /// Listen on a free port of 127.0.0.1.
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("memcache://{}", listener.local_addr()?);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let store = Arc::new(Store::with_virtual_clock(now));
+        let weak = Arc::downgrade(&store);
+        // Dropping the handle detaches the task, it ends with the first connection after the server is dropped.
+        drop(async_std::task::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                if weak.strong_count() == 0 {
+                    break;
+                }
+                drop(async_std::task::spawn(serve(stream, weak.clone())));
+            }
+        }));
+        Ok(MockServer { url, store })
+    }
+
+    /// The url to connect to.
+    #[must_use]
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// The virtual unix time of the server in seconds.
+    #[must_use]
+    pub fn now(&self) -> u64 {
+        self.store.now()
+    }
+
+    /// Move the clock of the server forward.
+    pub fn advance(&self, by: Duration) {
+        self.store.set_time(self.store.now() + by.as_secs());
+    }
+
+    /// Set the clock of the server, e.g. behind or ahead of the client to test absolute expirations.
+    pub fn set_time(&self, unix_secs: u64) {
+        self.store.set_time(unix_secs);
+    }
+
+    /// The requests received so far, in order, including the version probes of new
+    /// connections and liveness checks.
+    #[must_use]
+    pub fn requests(&self) -> Vec<RawPacket> {
+        self.store.requests(false)
+    }
+
+    /// The requests received since the last call, forgetting them.
+    #[must_use]
+    pub fn take_requests(&self) -> Vec<RawPacket> {
+        self.store.requests(true)
+    }
+}
+
+/// Answer the requests of one connection until it closes or the server is dropped.
+async fn serve(mut stream: TcpStream, store: Weak<Store>) {
+    let mut request = vec![0; 24];
+    loop {
+        request.truncate(24);
+        if stream.read_exact(&mut request).await.is_err() {
+            return;
+        }
+        let total = u32::from_be_bytes(request[8..12].try_into().unwrap_or_default()) as usize;
+        request.resize(24 + total, 0);
+        if stream.read_exact(&mut request[24..]).await.is_err() {
+            return;
+        }
+        let Some(store) = store.upgrade() else {
+            return;
+        };
+        let mut response = vec![];
+        let (_, quit) = store.serve(&request, &mut response);
+        if stream.write_all(&response).await.is_err() || quit {
+            return;
+        }
+    }
+}
@@ -1,8 +1,27 @@
 #[macro_use]
 extern crate lazy_static;
 
-use async_std::task;
-use memcached::Client;
+use async_std::{
+    io::{self, prelude::BufReadExt, BufReader, ReadExt, WriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    task,
+};
+use memcached::{
+    codec::RawBytes,
+    error::{ClientError, CommandError, MemcachedError, WriteState},
+    metrics::MetricsObserver,
+    CasResult, Client, KillSwitch, LivenessCheck, MetaDeleteFlags, MetaGetFlags, MetaSetFlags,
+    MetaStatus, Operation, OutOfMemoryRetry, PoolConfig, Reply, ServerCapabilities,
+    ServerOverrides, Tier, TierBudget, TierOutcome, TtlAction, TtlRule, TtlRules,
+};
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 lazy_static! {
     static ref CLIENT: Client = memcached::connect("memcache://127.0.0.1:11211").unwrap();
@@ -24,6 +43,27 @@ async fn doc_async_test() -> memcached::Result<()> {
     let s16 = task::spawn(async { t16().await.unwrap() });
     let s17 = task::spawn(async { t17().await.unwrap() });
     let s18 = task::spawn(async { t18().await.unwrap() });
+    let s19 = task::spawn(async { t19().await.unwrap() });
+    let s20 = task::spawn(async { t20().await.unwrap() });
+    let s21 = task::spawn(async { t21().await.unwrap() });
+    let s22 = task::spawn(async { t22().await.unwrap() });
+    let s23 = task::spawn(async { t23().await.unwrap() });
+    let s24 = task::spawn(async { t24().await.unwrap() });
+    let s25 = task::spawn(async { t25().await.unwrap() });
+    let s26 = task::spawn(async { t26().await.unwrap() });
+    let s27 = task::spawn(async { t27().await.unwrap() });
+    let s28 = task::spawn(async { t28().await.unwrap() });
+    let s29 = task::spawn(async { t29().await.unwrap() });
+    let s30 = task::spawn(async { t30().await.unwrap() });
+    let s31 = task::spawn(async { t31().await.unwrap() });
+    let s32 = task::spawn(async { t32().await.unwrap() });
+    let s33 = task::spawn(async { t33().await.unwrap() });
+    let s34 = task::spawn(async { t34().await.unwrap() });
+    let s35 = task::spawn(async { t35().await.unwrap() });
+    let s36 = task::spawn(async { t36().await.unwrap() });
+    let s37 = task::spawn(async { t37().await.unwrap() });
+    let s38 = task::spawn(async { t38().await.unwrap() });
+    let s39 = task::spawn(async { t39().await.unwrap() });
     task::block_on(s1);
     task::block_on(s3);
     task::block_on(s4);
@@ -38,6 +78,27 @@ async fn doc_async_test() -> memcached::Result<()> {
     task::block_on(s16);
     task::block_on(s17);
     task::block_on(s18);
+    task::block_on(s19);
+    task::block_on(s20);
+    task::block_on(s21);
+    task::block_on(s22);
+    task::block_on(s23);
+    task::block_on(s24);
+    task::block_on(s25);
+    task::block_on(s26);
+    task::block_on(s27);
+    task::block_on(s28);
+    task::block_on(s29);
+    task::block_on(s30);
+    task::block_on(s31);
+    task::block_on(s32);
+    task::block_on(s33);
+    task::block_on(s34);
+    task::block_on(s35);
+    task::block_on(s36);
+    task::block_on(s37);
+    task::block_on(s38);
+    task::block_on(s39);
     // flush op
     let s6 = task::spawn(async { t6().await.unwrap() });
     task::block_on(s6);
@@ -195,3 +256,1016 @@ async fn t18() -> memcached::Result<()> {
     assert_eq!(t.unwrap(), "300".to_owned());
     Ok(())
 }
+
+async fn t19() -> memcached::Result<()> {
+    // CI serves UDP on 12345 only, see `memcached -U` in the workflow.
+    let client = memcached::connect("memcache+udp://127.0.0.1:12345")?;
+    client.set("udp_test", "hello", 100).await?;
+    let t: Option<String> = client.get("udp_test").await?;
+    assert_eq!(t, Some("hello".to_owned()));
+    let big = "a".repeat(5000);
+    client.set("udp_big_test", big.clone(), 100).await?;
+    let t: Option<String> = client.get("udp_big_test").await?;
+    assert_eq!(t, Some(big));
+    Ok(())
+}
+
+async fn t20() -> memcached::Result<()> {
+    let client = memcached::connect("memcache://127.0.0.1:11211?protocol=ascii")?;
+    client.set("ascii_test", "hello", 100).await?;
+    let t: Option<String> = client.get("ascii_test").await?;
+    assert_eq!(t, Some("hello".to_owned()));
+    client.append("ascii_test", ", 233").await?;
+    client.prepend("ascii_test", "233! ").await?;
+    let t: Option<String> = client.get("ascii_test").await?;
+    assert_eq!(t, Some("233! hello, 233".to_owned()));
+    client.add("ascii_test", "hello", 100).await.unwrap_err();
+    assert!(client.delete("ascii_test").await?);
+    assert!(!client.delete("ascii_test").await?);
+    client
+        .replace("ascii_test", "hello", 100)
+        .await
+        .unwrap_err();
+    client.set("ascii_counter_test", 100, 100).await?;
+    assert_eq!(110, client.increment("ascii_counter_test", 10).await?);
+    assert_eq!(105, client.decrement("ascii_counter_test", 5).await?);
+    assert!(client.touch("ascii_counter_test", 100).await?);
+    let t = client
+        .gets::<u64, _>(&["ascii_counter_test", "ascii_missing_test"])
+        .await?;
+    let (value, _, cas) = t.get("ascii_counter_test").unwrap();
+    assert_eq!(*value, 105);
+    assert_eq!(
+        client
+            .cas("ascii_counter_test", 1, 100, cas.unwrap() + 1)
+            .await?,
+        CasResult::Exists
+    );
+    assert_eq!(
+        client
+            .cas("ascii_counter_test", 1, 100, cas.unwrap())
+            .await?,
+        CasResult::Stored
+    );
+    let (value, _, cas) = client
+        .get_with_cas::<u64, _>("ascii_counter_test")
+        .await?
+        .unwrap();
+    assert_eq!(value, 1);
+    assert_eq!(
+        client.cas("ascii_missing_test", 1, 100, cas).await?,
+        CasResult::NotFound
+    );
+    let t: Option<u64> = client.get_and_touch("ascii_counter_test", 100).await?;
+    assert_eq!(t, Some(1));
+    let t = client
+        .gets_and_touch::<u64, _>(&["ascii_counter_test", "ascii_missing_test"], 100)
+        .await?;
+    assert_eq!(t.len(), 1);
+    assert!(client.get::<String, _>("ascii key").await.is_err());
+    assert!(!client.version().await?.is_empty());
+    assert!(!client.stats().await?.is_empty());
+    let failed = client
+        .sets(&[
+            ("ascii_sets_test1", "1", 100),
+            ("ascii_sets_test2", "2", 100),
+        ])
+        .await?;
+    assert!(failed.is_empty());
+    // A bad key fails the batch before any item is sent.
+    let _ = client.delete("ascii_sets_partial_test").await?;
+    assert!(client
+        .sets(&[
+            ("ascii_sets_partial_test", "1", 100),
+            ("ascii key", "2", 100)
+        ])
+        .await
+        .is_err());
+    let t: Option<String> = client.get("ascii_sets_partial_test").await?;
+    assert_eq!(t, None);
+    let failed = client
+        .deletes(&["ascii_sets_test1", "ascii_sets_test2", "ascii_missing_test"])
+        .await?;
+    assert_eq!(
+        failed.keys().collect::<Vec<_>>(),
+        vec!["ascii_missing_test"]
+    );
+    let replies = client
+        .pipeline()
+        .set("ascii_pipeline_test", "1", 100)
+        .get("ascii_pipeline_test")
+        .get("ascii_missing_test")
+        .increment("ascii_pipeline_test", 2)
+        .increment("ascii_missing_test", 2)
+        .delete("ascii_pipeline_test")
+        .delete("ascii_missing_test")
+        .execute()
+        .await?;
+    assert_eq!(replies.value::<String>(1)?, Some("1".to_owned()));
+    assert_eq!(replies.value::<String>(2)?, None);
+    assert_eq!(
+        replies.into_iter().collect::<Vec<_>>(),
+        vec![
+            Reply::Stored,
+            Reply::Value(Some((b"1".to_vec(), 1 << 24 | 2 << 16))),
+            Reply::Value(None),
+            Reply::Counter(3),
+            Reply::Failed(CommandError::KeyNotFound),
+            Reply::Deleted(true),
+            Reply::Deleted(false),
+        ]
+    );
+    Ok(())
+}
+
+async fn t21() -> memcached::Result<()> {
+    let capabilities = CLIENT.server_capabilities().await?;
+    let limit = capabilities[0].1.item_size_max.unwrap();
+    let big = "a".repeat(limit as usize + 1);
+    let t = CLIENT.set("item_size_max_test", big.clone(), 100).await;
+    assert!(matches!(
+        t,
+        Err(MemcachedError::ClientError(ClientError::ValueTooLarge { size, limit: l }))
+            if size == big.len() && l == limit
+    ));
+    let client = memcached::connect("memcache://127.0.0.1:11211?protocol=ascii")?;
+    let t = client.set("item_size_max_test", big.clone(), 100).await;
+    assert!(matches!(
+        t,
+        Err(MemcachedError::ClientError(
+            ClientError::ValueTooLarge { .. }
+        ))
+    ));
+    // In a batch only the key of the value too large fails, the others are stored.
+    for client in [&*CLIENT, &client] {
+        let _ = client.delete("item_size_max_sets_test").await?;
+        let failures = client
+            .sets(&[
+                ("item_size_max_sets_test", "small".to_owned(), 100),
+                ("item_size_max_test", big.clone(), 100),
+            ])
+            .await?;
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(
+            failures["item_size_max_test"],
+            CommandError::ValueTooLarge
+        ));
+        let t: Option<String> = client.get("item_size_max_sets_test").await?;
+        assert_eq!(t.as_deref(), Some("small"));
+    }
+    CLIENT
+        .set("item_size_max_test", "a".repeat(limit as usize / 2), 100)
+        .await?;
+    Ok(())
+}
+
+async fn t22() -> memcached::Result<()> {
+    let client = memcached::Client::builder(vec![
+        "memcache://127.0.0.1:11211",
+        "memcache://127.0.0.1:12345",
+    ])
+    .hash_function(|key| key.len() as u64)
+    .build()?;
+    let explanation = client.explain("abc");
+    assert_eq!(explanation.route.hash, Some(3));
+    assert_eq!(explanation.route.ring_point, None);
+    assert_eq!(explanation.server_index, 1);
+    assert_eq!(explanation.server, "memcache://127.0.0.1:12345");
+    let explanation = client.tenant("explain")?.explain("abc");
+    assert_eq!(explanation.prefix, "explain:");
+    assert_eq!(explanation.wire_key, "explain:abc");
+    assert_eq!(explanation.route.hash, Some(11));
+    assert_eq!(explanation.server_index, 1);
+    client.tenant("explain")?.set("abc", 1, 100).await?;
+    let t: Option<u8> = client.get("explain:abc").await?;
+    assert_eq!(t, Some(1));
+    Ok(())
+}
+
+async fn t23() -> memcached::Result<()> {
+    for url in [
+        "memcache://127.0.0.1:11211",
+        "memcache://127.0.0.1:11211?protocol=ascii",
+    ] {
+        let client = memcached::connect(url)?;
+        let _ = client.delete("store_missing_test").await?;
+        // Appending to a missing key is "not stored", a status the binary protocol has no error for.
+        assert!(client.append("store_missing_test", "a").await.is_err());
+        assert!(client.prepend("store_missing_test", "a").await.is_err());
+        assert!(matches!(
+            client.replace("store_missing_test", "a", 100).await,
+            Err(MemcachedError::CommandError(CommandError::KeyNotFound))
+        ));
+        client.add("store_missing_test", "a", 100).await?;
+        assert!(matches!(
+            client.add("store_missing_test", "b", 100).await,
+            Err(MemcachedError::CommandError(CommandError::KeyExists))
+        ));
+        client.replace("store_missing_test", "b", 100).await?;
+        client.append("store_missing_test", "c").await?;
+        client.prepend("store_missing_test", "a").await?;
+        let t: Option<String> = client.get("store_missing_test").await?;
+        assert_eq!(t, Some("abc".to_owned()));
+    }
+    Ok(())
+}
+
+/// A proxy to the UDP server on 12345 that drops the first datagram of every UDP request
+/// and counts the TCP connections it forwards.
+async fn lossy_proxy() -> memcached::Result<(u16, Arc<AtomicUsize>)> {
+    let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+    let port = udp.local_addr()?.port();
+    let tcp = TcpListener::bind(("127.0.0.1", port)).await?;
+    let upstream = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+    upstream.connect("127.0.0.1:12345").await?;
+    let client = Arc::new(Mutex::new(None));
+    let (requests, upstream_in, client_in) =
+        (Arc::clone(&udp), Arc::clone(&upstream), Arc::clone(&client));
+    drop(task::spawn(async move {
+        let mut seen = HashSet::new();
+        let mut buf = vec![0; 65_536];
+        while let Ok((len, from)) = requests.recv_from(&mut buf).await {
+            *client_in.lock().unwrap() = Some(from);
+            if !seen.insert((from, buf[..2].to_vec())) {
+                let _ = upstream_in.send(&buf[..len]).await;
+            }
+        }
+    }));
+    drop(task::spawn(async move {
+        let mut buf = vec![0; 65_536];
+        while let Ok(len) = upstream.recv(&mut buf).await {
+            let to = *client.lock().unwrap();
+            if let Some(to) = to {
+                let _ = udp.send_to(&buf[..len], to).await;
+            }
+        }
+    }));
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = Arc::clone(&connections);
+    drop(task::spawn(async move {
+        while let Ok((inbound, _)) = tcp.accept().await {
+            let _ = accepted.fetch_add(1, Ordering::SeqCst);
+            let Ok(outbound) = TcpStream::connect("127.0.0.1:12345").await else {
+                continue;
+            };
+            let (mut client_read, mut client_write) = (inbound.clone(), inbound);
+            let (mut server_read, mut server_write) = (outbound.clone(), outbound);
+            drop(task::spawn(async move {
+                io::copy(&mut client_read, &mut server_write).await
+            }));
+            drop(task::spawn(async move {
+                io::copy(&mut server_read, &mut client_write).await
+            }));
+        }
+    }));
+    Ok((port, connections))
+}
+
+async fn t24() -> memcached::Result<()> {
+    let (port, tcp_connections) = lossy_proxy().await?;
+    let client = memcached::connect(&format!(
+        "memcache+udp://127.0.0.1:{port}?udp_retransmit_ms=50&udp_fallback_loss=0.5"
+    ))?;
+    // The retransmitted set gets through, and makes the loss 100%.
+    client.set("udp_retransmit_test", "hello", 100).await?;
+    assert_eq!(tcp_connections.load(Ordering::SeqCst), 0);
+    let t: Option<String> = client.get("udp_retransmit_test").await?;
+    assert_eq!(t, Some("hello".to_owned()));
+    assert_eq!(tcp_connections.load(Ordering::SeqCst), 1);
+    let client = memcached::connect(&format!(
+        "memcache+udp://127.0.0.1:{port}?udp_retransmit_ms=50&udp_retries=0"
+    ))?;
+    assert!(client
+        .get::<String, _>("udp_retransmit_test")
+        .await
+        .is_err());
+    Ok(())
+}
+
+/// A TCP proxy to 11211 that drops the responses while the returned flag is set.
+async fn stalling_proxy() -> memcached::Result<(u16, Arc<AtomicBool>)> {
+    let tcp = TcpListener::bind("127.0.0.1:0").await?;
+    let port = tcp.local_addr()?.port();
+    let stalled = Arc::new(AtomicBool::new(false));
+    let stalled_in = Arc::clone(&stalled);
+    drop(task::spawn(async move {
+        while let Ok((inbound, _)) = tcp.accept().await {
+            let Ok(outbound) = TcpStream::connect("127.0.0.1:11211").await else {
+                continue;
+            };
+            let (mut client_read, mut client_write) = (inbound.clone(), inbound);
+            let (mut server_read, mut server_write) = (outbound.clone(), outbound);
+            drop(task::spawn(async move {
+                io::copy(&mut client_read, &mut server_write).await
+            }));
+            let stalled = Arc::clone(&stalled_in);
+            drop(task::spawn(async move {
+                let mut buf = vec![0; 65_536];
+                while let Ok(len @ 1..) = server_read.read(&mut buf).await {
+                    if !stalled.load(Ordering::SeqCst) {
+                        client_write.write_all(&buf[..len]).await?;
+                    }
+                }
+                io::Result::Ok(())
+            }));
+        }
+    }));
+    Ok((port, stalled))
+}
+
+async fn t25() -> memcached::Result<()> {
+    let (port, stalled) = stalling_proxy().await?;
+    let client = memcached::Client::builder(format!("memcache://127.0.0.1:{port}"))
+        // One connection, opened before the proxy stalls, so the checkout does not wait on a probe.
+        .pool_config(PoolConfig {
+            max_open: 1,
+            liveness_check: LivenessCheck::Off,
+            ..PoolConfig::default()
+        })
+        .operation_timeout(Duration::from_millis(200))
+        .build()?;
+    client.set("operation_timeout_test", 1, 100).await?;
+    stalled.store(true, Ordering::SeqCst);
+    // Waits for the connection of the increment, the stall is over before it gets it.
+    let waiting = task::spawn({
+        let client = client.clone();
+        async move {
+            task::sleep(Duration::from_millis(50)).await;
+            client.get::<u64, _>("operation_timeout_test").await
+        }
+    });
+    let stalled_in = Arc::clone(&stalled);
+    drop(task::spawn(async move {
+        task::sleep(Duration::from_millis(100)).await;
+        stalled_in.store(false, Ordering::SeqCst);
+    }));
+    match client.increment("operation_timeout_test", 1).await {
+        Err(MemcachedError::Timeout(state)) => assert_eq!(state, WriteState::Written),
+        t => panic!("expected a timeout, got {:?}", t),
+    }
+    // The server applied the increment whose response was lost, the waiting read gets a new
+    // connection instead of the one holding that response.
+    let t: Option<u64> = CLIENT.get("operation_timeout_test").await?;
+    assert_eq!(t, Some(2));
+    assert_eq!(waiting.await?, Some(2));
+    Ok(())
+}
+
+async fn t26() -> memcached::Result<()> {
+    let client = memcached::connect("memcache+local://t26")?;
+    // Every client of the name shares the store.
+    let other = memcached::connect("memcache+local://t26")?;
+    client.set("local_test", "hello", 100).await?;
+    let t: Option<String> = other.get("local_test").await?;
+    assert_eq!(t, Some("hello".to_owned()));
+    assert!(client.add("local_test", "world", 100).await.is_err());
+
+    let (_, _, cas) = client
+        .get_with_cas::<String, _>("local_test")
+        .await?
+        .unwrap();
+    assert_eq!(
+        client.cas("local_test", "stale", 100, cas + 1).await?,
+        CasResult::Exists
+    );
+    assert_eq!(
+        client.cas("local_test", "world", 100, cas).await?,
+        CasResult::Stored
+    );
+    let t = client
+        .gets::<String, _>(&["local_test", "local_missing_test"])
+        .await?;
+    assert_eq!(t.len(), 1);
+
+    client.set("local_counter_test", 1, 0).await?;
+    assert_eq!(client.increment("local_counter_test", 2).await?, 3);
+    assert_eq!(client.decrement("local_counter_test", 5).await?, 0);
+
+    // An absolute expiration in the past.
+    let past = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32
+        - 10;
+    client.set("local_expired_test", "hello", past).await?;
+    let t: Option<String> = client.get("local_expired_test").await?;
+    assert_eq!(t, None);
+
+    client.delete("local_test").await?;
+    let t: Option<String> = client.get("local_test").await?;
+    assert_eq!(t, None);
+    let other_store = memcached::connect("memcache+local://t26_other")?;
+    let t: Option<u64> = other_store.get("local_counter_test").await?;
+    assert_eq!(t, None);
+    assert!(memcached::connect("memcache+local://t26?protocol=ascii")?
+        .version()
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[derive(Default)]
+struct MultiGetSizes(Mutex<Vec<(&'static str, usize, u64)>>);
+
+impl MetricsObserver for MultiGetSizes {
+    fn on_multi_get(&self, op: &'static str, _server: &str, keys: usize, request_bytes: u64) {
+        self.0.lock().unwrap().push((op, keys, request_bytes));
+    }
+}
+
+async fn t27() -> memcached::Result<()> {
+    let sizes = Arc::new(MultiGetSizes::default());
+    let client =
+        memcached::connect("memcache+local://t27")?.with_metrics_observer(Arc::clone(&sizes));
+    client.set("t27_a", "hello", 100).await?;
+    let keys: Vec<String> = (0..2000).map(|i| format!("t27_{i}")).collect();
+    let t = client.gets::<String, _>(&keys).await?;
+    assert!(t.is_empty());
+    let t = client
+        .gets_and_touch::<String, _>(&["t27_a", "t27_bb"], 100)
+        .await?;
+    assert_eq!(t.len(), 1);
+    // A header per key and one for the noop, touches carry 4 bytes of expiration per key.
+    let key_bytes: usize = keys.iter().map(String::len).sum();
+    assert_eq!(
+        *sizes.0.lock().unwrap(),
+        [
+            ("gets", 2000, (24 * 2001 + key_bytes) as u64),
+            ("gets_and_touch", 2, 24 * 3 + 4 * 2 + 5 + 6),
+        ]
+    );
+    Ok(())
+}
+
+/// A text protocol server answering the first `set` of every connection with out of memory,
+/// returns its port and the `set` lines received.
+async fn out_of_memory_server() -> memcached::Result<(u16, Arc<Mutex<Vec<String>>>)> {
+    let tcp = TcpListener::bind("127.0.0.1:0").await?;
+    let port = tcp.local_addr()?.port();
+    let sets = Arc::new(Mutex::new(vec![]));
+    let sets_in = Arc::clone(&sets);
+    drop(task::spawn(async move {
+        while let Ok((stream, _)) = tcp.accept().await {
+            let sets = Arc::clone(&sets_in);
+            drop(task::spawn(async move {
+                let mut reader = BufReader::new(stream.clone());
+                let mut writer = stream;
+                let mut out_of_memory = true;
+                let mut line = String::new();
+                while reader.read_line(&mut line).await? > 0 {
+                    let command = line.trim_end().to_owned();
+                    line.clear();
+                    let reply = if command == "version" {
+                        "VERSION 1.6.21"
+                    } else if command.starts_with("set ") {
+                        let _ = reader.read_line(&mut line).await?;
+                        line.clear();
+                        sets.lock().unwrap().push(command);
+                        if std::mem::take(&mut out_of_memory) {
+                            "SERVER_ERROR out of memory storing object"
+                        } else {
+                            "STORED"
+                        }
+                    } else {
+                        "ERROR"
+                    };
+                    writer.write_all(format!("{reply}\r\n").as_bytes()).await?;
+                }
+                io::Result::Ok(())
+            }));
+        }
+    }));
+    Ok((port, sets))
+}
+
+async fn t28() -> memcached::Result<()> {
+    let (port, sets) = out_of_memory_server().await?;
+    let url = format!("memcache://127.0.0.1:{port}?protocol=ascii");
+    let client = memcached::connect(url.as_str())?;
+    match client.set("t28", "hello", 3600).await {
+        Err(e) => {
+            assert!(matches!(
+                e,
+                MemcachedError::CommandError(CommandError::OutOfMemory)
+            ));
+            assert!(e.is_retryable());
+        }
+        t => panic!("expected out of memory, got {:?}", t),
+    }
+    let client = Client::builder(url)
+        .retry_out_of_memory(OutOfMemoryRetry {
+            delay: Duration::from_millis(1),
+            max_ttl: Some(60),
+        })
+        .build()?;
+    client.set("t28", "hello", 3600).await?;
+    let ttls: Vec<String> = sets
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|set| set.split(' ').nth(3).unwrap().to_owned())
+        .collect();
+    assert_eq!(ttls, ["3600", "3600", "60"]);
+    Ok(())
+}
+
+/// A Unix domain socket proxy to 11211, returns the socket path.
+#[cfg(unix)]
+async fn unix_proxy() -> memcached::Result<String> {
+    use async_std::os::unix::net::UnixListener;
+    let path = std::env::temp_dir().join(format!("memcached_t29_{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).await?;
+    drop(task::spawn(async move {
+        while let Ok((inbound, _)) = listener.accept().await {
+            let Ok(outbound) = TcpStream::connect("127.0.0.1:11211").await else {
+                continue;
+            };
+            let (mut client_read, mut client_write) = (inbound.clone(), inbound);
+            let (mut server_read, mut server_write) = (outbound.clone(), outbound);
+            drop(task::spawn(async move {
+                io::copy(&mut client_read, &mut server_write).await
+            }));
+            drop(task::spawn(async move {
+                io::copy(&mut server_read, &mut client_write).await
+            }));
+        }
+    }));
+    Ok(path.to_string_lossy().into_owned())
+}
+
+#[cfg(unix)]
+async fn t29() -> memcached::Result<()> {
+    let unix = format!("memcache+unix://{}", unix_proxy().await?);
+    let urls = vec![
+        unix.clone(),
+        "memcache+local://t29".to_owned(),
+        "memcache://127.0.0.1:12345?protocol=ascii".to_owned(),
+    ];
+    let local = ServerOverrides {
+        pool: Some(PoolConfig {
+            max_open: 1,
+            ..PoolConfig::default()
+        }),
+        read_timeout: Some(Duration::from_secs(1)),
+        tcp_nodelay: Some(true),
+        ..ServerOverrides::default()
+    };
+    let client = Client::builder(urls.clone())
+        .server_overrides(unix.as_str(), local)
+        .build()?;
+    let keys: Vec<String> = (0..30).map(|i| format!("t29_{i}")).collect();
+    for key in &keys {
+        client.set(key, key.clone(), 100).await?;
+    }
+    let values: std::collections::HashMap<String, (String, u32, Option<u64>)> =
+        client.gets(&keys).await?;
+    assert_eq!(values.len(), keys.len());
+    assert!(keys.iter().all(|key| &values[key].0 == key));
+
+    let unknown = Client::builder(urls)
+        .server_overrides("memcache://127.0.0.1:1", ServerOverrides::default())
+        .build();
+    assert!(matches!(unknown, Err(MemcachedError::BadURL(_))));
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn t29() -> memcached::Result<()> {
+    Ok(())
+}
+
+async fn t30() -> memcached::Result<()> {
+    let (port, _) = out_of_memory_server().await?;
+    // One connection, so a reply left unread by the sets would answer the version.
+    let client = Client::builder(format!("memcache://127.0.0.1:{port}?protocol=ascii"))
+        .pool_config(PoolConfig {
+            max_open: 1,
+            liveness_check: LivenessCheck::Off,
+            ..PoolConfig::default()
+        })
+        .build()?;
+    let failures = client
+        .sets(&[
+            ("t30_a", "a", 100),
+            ("t30_b", "b", 100),
+            ("t30_c", "c", 100),
+        ])
+        .await?;
+    assert_eq!(failures.len(), 1);
+    assert!(matches!(failures["t30_a"], CommandError::OutOfMemory));
+    let versions = client.version().await?;
+    assert!(versions.values().all(|version| version == "1.6.21"));
+
+    let (port, _) = out_of_memory_server().await?;
+    let client = Client::builder(format!("memcache://127.0.0.1:{port}?protocol=ascii"))
+        .pool_config(PoolConfig {
+            max_open: 1,
+            liveness_check: LivenessCheck::Off,
+            ..PoolConfig::default()
+        })
+        .build()?;
+    let replies = client
+        .pipeline()
+        .set("t30_a", "a", 100)
+        .set("t30_b", "b", 100)
+        .set("t30_c", "c", 100)
+        .execute()
+        .await?;
+    assert!(matches!(
+        replies.replies(),
+        [
+            Reply::Failed(CommandError::OutOfMemory),
+            Reply::Stored,
+            Reply::Stored
+        ]
+    ));
+    let versions = client.version().await?;
+    assert!(versions.values().all(|version| version == "1.6.21"));
+    Ok(())
+}
+
+async fn t31() -> memcached::Result<()> {
+    let (port, stalled) = stalling_proxy().await?;
+    let client = Client::builder(format!("memcache://127.0.0.1:{port}"))
+        .pool_config(PoolConfig {
+            max_open: 1,
+            liveness_check: LivenessCheck::Off,
+            ..PoolConfig::default()
+        })
+        // The background fill of the loaded value is sent while stalled, it gives up in time.
+        .read_timeout(Duration::from_millis(500))
+        .build()?;
+    let l1 = memcached::connect("memcache+local://t31")?;
+    client.set("t31", "remote", 100).await?;
+    stalled.store(true, Ordering::SeqCst);
+    let budget = TierBudget {
+        remote: Duration::from_millis(100),
+        ..TierBudget::default()
+    };
+    let t = client
+        .tiered_get(&l1, "t31", 100, budget, || async {
+            Ok("loaded".to_owned())
+        })
+        .await?;
+    assert_eq!((t.value.as_str(), t.tier), ("loaded", Tier::Loader));
+    assert_eq!(t.attempts[1].outcome, TierOutcome::OverBudget);
+    // The abandoned connection is closed in the background, like the one of the fill if its reply was dropped.
+    stalled.store(false, Ordering::SeqCst);
+    task::sleep(Duration::from_millis(700)).await;
+    let t: Option<String> = client.get("t31").await?;
+    assert_eq!(t.as_deref(), Some("loaded"));
+    // The only connection is busy until its read times out, the wait for it counts against the budget.
+    stalled.store(true, Ordering::SeqCst);
+    let busy = task::spawn({
+        let client = client.clone();
+        async move { client.get::<String, _>("t31").await }
+    });
+    task::sleep(Duration::from_millis(50)).await;
+    let t = client
+        .tiered_get(&l1, "t31_busy", 100, budget, || async {
+            Ok("loaded".to_owned())
+        })
+        .await?;
+    assert_eq!(t.attempts[1].outcome, TierOutcome::OverBudget);
+    assert!(t.attempts[1].elapsed < Duration::from_millis(300));
+    // The reply of the busy read is already dropped, its connection is replaced once it times out.
+    stalled.store(false, Ordering::SeqCst);
+    assert!(busy.await.is_err());
+    task::sleep(Duration::from_millis(200)).await;
+    let t: Option<String> = client.get("t31").await?;
+    assert_eq!(t.as_deref(), Some("loaded"));
+    Ok(())
+}
+
+async fn t32() -> memcached::Result<()> {
+    let switch = KillSwitch::default();
+    let client = Client::builder("memcache://127.0.0.1:12345?protocol=ascii")
+        .kill_switch(switch.clone())
+        .build()?;
+    client.set("t32", 5, 100).await?;
+    switch.engage();
+    // Counters
+    assert!(matches!(
+        client.increment("t32", 1).await,
+        Err(MemcachedError::CommandError(CommandError::KeyNotFound))
+    ));
+    assert!(matches!(
+        client.decrement("t32", 1).await,
+        Err(MemcachedError::CommandError(CommandError::KeyNotFound))
+    ));
+    assert_eq!(client.increment_with("t32", 1, 7, 100).await?, 7);
+    assert_eq!(client.decrement_with("t32", 1, 7, 100).await?, 7);
+    assert_eq!(client.increment_or_init("t32", 1, 7, 100).await?, 8);
+    assert!(client
+        .increment_with("t32", 1, 7, memcached::DONT_CREATE)
+        .await
+        .is_err());
+    // Cas
+    assert_eq!(client.cas("t32", 6, 100, 1).await?, CasResult::NotFound);
+    assert_eq!(client.delete_cas("t32", 1).await?, CasResult::NotFound);
+    assert_eq!(client.delete_with_cas("t32").await?, None);
+    // Meta commands
+    let t = client
+        .meta_get::<u64, _>("t32", &MetaGetFlags::default())
+        .await?;
+    assert_eq!((t.status, t.value), (MetaStatus::Miss, None));
+    assert!(client
+        .meta_set("t32", 6, &MetaSetFlags::default())
+        .await?
+        .is_ok());
+    let t = client
+        .meta_delete("t32", &MetaDeleteFlags::default())
+        .await?;
+    assert_eq!(t.status, MetaStatus::NotFound);
+    // Pipelines
+    let replies = client
+        .pipeline()
+        .get("t32")
+        .set("t32", 6, 100)
+        .delete("t32")
+        .increment("t32", 1)
+        .execute()
+        .await?;
+    assert_eq!(
+        replies.replies(),
+        [
+            Reply::Value(None),
+            Reply::Stored,
+            Reply::Deleted(false),
+            Reply::Failed(CommandError::KeyNotFound),
+        ]
+    );
+    // Namespaces
+    client
+        .schedule_invalidation("t32_namespace", SystemTime::now())
+        .await?;
+    assert_eq!(
+        client.namespace_key("t32_namespace", "1").await?,
+        "t32_namespace:0:1"
+    );
+    // Only the servers themselves can't be described.
+    assert!(matches!(
+        client.stats().await,
+        Err(MemcachedError::ClientError(ClientError::CacheBypassed))
+    ));
+    switch.release();
+    assert_eq!(client.get::<u64, _>("t32").await?, Some(5));
+    Ok(())
+}
+
+async fn t33() -> memcached::Result<()> {
+    let rules = TtlRules::default();
+    rules.update(vec![TtlRule {
+        prefix: "t33:".to_owned(),
+        action: TtlAction::Override(50),
+        fraction: 1.0,
+    }]);
+    let client = Client::builder("memcache://127.0.0.1:12345?protocol=ascii")
+        .ttl_rules(rules)
+        .build()?;
+    let ttl = |key: &'static str| {
+        let client = client.clone();
+        async move {
+            let flags = MetaGetFlags {
+                ttl: true,
+                ..MetaGetFlags::default()
+            };
+            let t = client.meta_get::<String, _>(key, &flags).await?;
+            memcached::Result::Ok(t.ttl.unwrap_or_default())
+        }
+    };
+    client
+        .pipeline()
+        .set("t33:pipeline", "a", 3000)
+        .execute()
+        .await?;
+    assert!((1..=50).contains(&ttl("t33:pipeline").await?));
+
+    for key in [
+        "t33:touch",
+        "t33:get_and_touch",
+        "t33:gets_and_touch",
+        "t33_other",
+    ] {
+        client.set(key, "a", 0).await?;
+    }
+    assert!(client.touch("t33:touch", 3000).await?);
+    assert!((1..=50).contains(&ttl("t33:touch").await?));
+    let _: Option<String> = client.get_and_touch("t33:get_and_touch", 3000).await?;
+    assert!((1..=50).contains(&ttl("t33:get_and_touch").await?));
+    let _ = client
+        .gets_and_touch::<String, _>(&["t33:gets_and_touch", "t33_other"], 3000)
+        .await?;
+    assert!((1..=50).contains(&ttl("t33:gets_and_touch").await?));
+    assert!(ttl("t33_other").await? > 50);
+
+    client.delete("t33:counter").await?;
+    assert_eq!(client.increment_with("t33:counter", 1, 1, 3000).await?, 1);
+    assert!((1..=50).contains(&ttl("t33:counter").await?));
+    Ok(())
+}
+
+/// Counts the refresh locks taken.
+#[derive(Default)]
+struct RefreshLocks(AtomicUsize);
+
+impl MetricsObserver for RefreshLocks {
+    fn on_operation(&self, operation: &Operation<'_>) {
+        if let Operation::Add { key, .. } = operation {
+            if key.starts_with("__refresh:") {
+                let _ = self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+async fn t34() -> memcached::Result<()> {
+    let locks = Arc::new(RefreshLocks::default());
+    let client =
+        memcached::connect("memcache://127.0.0.1:12345")?.with_metrics_observer(Arc::clone(&locks));
+    // The lock key of a key this long is longer than memcached accepts.
+    let key = format!("t34:{}", "x".repeat(240));
+    client.delete(&key).await?;
+    let _: String = client
+        .get_or_set(&key, 0, 100, || async { Ok("computed".to_owned()) })
+        .await?;
+    let readers: Vec<_> = (0..20)
+        .map(|_| {
+            let client = client.clone();
+            let key = key.clone();
+            task::spawn(async move {
+                client
+                    .get_or_set(&key, 60, 100, || async {
+                        task::sleep(Duration::from_millis(300)).await;
+                        Ok("recomputed".to_owned())
+                    })
+                    .await
+            })
+        })
+        .collect();
+    for reader in readers {
+        assert_eq!(reader.await?, "computed");
+    }
+    task::sleep(Duration::from_millis(700)).await;
+    assert_eq!(locks.0.load(Ordering::SeqCst), 1);
+    let t: String = client
+        .get_or_set(&key, 60, 100, || async { Ok("unused".to_owned()) })
+        .await?;
+    assert_eq!(t, "recomputed");
+    Ok(())
+}
+
+/// Records the lookups reported, by operation.
+#[derive(Default)]
+struct Lookups(Mutex<Vec<(&'static str, u64, u64)>>);
+
+impl MetricsObserver for Lookups {
+    fn on_lookup(&self, op: &'static str, _server: &str, hits: u64, misses: u64) {
+        self.0.lock().unwrap().push((op, hits, misses));
+    }
+}
+
+async fn t35() -> memcached::Result<()> {
+    let lookups = Arc::new(Lookups::default());
+    let client = memcached::connect("memcache://127.0.0.1:12345")?
+        .with_metrics_observer(Arc::clone(&lookups));
+    client.set("t35", "hello", 100).await?;
+    assert!(client.delete_with_cas("t35").await?.is_some());
+    assert!(client.delete_with_cas("t35").await?.is_none());
+    assert_eq!(
+        *lookups.0.lock().unwrap(),
+        vec![("delete_with_cas", 1, 0), ("delete_with_cas", 0, 1)]
+    );
+    Ok(())
+}
+
+async fn t36() -> memcached::Result<()> {
+    let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    let tenant = client.tenant("t36")?.with_ops_quota(2).with_bytes_quota(16);
+    // Refused for its bytes without spending an operation.
+    tenant
+        .set("big", "hello world, hello world", 100)
+        .await
+        .unwrap_err();
+    // Charged the 2 bytes the codec stores, not their bincode size.
+    tenant.set("a", "hi", 100).await?;
+    tenant.set("b", "hi", 100).await?;
+    let metrics = tenant.metrics();
+    assert_eq!(
+        (metrics.ops, metrics.bytes_written, metrics.rejected),
+        (2, 4, 1)
+    );
+    Ok(())
+}
+
+async fn t37() -> memcached::Result<()> {
+    let client = memcached::connect("memcache://127.0.0.1:12345")?;
+    // `t37:b` with key `c` would share `t37:b:c` with `t37` and key `b:c`.
+    assert!(matches!(
+        client.tenant("t37:b"),
+        Err(MemcachedError::ClientError(ClientError::InvalidTenantName(
+            _
+        )))
+    ));
+    let a = client.tenant("t37")?;
+    let b = client.tenant("t37b")?;
+    a.set("b:c", "a", 100).await?;
+    b.set("c", "b", 100).await?;
+    let t: Option<String> = b.get("c").await?;
+    assert_eq!(t, Some("b".to_owned()));
+    let found = b.gets::<String, _>(&["c", "b:c"]).await?;
+    assert_eq!(found.keys().collect::<Vec<_>>(), ["c"]);
+    let found = a.gets::<String, _>(&["c", "b:c"]).await?;
+    assert_eq!(found["b:c"].0, "a");
+    assert_eq!(found.len(), 1);
+    Ok(())
+}
+
+/// An ASCII server that stores nothing and records every command, answering `version`
+/// only if `versioned`.
+async fn probed_server(versioned: bool) -> memcached::Result<(u16, Arc<Mutex<Vec<String>>>)> {
+    let tcp = TcpListener::bind("127.0.0.1:0").await?;
+    let port = tcp.local_addr()?.port();
+    let commands = Arc::new(Mutex::new(vec![]));
+    let commands_in = Arc::clone(&commands);
+    drop(task::spawn(async move {
+        while let Ok((stream, _)) = tcp.accept().await {
+            let commands = Arc::clone(&commands_in);
+            drop(task::spawn(async move {
+                let mut reader = BufReader::new(stream.clone());
+                let mut writer = stream;
+                let mut line = String::new();
+                while reader.read_line(&mut line).await? > 0 {
+                    let command = line.trim_end().to_owned();
+                    line.clear();
+                    let reply = if command == "version" && versioned {
+                        "VERSION 1.6.21"
+                    } else if command.starts_with("set ") {
+                        let _ = reader.read_line(&mut line).await?;
+                        line.clear();
+                        "STORED"
+                    } else {
+                        "ERROR"
+                    };
+                    commands.lock().unwrap().push(command);
+                    writer.write_all(format!("{reply}\r\n").as_bytes()).await?;
+                }
+                io::Result::Ok(())
+            }));
+        }
+    }));
+    Ok((port, commands))
+}
+
+async fn t38() -> memcached::Result<()> {
+    // Three connections, the server is probed on the first only.
+    let (port, commands) = probed_server(true).await?;
+    let client = Client::builder(format!("memcache://127.0.0.1:{port}?protocol=ascii"))
+        .pool_config(PoolConfig {
+            max_open: 3,
+            max_idle: 3,
+            ..PoolConfig::default()
+        })
+        .prewarm(true)
+        .build()?;
+    task::sleep(Duration::from_millis(200)).await;
+    let capabilities = client.server_capabilities().await?;
+    assert_eq!(capabilities[0].1.version, "1.6.21");
+    let probes = commands.lock().unwrap().clone();
+    assert_eq!(probes, ["version", "stats settings"]);
+
+    // A server that fails the probe is still used, with the default capabilities.
+    let (port, _) = probed_server(false).await?;
+    let client =
+        memcached::connect(format!("memcache://127.0.0.1:{port}?protocol=ascii").as_str())?;
+    client.set("t38", "a", 100).await?;
+    let capabilities = client.server_capabilities().await?;
+    assert_eq!(capabilities[0].1, ServerCapabilities::default());
+    Ok(())
+}
+
+async fn t39() -> memcached::Result<()> {
+    let client = memcached::connect("memcache://127.0.0.1:12345?protocol=ascii")?;
+    let value: Vec<u64> = (0..200_000).collect();
+    client.set_chunked("t39", value.clone(), 100).await?;
+    let t: Option<Vec<u64>> = client.get_chunked("t39").await?;
+    assert_eq!(t.as_ref(), Some(&value));
+    // A flipped byte behind a valid chunk header fails the checksum of the whole value.
+    let chunks = client.chunk_keys("t39").await?;
+    assert_eq!(chunks.len(), 4);
+    let raw = client.clone().with_codec(RawBytes);
+    let mut chunk: Vec<u8> = raw.get(&chunks[2]).await?.unwrap();
+    chunk[100] ^= 1;
+    raw.set(&chunks[2], chunk, 100).await?;
+    match client.get_chunked::<Vec<u64>, _>("t39").await {
+        Err(MemcachedError::ClientError(ClientError::PartialValue {
+            key,
+            chunks: 4,
+            missing: 4,
+        })) => assert_eq!(key, "t39"),
+        t => panic!("expected a partial value, got {:?}", t),
+    }
+    assert!(client.chunk_keys("t39").await?.is_empty());
+    Ok(())
+}